@@ -0,0 +1,277 @@
+//! Structured wasm control flow from the block graph of the IR.
+//!
+//! Reducible graphs follow Ramsey, "Beyond Relooper": code is laid out along the dominator
+//! tree, merge nodes become the continuation of a `block` and loop headers open a `loop`.
+//! Irreducible graphs fall back to a `loop` dispatching on a label local with `br_table`.
+
+use clrs_pe::cil::cfg::{self, Graph};
+
+use crate::ir::{BlockId, Function, Target, Terminator, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Structured {
+    Block(Vec<Structured>),
+    Loop(Vec<Structured>),
+    /// Then and else arms on a non-zero condition
+    If(Value, Vec<Structured>, Vec<Structured>),
+    /// Instructions of an IR block without its terminator
+    Code(BlockId),
+    /// Copy edge arguments into the parameters of the target block
+    Copy(Target),
+    /// Relative label depth as in wasm `br`
+    Br(u32),
+    BrTable(Value, Vec<u32>, u32),
+    Return(Option<Value>),
+    /// Set the label local of the dispatch loop
+    SetLabel(u32),
+    /// `br_table` on the label local
+    Dispatch(Vec<u32>, u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Structure {
+    pub body: Vec<Structured>,
+    /// An `i32` label local is needed for the dispatch loop
+    pub label: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    LoopHeadedBy(usize),
+    BlockFollowedBy(usize),
+    /// `if` or a `switch` arm, not a branch target
+    Other,
+    Dispatch,
+}
+
+struct Layout<'f> {
+    func: &'f Function,
+    /// Reverse postorder number, `usize::MAX` for unreachable blocks
+    rank: Vec<usize>,
+    /// Children in the dominator tree
+    children: Vec<Vec<usize>>,
+    is_merge: Vec<bool>,
+    is_loop_header: Vec<bool>,
+}
+
+impl Graph for Function {
+    fn node_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn successors(&self, node: usize) -> Vec<usize> {
+        self.blocks[node]
+            .terminator
+            .targets()
+            .into_iter()
+            .map(|t| t.block.0 as usize)
+            .collect()
+    }
+}
+
+/// Lay out the blocks of `func` as nested wasm control instructions
+pub fn structure(func: &Function) -> Structure {
+    let order = cfg::reverse_postorder(func);
+    let mut rank = vec![usize::MAX; func.blocks.len()];
+    for (i, &b) in order.iter().enumerate() {
+        rank[b] = i;
+    }
+    let idom = cfg::dominators(func);
+
+    let mut children = vec![Vec::new(); func.blocks.len()];
+    let mut forward_in = vec![0; func.blocks.len()];
+    let mut is_loop_header = vec![false; func.blocks.len()];
+    for l in cfg::loops(func, &idom) {
+        is_loop_header[l.header] = true;
+    }
+    let mut reducible = true;
+    for &b in &order {
+        if let Some(d) = idom[b] {
+            children[d].push(b);
+        }
+        for s in func.successors(b) {
+            if rank[s] > rank[b] {
+                forward_in[s] += 1;
+            } else if !cfg::dominates(&idom, s, b) {
+                // a retreating edge that isn't a back edge
+                reducible = false;
+            }
+        }
+    }
+
+    let graph = Layout {
+        func,
+        rank,
+        children,
+        is_merge: forward_in.iter().map(|&n| n > 1).collect(),
+        is_loop_header,
+    };
+    if reducible {
+        Structure {
+            body: graph.tree(0, &mut Vec::new()),
+            label: false,
+        }
+    } else {
+        Structure {
+            body: graph.dispatch(&order),
+            label: true,
+        }
+    }
+}
+
+impl Layout<'_> {
+    fn depth(ctx: &[Frame], frame: Frame) -> u32 {
+        let pos = ctx.iter().rposition(|&f| f == frame).unwrap();
+        (ctx.len() - 1 - pos) as u32
+    }
+
+    fn tree(&self, x: usize, ctx: &mut Vec<Frame>) -> Vec<Structured> {
+        let mut merges = self.children[x]
+            .iter()
+            .copied()
+            .filter(|&c| self.is_merge[c])
+            .collect::<Vec<_>>();
+        // the last merge child in reverse postorder is the outermost block
+        merges.sort_by_key(|&c| std::cmp::Reverse(self.rank[c]));
+
+        if self.is_loop_header[x] {
+            ctx.push(Frame::LoopHeadedBy(x));
+            let body = self.within(x, &merges, ctx);
+            ctx.pop();
+            vec![Structured::Loop(body)]
+        } else {
+            self.within(x, &merges, ctx)
+        }
+    }
+
+    fn within(&self, x: usize, merges: &[usize], ctx: &mut Vec<Frame>) -> Vec<Structured> {
+        match merges.split_first() {
+            Some((&y, rest)) => {
+                ctx.push(Frame::BlockFollowedBy(y));
+                let inner = self.within(x, rest, ctx);
+                ctx.pop();
+                let mut out = vec![Structured::Block(inner)];
+                out.extend(self.tree(y, ctx));
+                out
+            }
+            None => {
+                let mut out = vec![Structured::Code(BlockId(x as u32))];
+                out.extend(self.terminator(x, ctx));
+                out
+            }
+        }
+    }
+
+    fn terminator(&self, x: usize, ctx: &mut Vec<Frame>) -> Vec<Structured> {
+        match &self.func.blocks[x].terminator {
+            Terminator::Return(value) => vec![Structured::Return(*value)],
+            Terminator::Jump(target) => self.branch(x, target, ctx),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                ctx.push(Frame::Other);
+                let then = self.branch(x, then, ctx);
+                let otherwise = self.branch(x, otherwise, ctx);
+                ctx.pop();
+                vec![Structured::If(*cond, then, otherwise)]
+            }
+            Terminator::Switch {
+                value,
+                targets,
+                default,
+            } => {
+                // each edge gets a block so that its arguments can be copied before leaving
+                let edges = targets.iter().chain(Some(default)).collect::<Vec<_>>();
+                ctx.extend(edges.iter().map(|_| Frame::Other));
+                let mut code = vec![Structured::BrTable(
+                    *value,
+                    (0..targets.len() as u32).collect(),
+                    targets.len() as u32,
+                )];
+                for edge in edges {
+                    ctx.pop();
+                    let mut out = vec![Structured::Block(code)];
+                    out.extend(self.branch(x, edge, ctx));
+                    code = out;
+                }
+                code
+            }
+        }
+    }
+
+    fn branch(&self, x: usize, target: &Target, ctx: &mut Vec<Frame>) -> Vec<Structured> {
+        let t = target.block.0 as usize;
+        let mut out = Vec::new();
+        if !target.args.is_empty() {
+            out.push(Structured::Copy(target.clone()));
+        }
+
+        if ctx.contains(&Frame::Dispatch) {
+            out.push(Structured::SetLabel(t as u32));
+            out.push(Structured::Br(Self::depth(ctx, Frame::Dispatch)));
+        } else if self.rank[t] <= self.rank[x] {
+            out.push(Structured::Br(Self::depth(ctx, Frame::LoopHeadedBy(t))));
+        } else if self.is_merge[t] {
+            out.push(Structured::Br(Self::depth(ctx, Frame::BlockFollowedBy(t))));
+        } else {
+            out.extend(self.tree(t, ctx));
+        }
+        out
+    }
+
+    /// `loop { block { .. block { br_table } code 0 } .. code n }` over reachable blocks
+    fn dispatch(&self, order: &[usize]) -> Vec<Structured> {
+        let mut blocks = order.to_vec();
+        blocks.sort_unstable();
+        let mut table = vec![0; self.func.blocks.len()];
+        for (i, &b) in blocks.iter().enumerate() {
+            table[b] = i as u32;
+        }
+
+        let mut ctx = vec![Frame::Dispatch];
+        ctx.extend(blocks.iter().map(|_| Frame::Other));
+        let mut code = vec![Structured::Dispatch(table, 0)];
+        for &b in &blocks {
+            ctx.pop();
+            let mut out = vec![Structured::Block(code)];
+            out.push(Structured::Code(BlockId(b as u32)));
+            out.extend(self.terminator(b, &mut ctx));
+            code = out;
+        }
+        vec![Structured::Loop(code)]
+    }
+}
+
+#[test]
+fn structure_control_flow() {
+    use clrs_pe::pe::Image;
+
+    let bytes =
+        clrs_pe::cil::asm::assemble(include_str!("../../tests/il/control_flow.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let structure = |name: &str| {
+        let (index, def) = table
+            .list_method_def()
+            .find(|(_, m)| m.name.resolve(root.heap) == Some(name))
+            .unwrap();
+        structure(&crate::ir::lower(index, &def.resolve_body(&image), root).unwrap())
+    };
+
+    // b0 jumps to the loop header b2, the loop body b1 follows it
+    let drain = structure("Drain");
+    assert!(!drain.label);
+    assert!(matches!(
+        &drain.body[..],
+        [Structured::Code(BlockId(0)), Structured::Loop(_)]
+    ));
+    assert!(!structure("Pick").label);
+    assert!(!structure("Nested").label);
+    assert!(structure("Irreducible").label);
+
+    let wasm = crate::compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+}
@@ -0,0 +1,1413 @@
+//! Typed SSA form of a method body, lowered from CIL and emitted as wasm
+
+use std::fmt;
+
+use wasm_encoder::ValType;
+
+use clrs_pe::cil::cfg::Cfg;
+use clrs_pe::cil::disasm::Names;
+use clrs_pe::cil::{Instruction, MethodBody, NumType};
+use clrs_pe::pe::{
+    DisplayWith, FieldAttributes, FieldIndex, LocalVar, MemberRefIndex, MemberRefParent,
+    MetadataRoot, MetadataToken, MethodCallingConvension, MethodDefIndex, MethodDefSig, Param,
+    RetType, TableIndex, Type, TypeDefOrRefOrSpecEncoded, UserStringIndex,
+};
+use scroll::Pread;
+
+use crate::layout::object_layout;
+
+/// Machine level type of a value, multi-word types lower to several wasm values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IrType {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Object reference, managed pointer or native int
+    Ptr,
+    /// PTR/LEN/CAP
+    String,
+    /// PTR/LEN
+    SzArray,
+}
+
+impl IrType {
+    /// `None` for types without a lowering yet, value types among them
+    pub fn of(ty: &Type) -> Option<Self> {
+        Some(match ty {
+            Type::I | Type::U => IrType::Ptr,
+            Type::Boolean
+            | Type::Char
+            | Type::I1
+            | Type::I2
+            | Type::I4
+            | Type::U1
+            | Type::U2
+            | Type::U4 => IrType::I32,
+            Type::I8 | Type::U8 => IrType::I64,
+            Type::R4 => IrType::F32,
+            Type::R8 => IrType::F64,
+            Type::Object | Type::Class(_) => IrType::Ptr,
+            Type::String => IrType::String,
+            Type::SzArray { .. } => IrType::SzArray,
+            _ => return None,
+        })
+    }
+
+    pub fn of_param(param: &Param) -> Option<Self> {
+        match param {
+            Param::Type { byref: true, .. } => Some(IrType::Ptr),
+            Param::Type {
+                byref: false, ty, ..
+            } => Self::of(ty),
+            Param::TypedByref { .. } => None,
+        }
+    }
+
+    /// `Some(None)` for `void`
+    pub fn of_ret(ret: &RetType) -> Option<Option<Self>> {
+        match ret {
+            RetType::Void { .. } => Some(None),
+            RetType::Type { byref: true, .. } => Some(Some(IrType::Ptr)),
+            RetType::Type {
+                byref: false, ty, ..
+            } => Self::of(ty).map(Some),
+            RetType::TypedByref { .. } => None,
+        }
+    }
+
+    /// Stack type after `conv` to `ty`
+    pub fn of_num(ty: NumType) -> Self {
+        match ty {
+            NumType::I1 | NumType::U1 | NumType::I2 | NumType::U2 | NumType::I4 | NumType::U4 => {
+                IrType::I32
+            }
+            NumType::I8 | NumType::U8 => IrType::I64,
+            NumType::I | NumType::U | NumType::Ref => IrType::Ptr,
+            NumType::R4 => IrType::F32,
+            NumType::R8 | NumType::RUn => IrType::F64,
+        }
+    }
+
+    /// A single word with arithmetic, strings and arrays are neither
+    pub fn is_scalar(self) -> bool {
+        !matches!(self, IrType::String | IrType::SzArray)
+    }
+
+    pub fn wasm_types(self) -> &'static [ValType] {
+        match self {
+            IrType::I32 | IrType::Ptr => &[ValType::I32],
+            IrType::I64 => &[ValType::I64],
+            IrType::F32 => &[ValType::F32],
+            IrType::F64 => &[ValType::F64],
+            IrType::String => &[ValType::I32; 3],
+            IrType::SzArray => &[ValType::I32; 2],
+        }
+    }
+}
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            IrType::I32 => "i32",
+            IrType::I64 => "i64",
+            IrType::F32 => "f32",
+            IrType::F64 => "f64",
+            IrType::Ptr => "ptr",
+            IrType::String => "string",
+            IrType::SzArray => "szarray",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Const {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+/// Comparison with an `i32` boolean result.
+/// Unsigned forms are unordered forms for floats, true when either side is NaN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    NeUn,
+    Lt,
+    LtUn,
+    Le,
+    LeUn,
+    Gt,
+    GtUn,
+    Ge,
+    GeUn,
+}
+
+impl Cmp {
+    /// III.1.5 table 4 without object references, which have no lowering yet
+    pub fn accepts(lhs: IrType, rhs: IrType) -> bool {
+        let int = |ty| matches!(ty, IrType::I32 | IrType::Ptr);
+        let float = |ty| matches!(ty, IrType::F32 | IrType::F64);
+        (int(lhs) && int(rhs))
+            || (float(lhs) && float(rhs))
+            || (lhs, rhs) == (IrType::I64, IrType::I64)
+    }
+
+    /// Comparison of a conditional branch
+    pub fn of_branch(inst: &Instruction) -> Option<Self> {
+        Some(match inst {
+            Instruction::Beq(_) => Cmp::Eq,
+            Instruction::BneUn(_) => Cmp::NeUn,
+            Instruction::Blt(_) => Cmp::Lt,
+            Instruction::BltUn(_) => Cmp::LtUn,
+            Instruction::Ble(_) => Cmp::Le,
+            Instruction::BleUn(_) => Cmp::LeUn,
+            Instruction::Bgt(_) => Cmp::Gt,
+            Instruction::BgtUn(_) => Cmp::GtUn,
+            Instruction::Bge(_) => Cmp::Ge,
+            Instruction::BgeUn(_) => Cmp::GeUn,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Cmp::Eq => "eq",
+            Cmp::NeUn => "ne.un",
+            Cmp::Lt => "lt",
+            Cmp::LtUn => "lt.un",
+            Cmp::Le => "le",
+            Cmp::LeUn => "le.un",
+            Cmp::Gt => "gt",
+            Cmp::GtUn => "gt.un",
+            Cmp::Ge => "ge",
+            Cmp::GeUn => "ge.un",
+        })
+    }
+}
+
+/// III.3 arithmetic and bitwise instructions, `Un` forms treat integers as unsigned.
+/// `Ovf` forms raise `OverflowException` instead of wrapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    AddOvf,
+    AddOvfUn,
+    SubOvf,
+    SubOvfUn,
+    MulOvf,
+    MulOvfUn,
+    Div,
+    DivUn,
+    Rem,
+    RemUn,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    ShrUn,
+}
+
+impl BinOp {
+    pub fn of(inst: &Instruction) -> Option<Self> {
+        Some(match inst {
+            Instruction::Add => BinOp::Add,
+            Instruction::Sub => BinOp::Sub,
+            Instruction::Mul => BinOp::Mul,
+            Instruction::AddOvf => BinOp::AddOvf,
+            Instruction::AddOvfUn => BinOp::AddOvfUn,
+            Instruction::SubOvf => BinOp::SubOvf,
+            Instruction::SubOvfUn => BinOp::SubOvfUn,
+            Instruction::MulOvf => BinOp::MulOvf,
+            Instruction::MulOvfUn => BinOp::MulOvfUn,
+            Instruction::Div => BinOp::Div,
+            Instruction::DivUn => BinOp::DivUn,
+            Instruction::Rem => BinOp::Rem,
+            Instruction::RemUn => BinOp::RemUn,
+            Instruction::And => BinOp::And,
+            Instruction::Or => BinOp::Or,
+            Instruction::Xor => BinOp::Xor,
+            Instruction::Shl => BinOp::Shl,
+            Instruction::Shr => BinOp::Shr,
+            Instruction::ShrUn => BinOp::ShrUn,
+            _ => return None,
+        })
+    }
+
+    /// Operation of an overflow checked form, wrapping on overflow
+    pub fn unchecked(self) -> Self {
+        match self {
+            BinOp::AddOvf | BinOp::AddOvfUn => BinOp::Add,
+            BinOp::SubOvf | BinOp::SubOvfUn => BinOp::Sub,
+            BinOp::MulOvf | BinOp::MulOvfUn => BinOp::Mul,
+            op => op,
+        }
+    }
+
+    pub fn is_shift(self) -> bool {
+        matches!(self, BinOp::Shl | BinOp::Shr | BinOp::ShrUn)
+    }
+
+    /// III.1.5 tables 2, 5 and 6, the stack types `self` applies to
+    pub fn accepts(self, lhs: IrType, rhs: IrType) -> bool {
+        let int = |ty| matches!(ty, IrType::I32 | IrType::Ptr);
+        let float = |ty| matches!(ty, IrType::F32 | IrType::F64);
+        if self.is_shift() {
+            return (int(lhs) || lhs == IrType::I64) && int(rhs);
+        }
+        match (lhs, rhs) {
+            (IrType::I64, IrType::I64) => true,
+            _ if int(lhs) && int(rhs) => true,
+            _ if float(lhs) && float(rhs) => matches!(
+                self,
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem
+            ),
+            _ => false,
+        }
+    }
+
+    /// III.1.5 tables 2, 5 and 6, float32 widens when mixed with float64
+    pub fn result_type(self, lhs: IrType, rhs: IrType) -> IrType {
+        if self.is_shift() {
+            return lhs;
+        }
+        match (lhs, rhs) {
+            (IrType::F64, _) | (_, IrType::F64) => IrType::F64,
+            (IrType::F32, _) | (_, IrType::F32) => IrType::F32,
+            (IrType::I64, _) | (_, IrType::I64) => IrType::I64,
+            (IrType::Ptr, _) | (_, IrType::Ptr) => IrType::Ptr,
+            _ => IrType::I32,
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::AddOvf => "add.ovf",
+            BinOp::AddOvfUn => "add.ovf.un",
+            BinOp::SubOvf => "sub.ovf",
+            BinOp::SubOvfUn => "sub.ovf.un",
+            BinOp::MulOvf => "mul.ovf",
+            BinOp::MulOvfUn => "mul.ovf.un",
+            BinOp::Div => "div",
+            BinOp::DivUn => "div.un",
+            BinOp::Rem => "rem",
+            BinOp::RemUn => "rem.un",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Xor => "xor",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr",
+            BinOp::ShrUn => "shr.un",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    /// Bitwise complement
+    Not,
+    /// The value itself, `ArithmeticException` for NaN and infinities
+    CkFinite,
+}
+
+impl UnOp {
+    /// III.1.5 tables 3 and 5
+    pub fn accepts(self, ty: IrType) -> bool {
+        match self {
+            UnOp::Neg => ty.is_scalar(),
+            UnOp::Not => matches!(ty, IrType::I32 | IrType::I64 | IrType::Ptr),
+            UnOp::CkFinite => matches!(ty, IrType::F32 | IrType::F64),
+        }
+    }
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UnOp::Neg => "neg",
+            UnOp::Not => "not",
+            UnOp::CkFinite => "ckfinite",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Const(Const),
+    Arg(u32),
+    SetArg(u32, Value),
+    Local(u32),
+    SetLocal(u32, Value),
+    /// `ldarga`, the argument lives in memory for the whole call
+    ArgAddr(u32),
+    /// `ldloca`, the local lives in memory for the whole call
+    LocalAddr(u32),
+    /// `ldstr`
+    Str(UserStringIndex),
+    /// `ldtoken` of a field with initial data
+    FieldData(FieldIndex),
+    /// `newarr`, zeroed elements of the type for a length
+    NewArr(Type, Value),
+    /// `ldlen`
+    ArrayLen(Value),
+    /// Array and index, `IndexOutOfRangeException` when out of bounds
+    LoadElem(Type, Value, Value),
+    /// Array, index and value
+    StoreElem(Type, Value, Value, Value),
+    /// `RuntimeHelpers.InitializeArray`, copy the data of a field into an array
+    InitArray(Value, Value),
+    /// `MethodDef` or `MemberRef` callee, `this` is the first argument
+    Call(MetadataToken, Vec<Value>),
+    /// `newobj`, allocate an instance of the class of the constructor and call it
+    New(MethodDefIndex, Vec<Value>),
+    /// `ldfld` from an object reference
+    LoadField(FieldIndex, Value),
+    /// Object reference and value
+    StoreField(FieldIndex, Value, Value),
+    FieldAddr(FieldIndex, Value),
+    LoadStatic(FieldIndex),
+    StoreStatic(FieldIndex, Value),
+    StaticAddr(FieldIndex),
+    Compare(Cmp, Value, Value),
+    Binary(BinOp, Value, Value),
+    Unary(UnOp, Value),
+    /// `conv.*`, small integers are truncated then extended back to `int32`
+    Convert(NumType, Value),
+    /// `conv.ovf.*`, `OverflowException` when out of range
+    ConvertOvf(NumType, Value),
+    /// `conv.ovf.*.un`, integers are read as unsigned
+    ConvertOvfUn(NumType, Value),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inst {
+    pub result: Option<Value>,
+    pub op: Op,
+}
+
+/// Edge to a block with values for its parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub block: BlockId,
+    pub args: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+    Return(Option<Value>),
+    Jump(Target),
+    /// Taken when `cond` is non-zero
+    Branch {
+        cond: Value,
+        then: Target,
+        otherwise: Target,
+    },
+    Switch {
+        value: Value,
+        targets: Vec<Target>,
+        default: Target,
+    },
+}
+
+impl Terminator {
+    pub fn targets(&self) -> Vec<&Target> {
+        match self {
+            Terminator::Return(_) => vec![],
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Terminator::Switch {
+                targets, default, ..
+            } => targets.iter().chain(Some(default)).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// Evaluation stack on entry, bottom first
+    pub params: Vec<Value>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    /// `this` comes first for instance methods
+    pub params: Vec<IrType>,
+    pub locals: Vec<IrType>,
+    pub ret: Option<IrType>,
+    /// Type of each value
+    pub values: Vec<IrType>,
+    /// The entry block comes first
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn value_type(&self, value: Value) -> IrType {
+        self.values[value.0 as usize]
+    }
+
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+}
+
+/// Parameters, `this` first, and return type of a signature, `None` if one has no lowering
+pub fn signature_types(sig: &MethodDefSig) -> Option<(Vec<IrType>, Option<IrType>)> {
+    let params = params_of(sig).into_iter().collect::<Option<_>>()?;
+    Some((params, IrType::of_ret(&sig.ret)?))
+}
+
+/// Signature types of a method, an error when one has no lowering
+pub fn method_signature(
+    method: MethodDefIndex,
+    root: &MetadataRoot,
+) -> Result<(Vec<IrType>, Option<IrType>), LowerError> {
+    let sig = method
+        .resolve_table(&root.metadata_stream.table)
+        .unwrap()
+        .resolve_signature(root.heap);
+    signature_types(&sig).ok_or_else(|| LowerError {
+        method,
+        offset: 0,
+        message: format!("unsupported signature {}", sig.display_with(root)),
+    })
+}
+
+/// Signature of a `MemberRef` called through a wasm import, a method of a TypeRef whose
+/// parameters and return type lower, `None` for fields
+pub fn import_signature(index: MemberRefIndex, root: &MetadataRoot) -> Option<MethodDefSig> {
+    let member = index.resolve_table(&root.metadata_stream.table)?;
+    if !matches!(member.class, MemberRefParent::TypeRefIndex(_)) {
+        return None;
+    }
+    let sig = member.resolve_signature(root.heap).ok()?;
+    signature_types(&sig)?;
+    Some(sig)
+}
+
+fn params_of(sig: &MethodDefSig) -> Vec<Option<IrType>> {
+    let this = sig
+        .calling_convension
+        .contains(MethodCallingConvension::HAS_THIS)
+        .then_some(Some(IrType::Ptr));
+    this.into_iter()
+        .chain(sig.params.iter().map(IrType::of_param))
+        .collect()
+}
+
+/// Call lowered inline instead of calling an import
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intrinsic {
+    /// `System.Runtime.CompilerServices.RuntimeHelpers.InitializeArray` of array initializers
+    InitializeArray,
+}
+
+impl Intrinsic {
+    pub fn of(token: MetadataToken, root: &MetadataRoot) -> Option<Self> {
+        let table = &root.metadata_stream.table;
+        let member = token.as_member_ref()?.resolve_table(table)?;
+        let class = match member.class {
+            MemberRefParent::TypeRefIndex(class) => class.resolve_table(table)?,
+            _ => return None,
+        };
+        let name = (
+            class.type_namespace.resolve(root.heap),
+            class.type_name.resolve(root.heap),
+            member.name.resolve(root.heap),
+        );
+        match name {
+            (
+                Some("System.Runtime.CompilerServices"),
+                Some("RuntimeHelpers"),
+                Some("InitializeArray"),
+            ) => Some(Intrinsic::InitializeArray),
+            _ => None,
+        }
+    }
+}
+
+/// Element type of `newarr`, primitive types may be named through their `System` TypeRef
+fn element_type(token: MetadataToken, root: &MetadataRoot) -> Option<Type> {
+    let table = &root.metadata_stream.table;
+    let heap = root.heap;
+    match token {
+        MetadataToken::TypeSpec(spec) => spec
+            .resolve_table(table)?
+            .signature
+            .resolve(heap)?
+            .pread_with(0, scroll::LE)
+            .ok(),
+        MetadataToken::TypeRef(ty) => {
+            let ty = ty.resolve_table(table)?;
+            if ty.type_namespace.resolve(heap) != Some("System") {
+                return Some(Type::Class(TypeDefOrRefOrSpecEncoded::TypeRef(
+                    token.as_type_ref()?,
+                )));
+            }
+            Some(match ty.type_name.resolve(heap)? {
+                "Boolean" => Type::Boolean,
+                "Char" => Type::Char,
+                "SByte" => Type::I1,
+                "Byte" => Type::U1,
+                "Int16" => Type::I2,
+                "UInt16" => Type::U2,
+                "Int32" => Type::I4,
+                "UInt32" => Type::U4,
+                "Int64" => Type::I8,
+                "UInt64" => Type::U8,
+                "Single" => Type::R4,
+                "Double" => Type::R8,
+                "IntPtr" => Type::I,
+                "UIntPtr" => Type::U,
+                "Object" => Type::Object,
+                "String" => Type::String,
+                _ => return None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Element type read or written by `ldelem.*` and `stelem.*`
+fn num_element_type(ty: NumType) -> Type {
+    match ty {
+        NumType::I1 => Type::I1,
+        NumType::U1 => Type::U1,
+        NumType::I2 => Type::I2,
+        NumType::U2 => Type::U2,
+        NumType::I4 => Type::I4,
+        NumType::U4 => Type::U4,
+        NumType::I8 => Type::I8,
+        NumType::U8 => Type::U8,
+        NumType::I => Type::I,
+        NumType::U => Type::U,
+        NumType::R4 => Type::R4,
+        NumType::R8 | NumType::RUn => Type::R8,
+        NumType::Ref => Type::Object,
+    }
+}
+
+/// Method body which can't be lowered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LowerError {
+    pub method: MethodDefIndex,
+    /// IL offset of the offending instruction
+    pub offset: u32,
+    pub message: String,
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:08X}] IL_{:04x}: {}",
+            MetadataToken::MethodDef(self.method).to_raw(),
+            self.offset,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+struct Builder {
+    values: Vec<IrType>,
+    /// Parameters of each reachable CFG block, widened by every incoming edge
+    params: Vec<Option<Vec<Value>>>,
+    /// Parameter types found by the previous pass
+    hints: Vec<Option<Vec<IrType>>>,
+    /// IR block of each reachable CFG block
+    ids: Vec<Option<BlockId>>,
+    /// CFG blocks whose instructions are already lowered
+    lowered: Vec<bool>,
+    /// A back edge widened a parameter of a lowered block, which takes another pass
+    widened: bool,
+}
+
+impl Builder {
+    fn value(&mut self, ty: IrType) -> Value {
+        self.values.push(ty);
+        Value(self.values.len() as u32 - 1)
+    }
+
+    fn ty(&self, value: Value) -> IrType {
+        self.values[value.0 as usize]
+    }
+
+    fn target(&mut self, block: usize, args: Vec<Value>) -> Result<Target, String> {
+        match self.params[block].clone() {
+            None => {
+                let mut types = args.iter().map(|&v| self.ty(v)).collect::<Vec<_>>();
+                if let Some(hints) = &self.hints[block] {
+                    for (ty, &hint) in types.iter_mut().zip(hints) {
+                        *ty = merge(*ty, hint)?;
+                    }
+                }
+                let params = types.into_iter().map(|ty| self.value(ty)).collect();
+                self.params[block] = Some(params);
+            }
+            Some(params) => {
+                for (&param, &arg) in params.iter().zip(&args) {
+                    let ty = merge(self.ty(param), self.ty(arg))?;
+                    if ty != self.ty(param) {
+                        self.values[param.0 as usize] = ty;
+                        self.widened |= self.lowered[block];
+                    }
+                }
+            }
+        }
+        Ok(Target {
+            block: self.ids[block].unwrap(),
+            args,
+        })
+    }
+
+    fn param_types(&self) -> Vec<Option<Vec<IrType>>> {
+        self.params
+            .iter()
+            .map(|params| {
+                let params = params.as_ref()?;
+                Some(params.iter().map(|&v| self.ty(v)).collect())
+            })
+            .collect()
+    }
+}
+
+/// Type of a block parameter reached with both types, `F` widens to `float64`
+fn merge(a: IrType, b: IrType) -> Result<IrType, String> {
+    match (a, b) {
+        _ if a == b => Ok(a),
+        (IrType::F32, IrType::F64) | (IrType::F64, IrType::F32) => Ok(IrType::F64),
+        (IrType::I32, IrType::Ptr) | (IrType::Ptr, IrType::I32) => Ok(IrType::Ptr),
+        _ => Err(format!("stack types {} and {} don't merge", a, b)),
+    }
+}
+
+/// Lower a verified method body, see `clrs_pe::cil::verify`
+pub fn lower(
+    method: MethodDefIndex,
+    body: &MethodBody,
+    root: &MetadataRoot,
+) -> Result<Function, LowerError> {
+    let table = &root.metadata_stream.table;
+    let heap = root.heap;
+    let error = |offset: u32, message: String| LowerError {
+        method,
+        offset,
+        message,
+    };
+    let unsupported =
+        |offset: u32, what: &dyn fmt::Display| error(offset, format!("unsupported {}", what));
+
+    if !body.exception_clauses.is_empty() {
+        return Err(error(0, "exception handling isn't supported".into()));
+    }
+    let cfg = Cfg::new(body, table, heap).map_err(|e| error(e.offset, e.message))?;
+
+    let (params, ret) = method_signature(method, root)?;
+    let locals: Vec<IrType> = match body
+        .local_var_sig
+        .map(|s| s.resolve_table(table).unwrap().resolve_local_var_sig(heap))
+    {
+        Some(sig) => sig
+            .locals
+            .iter()
+            .map(|local| match local {
+                LocalVar::Type { byref: true, .. } => Ok(IrType::Ptr),
+                LocalVar::Type { ty, .. } => {
+                    IrType::of(ty).ok_or_else(|| unsupported(0, &ty.display_with(root)))
+                }
+                LocalVar::TypedByref => Err(unsupported(0, &"typedref")),
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+
+    let order = cfg.reverse_postorder();
+    let mut ids = vec![None; cfg.blocks.len()];
+    let mut reachable = order.clone();
+    reachable.sort_unstable();
+    for (i, &b) in reachable.iter().enumerate() {
+        ids[b] = Some(BlockId(i as u32));
+    }
+
+    // block parameters only widen, so passes repeat until no back edge widens one
+    let mut hints = vec![None; cfg.blocks.len()];
+    let (values, blocks) = loop {
+        let mut builder = Builder {
+            values: Vec::new(),
+            params: vec![None; cfg.blocks.len()],
+            hints,
+            ids: ids.clone(),
+            lowered: vec![false; cfg.blocks.len()],
+            widened: false,
+        };
+        builder.params[0] = Some(Vec::new());
+        let mut blocks = vec![None; reachable.len()];
+
+        for &b in &order {
+            let block = &cfg.blocks[b];
+            let entry = builder.params[b].clone().unwrap();
+            builder.lowered[b] = true;
+            let mut stack = entry.clone();
+            let mut insts = Vec::new();
+            let mut terminator = None;
+
+            for i in block.start..block.end {
+                let inst = &body.instructions[i];
+                macro_rules! push {
+                    ($op:expr, $ty:expr) => {{
+                        let result = builder.value($ty);
+                        insts.push(Inst {
+                            result: Some(result),
+                            op: $op,
+                        });
+                        stack.push(result);
+                    }};
+                }
+                let offset = body.offsets[i];
+                macro_rules! target {
+                    ($block:expr, $args:expr) => {
+                        builder
+                            .target($block, $args)
+                            .map_err(|message| error(offset, message))?
+                    };
+                }
+                let target = |offset: u32| cfg.block_at(offset).unwrap();
+                let compare = |lhs, rhs| match Cmp::accepts(lhs, rhs) {
+                    true => Ok(()),
+                    false => Err(error(offset, format!("compare {} and {}", lhs, rhs))),
+                };
+
+                match inst {
+                    Instruction::Nop => {}
+                    Instruction::LdcI4(n) => push!(Op::Const(Const::I32(*n)), IrType::I32),
+                    Instruction::LdcI8(n) => push!(Op::Const(Const::I64(*n)), IrType::I64),
+                    Instruction::LdcR4(n) => push!(Op::Const(Const::F32(*n)), IrType::F32),
+                    Instruction::LdcR8(n) => push!(Op::Const(Const::F64(*n)), IrType::F64),
+                    Instruction::LdStr(s) => {
+                        push!(Op::Str(s.as_userstring().unwrap()), IrType::String)
+                    }
+                    Instruction::LdToken(MetadataToken::Field(field)) => {
+                        push!(Op::FieldData(*field), IrType::Ptr)
+                    }
+                    Instruction::LdArg(n) => push!(Op::Arg(*n), params[*n as usize]),
+                    Instruction::LdLoc(n) => push!(Op::Local(*n), locals[*n as usize]),
+                    Instruction::LdArgA(n) => push!(Op::ArgAddr(*n), IrType::Ptr),
+                    Instruction::LdLocA(n) => push!(Op::LocalAddr(*n), IrType::Ptr),
+                    Instruction::StArg(n) => insts.push(Inst {
+                        result: None,
+                        op: Op::SetArg(*n, stack.pop().unwrap()),
+                    }),
+                    Instruction::StLoc(n) => insts.push(Inst {
+                        result: None,
+                        op: Op::SetLocal(*n, stack.pop().unwrap()),
+                    }),
+                    Instruction::Dup => stack.push(*stack.last().unwrap()),
+                    Instruction::Pop => {
+                        stack.pop();
+                    }
+                    Instruction::Call(token) if Intrinsic::of(*token, root).is_some() => {
+                        let data = stack.pop().unwrap();
+                        let array = stack.pop().unwrap();
+                        insts.push(Inst {
+                            result: None,
+                            op: Op::InitArray(array, data),
+                        });
+                    }
+                    Instruction::Call(token) => {
+                        let sig = match token {
+                            MetadataToken::MethodDef(m) => {
+                                Some(m.resolve_table(table).unwrap().resolve_signature(heap))
+                            }
+                            MetadataToken::MemberRef(m) => import_signature(*m, root),
+                            _ => None,
+                        };
+                        let (params, ret) = sig
+                            .as_ref()
+                            .and_then(signature_types)
+                            .ok_or_else(|| unsupported(offset, &inst.display_with(root)))?;
+                        let args = stack.split_off(stack.len() - params.len());
+                        let op = Op::Call(*token, args);
+                        match ret {
+                            Some(ty) => push!(op, ty),
+                            None => insts.push(Inst { result: None, op }),
+                        }
+                    }
+                    Instruction::LdFld(token)
+                    | Instruction::LdFldA(token)
+                    | Instruction::StFld(token)
+                    | Instruction::LdSFld(token)
+                    | Instruction::LdSFldA(token)
+                    | Instruction::StSFld(token) => {
+                        // fields of other assemblies and of value types have no address
+                        let is_static = matches!(
+                            inst,
+                            Instruction::LdSFld(_)
+                                | Instruction::LdSFldA(_)
+                                | Instruction::StSFld(_)
+                        );
+                        let field = token
+                            .as_field()
+                            .filter(|&field| {
+                                let flags = field.resolve_table(table).unwrap().flags;
+                                if is_static {
+                                    flags.contains(FieldAttributes::STATIC)
+                                        && !flags.contains(FieldAttributes::LITERAL)
+                                } else {
+                                    table
+                                        .list_type_def()
+                                        .find(|(ty, _)| {
+                                            ty.resolve_fields(table).any(|(f, _)| f == field)
+                                        })
+                                        .and_then(|(ty, _)| object_layout(ty, root))
+                                        .is_some_and(|layout| layout.fields.contains_key(&field))
+                                }
+                            })
+                            .ok_or_else(|| unsupported(offset, &inst.display_with(root)))?;
+                        let sig = field.resolve_table(table).unwrap().resolve_signature(heap);
+                        let ty = IrType::of(&sig.ty)
+                            .ok_or_else(|| unsupported(offset, &inst.display_with(root)))?;
+                        match inst {
+                            Instruction::LdFld(_) => {
+                                let obj = stack.pop().unwrap();
+                                push!(Op::LoadField(field, obj), ty)
+                            }
+                            Instruction::LdFldA(_) => {
+                                let obj = stack.pop().unwrap();
+                                push!(Op::FieldAddr(field, obj), IrType::Ptr)
+                            }
+                            Instruction::StFld(_) => {
+                                let value = stack.pop().unwrap();
+                                let obj = stack.pop().unwrap();
+                                insts.push(Inst {
+                                    result: None,
+                                    op: Op::StoreField(field, obj, value),
+                                });
+                            }
+                            Instruction::LdSFld(_) => push!(Op::LoadStatic(field), ty),
+                            Instruction::LdSFldA(_) => push!(Op::StaticAddr(field), IrType::Ptr),
+                            _ => insts.push(Inst {
+                                result: None,
+                                op: Op::StoreStatic(field, stack.pop().unwrap()),
+                            }),
+                        }
+                    }
+                    Instruction::NewObj(token) => {
+                        // value type constructors and classes with bases from other
+                        // assemblies have no object layout
+                        let ctor = token
+                            .as_method_def()
+                            .filter(|&ctor| {
+                                table
+                                    .list_type_def()
+                                    .find(|(ty, _)| {
+                                        ty.resolve_methods(table).any(|(m, _)| m == ctor)
+                                    })
+                                    .and_then(|(ty, _)| object_layout(ty, root))
+                                    .is_some()
+                            })
+                            .ok_or_else(|| unsupported(offset, &inst.display_with(root)))?;
+                        let sig = ctor.resolve_table(table).unwrap().resolve_signature(heap);
+                        let args = stack.split_off(stack.len() - sig.params.len());
+                        push!(Op::New(ctor, args), IrType::Ptr)
+                    }
+                    Instruction::NewArr(token) => {
+                        let ty = element_type(*token, root)
+                            .filter(|ty| IrType::of(ty).is_some())
+                            .ok_or_else(|| unsupported(offset, &inst.display_with(root)))?;
+                        let len = stack.pop().unwrap();
+                        push!(Op::NewArr(ty, len), IrType::SzArray)
+                    }
+                    Instruction::LdLen => {
+                        let array = stack.pop().unwrap();
+                        push!(Op::ArrayLen(array), IrType::Ptr)
+                    }
+                    Instruction::LdElem(ty) | Instruction::StElem(ty) if *ty != NumType::Ref => {
+                        let elem = num_element_type(*ty);
+                        if let Instruction::LdElem(_) = inst {
+                            let index = stack.pop().unwrap();
+                            let array = stack.pop().unwrap();
+                            push!(Op::LoadElem(elem, array, index), IrType::of_num(*ty))
+                        } else {
+                            let value = stack.pop().unwrap();
+                            let index = stack.pop().unwrap();
+                            let array = stack.pop().unwrap();
+                            insts.push(Inst {
+                                result: None,
+                                op: Op::StoreElem(elem, array, index, value),
+                            });
+                        }
+                    }
+                    Instruction::Ret => {
+                        terminator = Some(Terminator::Return(ret.map(|_| stack.pop().unwrap())));
+                    }
+                    Instruction::Br(_) => {
+                        let to = target(inst.branch_targets(body.next_offset(i))[0]);
+                        terminator = Some(Terminator::Jump(target!(to, stack.clone())));
+                    }
+                    Instruction::BrTrue(_) | Instruction::BrFalse(_) => {
+                        let cond = stack.pop().unwrap();
+                        let taken = target(inst.branch_targets(body.next_offset(i))[0]);
+                        let taken = target!(taken, stack.clone());
+                        let next = target!(b + 1, stack.clone());
+                        let (then, otherwise) = match inst {
+                            Instruction::BrTrue(_) => (taken, next),
+                            _ => (next, taken),
+                        };
+                        terminator = Some(Terminator::Branch {
+                            cond,
+                            then,
+                            otherwise,
+                        });
+                    }
+                    Instruction::Beq(_)
+                    | Instruction::BneUn(_)
+                    | Instruction::Blt(_)
+                    | Instruction::BltUn(_)
+                    | Instruction::Ble(_)
+                    | Instruction::BleUn(_)
+                    | Instruction::Bgt(_)
+                    | Instruction::BgtUn(_)
+                    | Instruction::Bge(_)
+                    | Instruction::BgeUn(_) => {
+                        let rhs = stack.pop().unwrap();
+                        let lhs = stack.pop().unwrap();
+                        compare(builder.ty(lhs), builder.ty(rhs))?;
+                        let cond = builder.value(IrType::I32);
+                        insts.push(Inst {
+                            result: Some(cond),
+                            op: Op::Compare(Cmp::of_branch(inst).unwrap(), lhs, rhs),
+                        });
+                        let taken = target(inst.branch_targets(body.next_offset(i))[0]);
+                        terminator = Some(Terminator::Branch {
+                            cond,
+                            then: target!(taken, stack.clone()),
+                            otherwise: target!(b + 1, stack.clone()),
+                        });
+                    }
+                    Instruction::Add
+                    | Instruction::Sub
+                    | Instruction::Mul
+                    | Instruction::AddOvf
+                    | Instruction::AddOvfUn
+                    | Instruction::SubOvf
+                    | Instruction::SubOvfUn
+                    | Instruction::MulOvf
+                    | Instruction::MulOvfUn
+                    | Instruction::Div
+                    | Instruction::DivUn
+                    | Instruction::Rem
+                    | Instruction::RemUn
+                    | Instruction::And
+                    | Instruction::Or
+                    | Instruction::Xor
+                    | Instruction::Shl
+                    | Instruction::Shr
+                    | Instruction::ShrUn => {
+                        let rhs = stack.pop().unwrap();
+                        let lhs = stack.pop().unwrap();
+                        let op = BinOp::of(inst).unwrap();
+                        let types = (builder.ty(lhs), builder.ty(rhs));
+                        if !op.accepts(types.0, types.1) {
+                            let message = format!("{} on {} and {}", op, types.0, types.1);
+                            return Err(error(offset, message));
+                        }
+                        push!(Op::Binary(op, lhs, rhs), op.result_type(types.0, types.1))
+                    }
+                    Instruction::Neg | Instruction::Not | Instruction::CkFinite => {
+                        let value = stack.pop().unwrap();
+                        let op = match inst {
+                            Instruction::Neg => UnOp::Neg,
+                            Instruction::Not => UnOp::Not,
+                            _ => UnOp::CkFinite,
+                        };
+                        let ty = builder.ty(value);
+                        if !op.accepts(ty) {
+                            return Err(error(offset, format!("{} on {}", op, ty)));
+                        }
+                        push!(Op::Unary(op, value), ty)
+                    }
+                    Instruction::Ceq
+                    | Instruction::Cgt
+                    | Instruction::CgtUn
+                    | Instruction::Clt
+                    | Instruction::CltUn => {
+                        let rhs = stack.pop().unwrap();
+                        let lhs = stack.pop().unwrap();
+                        let cmp = match inst {
+                            Instruction::Ceq => Cmp::Eq,
+                            Instruction::Cgt => Cmp::Gt,
+                            Instruction::CgtUn => Cmp::GtUn,
+                            Instruction::Clt => Cmp::Lt,
+                            _ => Cmp::LtUn,
+                        };
+                        compare(builder.ty(lhs), builder.ty(rhs))?;
+                        push!(Op::Compare(cmp, lhs, rhs), IrType::I32)
+                    }
+                    Instruction::Conv(ty)
+                    | Instruction::ConvOvf(ty)
+                    | Instruction::ConvOvfUn(ty) => {
+                        let value = stack.pop().unwrap();
+                        if !builder.ty(value).is_scalar() {
+                            let message =
+                                format!("{} from {}", inst.display_with(root), builder.ty(value));
+                            return Err(error(offset, message));
+                        }
+                        let op = match inst {
+                            Instruction::Conv(_) => Op::Convert(*ty, value),
+                            Instruction::ConvOvf(_) => Op::ConvertOvf(*ty, value),
+                            _ => Op::ConvertOvfUn(*ty, value),
+                        };
+                        push!(op, IrType::of_num(*ty))
+                    }
+                    Instruction::Switch(_) => {
+                        let value = stack.pop().unwrap();
+                        let targets = inst
+                            .branch_targets(body.next_offset(i))
+                            .into_iter()
+                            .map(|to| {
+                                builder
+                                    .target(target(to), stack.clone())
+                                    .map_err(|message| error(offset, message))
+                            })
+                            .collect::<Result<_, _>>()?;
+                        terminator = Some(Terminator::Switch {
+                            value,
+                            targets,
+                            default: target!(b + 1, stack.clone()),
+                        });
+                    }
+                    _ => return Err(unsupported(offset, &inst.display_with(root))),
+                }
+            }
+
+            let terminator = match terminator {
+                Some(terminator) => terminator,
+                None => Terminator::Jump(
+                    builder
+                        .target(b + 1, stack)
+                        .map_err(|message| error(body.offsets[block.end - 1], message))?,
+                ),
+            };
+            blocks[builder.ids[b].unwrap().0 as usize] = Some(Block {
+                params: entry,
+                insts,
+                terminator,
+            });
+        }
+
+        if !builder.widened {
+            break (builder.values, blocks);
+        }
+        hints = builder.param_types();
+    };
+
+    Ok(Function {
+        params,
+        locals,
+        ret,
+        values,
+        blocks: blocks.into_iter().map(Option::unwrap).collect(),
+    })
+}
+
+fn list(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.block, list(&self.args))
+    }
+}
+
+impl DisplayWith for Op {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Const(Const::I32(n)) => write!(f, "i32.const {}", n),
+            Op::Const(Const::I64(n)) => write!(f, "i64.const {}", n),
+            Op::Const(Const::F32(n)) => write!(f, "f32.const {:?}", n),
+            Op::Const(Const::F64(n)) => write!(f, "f64.const {:?}", n),
+            Op::Arg(n) => write!(f, "arg {}", n),
+            Op::SetArg(n, v) => write!(f, "arg {} = {}", n, v),
+            Op::Local(n) => write!(f, "local {}", n),
+            Op::SetLocal(n, v) => write!(f, "local {} = {}", n, v),
+            Op::ArgAddr(n) => write!(f, "arg_addr {}", n),
+            Op::LocalAddr(n) => write!(f, "local_addr {}", n),
+            Op::Str(s) => write!(f, "str #{}", s.0),
+            Op::FieldData(field) => write!(f, "field_data {}", field.0),
+            Op::NewArr(ty, len) => write!(f, "newarr {} {}", names.ty(ty), len),
+            Op::ArrayLen(array) => write!(f, "len {}", array),
+            Op::LoadElem(ty, array, index) => {
+                write!(f, "elem {} {}[{}]", names.ty(ty), array, index)
+            }
+            Op::StoreElem(ty, array, index, value) => {
+                write!(f, "elem {} {}[{}] = {}", names.ty(ty), array, index, value)
+            }
+            Op::InitArray(array, data) => write!(f, "init_array {}, {}", array, data),
+            Op::Call(token, args) => write!(f, "call {:08X}({})", token.to_raw(), list(args)),
+            Op::New(ctor, args) => write!(
+                f,
+                "new {:08X}({})",
+                MetadataToken::MethodDef(*ctor).to_raw(),
+                list(args)
+            ),
+            Op::LoadField(field, obj) => write!(f, "field {}.{}", obj, field.0),
+            Op::StoreField(field, obj, value) => {
+                write!(f, "field {}.{} = {}", obj, field.0, value)
+            }
+            Op::FieldAddr(field, obj) => {
+                write!(f, "field_addr {}.{}", obj, field.0)
+            }
+            Op::LoadStatic(field) => write!(f, "static {}", field.0),
+            Op::StoreStatic(field, value) => {
+                write!(f, "static {} = {}", field.0, value)
+            }
+            Op::StaticAddr(field) => write!(f, "static_addr {}", field.0),
+            Op::Compare(cmp, lhs, rhs) => write!(f, "cmp.{} {}, {}", cmp, lhs, rhs),
+            Op::Binary(op, lhs, rhs) => write!(f, "{} {}, {}", op, lhs, rhs),
+            Op::Unary(op, value) => write!(f, "{} {}", op, value),
+            Op::Convert(ty, value) => write!(f, "conv.{} {}", ty.suffix(), value),
+            Op::ConvertOvf(ty, value) => write!(f, "conv.ovf.{} {}", ty.suffix(), value),
+            Op::ConvertOvfUn(ty, value) => write!(f, "conv.ovf.{}.un {}", ty.suffix(), value),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Return(Some(v)) => write!(f, "return {}", v),
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {}, {}, {}", cond, then, otherwise),
+            Terminator::Switch {
+                value,
+                targets,
+                default,
+            } => {
+                let targets = targets.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+                write!(f, "switch {} [{}], {}", value, targets.join(", "), default)
+            }
+        }
+    }
+}
+
+impl DisplayWith for Function {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        let types = |types: &[IrType]| {
+            types
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "fn({})", types(&self.params))?;
+        if let Some(ret) = self.ret {
+            write!(f, " -> {}", ret)?;
+        }
+        writeln!(f)?;
+        if !self.locals.is_empty() {
+            writeln!(f, "locals({})", types(&self.locals))?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let params = block
+                .params
+                .iter()
+                .map(|&p| format!("{}: {}", p, self.value_type(p)))
+                .collect::<Vec<_>>();
+            writeln!(f, "{}({}):", BlockId(i as u32), params.join(", "))?;
+            for inst in &block.insts {
+                match inst.result {
+                    Some(v) => write!(f, "  {} = ", v)?,
+                    None => write!(f, "  ")?,
+                }
+                inst.op.fmt_with(names, f)?;
+                writeln!(f)?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn lower_merge() {
+    use clrs_pe::pe::Image;
+
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly ir { .ver 0:0:0:0 }
+
+        .method public static int32 Pick(bool b, int32 x) cil managed
+        {
+          .locals init (int32 t)
+          ldarg.1
+          stloc.0
+          ldc.i4.5
+          ldarg.0
+          brtrue.s One
+          ldc.i4.2
+          br.s Join
+        One:
+          ldloc.0
+        Join:
+          pop
+          ret
+        }
+    ";
+    let bytes = clrs_pe::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let (index, def) = root.metadata_stream.table.list_method_def().next().unwrap();
+    let function = lower(index, &def.resolve_body(&image), root).unwrap();
+
+    assert_eq!(
+        function.display_with(root).to_string(),
+        "\
+fn(i32, i32) -> i32
+locals(i32)
+b0():
+  v0 = arg 1
+  local 0 = v0
+  v1 = i32.const 5
+  v2 = arg 0
+  branch v2, b2(v1), b1(v1)
+b1(v4: i32):
+  v5 = i32.const 2
+  jump b3(v4, v5)
+b2(v3: i32):
+  v8 = local 0
+  jump b3(v3, v8)
+b3(v6: i32, v7: i32):
+  return v6
+"
+    );
+}
+
+#[test]
+fn lower_widen() {
+    use clrs_pe::pe::Image;
+
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly ir { .ver 0:0:0:0 }
+
+        .method public static native int Pick(bool b, native int p) cil managed
+        {
+          ldarg.0
+          brtrue.s One
+          ldc.i4.0
+          br.s Join
+        One:
+          ldarg.1
+        Join:
+          ret
+        }
+
+        .method public static float64 Loop(int32 n) cil managed
+        {
+          ldc.r4 1.5
+        Head:
+          ldarg.0
+          brfalse.s Done
+          ldc.r8 2
+          mul
+          ldarg.0
+          ldc.i4.1
+          sub
+          starg.s 0
+          br.s Head
+        Done:
+          ret
+        }
+
+        .method public static void Protected() cil managed
+        {
+        Start:
+          leave.s Done
+        Handler:
+          endfinally
+        Done:
+          ret
+          .try Start to Handler finally handler Handler to Done
+        }
+
+        .method public static void Builders() cil managed
+        {
+          ldc.i4.2
+          newarr [mscorlib]System.Text.StringBuilder
+          pop
+          ret
+        }
+    ";
+    let bytes = clrs_pe::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let mut methods = root.metadata_stream.table.list_method_def();
+    let mut lower_next = || {
+        let (index, def) = methods.next().unwrap();
+        lower(index, &def.resolve_body(&image), root)
+    };
+
+    let pick = lower_next().unwrap().display_with(root).to_string();
+    assert!(pick.contains("b3(v2: ptr):"), "{}", pick);
+    // the back edge brings a float64 to the loop head entered with a float32
+    let function = lower_next().unwrap().display_with(root).to_string();
+    assert!(function.contains("b1(v1: f64):"), "{}", function);
+
+    let error = lower_next().unwrap_err();
+    assert_eq!(error.offset, 0);
+    assert_eq!(error.message, "exception handling isn't supported");
+
+    // IR dumps name types as IL does
+    let builders = lower_next().unwrap().display_with(root).to_string();
+    assert!(
+        builders.contains("newarr class [mscorlib]System.Text.StringBuilder v0"),
+        "{}",
+        builders
+    );
+}
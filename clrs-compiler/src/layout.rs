@@ -0,0 +1,187 @@
+//! Layout of objects in linear memory.
+//!
+//! An object starts with a header holding its type id, the `TypeDef` row of its class. The
+//! instance fields of its base classes follow, then its own fields, in declaration order for
+//! auto and sequential layout or at their `FieldLayout` offset for explicit layout.
+
+use std::collections::HashMap;
+
+use clrs_pe::pe::{
+    FieldAttributes, FieldIndex, MetadataRoot, TableIndex, Type, TypeAttributes, TypeDefIndex,
+    TypeDefOrRef,
+};
+
+/// Size of the type id header
+pub const HEADER_SIZE: u32 = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectLayout {
+    pub type_id: u32,
+    /// Size of an instance with its header, a multiple of `align`
+    pub size: u32,
+    pub align: u32,
+    /// Byte offset of each instance field, inherited fields included
+    pub fields: HashMap<FieldIndex, u32>,
+}
+
+fn align_to(n: u32, align: u32) -> Option<u32> {
+    n.checked_next_multiple_of(align)
+}
+
+/// Size and alignment of a field, strings and arrays are stored as on the evaluation stack
+pub fn field_layout(ty: &Type, root: &MetadataRoot) -> Option<(u32, u32)> {
+    match ty {
+        Type::String => Some((12, 4)),
+        Type::SzArray { .. } => Some((8, 4)),
+        ty => ty.layout(&root.metadata_stream.table, root.heap),
+    }
+}
+
+/// `None` for value types, interfaces, classes whose base class comes from another assembly
+/// except `System.Object`, classes in a circular `extends` chain, and instances too large for
+/// 32-bit offsets
+pub fn object_layout(ty: TypeDefIndex, root: &MetadataRoot) -> Option<ObjectLayout> {
+    object_layout_in(ty, root, &mut Vec::new())
+}
+
+fn object_layout_in(
+    ty: TypeDefIndex,
+    root: &MetadataRoot,
+    visiting: &mut Vec<TypeDefIndex>,
+) -> Option<ObjectLayout> {
+    let table = &root.metadata_stream.table;
+    let heap = root.heap;
+    let def = ty.resolve_table(table)?;
+    if def.flags.contains(TypeAttributes::INTERFACE) || visiting.contains(&ty) {
+        return None;
+    }
+
+    let (mut size, mut align, mut fields) = match def.extends {
+        TypeDefOrRef::TypeDefIndex(base) if base.0 != 0 => {
+            visiting.push(ty);
+            let base = object_layout_in(base, root, visiting)?;
+            visiting.pop();
+            (base.size, base.align, base.fields)
+        }
+        TypeDefOrRef::TypeRefIndex(base) => {
+            let base = base.resolve_table(table)?;
+            let name = (
+                base.type_namespace.resolve(heap),
+                base.type_name.resolve(heap),
+            );
+            if name != (Some("System"), Some("Object")) {
+                return None;
+            }
+            (HEADER_SIZE, HEADER_SIZE, HashMap::new())
+        }
+        TypeDefOrRef::TypeDefIndex(_) => (HEADER_SIZE, HEADER_SIZE, HashMap::new()),
+        TypeDefOrRef::TypeSpecIndex(_) => return None,
+    };
+
+    // II.10.7, packing caps the alignment of each field and the class size is a minimum
+    let class_layout = ty.resolve_class_layout(table);
+    let packing = class_layout
+        .map(|l| l.packing_size as u32)
+        .filter(|&p| p != 0)
+        .unwrap_or(8);
+    let explicit = def.flags & TypeAttributes::LAYOUT_MASK == TypeAttributes::EXPLICIT_LAYOUT;
+    let start = size;
+
+    for (field, row) in ty.resolve_fields(table) {
+        if row.flags.contains(FieldAttributes::STATIC) {
+            continue;
+        }
+        let (field_size, field_align) = field_layout(&row.resolve_signature(heap).ty, root)?;
+        let field_align = field_align.min(packing);
+        align = align.max(field_align);
+
+        let offset = if explicit {
+            start.checked_add(field.resolve_layout(table)?.offset)?
+        } else {
+            align_to(size, field_align)?
+        };
+        fields.insert(field, offset);
+        size = size.max(offset.checked_add(field_size)?);
+    }
+
+    if let Some(layout) = class_layout {
+        size = size.max(start.checked_add(layout.class_size)?);
+    }
+
+    Some(ObjectLayout {
+        type_id: ty.0,
+        size: align_to(size, align)?,
+        align,
+        fields,
+    })
+}
+
+#[test]
+fn object_layouts() {
+    use clrs_pe::pe::Image;
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/objects.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let class = |name: &str| {
+        let (index, _) = table
+            .list_type_def()
+            .find(|(_, t)| t.type_name.resolve(root.heap) == Some(name))
+            .unwrap();
+        index
+    };
+    let field = |name: &str| {
+        let (index, _) = table
+            .list_field()
+            .find(|(_, f)| f.name.resolve(root.heap) == Some(name))
+            .unwrap();
+        index
+    };
+
+    // int64 is aligned after the header and int32, statics take no space
+    let point = object_layout(class("Point"), root).unwrap();
+    assert_eq!((point.size, point.align), (16, 8));
+    assert_eq!(point.fields.len(), 2);
+    assert_eq!(point.fields[&field("X")], 4);
+    assert_eq!(point.fields[&field("Y")], 8);
+
+    let point3 = object_layout(class("Point3"), root).unwrap();
+    assert_eq!(point3.type_id, class("Point3").0);
+    assert_eq!(point3.fields[&field("Y")], 8);
+    assert_eq!(point3.fields[&field("Z")], 16);
+    assert_eq!(point3.size, 24);
+
+    // offsets and `.size` count from the end of the header
+    let union = object_layout(class("Union"), root).unwrap();
+    assert_eq!(union.fields[&field("I")], 4);
+    assert_eq!(union.fields[&field("F")], 4);
+    assert_eq!(union.fields[&field("S")], 8);
+    assert_eq!(union.size, 24);
+
+    assert_eq!(object_layout(class("Pair"), root), None);
+    // the header and an explicit offset or `.size` overflow
+    assert_eq!(object_layout(class("Far"), root), None);
+    assert_eq!(object_layout(class("Vast"), root), None);
+
+    let cyclic = clrs_pe::cil::asm::assemble(
+        "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly cycle { .ver 0:0:0:0 }
+
+        .class public A extends B {}
+        .class public B extends A {}
+        .class public C extends A {}
+        ",
+    )
+    .unwrap();
+    let cyclic = Image::from_bytes(&cyclic).unwrap();
+    let root = cyclic.metadata_root();
+    for (ty, def) in root.metadata_stream.table.list_type_def().skip(1) {
+        let name = def.type_name.resolve(root.heap);
+        assert_eq!(object_layout(ty, root), None, "{:?}", name);
+    }
+
+    let wasm = crate::compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+}
@@ -1,18 +1,27 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 use wasm_encoder::{
-    CodeSection, DataSection, EntityType, Export, ExportSection, Function, FunctionSection,
-    ImportSection, Instruction as WasmInst, MemorySection, MemoryType, Module, TypeSection,
-    ValType,
+    BlockType, CodeSection, DataSection, EntityType, Export, ExportSection, Function,
+    FunctionSection, GlobalSection, GlobalType, ImportSection, Instruction as WasmInst, MemArg,
+    MemorySection, MemoryType, Module, TypeSection, ValType,
 };
 
-use clrs_pe::cil::{Instruction, MethodBody};
+use clrs_pe::cil::verify::{verify, VerifyError};
+use clrs_pe::cil::{MethodBody, NumType};
 use clrs_pe::pe::{
-    Heap, Image, MemberRef, MemberRefIndex, MemberRefParent, MetadataRoot, MetadataTable,
-    MethodCallingConvension, MethodDefIndex, MethodDefSig, Param, RetType, TableIndex, Type,
+    DisplayWith, EntryPoint, FieldAttributes, FieldIndex, Image, MemberRef, MemberRefIndex,
+    MemberRefParent, MetadataRoot, MetadataToken, MethodDefIndex, MethodDefSig, TableIndex, Type,
     TypeDef, TypeDefIndex, UserStringIndex,
 };
 
+pub mod control;
+pub mod ir;
+pub mod layout;
+
+use self::control::Structured;
+use self::ir::{BinOp, Cmp, Const, Intrinsic, IrType, LowerError, Op, UnOp, Value};
+use self::layout::{object_layout, ObjectLayout};
+
 #[derive(Clone)]
 struct MethodCacheData {
     pub fn_index: u32,
@@ -21,6 +30,7 @@ struct MethodCacheData {
 #[derive(Clone)]
 struct SignatureCacheData {
     pub type_index: u32,
+    #[allow(dead_code)]
     pub param_types: Rc<Vec<ValType>>,
 }
 
@@ -30,6 +40,11 @@ struct StringCacheData {
     pub str_len: u32,
 }
 
+#[derive(Clone)]
+struct FieldDataCacheData {
+    pub data_index: i32,
+}
+
 struct MemberRefCacheData {
     pub fn_index: u32,
 }
@@ -41,31 +56,90 @@ struct WasmContext {
     imports: ImportSection,
     codes: CodeSection,
     data: DataSection,
-    memory: MemorySection,
+    /// End of the strings, field data and statics, the stack and the heap follow
+    data_end: u32,
     signature_cache: HashMap<MethodDefSig, SignatureCacheData>,
     string_cache: HashMap<UserStringIndex, StringCacheData>,
+    field_data_cache: HashMap<FieldIndex, FieldDataCacheData>,
     method_cache: HashMap<MethodDefIndex, MethodCacheData>,
     member_ref_cache: HashMap<MemberRefIndex, MemberRefCacheData>,
+    /// Empty when exceptions are off
+    fault_hooks: HashMap<Fault, u32>,
+    /// Classes which can be instantiated
+    object_layouts: HashMap<TypeDefIndex, ObjectLayout>,
+    /// Offset of instance fields in their object
+    field_offsets: HashMap<FieldIndex, u32>,
+    /// Address of static fields in the data area
+    static_fields: HashMap<FieldIndex, u32>,
+    method_owner: HashMap<MethodDefIndex, TypeDefIndex>,
+    /// `alloc(size) -> ptr`, see `emit_wasm_runtime`
+    alloc_fn: Option<u32>,
+    fmod_fn: Option<u32>,
 }
 
 const VAL_PTR: ValType = ValType::I32;
 
-impl WasmContext {
-    pub fn new(root: &MetadataRoot) -> Self {
-        let mut memory = MemorySection::new();
+/// Module of the runtime hooks imported when exceptions are on
+const RUNTIME_MODULE: &str = "clrs";
 
-        memory.memory(MemoryType {
-            minimum: 1,
-            maximum: None,
-            memory64: false,
-        });
+/// Exception raised by a checked instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Fault {
+    /// `System.OverflowException`
+    Overflow,
+    /// `System.DivideByZeroException`
+    DivideByZero,
+    /// `System.ArithmeticException` from `ckfinite`
+    NotFinite,
+    /// `System.IndexOutOfRangeException`
+    IndexOutOfRange,
+}
+
+impl Fault {
+    const ALL: [Fault; 4] = [
+        Fault::Overflow,
+        Fault::DivideByZero,
+        Fault::NotFinite,
+        Fault::IndexOutOfRange,
+    ];
 
+    /// Runtime hook which raises the exception and never returns
+    fn hook(self) -> &'static str {
+        match self {
+            Fault::Overflow => "throw_overflow",
+            Fault::DivideByZero => "throw_divide_by_zero",
+            Fault::NotFinite => "throw_arithmetic",
+            Fault::IndexOutOfRange => "throw_index_out_of_range",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Checked instructions call a runtime hook raising the managed exception instead of trapping
+    pub exceptions: bool,
+}
+
+/// Global holding the shadow stack pointer, the stack grows down from the start of the heap
+const STACK_POINTER: u32 = 0;
+/// Size of the shadow stack region between the static data and the heap
+const STACK_SIZE: u32 = 0x1_0000;
+/// Global holding the end of the heap, which starts above the stack and never shrinks
+const HEAP_POINTER: u32 = 1;
+
+impl WasmContext {
+    pub fn new(image: &Image, options: &Options) -> Self {
+        let root = image.metadata_root();
         let mut data = DataSection::new();
         let mut string_cache = HashMap::new();
 
         let mut offset = 1;
 
+        // `System.String` is a UTF-8 PTR/LEN/CAP triple in the guest, so literals are
+        // transcoded from UTF-16 here. Unpaired surrogates become U+FFFD. Literals live in
+        // static data and have zero capacity since they are never owned.
         for (index, s) in root.heap.list_user_string() {
+            let s = s.to_string();
             string_cache.insert(
                 index,
                 StringCacheData {
@@ -73,22 +147,86 @@ impl WasmContext {
                     str_len: s.len() as u32,
                 },
             );
-            data.active(0, WasmInst::I32Const(offset), s.iter().copied());
+            data.active(0, WasmInst::I32Const(offset), s.bytes());
             offset += s.len() as i32;
         }
 
+        let mut field_data_cache = HashMap::new();
+
+        // RuntimeFieldHandle of a field with RVA points to u32 length followed by its initial data
+        for field_rva in root.metadata_stream.table.field_rva.iter() {
+            let s = match field_rva.field.resolve_initial_data(image) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            offset = (offset + 7) & !7;
+            field_data_cache.insert(field_rva.field, FieldDataCacheData { data_index: offset });
+            let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(s);
+            data.active(0, WasmInst::I32Const(offset), bytes);
+            offset += 4 + s.len() as i32;
+        }
+
+        // Static fields follow in the data area. A field with RVA is stored in the bytes after
+        // the length of its RuntimeFieldHandle, the others start zeroed.
+        let mut static_fields = HashMap::new();
+        for (field, row) in root.metadata_stream.table.list_field() {
+            if !row.flags.contains(FieldAttributes::STATIC)
+                || row.flags.contains(FieldAttributes::LITERAL)
+            {
+                continue;
+            }
+            if let Some(field_data) = field_data_cache.get(&field) {
+                static_fields.insert(field, field_data.data_index as u32 + 4);
+                continue;
+            }
+            let ty = row.resolve_signature(root.heap).ty;
+            let (size, align) = match layout::field_layout(&ty, root) {
+                Some(layout) => layout,
+                None => continue,
+            };
+            offset = (offset + align as i32 - 1) & !(align as i32 - 1);
+            static_fields.insert(field, offset as u32);
+            offset += size as i32;
+        }
+
+        let mut types = TypeSection::new();
+        let mut imports = ImportSection::new();
+        let mut fault_hooks = HashMap::new();
+        if options.exceptions {
+            let type_index = types.len();
+            types.function(vec![], vec![]);
+            for fault in Fault::ALL {
+                fault_hooks.insert(fault, imports.len());
+                imports.import(
+                    RUNTIME_MODULE,
+                    Some(fault.hook()),
+                    EntityType::Function(type_index),
+                );
+            }
+        }
+
         WasmContext {
-            types: TypeSection::new(),
+            types,
             functions: FunctionSection::new(),
             exports: ExportSection::new(),
-            imports: ImportSection::new(),
+            imports,
             data,
-            memory,
+            data_end: offset as u32,
             codes: CodeSection::new(),
             string_cache,
+            field_data_cache,
             signature_cache: HashMap::new(),
             method_cache: HashMap::new(),
             member_ref_cache: HashMap::new(),
+            fault_hooks,
+            object_layouts: HashMap::new(),
+            field_offsets: HashMap::new(),
+            static_fields,
+            method_owner: HashMap::new(),
+            alloc_fn: None,
+            fmod_fn: None,
         }
     }
 
@@ -100,175 +238,154 @@ impl WasmContext {
         }
     }
 
+    /// Lowest address of the shadow stack, right above the static data
+    fn stack_limit(&self) -> u32 {
+        (self.data_end + 15) & !15
+    }
+
+    /// Where the stack starts growing down and the heap up
+    fn heap_base(&self) -> u32 {
+        self.stack_limit() + STACK_SIZE
+    }
+
     pub fn finish(&self) -> Vec<u8> {
+        let heap_base = self.heap_base();
+        let mut memory = MemorySection::new();
+        memory.memory(MemoryType {
+            minimum: (heap_base as u64 + 0xFFFF) >> 16,
+            maximum: None,
+            memory64: false,
+        });
+
+        let mut globals = GlobalSection::new();
+        for _ in [STACK_POINTER, HEAP_POINTER] {
+            globals.global(
+                GlobalType {
+                    val_type: VAL_PTR,
+                    mutable: true,
+                },
+                WasmInst::I32Const(heap_base as i32),
+            );
+        }
+
         let mut module = Module::new();
         module
             .section(&self.types)
             .section(&self.imports)
             .section(&self.functions)
-            .section(&self.memory)
+            .section(&memory)
+            .section(&globals)
             .section(&self.exports)
             .section(&self.codes)
             .section(&self.data);
         module.finish()
     }
 
-    fn convert_wasm_param(out: &mut Vec<ValType>, param: &Param) {
-        match param {
-            Param::Type { byref: true, .. } => {
-                out.push(VAL_PTR);
-            }
-            Param::Type { byref: false, ty } => {
-                match ty {
-                    Type::I | Type::U => out.push(VAL_PTR),
-                    Type::Boolean
-                    | Type::Char
-                    | Type::I1
-                    | Type::I2
-                    | Type::I4
-                    | Type::U1
-                    | Type::U2
-                    | Type::U4 => out.push(ValType::I32),
-                    Type::I8 | Type::U8 => out.push(ValType::I64),
-                    Type::R4 => out.push(ValType::F32),
-                    Type::R8 => out.push(ValType::F64),
-                    Type::String => {
-                        // 3 word PTR/LEN/CAP
-                        out.push(VAL_PTR);
-                        out.push(VAL_PTR);
-                        out.push(VAL_PTR);
-                    }
-                    Type::SzArray { .. } => {
-                        // 2 word PTR/LEN
-                        out.push(VAL_PTR);
-                        out.push(VAL_PTR);
-                    }
-                    _ => todo!(),
-                }
-            }
-            Param::TypedByref => todo!(),
-        }
-    }
-
-    fn convert_wasm_return(ret: &RetType) -> Vec<ValType> {
-        match ret {
-            RetType::Void => vec![],
-            RetType::Type { byref: true, .. } => vec![VAL_PTR],
-            RetType::Type { byref: false, ty } => {
-                match ty {
-                    Type::I | Type::U => vec![VAL_PTR],
-                    Type::Boolean
-                    | Type::Char
-                    | Type::I1
-                    | Type::I2
-                    | Type::I4
-                    | Type::U1
-                    | Type::U2
-                    | Type::U4 => vec![ValType::I32],
-                    Type::I8 | Type::U8 => vec![ValType::I64],
-                    Type::R4 => vec![ValType::F32],
-                    Type::R8 => vec![ValType::F64],
-                    Type::String => {
-                        // 3 word PTR/LEN/CAP
-                        vec![VAL_PTR; 3]
-                    }
-                    Type::SzArray { .. } => {
-                        // 2 word PTR/LEN
-                        vec![VAL_PTR; 2]
-                    }
-                    _ => todo!(),
-                }
+    fn convert_wasm_function(&self, func: &ir::Function, root: &MetadataRoot) -> Function {
+        let structure = control::structure(func);
+
+        // `ldarga` and `ldloca` need an address, so those slots live in a shadow stack frame
+        let mut arg_in_frame = vec![false; func.params.len()];
+        let mut local_in_frame = vec![false; func.locals.len()];
+        for inst in func.blocks.iter().flat_map(|b| &b.insts) {
+            match inst.op {
+                Op::ArgAddr(n) => arg_in_frame[n as usize] = true,
+                Op::LocalAddr(n) => local_in_frame[n as usize] = true,
+                _ => {}
             }
-            _ => todo!("{:?}", ret),
         }
-    }
 
-    fn convert_wasm_function(
-        &self,
-        body: &MethodBody,
-        table: &MetadataTable,
-        heap: Heap,
-    ) -> Function {
-        // TODO: locals
-        let mut f = Function::new(vec![]);
+        let mut frame_size = 0;
+        let mut frame_slot = |ty: IrType| {
+            let offset = (frame_size + 7) & !7;
+            frame_size = offset + Slot::size(ty);
+            Slot::Frame(offset)
+        };
 
-        for inst in body.instructions.iter() {
-            match inst {
-                Instruction::Nop => {
-                    f.instruction(WasmInst::Nop);
-                }
-                Instruction::LdStr(s) => {
-                    let s = s.as_userstring().unwrap();
-                    let str_data = &self.string_cache[&s];
-
-                    // TODO: handling null
-                    // if s.0 == 0 {
-                    // }
-
-                    f.instruction(WasmInst::I32Const(str_data.data_index));
-                    f.instruction(WasmInst::I32Const(str_data.str_len as i32));
-                    f.instruction(WasmInst::I32Const(0));
-                }
-                Instruction::Call(method) => {
-                    if let Some(member) = method.as_member_ref() {
-                        f.instruction(WasmInst::Call(self.member_ref_cache[&member].fn_index));
-                    } else if let Some(method) = method.as_method_def() {
-                        f.instruction(WasmInst::Call(self.method_cache[&method].fn_index));
-                    } else if let Some(_spec) = method.as_method_spec() {
-                        todo!("Call via MethodSpec");
-                    } else {
-                        panic!("Invalid Call argument");
-                    }
-                }
-                Instruction::Ret => {
-                    f.instruction(WasmInst::Return);
-                }
-                Instruction::LdArg(n) => {
-                    f.instruction(WasmInst::LocalGet(*n));
-                }
-                Instruction::LdFld(token) => {
-                    let field_index = token.as_field().unwrap();
-                    let field = field_index.resolve_table(table).unwrap();
-                    let sig = field.resolve_signature(heap);
-                    dbg!(sig);
-                    // TODO
-                }
-                Instruction::StFld(token) => {
-                    let field_index = token.as_field().unwrap();
-                    let field = field_index.resolve_table(table).unwrap();
-                    // TODO
-                }
-                _ => todo!("{:?}", inst),
+        // params, then CIL locals, then one local per word of each IR value
+        let mut next = 0;
+        let mut arg_slots = Vec::with_capacity(func.params.len());
+        for (&ty, &in_frame) in func.params.iter().zip(&arg_in_frame) {
+            arg_slots.push(if in_frame {
+                frame_slot(ty)
+            } else {
+                Slot::Local(next)
+            });
+            next += ty.wasm_types().len() as u32;
+        }
+        let param_words = next;
+
+        let mut locals = Vec::new();
+        let mut local_slots = Vec::with_capacity(func.locals.len());
+        for (&ty, &in_frame) in func.locals.iter().zip(&local_in_frame) {
+            if in_frame {
+                local_slots.push(frame_slot(ty));
+            } else {
+                local_slots.push(Slot::Local(next));
+                next += ty.wasm_types().len() as u32;
+                locals.extend(ty.wasm_types().iter().map(|&t| (1, t)));
             }
         }
 
-        f.instruction(WasmInst::End);
+        let mut value_locals = Vec::with_capacity(func.values.len());
+        for ty in &func.values {
+            value_locals.push(next);
+            next += ty.wasm_types().len() as u32;
+            locals.extend(ty.wasm_types().iter().map(|&t| (1, t)));
+        }
+
+        let label_local = next;
+        if structure.label {
+            locals.push((1, ValType::I32));
+            next += 1;
+        }
+        let frame = if frame_size > 0 {
+            locals.push((1, VAL_PTR));
+            Some((next, frame_size))
+        } else {
+            None
+        };
+
+        let mut emitter = FunctionEmitter {
+            ctx: self,
+            func,
+            root,
+            arg_slots,
+            local_slots,
+            value_locals,
+            label_local,
+            frame,
+            f: Function::new(locals),
+        };
+        emitter.prologue(param_words);
+        emitter.structured(&structure.body);
+        // every path has left through a return, but the end of a loop is still reachable
+        emitter.f.instruction(WasmInst::Unreachable);
+        emitter.f.instruction(WasmInst::End);
 
-        f
+        emitter.f
     }
 
+    /// Wasm type of a signature which lowers, see `ir::signature_types`
     fn wasm_method_sig(&mut self, signature: MethodDefSig) -> SignatureCacheData {
         let types = &mut self.types;
 
         self.signature_cache
             .entry(signature)
             .or_insert_with_key(|signature| {
-                let mut params = Vec::new();
-                if signature
-                    .calling_convension
-                    .contains(MethodCallingConvension::HAS_THIS)
-                {
-                    params.push(VAL_PTR);
-                }
-                signature
-                    .params
-                    .iter()
-                    .for_each(|p| Self::convert_wasm_param(&mut params, p));
+                let (params, ret) = ir::signature_types(signature)
+                    .expect("signatures are checked before they are emitted");
                 let type_index = types.len();
-                let params = Rc::new(params);
+                let params = Rc::new(
+                    params
+                        .iter()
+                        .flat_map(|ty| ty.wasm_types())
+                        .copied()
+                        .collect::<Vec<_>>(),
+                );
                 types.function(
                     params.iter().copied(),
-                    Self::convert_wasm_return(&signature.ret),
+                    ret.map_or(&[][..], IrType::wasm_types).iter().copied(),
                 );
                 SignatureCacheData {
                     type_index,
@@ -293,48 +410,54 @@ impl WasmContext {
         &mut self,
         member_ref_index: MemberRefIndex,
         member_ref: &MemberRef,
-        table: &MetadataTable,
-        heap: Heap,
+        root: &MetadataRoot,
     ) {
-        let member_sig = member_ref.resolve_signature(heap);
+        let table = &root.metadata_stream.table;
+        let heap = root.heap;
+        // `ir::lower` rejects calls to anything else, so there is nothing to import
+        let (member_sig, ty_ref) = match (
+            ir::import_signature(member_ref_index, root),
+            member_ref.class,
+        ) {
+            (Some(sig), MemberRefParent::TypeRefIndex(ty_ref)) => (sig, ty_ref),
+            _ => return,
+        };
         let member_func_name = member_ref.name.resolve(heap).unwrap();
         let member_func_ty = self.wasm_method_sig(member_sig);
-        match member_ref.class {
-            MemberRefParent::TypeRefIndex(ty_ref) => {
-                let ty_ref = ty_ref.resolve_table(table).unwrap();
+        let ty_ref = ty_ref.resolve_table(table).unwrap();
 
-                let namespace = ty_ref.type_namespace.resolve(heap);
-                let name = ty_ref.type_name.resolve(heap);
+        let namespace = ty_ref.type_namespace.resolve(heap);
+        let name = ty_ref.type_name.resolve(heap);
 
-                let fullname =
-                    Self::get_method_full_name(namespace, name.unwrap(), member_func_name);
+        let fullname = Self::get_method_full_name(namespace, name.unwrap(), member_func_name);
 
-                let fn_index = self.compute_fn_index(true);
-                self.imports.import(
-                    "env",
-                    Some(&fullname),
-                    EntityType::Function(member_func_ty.type_index),
-                );
+        let fn_index = self.compute_fn_index(true);
+        self.imports.import(
+            "env",
+            Some(&fullname),
+            EntityType::Function(member_func_ty.type_index),
+        );
 
-                self.member_ref_cache
-                    .insert(member_ref_index, MemberRefCacheData { fn_index });
-            }
-            other => todo!("{:?}", other),
-        }
+        self.member_ref_cache
+            .insert(member_ref_index, MemberRefCacheData { fn_index });
     }
 
     pub fn emit_wasm_type_header(
         &mut self,
         ty_index: TypeDefIndex,
         ty_def: &TypeDef,
-        table: &MetadataTable,
-        heap: Heap,
+        root: &MetadataRoot,
     ) {
+        let table = &root.metadata_stream.table;
+        let heap = root.heap;
         let namespace = ty_def.type_namespace.resolve(heap);
         let ty_name = ty_def.type_name.resolve(heap).unwrap();
-        // TODO: create field type
-        // let fields = ty_index.resolve_fields(table).unwrap();
+        if let Some(layout) = object_layout(ty_index, root) {
+            self.field_offsets.extend(&layout.fields);
+            self.object_layouts.insert(ty_index, layout);
+        }
         for (method_index, method_def) in ty_index.resolve_methods(table) {
+            self.method_owner.insert(method_index, ty_index);
             let full_name = Self::get_method_full_name(
                 namespace,
                 ty_name,
@@ -360,44 +483,2098 @@ impl WasmContext {
         self.exports.export(name, Export::Function(fn_index));
     }
 
+    /// Bump allocator, memory is never reused so fresh objects are already zeroed
+    pub fn emit_wasm_runtime(&mut self) {
+        let type_index = self.types.len();
+        self.types.function(vec![VAL_PTR], vec![VAL_PTR]);
+        let fn_index = self.compute_fn_index(false);
+        self.functions.function(type_index);
+        self.alloc_fn = Some(fn_index);
+
+        let (size, ptr) = (0, 1);
+        let mut f = Function::new(vec![(1, VAL_PTR)]);
+        for inst in [
+            WasmInst::GlobalGet(HEAP_POINTER),
+            WasmInst::LocalTee(ptr),
+            WasmInst::LocalGet(size),
+            WasmInst::I32Add,
+            WasmInst::I32Const(7),
+            WasmInst::I32Add,
+            WasmInst::I32Const(-8),
+            WasmInst::I32And,
+            WasmInst::GlobalSet(HEAP_POINTER),
+            // grow memory to the pages covering the new end of the heap
+            WasmInst::Block(BlockType::Empty),
+            WasmInst::GlobalGet(HEAP_POINTER),
+            WasmInst::MemorySize(0),
+            WasmInst::I32Const(16),
+            WasmInst::I32Shl,
+            WasmInst::I32LeU,
+            WasmInst::BrIf(0),
+            WasmInst::GlobalGet(HEAP_POINTER),
+            WasmInst::MemorySize(0),
+            WasmInst::I32Const(16),
+            WasmInst::I32Shl,
+            WasmInst::I32Sub,
+            WasmInst::I32Const(0xFFFF),
+            WasmInst::I32Add,
+            WasmInst::I32Const(16),
+            WasmInst::I32ShrU,
+            WasmInst::MemoryGrow(0),
+            WasmInst::I32Const(-1),
+            WasmInst::I32Neq,
+            WasmInst::BrIf(0),
+            WasmInst::Unreachable,
+            WasmInst::End,
+            WasmInst::LocalGet(ptr),
+            WasmInst::End,
+        ] {
+            f.instruction(inst);
+        }
+        self.codes.function(&f);
+
+        self.emit_wasm_fmod();
+    }
+
+    /// Exact float64 remainder with the sign of the dividend. The divisor is doubled up to the
+    /// dividend, then subtracted while halving back, each subtraction is exact by Sterbenz lemma.
+    fn emit_wasm_fmod(&mut self) {
+        let type_index = self.types.len();
+        self.types
+            .function(vec![ValType::F64, ValType::F64], vec![ValType::F64]);
+        let fn_index = self.compute_fn_index(false);
+        self.functions.function(type_index);
+        self.fmod_fn = Some(fn_index);
+
+        let (a, b, r, m, t) = (0, 1, 2, 3, 4);
+        let mut f = Function::new(vec![(3, ValType::F64)]);
+        for inst in [
+            WasmInst::LocalGet(a),
+            WasmInst::F64Abs,
+            WasmInst::LocalSet(r),
+            WasmInst::LocalGet(b),
+            WasmInst::F64Abs,
+            WasmInst::LocalTee(m),
+            WasmInst::LocalSet(t),
+            // NaN for a NaN operand, an infinite dividend or a zero divisor
+            WasmInst::LocalGet(r),
+            WasmInst::F64Const(f64::INFINITY),
+            WasmInst::F64Eq,
+            WasmInst::LocalGet(r),
+            WasmInst::LocalGet(r),
+            WasmInst::F64Neq,
+            WasmInst::I32Or,
+            WasmInst::LocalGet(m),
+            WasmInst::LocalGet(m),
+            WasmInst::F64Neq,
+            WasmInst::I32Or,
+            WasmInst::LocalGet(m),
+            WasmInst::F64Const(0.0),
+            WasmInst::F64Eq,
+            WasmInst::I32Or,
+            WasmInst::If(BlockType::Empty),
+            WasmInst::F64Const(f64::NAN),
+            WasmInst::Return,
+            WasmInst::End,
+            // also covers an infinite divisor
+            WasmInst::LocalGet(r),
+            WasmInst::LocalGet(m),
+            WasmInst::F64Lt,
+            WasmInst::If(BlockType::Empty),
+            WasmInst::LocalGet(a),
+            WasmInst::Return,
+            WasmInst::End,
+            // largest `t = m * 2^k <= r`, doubling past the largest float gives infinity
+            WasmInst::Loop(BlockType::Empty),
+            WasmInst::LocalGet(t),
+            WasmInst::LocalGet(t),
+            WasmInst::F64Add,
+            WasmInst::LocalGet(r),
+            WasmInst::F64Le,
+            WasmInst::If(BlockType::Empty),
+            WasmInst::LocalGet(t),
+            WasmInst::LocalGet(t),
+            WasmInst::F64Add,
+            WasmInst::LocalSet(t),
+            WasmInst::Br(1),
+            WasmInst::End,
+            WasmInst::End,
+            // `r < 2t` holds on every step, so `r - t` is exact when `t <= r`
+            WasmInst::Loop(BlockType::Empty),
+            WasmInst::LocalGet(t),
+            WasmInst::LocalGet(r),
+            WasmInst::F64Le,
+            WasmInst::If(BlockType::Empty),
+            WasmInst::LocalGet(r),
+            WasmInst::LocalGet(t),
+            WasmInst::F64Sub,
+            WasmInst::LocalSet(r),
+            WasmInst::End,
+            WasmInst::LocalGet(t),
+            WasmInst::LocalGet(m),
+            WasmInst::F64Neq,
+            WasmInst::If(BlockType::Empty),
+            WasmInst::LocalGet(t),
+            WasmInst::F64Const(0.5),
+            WasmInst::F64Mul,
+            WasmInst::LocalSet(t),
+            WasmInst::Br(1),
+            WasmInst::End,
+            WasmInst::End,
+            WasmInst::LocalGet(r),
+            WasmInst::LocalGet(a),
+            WasmInst::F64Copysign,
+            WasmInst::End,
+        ] {
+            f.instruction(inst);
+        }
+        self.codes.function(&f);
+    }
+
     pub fn emit_wasm_function_body(
         &mut self,
+        index: MethodDefIndex,
         body: &MethodBody,
-        table: &MetadataTable,
-        heap: Heap,
-    ) {
-        let func = self.convert_wasm_function(body, table, heap);
+        root: &MetadataRoot,
+    ) -> Result<(), LowerError> {
+        let func = self.convert_wasm_function(&ir::lower(index, body, root)?, root);
         self.codes.function(&func);
+        Ok(())
+    }
+
+    /// Export `_start` which calls the entry point with zeroed args and drops its result
+    pub fn emit_wasm_entry_point(&mut self, index: MethodDefIndex, root: &MetadataRoot) {
+        let target = self.method_cache[&index].fn_index;
+        let (params, ret) =
+            ir::method_signature(index, root).expect("method signatures are checked first");
+
+        let type_index = self.types.len();
+        self.types.function(vec![], vec![]);
+
+        let fn_index = self.compute_fn_index(false);
+        self.functions.function(type_index);
+        self.exports.export("_start", Export::Function(fn_index));
+
+        let mut f = Function::new(vec![]);
+        for &param in params.iter().flat_map(|ty| ty.wasm_types()) {
+            f.instruction(match param {
+                ValType::I32 => WasmInst::I32Const(0),
+                ValType::I64 => WasmInst::I64Const(0),
+                ValType::F32 => WasmInst::F32Const(0.0),
+                ValType::F64 => WasmInst::F64Const(0.0),
+                other => unreachable!("{:?} word", other),
+            });
+        }
+        f.instruction(WasmInst::Call(target));
+        for _ in ret.map_or(&[][..], IrType::wasm_types) {
+            f.instruction(WasmInst::Drop);
+        }
+        f.instruction(WasmInst::End);
+        self.codes.function(&f);
+    }
+}
+
+/// Storage of a CIL argument or local
+#[derive(Clone, Copy)]
+enum Slot {
+    /// First of the wasm locals holding its words
+    Local(u32),
+    /// Byte offset in the shadow stack frame
+    Frame(u32),
+}
+
+impl Slot {
+    /// Byte offset of each word in memory, words are naturally aligned
+    fn words(ty: IrType) -> Vec<(u32, ValType)> {
+        let mut offset = 0;
+        ty.wasm_types()
+            .iter()
+            .map(|&t| {
+                let size = word_size(t);
+                offset = (offset + size - 1) & !(size - 1);
+                let word = (offset, t);
+                offset += size;
+                word
+            })
+            .collect()
+    }
+
+    fn size(ty: IrType) -> u32 {
+        Self::words(ty)
+            .last()
+            .map_or(0, |&(offset, t)| offset + word_size(t))
+    }
+}
+
+fn word_size(ty: ValType) -> u32 {
+    match ty {
+        ValType::I64 | ValType::F64 => 8,
+        _ => 4,
+    }
+}
+
+fn mem_arg(offset: u32, ty: ValType) -> MemArg {
+    MemArg {
+        offset: offset as u64,
+        align: word_size(ty).trailing_zeros(),
+        memory_index: 0,
     }
 }
 
-pub fn compile(image: &Image) -> Vec<u8> {
+fn load(ty: ValType, offset: u32) -> WasmInst<'static> {
+    match ty {
+        ValType::I64 => WasmInst::I64Load(mem_arg(offset, ty)),
+        ValType::F32 => WasmInst::F32Load(mem_arg(offset, ty)),
+        ValType::F64 => WasmInst::F64Load(mem_arg(offset, ty)),
+        _ => WasmInst::I32Load(mem_arg(offset, ty)),
+    }
+}
+
+fn store(ty: ValType, offset: u32) -> WasmInst<'static> {
+    match ty {
+        ValType::I64 => WasmInst::I64Store(mem_arg(offset, ty)),
+        ValType::F32 => WasmInst::F32Store(mem_arg(offset, ty)),
+        ValType::F64 => WasmInst::F64Store(mem_arg(offset, ty)),
+        _ => WasmInst::I32Store(mem_arg(offset, ty)),
+    }
+}
+
+/// Base of a memory access
+#[derive(Clone, Copy)]
+enum Address {
+    /// Object reference
+    Object(Value),
+    /// Static data, the offset is the address
+    Static,
+    /// Array, index and element size
+    Element(Value, Value, u32),
+}
+
+/// Memory access of one word of a field
+#[derive(Clone, Copy)]
+enum Access {
+    I8,
+    U8,
+    I16,
+    U16,
+    Word(ValType),
+}
+
+impl Access {
+    /// Byte offset and access of each word, `None` for value types
+    fn of(ty: &Type) -> Option<Vec<(u32, Access)>> {
+        let access = match ty {
+            Type::Boolean | Type::U1 => Access::U8,
+            Type::I1 => Access::I8,
+            Type::I2 => Access::I16,
+            Type::Char | Type::U2 => Access::U16,
+            Type::ValueType(_) => return None,
+            ty => {
+                let words = Slot::words(IrType::of(ty)?);
+                return Some(
+                    words
+                        .into_iter()
+                        .map(|(offset, t)| (offset, Access::Word(t)))
+                        .collect(),
+                );
+            }
+        };
+        Some(vec![(0, access)])
+    }
+
+    /// Small integers extend to `int32`
+    fn load(self, offset: u32) -> WasmInst<'static> {
+        let arg = |align| MemArg {
+            offset: offset as u64,
+            align,
+            memory_index: 0,
+        };
+        match self {
+            Access::I8 => WasmInst::I32Load8_S(arg(0)),
+            Access::U8 => WasmInst::I32Load8_U(arg(0)),
+            Access::I16 => WasmInst::I32Load16_S(arg(1)),
+            Access::U16 => WasmInst::I32Load16_U(arg(1)),
+            Access::Word(ty) => load(ty, offset),
+        }
+    }
+
+    /// Small integers are truncated
+    fn store(self, offset: u32) -> WasmInst<'static> {
+        let arg = |align| MemArg {
+            offset: offset as u64,
+            align,
+            memory_index: 0,
+        };
+        match self {
+            Access::I8 | Access::U8 => WasmInst::I32Store8(arg(0)),
+            Access::I16 | Access::U16 => WasmInst::I32Store16(arg(1)),
+            Access::Word(ty) => store(ty, offset),
+        }
+    }
+}
+
+fn zero(ty: ValType) -> WasmInst<'static> {
+    match ty {
+        ValType::I64 => WasmInst::I64Const(0),
+        ValType::F32 => WasmInst::F32Const(0.0),
+        ValType::F64 => WasmInst::F64Const(0.0),
+        _ => WasmInst::I32Const(0),
+    }
+}
+
+struct FunctionEmitter<'c, 'r, 'a> {
+    ctx: &'c WasmContext,
+    func: &'c ir::Function,
+    root: &'r MetadataRoot<'a>,
+    arg_slots: Vec<Slot>,
+    local_slots: Vec<Slot>,
+    /// First wasm local of each IR value
+    value_locals: Vec<u32>,
+    label_local: u32,
+    /// Local holding the frame address and the frame size
+    frame: Option<(u32, u32)>,
+    f: Function,
+}
+
+impl FunctionEmitter<'_, '_, '_> {
+    /// Allocate the frame, spill address-taken args and zero address-taken locals
+    fn prologue(&mut self, param_words: u32) {
+        let (frame, size) = match self.frame {
+            Some(frame) => frame,
+            None => return,
+        };
+        self.f.instruction(WasmInst::GlobalGet(STACK_POINTER));
+        self.f.instruction(WasmInst::I32Const(size as i32));
+        self.f.instruction(WasmInst::I32Sub);
+        self.f.instruction(WasmInst::LocalTee(frame));
+        self.f.instruction(WasmInst::GlobalSet(STACK_POINTER));
+
+        // trap on a frame below the stack region, which also catches one wrapping below zero
+        let limit = self.ctx.stack_limit();
+        self.f.instruction(WasmInst::LocalGet(frame));
+        self.f.instruction(WasmInst::I32Const(limit as i32));
+        self.f.instruction(WasmInst::I32Sub);
+        self.f.instruction(WasmInst::I32Const(STACK_SIZE as i32));
+        self.f.instruction(WasmInst::I32GeU);
+        self.f.instruction(WasmInst::If(BlockType::Empty));
+        self.f.instruction(WasmInst::Unreachable);
+        self.f.instruction(WasmInst::End);
+
+        let mut param_local = 0;
+        for (n, &ty) in self.func.params.iter().enumerate() {
+            if let Slot::Frame(offset) = self.arg_slots[n] {
+                for (i, (word, t)) in Slot::words(ty).into_iter().enumerate() {
+                    self.f.instruction(WasmInst::LocalGet(frame));
+                    self.f
+                        .instruction(WasmInst::LocalGet(param_local + i as u32));
+                    self.f.instruction(store(t, offset + word));
+                }
+            }
+            param_local += ty.wasm_types().len() as u32;
+        }
+        debug_assert_eq!(param_local, param_words);
+
+        for (n, &ty) in self.func.locals.iter().enumerate() {
+            if let Slot::Frame(offset) = self.local_slots[n] {
+                for (word, t) in Slot::words(ty) {
+                    self.f.instruction(WasmInst::LocalGet(frame));
+                    self.f.instruction(zero(t));
+                    self.f.instruction(store(t, offset + word));
+                }
+            }
+        }
+    }
+
+    fn epilogue(&mut self) {
+        if let Some((frame, size)) = self.frame {
+            self.f.instruction(WasmInst::LocalGet(frame));
+            self.f.instruction(WasmInst::I32Const(size as i32));
+            self.f.instruction(WasmInst::I32Add);
+            self.f.instruction(WasmInst::GlobalSet(STACK_POINTER));
+        }
+    }
+
+    fn load_slot(&mut self, slot: Slot, ty: IrType) {
+        match slot {
+            Slot::Local(base) => {
+                for word in 0..ty.wasm_types().len() as u32 {
+                    self.f.instruction(WasmInst::LocalGet(base + word));
+                }
+            }
+            Slot::Frame(offset) => {
+                let frame = self.frame.unwrap().0;
+                for (word, t) in Slot::words(ty) {
+                    self.f.instruction(WasmInst::LocalGet(frame));
+                    self.f.instruction(load(t, offset + word));
+                }
+            }
+        }
+    }
+
+    fn store_slot(&mut self, slot: Slot, ty: IrType, value: Value) {
+        let base = self.value_locals[value.0 as usize];
+        match slot {
+            Slot::Local(slot) => {
+                for word in 0..ty.wasm_types().len() as u32 {
+                    self.f.instruction(WasmInst::LocalGet(base + word));
+                    self.f.instruction(WasmInst::LocalSet(slot + word));
+                }
+            }
+            Slot::Frame(offset) => {
+                let frame = self.frame.unwrap().0;
+                for (i, (word, t)) in Slot::words(ty).into_iter().enumerate() {
+                    self.f.instruction(WasmInst::LocalGet(frame));
+                    self.f.instruction(WasmInst::LocalGet(base + i as u32));
+                    self.f.instruction(store(t, offset + word));
+                }
+            }
+        }
+    }
+
+    fn slot_addr(&mut self, slot: Slot) {
+        match slot {
+            Slot::Frame(offset) => {
+                self.f
+                    .instruction(WasmInst::LocalGet(self.frame.unwrap().0));
+                self.f.instruction(WasmInst::I32Const(offset as i32));
+                self.f.instruction(WasmInst::I32Add);
+            }
+            Slot::Local(_) => unreachable!("address of a slot outside the frame"),
+        }
+    }
+
+    fn get(&mut self, value: Value) {
+        let base = self.value_locals[value.0 as usize];
+        for word in 0..self.func.value_type(value).wasm_types().len() as u32 {
+            self.f.instruction(WasmInst::LocalGet(base + word));
+        }
+    }
+
+    fn set(&mut self, value: Value) {
+        let base = self.value_locals[value.0 as usize];
+        for word in (0..self.func.value_type(value).wasm_types().len() as u32).rev() {
+            self.f.instruction(WasmInst::LocalSet(base + word));
+        }
+    }
+
+    /// First word of a value as an `i32` condition
+    fn condition(&mut self, value: Value) {
+        let base = self.value_locals[value.0 as usize];
+        self.f.instruction(WasmInst::LocalGet(base));
+        if self.func.value_type(value) == IrType::I64 {
+            self.f.instruction(WasmInst::I64Eqz);
+            self.f.instruction(WasmInst::I32Eqz);
+        }
+    }
+
+    fn structured(&mut self, code: &[Structured]) {
+        for s in code {
+            match s {
+                Structured::Block(body) => {
+                    self.f.instruction(WasmInst::Block(BlockType::Empty));
+                    self.structured(body);
+                    self.f.instruction(WasmInst::End);
+                }
+                Structured::Loop(body) => {
+                    self.f.instruction(WasmInst::Loop(BlockType::Empty));
+                    self.structured(body);
+                    self.f.instruction(WasmInst::End);
+                }
+                Structured::If(cond, then, otherwise) => {
+                    self.condition(*cond);
+                    self.f.instruction(WasmInst::If(BlockType::Empty));
+                    self.structured(then);
+                    self.f.instruction(WasmInst::Else);
+                    self.structured(otherwise);
+                    self.f.instruction(WasmInst::End);
+                }
+                Structured::Code(block) => {
+                    for inst in &self.func.block(*block).insts {
+                        self.inst(inst);
+                    }
+                }
+                Structured::Copy(target) => {
+                    // all arguments are read before any parameter is written
+                    let params = &self.func.block(target.block).params;
+                    for (&arg, &param) in target.args.iter().zip(params) {
+                        self.get(arg);
+                        if self.func.value_type(arg) == IrType::F32
+                            && self.func.value_type(param) == IrType::F64
+                        {
+                            self.f.instruction(WasmInst::F64PromoteF32);
+                        }
+                    }
+                    for &param in params.iter().rev() {
+                        self.set(param);
+                    }
+                }
+                Structured::Br(depth) => {
+                    self.f.instruction(WasmInst::Br(*depth));
+                }
+                Structured::BrTable(value, targets, default) => {
+                    self.condition(*value);
+                    self.f.instruction(WasmInst::BrTable(targets, *default));
+                }
+                Structured::Return(value) => {
+                    if let Some(value) = value {
+                        self.get(*value);
+                    }
+                    self.epilogue();
+                    self.f.instruction(WasmInst::Return);
+                }
+                Structured::SetLabel(label) => {
+                    self.f.instruction(WasmInst::I32Const(*label as i32));
+                    self.f.instruction(WasmInst::LocalSet(self.label_local));
+                }
+                Structured::Dispatch(targets, default) => {
+                    self.f.instruction(WasmInst::LocalGet(self.label_local));
+                    self.f.instruction(WasmInst::BrTable(targets, *default));
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &ir::Inst) {
+        let ctx = self.ctx;
+        let root = self.root;
+        match &inst.op {
+            Op::Const(Const::I32(n)) => {
+                self.f.instruction(WasmInst::I32Const(*n));
+            }
+            Op::Const(Const::I64(n)) => {
+                self.f.instruction(WasmInst::I64Const(*n));
+            }
+            Op::Const(Const::F32(n)) => {
+                self.f.instruction(WasmInst::F32Const(*n));
+            }
+            Op::Const(Const::F64(n)) => {
+                self.f.instruction(WasmInst::F64Const(*n));
+            }
+            Op::Arg(n) => {
+                let n = *n as usize;
+                self.load_slot(self.arg_slots[n], self.func.params[n]);
+            }
+            Op::SetArg(n, value) => {
+                let n = *n as usize;
+                self.store_slot(self.arg_slots[n], self.func.params[n], *value);
+            }
+            Op::Local(n) => {
+                let n = *n as usize;
+                self.load_slot(self.local_slots[n], self.func.locals[n]);
+            }
+            Op::SetLocal(n, value) => {
+                let n = *n as usize;
+                self.store_slot(self.local_slots[n], self.func.locals[n], *value);
+            }
+            Op::ArgAddr(n) => self.slot_addr(self.arg_slots[*n as usize]),
+            Op::LocalAddr(n) => self.slot_addr(self.local_slots[*n as usize]),
+            Op::Str(s) => {
+                let str_data = &ctx.string_cache[s];
+
+                // TODO: handling null
+                // if s.0 == 0 {
+                // }
+
+                self.f.instruction(WasmInst::I32Const(str_data.data_index));
+                self.f
+                    .instruction(WasmInst::I32Const(str_data.str_len as i32));
+                self.f.instruction(WasmInst::I32Const(0));
+            }
+            Op::FieldData(field) => {
+                let field_data = ctx
+                    .field_data_cache
+                    .get(field)
+                    .unwrap_or_else(|| panic!("ldtoken {} without RVA", field.display_with(root)));
+                self.f
+                    .instruction(WasmInst::I32Const(field_data.data_index));
+            }
+            Op::NewArr(ty, len) => {
+                let (size, _) = self.element_access(ty);
+                // negative lengths are above the limit as unsigned
+                self.get(*len);
+                self.f
+                    .instruction(WasmInst::I32Const(i32::MAX / size as i32));
+                self.f.instruction(WasmInst::I32GtU);
+                self.fault_if(Fault::Overflow);
+                self.get(*len);
+                self.f.instruction(WasmInst::I32Const(size as i32));
+                self.f.instruction(WasmInst::I32Mul);
+                self.f
+                    .instruction(WasmInst::Call(ctx.alloc_fn.expect("runtime is emitted")));
+                self.get(*len);
+            }
+            Op::ArrayLen(array) => {
+                self.f
+                    .instruction(WasmInst::LocalGet(self.value_locals[array.0 as usize] + 1));
+            }
+            Op::LoadElem(ty, array, index) => {
+                let (size, words) = self.element_access(ty);
+                self.check_index(*array, *index);
+                self.load_words(words, Address::Element(*array, *index, size), 0);
+            }
+            Op::StoreElem(ty, array, index, value) => {
+                let (size, words) = self.element_access(ty);
+                self.check_index(*array, *index);
+                self.store_words(words, Address::Element(*array, *index, size), 0, *value);
+            }
+            Op::InitArray(array, data) => {
+                // the handle points to the length of the data, which follows it
+                let data = self.value_locals[data.0 as usize];
+                self.f
+                    .instruction(WasmInst::LocalGet(self.value_locals[array.0 as usize]));
+                self.f.instruction(WasmInst::LocalGet(data));
+                self.f.instruction(WasmInst::I32Const(4));
+                self.f.instruction(WasmInst::I32Add);
+                self.f.instruction(WasmInst::LocalGet(data));
+                self.f.instruction(load(ValType::I32, 0));
+                self.f.instruction(WasmInst::MemoryCopy { src: 0, dst: 0 });
+            }
+            Op::Call(method, args) => {
+                for &arg in args {
+                    self.get(arg);
+                }
+                if let Some(member) = method.as_member_ref() {
+                    self.f
+                        .instruction(WasmInst::Call(ctx.member_ref_cache[&member].fn_index));
+                } else if let Some(method) = method.as_method_def() {
+                    self.f
+                        .instruction(WasmInst::Call(ctx.method_cache[&method].fn_index));
+                } else {
+                    panic!("Invalid Call argument {}", method.display_with(root));
+                }
+            }
+            Op::New(ctor, args) => {
+                let class = ctx.method_owner[ctor];
+                let layout = ctx
+                    .object_layouts
+                    .get(&class)
+                    .expect("newobj without an object layout is rejected by ir::lower");
+                let result = inst.result.unwrap();
+                self.f.instruction(WasmInst::I32Const(layout.size as i32));
+                self.f
+                    .instruction(WasmInst::Call(ctx.alloc_fn.expect("runtime is emitted")));
+                self.set(result);
+
+                self.get(result);
+                self.f
+                    .instruction(WasmInst::I32Const(layout.type_id as i32));
+                self.f.instruction(store(ValType::I32, 0));
+
+                self.get(result);
+                for &arg in args {
+                    self.get(arg);
+                }
+                self.f
+                    .instruction(WasmInst::Call(ctx.method_cache[ctor].fn_index));
+                return;
+            }
+            Op::LoadField(field, obj) => {
+                let offset = self.field_offset(*field);
+                let words = self.field_access(*field);
+                self.load_words(words, Address::Object(*obj), offset);
+            }
+            Op::StoreField(field, obj, value) => {
+                let offset = self.field_offset(*field);
+                let words = self.field_access(*field);
+                self.store_words(words, Address::Object(*obj), offset, *value);
+            }
+            Op::FieldAddr(field, obj) => {
+                let offset = self.field_offset(*field);
+                self.get(*obj);
+                self.f.instruction(WasmInst::I32Const(offset as i32));
+                self.f.instruction(WasmInst::I32Add);
+            }
+            Op::LoadStatic(field) => {
+                let address = self.static_address(*field);
+                let words = self.field_access(*field);
+                self.load_words(words, Address::Static, address);
+            }
+            Op::StoreStatic(field, value) => {
+                let address = self.static_address(*field);
+                let words = self.field_access(*field);
+                self.store_words(words, Address::Static, address, *value);
+            }
+            Op::StaticAddr(field) => {
+                let address = self.static_address(*field);
+                self.f.instruction(WasmInst::I32Const(address as i32));
+            }
+            Op::Compare(cmp, lhs, rhs) => self.compare(*cmp, *lhs, *rhs),
+            Op::Binary(op, lhs, rhs) => self.binary(*op, *lhs, *rhs),
+            Op::Unary(op, value) => self.unary(*op, *value),
+            Op::Convert(ty, value) => self.convert(*ty, *value, false),
+            Op::ConvertOvf(ty, value) => {
+                self.check_convert(*ty, *value, false);
+                self.convert(*ty, *value, false);
+            }
+            Op::ConvertOvfUn(ty, value) => {
+                self.check_convert(*ty, *value, true);
+                self.convert(*ty, *value, true);
+            }
+        }
+        if let Some(result) = inst.result {
+            self.set(result);
+        }
+    }
+
+    /// Push a float operand, float32 widens when `wide`
+    fn float(&mut self, value: Value, wide: bool) {
+        self.get(value);
+        if wide && self.func.value_type(value) == IrType::F32 {
+            self.f.instruction(WasmInst::F64PromoteF32);
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Value, rhs: Value) {
+        let types = (self.func.value_type(lhs), self.func.value_type(rhs));
+        match op.result_type(types.0, types.1) {
+            ty @ (IrType::F32 | IrType::F64) => {
+                let wide = ty == IrType::F64;
+                if op == BinOp::Rem {
+                    // the exact float64 remainder of float32 operands fits a float32
+                    self.float(lhs, true);
+                    self.float(rhs, true);
+                    let fmod = self.ctx.fmod_fn.expect("runtime is emitted");
+                    self.f.instruction(WasmInst::Call(fmod));
+                    if !wide {
+                        self.f.instruction(WasmInst::F32DemoteF64);
+                    }
+                    return;
+                }
+                self.float(lhs, wide);
+                self.float(rhs, wide);
+                self.f.instruction(match (op, wide) {
+                    (BinOp::Add, false) => WasmInst::F32Add,
+                    (BinOp::Sub, false) => WasmInst::F32Sub,
+                    (BinOp::Mul, false) => WasmInst::F32Mul,
+                    (BinOp::Div, false) => WasmInst::F32Div,
+                    (BinOp::Add, true) => WasmInst::F64Add,
+                    (BinOp::Sub, true) => WasmInst::F64Sub,
+                    (BinOp::Mul, true) => WasmInst::F64Mul,
+                    (BinOp::Div, true) => WasmInst::F64Div,
+                    _ => unreachable!("{} on floats", op),
+                });
+            }
+            IrType::I64 => {
+                self.check_binary(op, lhs, rhs, true);
+                self.get(lhs);
+                self.get(rhs);
+                // the shift amount is an int32 or native int
+                if op.is_shift() && types.1 != IrType::I64 {
+                    self.f.instruction(WasmInst::I64ExtendI32U);
+                }
+                self.f.instruction(match op.unchecked() {
+                    BinOp::Add => WasmInst::I64Add,
+                    BinOp::Sub => WasmInst::I64Sub,
+                    BinOp::Mul => WasmInst::I64Mul,
+                    BinOp::Div => WasmInst::I64DivS,
+                    BinOp::DivUn => WasmInst::I64DivU,
+                    BinOp::Rem => WasmInst::I64RemS,
+                    BinOp::RemUn => WasmInst::I64RemU,
+                    BinOp::And => WasmInst::I64And,
+                    BinOp::Or => WasmInst::I64Or,
+                    BinOp::Xor => WasmInst::I64Xor,
+                    BinOp::Shl => WasmInst::I64Shl,
+                    BinOp::Shr => WasmInst::I64ShrS,
+                    BinOp::ShrUn => WasmInst::I64ShrU,
+                    op => unreachable!("{}", op),
+                });
+            }
+            IrType::I32 | IrType::Ptr => {
+                self.check_binary(op, lhs, rhs, false);
+                self.get(lhs);
+                self.get(rhs);
+                if op.is_shift() && types.1 == IrType::I64 {
+                    self.f.instruction(WasmInst::I32WrapI64);
+                }
+                self.f.instruction(match op.unchecked() {
+                    BinOp::Add => WasmInst::I32Add,
+                    BinOp::Sub => WasmInst::I32Sub,
+                    BinOp::Mul => WasmInst::I32Mul,
+                    BinOp::Div => WasmInst::I32DivS,
+                    BinOp::DivUn => WasmInst::I32DivU,
+                    BinOp::Rem => WasmInst::I32RemS,
+                    BinOp::RemUn => WasmInst::I32RemU,
+                    BinOp::And => WasmInst::I32And,
+                    BinOp::Or => WasmInst::I32Or,
+                    BinOp::Xor => WasmInst::I32Xor,
+                    BinOp::Shl => WasmInst::I32Shl,
+                    BinOp::Shr => WasmInst::I32ShrS,
+                    BinOp::ShrUn => WasmInst::I32ShrU,
+                    op => unreachable!("{}", op),
+                });
+            }
+            ty => unreachable!("{} on {}, rejected by ir::lower", op, ty),
+        }
+    }
+
+    fn unary(&mut self, op: UnOp, value: Value) {
+        let ty = self.func.value_type(value);
+        match (op, ty) {
+            (UnOp::Neg, IrType::F32) => {
+                self.get(value);
+                self.f.instruction(WasmInst::F32Neg);
+            }
+            (UnOp::Neg, IrType::F64) => {
+                self.get(value);
+                self.f.instruction(WasmInst::F64Neg);
+            }
+            (UnOp::Neg, IrType::I64) => {
+                self.f.instruction(WasmInst::I64Const(0));
+                self.get(value);
+                self.f.instruction(WasmInst::I64Sub);
+            }
+            (UnOp::Neg, IrType::I32 | IrType::Ptr) => {
+                self.f.instruction(WasmInst::I32Const(0));
+                self.get(value);
+                self.f.instruction(WasmInst::I32Sub);
+            }
+            (UnOp::Not, IrType::I64) => {
+                self.get(value);
+                self.f.instruction(WasmInst::I64Const(-1));
+                self.f.instruction(WasmInst::I64Xor);
+            }
+            (UnOp::Not, IrType::I32 | IrType::Ptr) => {
+                self.get(value);
+                self.f.instruction(WasmInst::I32Const(-1));
+                self.f.instruction(WasmInst::I32Xor);
+            }
+            // NaN fails the comparison as well
+            (UnOp::CkFinite, IrType::F32) => {
+                self.get(value);
+                self.emit([
+                    WasmInst::F32Abs,
+                    WasmInst::F32Const(f32::INFINITY),
+                    WasmInst::F32Lt,
+                    WasmInst::I32Eqz,
+                ]);
+                self.fault_if(Fault::NotFinite);
+                self.get(value);
+            }
+            (UnOp::CkFinite, IrType::F64) => {
+                self.get(value);
+                self.emit([
+                    WasmInst::F64Abs,
+                    WasmInst::F64Const(f64::INFINITY),
+                    WasmInst::F64Lt,
+                    WasmInst::I32Eqz,
+                ]);
+                self.fault_if(Fault::NotFinite);
+                self.get(value);
+            }
+            _ => unreachable!("{} on {}, rejected by ir::lower", op, ty),
+        }
+    }
+
+    /// III.3.27, float to integer conversions saturate where the CLI leaves the result unspecified
+    fn convert(&mut self, ty: NumType, value: Value, from_unsigned: bool) {
+        let from = self.func.value_type(value);
+        self.get(value);
+        let unsigned = matches!(
+            ty,
+            NumType::U1 | NumType::U2 | NumType::U4 | NumType::U8 | NumType::U
+        );
+        let extend_unsigned = unsigned || from_unsigned;
+        let inst = match (IrType::of_num(ty), from) {
+            (IrType::I32 | IrType::Ptr, IrType::I32 | IrType::Ptr) => None,
+            (IrType::I32 | IrType::Ptr, IrType::I64) => Some(WasmInst::I32WrapI64),
+            (IrType::I32 | IrType::Ptr, IrType::F32) if unsigned => Some(WasmInst::I32TruncSatF32U),
+            (IrType::I32 | IrType::Ptr, IrType::F32) => Some(WasmInst::I32TruncSatF32S),
+            (IrType::I32 | IrType::Ptr, IrType::F64) if unsigned => Some(WasmInst::I32TruncSatF64U),
+            (IrType::I32 | IrType::Ptr, IrType::F64) => Some(WasmInst::I32TruncSatF64S),
+            (IrType::I64, IrType::I32 | IrType::Ptr) if extend_unsigned => {
+                Some(WasmInst::I64ExtendI32U)
+            }
+            (IrType::I64, IrType::I32 | IrType::Ptr) => Some(WasmInst::I64ExtendI32S),
+            (IrType::I64, IrType::I64) => None,
+            (IrType::I64, IrType::F32) if unsigned => Some(WasmInst::I64TruncSatF32U),
+            (IrType::I64, IrType::F32) => Some(WasmInst::I64TruncSatF32S),
+            (IrType::I64, IrType::F64) if unsigned => Some(WasmInst::I64TruncSatF64U),
+            (IrType::I64, IrType::F64) => Some(WasmInst::I64TruncSatF64S),
+            (IrType::F32, IrType::I32 | IrType::Ptr) => Some(WasmInst::F32ConvertI32S),
+            (IrType::F32, IrType::I64) => Some(WasmInst::F32ConvertI64S),
+            (IrType::F32, IrType::F32) => None,
+            (IrType::F32, IrType::F64) => Some(WasmInst::F32DemoteF64),
+            // `conv.r.un` reads integers as unsigned
+            (IrType::F64, IrType::I32 | IrType::Ptr) if ty == NumType::RUn => {
+                Some(WasmInst::F64ConvertI32U)
+            }
+            (IrType::F64, IrType::I32 | IrType::Ptr) => Some(WasmInst::F64ConvertI32S),
+            (IrType::F64, IrType::I64) if ty == NumType::RUn => Some(WasmInst::F64ConvertI64U),
+            (IrType::F64, IrType::I64) => Some(WasmInst::F64ConvertI64S),
+            (IrType::F64, IrType::F32) => Some(WasmInst::F64PromoteF32),
+            (IrType::F64, IrType::F64) => None,
+            (to, from) => unreachable!("conv.{} from {} to {}", ty.suffix(), from, to),
+        };
+        if let Some(inst) = inst {
+            self.f.instruction(inst);
+        }
+
+        // small integers keep only their low bits and extend back to int32
+        match ty {
+            NumType::I1 => {
+                self.f.instruction(WasmInst::I32Extend8S);
+            }
+            NumType::I2 => {
+                self.f.instruction(WasmInst::I32Extend16S);
+            }
+            NumType::U1 => {
+                self.f.instruction(WasmInst::I32Const(0xFF));
+                self.f.instruction(WasmInst::I32And);
+            }
+            NumType::U2 => {
+                self.f.instruction(WasmInst::I32Const(0xFFFF));
+                self.f.instruction(WasmInst::I32And);
+            }
+            _ => {}
+        }
+    }
+
+    fn field_offset(&self, field: FieldIndex) -> u32 {
+        *self
+            .ctx
+            .field_offsets
+            .get(&field)
+            .expect("instance fields without an offset are rejected by ir::lower")
+    }
+
+    fn static_address(&self, field: FieldIndex) -> u32 {
+        *self
+            .ctx
+            .static_fields
+            .get(&field)
+            .expect("static fields without an address are rejected by ir::lower")
+    }
+
+    fn field_access(&self, field: FieldIndex) -> Vec<(u32, Access)> {
+        let root = self.root;
+        let ty = field
+            .resolve_table(&root.metadata_stream.table)
+            .unwrap()
+            .resolve_signature(root.heap)
+            .ty;
+        Access::of(&ty).expect("value type fields are rejected by ir::lower")
+    }
+
+    /// Push the address of `base`, the offset is left to the access
+    fn address(&mut self, base: Address) {
+        match base {
+            Address::Object(obj) => self.get(obj),
+            Address::Static => {
+                self.f.instruction(WasmInst::I32Const(0));
+            }
+            Address::Element(array, index, size) => {
+                self.f
+                    .instruction(WasmInst::LocalGet(self.value_locals[array.0 as usize]));
+                self.get(index);
+                self.f.instruction(WasmInst::I32Const(size as i32));
+                self.f.instruction(WasmInst::I32Mul);
+                self.f.instruction(WasmInst::I32Add);
+            }
+        }
+    }
+
+    fn load_words(&mut self, words: Vec<(u32, Access)>, base: Address, offset: u32) {
+        for (word, access) in words {
+            self.address(base);
+            self.f.instruction(access.load(offset + word));
+        }
+    }
+
+    fn store_words(&mut self, words: Vec<(u32, Access)>, base: Address, offset: u32, value: Value) {
+        let local = self.value_locals[value.0 as usize];
+        let ty = self.func.value_type(value);
+        for (i, (word, access)) in words.into_iter().enumerate() {
+            self.address(base);
+            self.f.instruction(WasmInst::LocalGet(local + i as u32));
+            // F is a single stack type, it narrows or widens to the field
+            match (access, ty) {
+                (Access::Word(ValType::F32), IrType::F64) => {
+                    self.f.instruction(WasmInst::F32DemoteF64);
+                }
+                (Access::Word(ValType::F64), IrType::F32) => {
+                    self.f.instruction(WasmInst::F64PromoteF32);
+                }
+                _ => {}
+            }
+            self.f.instruction(access.store(offset + word));
+        }
+    }
+
+    fn element_access(&self, ty: &Type) -> (u32, Vec<(u32, Access)>) {
+        let root = self.root;
+        match (layout::field_layout(ty, root), Access::of(ty)) {
+            (Some((size, _)), Some(words)) => (size, words),
+            _ => unreachable!("array of {}, rejected by ir::lower", ty.display_with(root)),
+        }
+    }
+
+    /// `IndexOutOfRangeException` unless `index` is below the length of `array`
+    fn check_index(&mut self, array: Value, index: Value) {
+        self.get(index);
+        self.f
+            .instruction(WasmInst::LocalGet(self.value_locals[array.0 as usize] + 1));
+        self.f.instruction(WasmInst::I32GeU);
+        self.fault_if(Fault::IndexOutOfRange);
+    }
+
+    fn emit<'i>(&mut self, insts: impl IntoIterator<Item = WasmInst<'i>>) {
+        for inst in insts {
+            self.f.instruction(inst);
+        }
+    }
+
+    /// Raise `fault` when the `i32` condition on the stack is non-zero
+    fn fault_if(&mut self, fault: Fault) {
+        self.f.instruction(WasmInst::If(BlockType::Empty));
+        if let Some(&hook) = self.ctx.fault_hooks.get(&fault) {
+            self.f.instruction(WasmInst::Call(hook));
+        }
+        self.f.instruction(WasmInst::Unreachable);
+        self.f.instruction(WasmInst::End);
+    }
+
+    /// Overflow and division checks of an integer operation, made on its operands
+    fn check_binary(&mut self, op: BinOp, lhs: Value, rhs: Value, wide: bool) {
+        let hooks = !self.ctx.fault_hooks.is_empty();
+        // wasm division traps by itself, checks are only needed to raise the exception
+        if hooks && matches!(op, BinOp::Div | BinOp::DivUn | BinOp::Rem | BinOp::RemUn) {
+            self.get(rhs);
+            self.f.instruction(if wide {
+                WasmInst::I64Eqz
+            } else {
+                WasmInst::I32Eqz
+            });
+            self.fault_if(Fault::DivideByZero);
+        }
+        // `MIN % -1` is 0 in wasm but the CLR raises as it does for `MIN / -1`
+        if op == BinOp::Rem || hooks && op == BinOp::Div {
+            self.get(lhs);
+            if wide {
+                self.emit([WasmInst::I64Const(i64::MIN), WasmInst::I64Eq]);
+            } else {
+                self.emit([WasmInst::I32Const(i32::MIN), WasmInst::I32Eq]);
+            }
+            self.get(rhs);
+            if wide {
+                self.emit([WasmInst::I64Const(-1), WasmInst::I64Eq]);
+            } else {
+                self.emit([WasmInst::I32Const(-1), WasmInst::I32Eq]);
+            }
+            self.f.instruction(WasmInst::I32And);
+            self.fault_if(Fault::Overflow);
+        }
+
+        match (op, wide) {
+            // int32 results are exact in int64, biased into `0..=u32::MAX` when in range
+            (BinOp::AddOvf | BinOp::SubOvf | BinOp::MulOvf, false) => {
+                self.get(lhs);
+                self.f.instruction(WasmInst::I64ExtendI32S);
+                self.get(rhs);
+                self.f.instruction(WasmInst::I64ExtendI32S);
+                self.emit([
+                    match op {
+                        BinOp::AddOvf => WasmInst::I64Add,
+                        BinOp::SubOvf => WasmInst::I64Sub,
+                        _ => WasmInst::I64Mul,
+                    },
+                    WasmInst::I64Const(0x8000_0000),
+                    WasmInst::I64Add,
+                    WasmInst::I64Const(0xFFFF_FFFF),
+                    WasmInst::I64GtU,
+                ]);
+            }
+            (BinOp::AddOvfUn | BinOp::MulOvfUn, false) => {
+                self.get(lhs);
+                self.f.instruction(WasmInst::I64ExtendI32U);
+                self.get(rhs);
+                self.f.instruction(WasmInst::I64ExtendI32U);
+                self.emit([
+                    match op {
+                        BinOp::AddOvfUn => WasmInst::I64Add,
+                        _ => WasmInst::I64Mul,
+                    },
+                    WasmInst::I64Const(0xFFFF_FFFF),
+                    WasmInst::I64GtU,
+                ]);
+            }
+            (BinOp::SubOvfUn, _) => {
+                self.get(lhs);
+                self.get(rhs);
+                self.f.instruction(if wide {
+                    WasmInst::I64LtU
+                } else {
+                    WasmInst::I32LtU
+                });
+            }
+            // a > !b
+            (BinOp::AddOvfUn, true) => {
+                self.get(lhs);
+                self.get(rhs);
+                self.emit([WasmInst::I64Const(-1), WasmInst::I64Xor, WasmInst::I64GtU]);
+            }
+            // a > u64::MAX / b, dividing by 1 when b is 0
+            (BinOp::MulOvfUn, true) => {
+                self.get(lhs);
+                self.f.instruction(WasmInst::I64Const(-1));
+                self.get(rhs);
+                self.get(rhs);
+                self.emit([
+                    WasmInst::I64Eqz,
+                    WasmInst::I64ExtendI32U,
+                    WasmInst::I64Or,
+                    WasmInst::I64DivU,
+                    WasmInst::I64GtU,
+                ]);
+            }
+            // b > 0 && a > MAX - b || b < 0 && a < MIN - b, and the mirror for sub
+            (BinOp::AddOvf | BinOp::SubOvf, true) => {
+                let (first, second) = match op {
+                    BinOp::AddOvf => (WasmInst::I64GtS, WasmInst::I64LtS),
+                    _ => (WasmInst::I64LtS, WasmInst::I64GtS),
+                };
+                let bound = match op {
+                    BinOp::AddOvf => WasmInst::I64Sub,
+                    _ => WasmInst::I64Add,
+                };
+                for (sign, limit, cmp) in [
+                    (first, i64::MAX, WasmInst::I64GtS),
+                    (second, i64::MIN, WasmInst::I64LtS),
+                ] {
+                    self.get(rhs);
+                    self.emit([WasmInst::I64Const(0), sign]);
+                    self.get(lhs);
+                    self.f.instruction(WasmInst::I64Const(limit));
+                    self.get(rhs);
+                    self.emit([bound, cmp, WasmInst::I32And]);
+                }
+                self.f.instruction(WasmInst::I32Or);
+            }
+            // b == -1 && a == MIN || a * b / b != a for b outside -1..=0, dividing by 1 there
+            (BinOp::MulOvf, true) => {
+                self.get(rhs);
+                self.emit([WasmInst::I64Const(-1), WasmInst::I64Eq]);
+                self.get(lhs);
+                self.emit([
+                    WasmInst::I64Const(i64::MIN),
+                    WasmInst::I64Eq,
+                    WasmInst::I32And,
+                ]);
+                self.get(rhs);
+                self.emit([
+                    WasmInst::I64Const(1),
+                    WasmInst::I64Add,
+                    WasmInst::I64Const(1),
+                    WasmInst::I64GtU,
+                ]);
+                self.get(lhs);
+                self.get(rhs);
+                self.f.instruction(WasmInst::I64Mul);
+                self.get(rhs);
+                self.f.instruction(WasmInst::I64Const(1));
+                self.get(rhs);
+                self.emit([
+                    WasmInst::I64Const(1),
+                    WasmInst::I64Add,
+                    WasmInst::I64Const(1),
+                    WasmInst::I64GtU,
+                    WasmInst::Select,
+                    WasmInst::I64DivS,
+                ]);
+                self.get(lhs);
+                self.emit([WasmInst::I64Neq, WasmInst::I32And, WasmInst::I32Or]);
+            }
+            _ => return,
+        }
+        self.fault_if(Fault::Overflow);
+    }
+
+    /// Raise `OverflowException` unless `value` truncated toward zero fits `ty`
+    fn check_convert(&mut self, ty: NumType, value: Value, from_unsigned: bool) {
+        let (lo, hi): (i128, i128) = match ty {
+            NumType::I1 => (i8::MIN.into(), i8::MAX.into()),
+            NumType::U1 => (0, u8::MAX.into()),
+            NumType::I2 => (i16::MIN.into(), i16::MAX.into()),
+            NumType::U2 => (0, u16::MAX.into()),
+            NumType::I4 | NumType::I => (i32::MIN.into(), i32::MAX.into()),
+            NumType::U4 | NumType::U => (0, u32::MAX.into()),
+            NumType::I8 => (i64::MIN.into(), i64::MAX.into()),
+            NumType::U8 => (0, u64::MAX.into()),
+            _ => unreachable!("conv.ovf.{}", ty.suffix()),
+        };
+
+        let from = self.func.value_type(value);
+        match from {
+            // `v - lo > hi - lo` as unsigned, within the range of the source
+            IrType::I32 | IrType::Ptr | IrType::I64 => {
+                let wide = from == IrType::I64;
+                let (from_lo, from_hi): (i128, i128) = match (wide, from_unsigned) {
+                    (false, false) => (i32::MIN.into(), i32::MAX.into()),
+                    (false, true) => (0, u32::MAX.into()),
+                    (true, false) => (i64::MIN.into(), i64::MAX.into()),
+                    (true, true) => (0, u64::MAX.into()),
+                };
+                if lo <= from_lo && from_hi <= hi {
+                    return;
+                }
+                let (lo, hi) = (lo.max(from_lo), hi.min(from_hi));
+                self.get(value);
+                if wide {
+                    self.emit([
+                        WasmInst::I64Const(lo as i64),
+                        WasmInst::I64Sub,
+                        WasmInst::I64Const((hi - lo) as u64 as i64),
+                        WasmInst::I64GtU,
+                    ]);
+                } else {
+                    self.emit([
+                        WasmInst::I32Const(lo as i32),
+                        WasmInst::I32Sub,
+                        WasmInst::I32Const((hi - lo) as u32 as i32),
+                        WasmInst::I32GtU,
+                    ]);
+                }
+            }
+            // lo - 1 < v < hi + 1, NaN fails both. -2^63 - 1 isn't a float64 so
+            // that bound is inclusive instead
+            IrType::F32 | IrType::F64 => {
+                let lower = if lo == i64::MIN.into() {
+                    [WasmInst::F64Const(lo as f64), WasmInst::F64Ge]
+                } else {
+                    [WasmInst::F64Const((lo - 1) as f64), WasmInst::F64Gt]
+                };
+                self.float(value, true);
+                self.emit(lower);
+                self.float(value, true);
+                self.emit([
+                    WasmInst::F64Const((hi + 1) as f64),
+                    WasmInst::F64Lt,
+                    WasmInst::I32And,
+                    WasmInst::I32Eqz,
+                ]);
+            }
+            _ => unreachable!("conv.ovf.{} from {}", ty.suffix(), from),
+        }
+        self.fault_if(Fault::Overflow);
+    }
+
+    fn compare(&mut self, cmp: Cmp, lhs: Value, rhs: Value) {
+        let types = (self.func.value_type(lhs), self.func.value_type(rhs));
+        let is_float = |ty| matches!(ty, IrType::F32 | IrType::F64);
+
+        if is_float(types.0) || is_float(types.1) {
+            // F on the stack is a single type, float32 operands widen when mixed
+            let wide = types.0 == IrType::F64 || types.1 == IrType::F64;
+            for (value, ty) in [(lhs, types.0), (rhs, types.1)] {
+                self.get(value);
+                if wide && ty == IrType::F32 {
+                    self.f.instruction(WasmInst::F64PromoteF32);
+                }
+            }
+            // unordered forms are the negated ordered opposite
+            let (inst, negate) = match (cmp, wide) {
+                (Cmp::Eq, false) => (WasmInst::F32Eq, false),
+                (Cmp::NeUn, false) => (WasmInst::F32Neq, false),
+                (Cmp::Lt, false) => (WasmInst::F32Lt, false),
+                (Cmp::Le, false) => (WasmInst::F32Le, false),
+                (Cmp::Gt, false) => (WasmInst::F32Gt, false),
+                (Cmp::Ge, false) => (WasmInst::F32Ge, false),
+                (Cmp::LtUn, false) => (WasmInst::F32Ge, true),
+                (Cmp::LeUn, false) => (WasmInst::F32Gt, true),
+                (Cmp::GtUn, false) => (WasmInst::F32Le, true),
+                (Cmp::GeUn, false) => (WasmInst::F32Lt, true),
+                (Cmp::Eq, true) => (WasmInst::F64Eq, false),
+                (Cmp::NeUn, true) => (WasmInst::F64Neq, false),
+                (Cmp::Lt, true) => (WasmInst::F64Lt, false),
+                (Cmp::Le, true) => (WasmInst::F64Le, false),
+                (Cmp::Gt, true) => (WasmInst::F64Gt, false),
+                (Cmp::Ge, true) => (WasmInst::F64Ge, false),
+                (Cmp::LtUn, true) => (WasmInst::F64Ge, true),
+                (Cmp::LeUn, true) => (WasmInst::F64Gt, true),
+                (Cmp::GtUn, true) => (WasmInst::F64Le, true),
+                (Cmp::GeUn, true) => (WasmInst::F64Lt, true),
+            };
+            self.f.instruction(inst);
+            if negate {
+                self.f.instruction(WasmInst::I32Eqz);
+            }
+            return;
+        }
+
+        let wide = match types {
+            (IrType::I64, IrType::I64) => true,
+            (IrType::I32 | IrType::Ptr, IrType::I32 | IrType::Ptr) => false,
+            _ => unreachable!("compare {} and {}, rejected by ir::lower", types.0, types.1),
+        };
+        self.get(lhs);
+        self.get(rhs);
+        self.f.instruction(match (cmp, wide) {
+            (Cmp::Eq, false) => WasmInst::I32Eq,
+            (Cmp::NeUn, false) => WasmInst::I32Neq,
+            (Cmp::Lt, false) => WasmInst::I32LtS,
+            (Cmp::LtUn, false) => WasmInst::I32LtU,
+            (Cmp::Le, false) => WasmInst::I32LeS,
+            (Cmp::LeUn, false) => WasmInst::I32LeU,
+            (Cmp::Gt, false) => WasmInst::I32GtS,
+            (Cmp::GtUn, false) => WasmInst::I32GtU,
+            (Cmp::Ge, false) => WasmInst::I32GeS,
+            (Cmp::GeUn, false) => WasmInst::I32GeU,
+            (Cmp::Eq, true) => WasmInst::I64Eq,
+            (Cmp::NeUn, true) => WasmInst::I64Neq,
+            (Cmp::Lt, true) => WasmInst::I64LtS,
+            (Cmp::LtUn, true) => WasmInst::I64LtU,
+            (Cmp::Le, true) => WasmInst::I64LeS,
+            (Cmp::LeUn, true) => WasmInst::I64LeU,
+            (Cmp::Gt, true) => WasmInst::I64GtS,
+            (Cmp::GtUn, true) => WasmInst::I64GtU,
+            (Cmp::Ge, true) => WasmInst::I64GeS,
+            (Cmp::GeUn, true) => WasmInst::I64GeU,
+        });
+    }
+}
+
+/// Reason an image can't be compiled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompileError {
+    /// Mixed-mode image with native method bodies, which have no IL
+    NativeMethods(usize),
+    /// IL which would otherwise turn into invalid wasm
+    Verification(Vec<VerifyError>),
+    /// Verified IL using something the compiler doesn't support yet
+    Lower(LowerError),
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NativeMethods(n) => write!(
+                f,
+                "mixed-mode image has {} native method bodies which can't be compiled",
+                n
+            ),
+            Self::Verification(errors) => {
+                write!(f, "IL verification failed")?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+            Self::Lower(error) => write!(f, "can't compile method {}", error),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Compiled module and the problems which didn't stop compilation
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub wasm: Vec<u8>,
+    pub warnings: Vec<String>,
+}
+
+pub fn compile(image: &Image) -> Result<Output, CompileError> {
+    compile_with(image, &Options::default())
+}
+
+pub fn compile_with(image: &Image, options: &Options) -> Result<Output, CompileError> {
     let root = image.metadata_root();
-    let mut ctx = WasmContext::new(root);
+    let mut warnings = Vec::new();
+
+    // IL is kept next to precompiled code, but native method bodies have no IL to compile
+    if image.is_ready_to_run() {
+        warnings.push("ReadyToRun image, precompiled native code is ignored".to_string());
+    }
+    if image.is_mixed_mode() {
+        let native_methods = image.native_methods();
+        if !native_methods.is_empty() {
+            return Err(CompileError::NativeMethods(native_methods.len()));
+        }
+        warnings.push("Image is not IL only".to_string());
+    }
+    let errors = verify(image);
+    if !errors.is_empty() {
+        return Err(CompileError::Verification(errors));
+    }
+    let mut ctx = WasmContext::new(image, options);
     let table = &root.metadata_stream.table;
 
+    // every method has a wasm type before any body is lowered
+    for (method_index, _) in table.list_method_def() {
+        ir::method_signature(method_index, root).map_err(CompileError::Lower)?;
+    }
+
     for (index, member_ref) in table.list_member_ref() {
-        ctx.emit_wasm_member_ref(index, member_ref, table, root.heap);
+        if Intrinsic::of(MetadataToken::MemberRef(index), root).is_some() {
+            continue;
+        }
+        ctx.emit_wasm_member_ref(index, member_ref, root);
     }
+    ctx.emit_wasm_runtime();
 
     for (ty_index, ty_def) in table.list_type_def() {
-        ctx.emit_wasm_type_header(ty_index, ty_def, table, root.heap);
+        ctx.emit_wasm_type_header(ty_index, ty_def, root);
     }
 
-    for (_method_index, method_def) in table.list_method_def() {
-        ctx.emit_wasm_function_body(&method_def.resolve_body(image), table, root.heap);
+    for (method_index, method_def) in table.list_method_def() {
+        ctx.emit_wasm_function_body(method_index, &method_def.resolve_body(image), root)
+            .map_err(CompileError::Lower)?;
     }
 
-    ctx.finish()
+    match image.entry_point() {
+        Some(EntryPoint::Method(index)) => ctx.emit_wasm_entry_point(index, root),
+        Some(other) => warnings.push(format!(
+            "Unsupported entry point {}, no _start emitted",
+            other.display_with(root)
+        )),
+        None => {}
+    }
+
+    Ok(Output {
+        wasm: ctx.finish(),
+        warnings,
+    })
 }
 
 pub fn dump(image: &Image) {
-    let root = image.metadata_root();
-    let table = &root.metadata_stream.table;
+    print!("{}", clrs_pe::cil::disasm::disassemble(image));
+}
+
+/// Operators of each method body, skipping the runtime
+#[cfg(test)]
+fn operators(wasm: &[u8]) -> Vec<Vec<wasmparser::Operator<'_>>> {
+    use wasmparser::{Parser, Payload};
+
+    Parser::new(0)
+        .parse_all(wasm)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::CodeSectionEntry(body) => Some(body),
+            _ => None,
+        })
+        // after the allocator and fmod
+        .skip(2)
+        .map(|body| {
+            body.get_operators_reader()
+                .unwrap()
+                .into_iter()
+                .map(Result::unwrap)
+                .collect()
+        })
+        .collect()
+}
+
+/// Initial pages of the memory and initial values of the globals
+#[cfg(test)]
+fn memory_layout(wasm: &[u8]) -> (u64, Vec<i32>) {
+    use wasmparser::{Operator, Parser, Payload};
+
+    let mut pages = 0;
+    let mut globals = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.unwrap() {
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    pages = memory.unwrap().initial;
+                }
+            }
+            Payload::GlobalSection(reader) => {
+                for global in reader {
+                    let mut init = global.unwrap().init_expr.get_operators_reader();
+                    match init.read().unwrap() {
+                        Operator::I32Const { value } => globals.push(value),
+                        other => panic!("{:?}", other),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    (pages, globals)
+}
+
+/// Instantiate a module without imports
+#[cfg(test)]
+fn instantiate(wasm: &[u8]) -> (wasmi::Store<()>, wasmi::Instance) {
+    let mut config = wasmi::Config::default();
+    // the shadow stack runs out long before the call stack
+    config.set_stack_limits(wasmi::StackLimits::new(1024, 1024 * 1024, 100_000).unwrap());
+    let engine = wasmi::Engine::new(&config);
+    let module = wasmi::Module::new(&engine, wasm).unwrap();
+    let mut store = wasmi::Store::new(&engine, ());
+    let instance = wasmi::Linker::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    (store, instance)
+}
+
+#[test]
+fn compile_locals() {
+    use wasmparser::Operator;
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/locals.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+
+    // `count` follows the three words of `text`
+    let count = &operators(&wasm)[0];
+    assert!(count
+        .iter()
+        .any(|op| matches!(op, Operator::LocalGet { local_index: 3 })));
+    // `ldloca` moves `n` into the stack frame
+    assert!(matches!(
+        &count[..3],
+        [
+            Operator::GlobalGet { global_index: 0 },
+            Operator::I32Const { value: 4 },
+            Operator::I32Sub,
+        ]
+    ));
+}
+
+#[test]
+fn compile_arithmetic() {
+    use wasmparser::Operator;
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/arithmetic.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+
+    let bodies = operators(&wasm);
+    // `Operator` has no `PartialEq`
+    let contains = |n: usize, ops: &[Operator]| {
+        let expected = format!("{:?}", ops);
+        bodies[n]
+            .windows(ops.len())
+            .any(|w| format!("{:?}", w) == expected)
+    };
+    // `conv.i1` sign extends, `conv.u2` masks after wrapping the int64
+    assert!(contains(0, &[Operator::I32Extend8S]));
+    assert!(contains(
+        1,
+        &[
+            Operator::I32WrapI64,
+            Operator::I32Const { value: 0xFFFF },
+            Operator::I32And,
+        ]
+    ));
+    // the int32 shift amount widens for an int64 shift
+    assert!(contains(1, &[Operator::I64ExtendI32U, Operator::I64Shl]));
+    assert!(contains(2, &[Operator::F64ConvertI32U]));
+    assert!(contains(3, &[Operator::I32TruncSatF64S]));
+    assert!(contains(3, &[Operator::I32GtU]));
+    assert!(contains(4, &[Operator::I32WrapI64]));
+}
+
+#[test]
+fn compile_checked() {
+    use wasmparser::Operator;
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/checked.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+
+    // without exceptions every check ends in a trap
+    let wasm = compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+    assert!(operators(&wasm)
+        .iter()
+        .flatten()
+        .all(|op| !matches!(op, Operator::Call { .. })));
+
+    let wasm = compile_with(&image, &Options { exceptions: true })
+        .unwrap()
+        .wasm;
+    wasmparser::validate(&wasm).unwrap();
+    let bodies = operators(&wasm);
+    let calls = |n: usize| {
+        bodies[n]
+            .iter()
+            .filter_map(|op| match op {
+                Operator::Call { function_index } => Some(*function_index),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    // hooks come first, in `Fault::ALL` order
+    assert_eq!(calls(0), [0]);
+    // `rem` checks for zero, then for `MIN % -1`
+    assert_eq!(calls(8), [1, 0]);
+    assert_eq!(calls(15), [2]);
+}
+
+#[test]
+fn compile_fields() {
+    use wasmparser::Operator;
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/fields.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile(&image).unwrap().wasm;
+    wasmparser::validate(&wasm).unwrap();
+
+    let bodies = operators(&wasm);
+    let has = |n: usize, op: &str| bodies[n].iter().any(|o| format!("{:?}", o).starts_with(op));
+    // the header comes before `Value`, small fields keep their width and sign
+    assert!(has(
+        2,
+        "I32Load { memarg: MemoryImmediate { align: 2, offset: 4"
+    ));
+    assert!(has(3, "I32Store16"));
+    assert!(has(3, "I32Load16S"));
+    assert!(has(4, "I32Store8"));
+    assert!(has(4, "I32Load8U"));
+    assert!(has(5, "F32DemoteF64"));
+    // `Seed` keeps its `FieldRVA` bytes after their length prefix, `Total` follows it
+    assert!(has(
+        0,
+        "I32Load { memarg: MemoryImmediate { align: 2, offset: 12"
+    ));
+    assert!(has(
+        6,
+        "I64Load { memarg: MemoryImmediate { align: 3, offset: 16"
+    ));
+    assert!(matches!(
+        &bodies[7][..],
+        [Operator::I32Const { value: 12 }, ..]
+    ));
+}
 
-    for (_index, method) in table.list_method_def() {
-        let name = method.name.resolve(root.heap).unwrap();
-        let signature = method.resolve_signature(root.heap);
-        println!("{}: {:?}", name, signature);
+#[test]
+fn compile_arrays() {
+    use wasmparser::{Operator, Parser, Payload};
+
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/arrays.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile_with(&image, &Options { exceptions: true })
+        .unwrap()
+        .wasm;
+    wasmparser::validate(&wasm).unwrap();
+
+    // `InitializeArray` is not imported, only the runtime hooks are
+    let imports = Parser::new(0)
+        .parse_all(&wasm)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::ImportSection(reader) => Some(reader.get_count()),
+            _ => None,
+        })
+        .sum::<u32>();
+    assert_eq!(imports as usize, Fault::ALL.len());
+
+    let bodies = operators(&wasm);
+    let has = |n: usize, op: &str| bodies[n].iter().any(|o| format!("{:?}", o).starts_with(op));
+    assert!(has(0, "MemoryCopy"));
+    assert!(has(2, "I32Load8S"));
+    assert!(has(3, "F32DemoteF64"));
+    // out of range indices raise `IndexOutOfRangeException`
+    assert!(bodies[2]
+        .iter()
+        .any(|op| matches!(op, Operator::Call { function_index: 3 })));
+}
+
+#[test]
+fn compile_mixed_mode() {
+    let il = include_str!("../../tests/il/hello.il");
+    let assemble = |il: &str| {
+        let mut bytes = clrs_pe::cil::asm::assemble(il).unwrap();
+        // clear `COMIMAGE_FLAGS_ILONLY` after the size and version of the CLI header
+        let cli_header = bytes
+            .windows(8)
+            .position(|w| w == [0x48, 0, 0, 0, 2, 0, 5, 0])
+            .unwrap();
+        bytes[cli_header + 16] &= !1;
+        bytes
+    };
+
+    let bytes = assemble(il);
+    let image = Image::from_bytes(&bytes).unwrap();
+    let output = compile(&image).unwrap();
+    assert_eq!(output.warnings, ["Image is not IL only"]);
+    wasmparser::validate(&output.wasm).unwrap();
+
+    let native = ".method public static void Native() native unmanaged {}\n  .method";
+    let bytes = assemble(&il.replacen(".method", native, 1));
+    let image = Image::from_bytes(&bytes).unwrap();
+    assert_eq!(compile(&image).unwrap_err(), CompileError::NativeMethods(1));
+}
+
+#[test]
+fn compile_unverifiable() {
+    let il = include_str!("../../tests/il/hello.il").replacen("ret", "add\n    ret", 1);
+    let bytes = clrs_pe::cil::asm::assemble(&il).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let error = compile(&image).unwrap_err();
+    match &error {
+        CompileError::Verification(errors) => assert_eq!(errors.len(), 1),
+        other => panic!("{:?}", other),
+    }
+    assert!(error
+        .to_string()
+        .starts_with("IL verification failed\n[06000001] IL_"));
+}
+
+#[test]
+fn compile_unsupported() {
+    let lower_error = |members: &str| {
+        let source = format!(
+            "
+            .assembly extern mscorlib {{ .ver 4:0:0:0 }}
+            .assembly unsupported {{ .ver 0:0:0:0 }}
+
+            .class public sequential ansi sealed Pair extends [mscorlib]System.ValueType
+            {{
+              .field public int32 A
+
+              .method public specialname rtspecialname instance void .ctor() cil managed
+              {{
+                ret
+              }}
+            }}
+
+            .class public Failure extends [mscorlib]System.Exception
+            {{
+              .method public specialname rtspecialname instance void .ctor() cil managed
+              {{
+                ret
+              }}
+            }}
+
+            .class public Program extends [mscorlib]System.Object
+            {{
+              {}
+            }}
+            ",
+            members
+        );
+        let bytes = clrs_pe::cil::asm::assemble(&source).unwrap();
+        let image = Image::from_bytes(&bytes).unwrap();
+        match compile(&image) {
+            Err(CompileError::Lower(error)) => (error.offset, error.message),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    };
+
+    // reference equality of multi-word strings has no lowering
+    let same = ".method public static bool Same(string a, string b) cil managed
+        {
+          ldarg.0
+          ldarg.1
+          ceq
+          ret
+        }";
+    assert_eq!(
+        lower_error(same),
+        (2, "compare string and string".to_string())
+    );
+
+    let param = ".method public static int32 First(valuetype Pair p) cil managed
+        {
+          ldc.i4.0
+          ret
+        }";
+    assert_eq!(
+        lower_error(param),
+        (0, "unsupported signature int32(valuetype Pair)".to_string())
+    );
+
+    let local = ".method public static int32 Zero() cil managed
+        {
+          .locals init (valuetype Pair p)
+          ldc.i4.0
+          ret
+        }";
+    assert_eq!(
+        lower_error(local),
+        (0, "unsupported valuetype Pair".to_string())
+    );
+
+    let vararg = ".method public static vararg void Log() cil managed
+        {
+          ret
+        }
+
+        .method public static void Main() cil managed
+        {
+          ldc.i4.1
+          call vararg void Program::Log(..., int32)
+          ret
+        }";
+    assert_eq!(lower_error(vararg).0, 1);
+
+    // neither a value type nor a class with a base from another assembly has an object layout
+    let pair = ".method public static void Make() cil managed
+        {
+          newobj instance void Pair::.ctor()
+          pop
+          ret
+        }";
+    assert_eq!(
+        lower_error(pair),
+        (
+            0,
+            "unsupported newobj instance void Pair::.ctor()".to_string()
+        )
+    );
+    let failure = ".method public static void Fail() cil managed
+        {
+          newobj instance void Failure::.ctor()
+          pop
+          ret
+        }";
+    assert_eq!(
+        lower_error(failure),
+        (
+            0,
+            "unsupported newobj instance void Failure::.ctor()".to_string()
+        )
+    );
+
+    // fields of other assemblies and of value types have no address, value type fields have
+    // no lowering
+    let empty = ".method public static string Empty() cil managed
+        {
+          ldsfld string [mscorlib]System.String::Empty
+          ret
+        }";
+    assert_eq!(
+        lower_error(empty),
+        (
+            0,
+            "unsupported ldsfld string [mscorlib]System.String::Empty".to_string()
+        )
+    );
+    let first = ".method public static int32 First(valuetype Pair& p) cil managed
+        {
+          ldarg.0
+          ldc.i4.1
+          stfld int32 Pair::A
+          ldarg.0
+          ldfld int32 Pair::A
+          ret
+        }";
+    assert_eq!(
+        lower_error(first),
+        (2, "unsupported stfld int32 Pair::A".to_string())
+    );
+    let held = ".field public valuetype Pair Held
+
+        .method public static int32 Held(class Program p) cil managed
+        {
+          ldarg.0
+          ldfld valuetype Pair Program::Held
+          pop
+          ldc.i4.0
+          ret
+        }";
+    assert_eq!(
+        lower_error(held),
+        (
+            1,
+            "unsupported ldfld valuetype Pair Program::Held".to_string()
+        )
+    );
+}
+
+#[test]
+fn compile_stack_overflow() {
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly stack { .ver 0:0:0:0 }
+
+        .class public Program extends [mscorlib]System.Object
+        {
+          .method public static int32 Deep(int32 n) cil managed
+          {
+            .locals init (int32 x)
+            ldloca.s 0
+            pop
+            ldarg.0
+            brfalse.s Done
+            ldarg.0
+            ldc.i4.1
+            sub
+            call int32 Program::Deep(int32)
+            ret
+          Done:
+            ldc.i4.7
+            ret
+          }
+        }
+    ";
+    let bytes = clrs_pe::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let (mut store, instance) = instantiate(&compile(&image).unwrap().wasm);
+    let deep = instance
+        .get_typed_func::<i32, i32>(&store, "Program::Deep")
+        .unwrap();
+
+    // each frame takes 4 bytes of the 64 KiB stack
+    assert_eq!(deep.call(&mut store, 16_000).unwrap(), 7);
+    let error = deep.call(&mut store, 20_000).unwrap_err();
+    assert!(matches!(
+        error.trap_code(),
+        Some(wasmi::core::TrapCode::UnreachableCodeReached)
+    ));
+}
+
+#[test]
+fn compile_float_rem() {
+    use wasmi::core::{F32, F64};
+
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly rem { .ver 0:0:0:0 }
+
+        .class public Program extends [mscorlib]System.Object
+        {
+          .method public static float64 Rem(float64 a, float64 b) cil managed
+          {
+            ldarg.0
+            ldarg.1
+            rem
+            ret
+          }
+
+          .method public static float32 Rem32(float32 a, float32 b) cil managed
+          {
+            ldarg.0
+            ldarg.1
+            rem
+            ret
+          }
+        }
+    ";
+    let bytes = clrs_pe::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let (mut store, instance) = instantiate(&compile(&image).unwrap().wasm);
+    let rem = instance
+        .get_typed_func::<(F64, F64), F64>(&store, "Program::Rem")
+        .unwrap();
+    let rem32 = instance
+        .get_typed_func::<(F32, F32), F32>(&store, "Program::Rem32")
+        .unwrap();
+
+    // Rust's `%` is the exact fmod
+    let tiny = f64::from_bits(1);
+    for &(a, b) in &[
+        (5.5, 2.0),
+        (-5.5, 2.0),
+        (5.5, -2.0),
+        (5.5, f64::INFINITY),
+        (-5.5, f64::NEG_INFINITY),
+        (1e300, 3.0),
+        (-1e308, 1e-308),
+        (f64::MAX, f64::MIN_POSITIVE),
+        (1e-310, 3e-320),
+        (7.0 * tiny, 2.0 * tiny),
+        (0.1, 0.01),
+        (-0.0, 1.0),
+        (6.0, 3.0),
+        (-6.0, 3.0),
+        (1.0, 0.0),
+        (f64::INFINITY, 2.0),
+        (f64::NAN, 2.0),
+        (2.0, f64::NAN),
+    ] {
+        let result = f64::from(rem.call(&mut store, (a.into(), b.into())).unwrap());
+        let expected = a % b;
+        if expected.is_nan() {
+            assert!(result.is_nan(), "{} % {} = {}", a, b, result);
+        } else {
+            assert_eq!(result.to_bits(), expected.to_bits(), "{} % {}", a, b);
+        }
+    }
+
+    for &(a, b) in &[
+        (1e30f32, 7.0f32),
+        (-3.5, f32::INFINITY),
+        (5.0, 0.0),
+        (0.7, 0.1),
+    ] {
+        let result = f32::from(rem32.call(&mut store, (a.into(), b.into())).unwrap());
+        let expected = a % b;
+        if expected.is_nan() {
+            assert!(result.is_nan(), "{} % {} = {}", a, b, result);
+        } else {
+            assert_eq!(result.to_bits(), expected.to_bits(), "{} % {}", a, b);
+        }
     }
 }
+
+#[test]
+fn compile_memory_layout() {
+    let bytes = clrs_pe::cil::asm::assemble(include_str!("../../tests/il/hello.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile(&image).unwrap().wasm;
+
+    // "Hello, World!" from address 1, then the stack up to the heap, both in the first two pages
+    let heap_base = 16 + STACK_SIZE as i32;
+    assert_eq!(memory_layout(&wasm), (2, vec![heap_base, heap_base]));
+}
+
+#[test]
+fn compile_large_data() {
+    // 80 KiB of field data, so the statics after it are past the first page
+    let blob = "00 ".repeat(80 * 1024);
+    let source = format!(
+        "
+        .assembly extern mscorlib {{ .ver 4:0:0:0 }}
+        .assembly large {{ .ver 0:0:0:0 }}
+
+        .data D_BLOB = bytearray ({})
+        .data D_X = bytearray (D2 04 00 00)
+
+        .class private explicit ansi sealed Blob extends [mscorlib]System.ValueType
+        {{
+          .pack 1
+          .size 81920
+        }}
+
+        .class public Program extends [mscorlib]System.Object
+        {{
+          .field static assembly valuetype Blob blob at D_BLOB
+          .field static int32 X at D_X
+          .field static int32 Counter
+
+          .method public static int32 GetX() cil managed
+          {{
+            ldsfld int32 Program::X
+            ret
+          }}
+
+          .method public static int32 Bump() cil managed
+          {{
+            ldsfld int32 Program::Counter
+            ldc.i4.1
+            add
+            dup
+            stsfld int32 Program::Counter
+            ret
+          }}
+        }}
+        ",
+        blob
+    );
+    let bytes = clrs_pe::cil::asm::assemble(&source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let wasm = compile(&image).unwrap().wasm;
+
+    // the stack and heap start above all the data, which the initial pages cover
+    let (pages, globals) = memory_layout(&wasm);
+    let heap_base = globals[1] as u64;
+    assert!(heap_base > 80 * 1024 + STACK_SIZE as u64);
+    assert_eq!(globals[0] as u64, heap_base);
+    assert_eq!(pages, (heap_base + 0xFFFF) >> 16);
+
+    let (mut store, instance) = instantiate(&wasm);
+    let get_x = instance
+        .get_typed_func::<(), i32>(&store, "Program::GetX")
+        .unwrap();
+    let bump = instance
+        .get_typed_func::<(), i32>(&store, "Program::Bump")
+        .unwrap();
+    assert_eq!(get_x.call(&mut store, ()).unwrap(), 1234);
+    assert_eq!(bump.call(&mut store, ()).unwrap(), 1);
+    assert_eq!(bump.call(&mut store, ()).unwrap(), 2);
+    assert_eq!(get_x.call(&mut store, ()).unwrap(), 1234);
+}
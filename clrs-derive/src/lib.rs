@@ -3,13 +3,78 @@ extern crate proc_macro;
 use quote::quote;
 use syn::{braced, parse_macro_input, punctuated::Punctuated};
 
+fn is_list_field(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|attr| {
+        attr.path.is_ident("clr")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|arg| arg == "list")
+    })
+}
+
 fn impl_struct(
     name: &syn::Ident,
     fields: &syn::punctuated::Punctuated<syn::Field, syn::token::Comma>,
 ) -> proc_macro2::TokenStream {
+    let checks = fields.iter().map(|f| {
+        let ident = f.ident.as_ref().unwrap();
+        let check_fn = if is_list_field(f) {
+            quote!(check_list_field)
+        } else {
+            quote!(check_field)
+        };
+
+        quote! {
+            if let Some(msg) = CheckField::#check_fn(&self.#ident, table, heap) {
+                out.push((stringify!(#ident), msg));
+            }
+        }
+    });
+
+    let field_count = fields.len();
+    let serialize_fields = fields.iter().map(|f| {
+        let ident = f.ident.as_ref().unwrap();
+        quote! {
+            state.serialize_field(stringify!(#ident), &self.#ident)?;
+        }
+    });
+
+    // signature blobs are decoded by the kind of row holding them
+    let json_fields = fields.iter().map(|f| {
+        let ident = f.ident.as_ref().unwrap();
+        let value = if ident == "signature" || ident == "instantiation" {
+            quote!(crate::pe::signature_to_json(stringify!(#name), self.#ident, names))
+        } else {
+            quote!(crate::pe::ToJson::to_json(&self.#ident, names))
+        };
+        quote! {
+            map.insert(stringify!(#ident).into(), #value);
+        }
+    });
+
     let fields = fields.iter().map(|f| f.ident.as_ref().unwrap());
 
     quote! {
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for #name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ::serde::ser::SerializeStruct;
+
+                let mut state = serializer.serialize_struct(stringify!(#name), #field_count)?;
+                #(#serialize_fields)*
+                state.end()
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl crate::pe::ToJson for #name {
+            fn to_json(&self, names: &crate::cil::disasm::Names) -> ::serde_json::Value {
+                let mut map = ::serde_json::Map::new();
+                #(#json_fields)*
+                ::serde_json::Value::Object(map)
+            }
+        }
+
         impl<'a> ::scroll::ctx::TryFromCtx<'a, PeCtx> for #name {
             type Error = ::scroll::Error;
 
@@ -25,6 +90,46 @@ fn impl_struct(
                 Ok((s, *offset))
             }
         }
+
+        impl CheckRow for #name {
+            fn check_row(&self, table: &MetadataTable, heap: Heap) -> Vec<(&'static str, String)> {
+                let mut out = Vec::new();
+                #(#checks)*
+                out
+            }
+        }
+
+        impl CheckField for #name {
+            fn check_field(&self, table: &MetadataTable, heap: Heap) -> Option<String> {
+                self.check_row(table, heap).into_iter().next().map(|(field, msg)| format!("{}: {}", field, msg))
+            }
+        }
+    }
+}
+
+fn impl_try_into_ctx(input: &syn::DeriveInput) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields.named.iter().map(|f| f.ident.as_ref().unwrap()),
+        _ => panic!("Only named struct supported"),
+    };
+
+    quote! {
+        impl ::scroll::ctx::TryIntoCtx<PeCtx> for #name {
+            type Error = ::scroll::Error;
+
+            fn try_into_ctx(self, dst: &mut [u8], ctx: PeCtx) -> Result<usize, Self::Error> {
+                use ::scroll::Pwrite;
+
+                let offset = &mut 0;
+                #( dst.gwrite_with(self.#fields, offset, ctx)?; )*
+
+                Ok(*offset)
+            }
+        }
     }
 }
 
@@ -47,11 +152,16 @@ fn impl_try_from_ctx(
     }
 }
 
-#[proc_macro_derive(ClrPread)]
+#[proc_macro_derive(ClrPread, attributes(clr))]
 pub fn derive_clr_pread(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     impl_try_from_ctx(&syn::parse_macro_input!(input as syn::DeriveInput)).into()
 }
 
+#[proc_macro_derive(ClrPwrite)]
+pub fn derive_clr_pwrite(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    impl_try_into_ctx(&syn::parse_macro_input!(input as syn::DeriveInput)).into()
+}
+
 struct MakeTableInput {
     lines: Punctuated<
         (
@@ -169,6 +279,57 @@ pub fn make_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 }
             }
 
+            impl From<#index_ty_name> for u32 {
+                fn from(index: #index_ty_name) -> Self {
+                    index.0
+                }
+            }
+
+            impl CheckField for #index_ty_name {
+                fn check_field(&self, table: &MetadataTable, _heap: Heap) -> Option<String> {
+                    let len = table.#field.len();
+                    if self.0 as usize > len {
+                        Some(format!("{} row {} is out of {} rows", stringify!(#ty), self.0, len))
+                    } else {
+                        None
+                    }
+                }
+
+                fn check_list_field(&self, table: &MetadataTable, _heap: Heap) -> Option<String> {
+                    // list may point one past the last row when it is empty
+                    let len = table.#field.len();
+                    if self.0 == 0 || self.0 as usize > len + 1 {
+                        Some(format!("{} list start {} is out of {} rows", stringify!(#ty), self.0, len))
+                    } else {
+                        None
+                    }
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            impl ::serde::Serialize for #index_ty_name {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_u32(self.0)
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            impl crate::pe::ToJson for #index_ty_name {
+                fn to_json(&self, names: &Names) -> ::serde_json::Value {
+                    if self.0 == 0 {
+                        ::serde_json::Value::Null
+                    } else {
+                        MetadataToken::#ty(*self).to_json(names)
+                    }
+                }
+            }
+
+            impl DisplayWith for #index_ty_name {
+                fn fmt_with(&self, names: &Names, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    MetadataToken::#ty(*self).fmt_with(names, f)
+                }
+            }
+
             impl MetadataTable {
                 pub fn #list_fn_name(&self) -> impl Iterator<Item = (#index_ty_name, &#ty)> {
                     self.#field.iter().enumerate().map(|(i, v)| (#index_ty_name((i as u32) + 1), v))
@@ -184,6 +345,14 @@ pub fn make_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 }
             }
 
+            impl ::scroll::ctx::TryIntoCtx<PeCtx> for #index_ty_name {
+                type Error = scroll::Error;
+
+                fn try_into_ctx(self, dst: &mut [u8], ctx: PeCtx) -> Result<usize, Self::Error> {
+                    ::scroll::Pwrite::pwrite_with(dst, self.0 as u16, 0, ctx)
+                }
+            }
+
             impl TableIndex<#ty> for #index_ty_name {
                 fn resolve_table(self, table: &MetadataTable) -> Option<&#ty> {
                     // row index is one based zero means `NULL`
@@ -193,6 +362,100 @@ pub fn make_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         }
     });
 
+    let valid_bits = lines.iter().map(|(field, .., expr)| {
+        quote! {
+            if !self.#field.is_empty() {
+                valid |= 1 << #expr;
+            }
+        }
+    });
+
+    let row_counts = lines.iter().map(|(field, ..)| {
+        quote! {
+            if !self.#field.is_empty() {
+                push_with(&mut out, self.#field.len() as u32, ctx)?;
+            }
+        }
+    });
+
+    let write_rows = lines.iter().map(|(field, ..)| {
+        quote! {
+            for row in self.#field.iter() {
+                push_with(&mut out, *row, ctx)?;
+            }
+        }
+    });
+
+    let check_rows = lines.iter().map(|(field, _, ty, ..)| {
+        let list_fn_name = syn::Ident::new(&format!("list_{}", field), field.span());
+
+        quote! {
+            for (index, row) in self.#list_fn_name() {
+                for (field, msg) in row.check_row(self, heap) {
+                    out.push((MetadataToken::#ty(index), field, msg));
+                }
+            }
+        }
+    });
+
+    let raw_token_arms = lines
+        .iter()
+        .map(|(_, _, ty, _, expr)| {
+            quote! {
+                Self::#ty(index) => (#expr << 24) | u32::from(index),
+            }
+        })
+        .chain(add_tokens.iter().map(|(name, _, _, _, expr)| {
+            quote! {
+                Self::#name(index) => (#expr << 24) | u32::from(index),
+            }
+        }));
+
+    let table_count = lines.len();
+    let serialize_tables = lines.iter().map(|(field, ..)| {
+        quote! {
+            state.serialize_field(stringify!(#field), &self.#field)?;
+        }
+    });
+
+    let json_tables = lines.iter().map(|(field, _, ty, ..)| {
+        let list_fn_name = syn::Ident::new(&format!("list_{}", field), field.span());
+
+        quote! {
+            if !self.#field.is_empty() {
+                let rows = self
+                    .#list_fn_name()
+                    .map(|(index, row)| {
+                        let mut json = row.to_json(names);
+                        if let ::serde_json::Value::Object(map) = &mut json {
+                            map.insert("token".into(), MetadataToken::#ty(index).to_raw().into());
+                        }
+                        json
+                    })
+                    .collect();
+                map.insert(stringify!(#ty).into(), ::serde_json::Value::Array(rows));
+            }
+        }
+    });
+
+    let table_name_arms = lines
+        .iter()
+        .map(|(_, _, ty, ..)| quote!(Self::#ty(_) => stringify!(#ty),))
+        .chain(
+            add_tokens
+                .iter()
+                .map(|(name, ..)| quote!(Self::#name(_) => stringify!(#name),)),
+        );
+
+    let row_exists_arms = lines
+        .iter()
+        .map(|(_, _, ty, ..)| quote!(Self::#ty(index) => index.resolve_table(table).is_some(),))
+        .chain(
+            add_tokens
+                .iter()
+                .map(|(name, ..)| quote!(Self::#name(_) => false,)),
+        );
+
     let add_token_variants = add_tokens.iter().map(|(name, _, ty, ..)| {
         quote! {
             #name(#ty),
@@ -257,7 +520,7 @@ pub fn make_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         .chain(add_token_methods);
 
     (quote! {
-        #[derive(Clone, Debug)]
+        #[derive(Clone, Debug, Default)]
         pub struct MetadataTable {
             #(#fields)*
         }
@@ -285,13 +548,83 @@ pub fn make_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
         }
 
-        #[derive(Clone, Copy, Debug)]
+        impl MetadataTable {
+            /// Bitvecs, row counts and rows of the #~ stream, the reverse of `try_from_ctx`
+            pub fn write_tables(&self, sorted: u64, ctx: PeCtx) -> Result<Vec<u8>, scroll::Error> {
+                let mut out = Vec::new();
+                let mut valid: u64 = 0;
+                #(#valid_bits)*
+
+                push_with(&mut out, valid, ctx)?;
+                push_with(&mut out, sorted, ctx)?;
+                #(#row_counts)*
+                #(#write_rows)*
+
+                Ok(out)
+            }
+        }
+
+        impl MetadataTable {
+            /// Check every field of every row, returns offending token, field name and message
+            pub fn check_rows(&self, heap: Heap) -> Vec<(MetadataToken, &'static str, String)> {
+                let mut out = Vec::new();
+                #(#check_rows)*
+                out
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for MetadataTable {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ::serde::ser::SerializeStruct;
+
+                let mut state = serializer.serialize_struct("MetadataTable", #table_count)?;
+                #(#serialize_tables)*
+                state.end()
+            }
+        }
+
+        /// Non-empty tables by name, each a list of rows
+        #[cfg(feature = "serde")]
+        impl crate::pe::ToJson for MetadataTable {
+            fn to_json(&self, names: &Names) -> ::serde_json::Value {
+                let mut map = ::serde_json::Map::new();
+                #(#json_tables)*
+                ::serde_json::Value::Object(map)
+            }
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
         pub enum MetadataToken {
             #(#token_variants)*
         }
 
         impl MetadataToken {
             #(#token_methods)*
+
+            /// Name of the table or heap
+            pub fn table_name(self) -> &'static str {
+                match self {
+                    #(#table_name_arms)*
+                }
+            }
+
+            /// Whether the token is a row of `table`, tokens of other tables and heaps never are
+            pub fn row_exists(self, table: &MetadataTable) -> bool {
+                match self {
+                    #(#row_exists_arms)*
+                }
+            }
+
+            pub fn to_raw(self) -> u32 {
+                match self {
+                    #(#raw_token_arms)*
+                }
+            }
+
+            pub fn from_raw(token: u32) -> Option<Self> {
+                token.to_le_bytes().pread_with(0, ::scroll::LE).ok()
+            }
         }
 
         impl<'a> TryFromCtx<'a, ::scroll::Endian> for MetadataToken {
@@ -1,38 +1,339 @@
 use scroll::Pread;
 use scroll::{ctx::TryFromCtx, Endian};
 
+use crate::pe::{MetadataToken, StandAloneSigIndex};
+
+pub mod asm;
+pub mod cfg;
+pub mod disasm;
 mod opcode;
+pub mod verify;
+
+pub use self::opcode::{Form, Instruction, NumType};
 
-pub use self::opcode::Instruction;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExceptionClauseKind {
+    Catch(MetadataToken),
+    /// Offset of the filter block
+    Filter(u32),
+    Finally,
+    Fault,
+}
+
+/// II.25.4.6
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExceptionClause {
+    pub kind: ExceptionClauseKind,
+    pub try_offset: u32,
+    pub try_length: u32,
+    pub handler_offset: u32,
+    pub handler_length: u32,
+}
 
 #[derive(Debug)]
 pub struct MethodBody {
+    pub max_stack: u16,
+    pub init_locals: bool,
+    pub local_var_sig: Option<StandAloneSigIndex>,
+    pub code_size: u32,
     pub instructions: Vec<Instruction>,
+    /// IL offset of each instruction
+    pub offsets: Vec<u32>,
+    pub exception_clauses: Vec<ExceptionClause>,
+}
+
+impl MethodBody {
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Instruction)> {
+        self.offsets.iter().copied().zip(self.instructions.iter())
+    }
+
+    /// Offset of the instruction after `index`
+    pub fn next_offset(&self, index: usize) -> u32 {
+        self.offsets
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.code_size)
+    }
 }
 
+impl MethodBody {
+    /// Header, code and exception sections.
+    /// Instruction forms are picked so that they land at `offsets`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut code = Vec::new();
+        for (i, (offset, inst)) in self.iter().enumerate() {
+            inst.encode(inst.form(self.next_offset(i) - offset), &mut code);
+        }
+
+        let mut out = Vec::new();
+        let is_tiny = code.len() < 64
+            && self.max_stack <= 8
+            && self.local_var_sig.is_none()
+            && self.exception_clauses.is_empty();
+
+        if is_tiny {
+            out.push(((code.len() as u8) << 2) | 0b10);
+            out.extend_from_slice(&code);
+            return out;
+        }
+
+        let mut flags = FAT_FORMAT | (3 << 12);
+        if self.init_locals {
+            flags |= INIT_LOCALS;
+        }
+        if !self.exception_clauses.is_empty() {
+            flags |= MORE_SECTS;
+        }
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&self.max_stack.to_le_bytes());
+        out.extend_from_slice(&(code.len() as u32).to_le_bytes());
+        let token = self
+            .local_var_sig
+            .map(|sig| MetadataToken::StandAloneSig(sig).to_raw())
+            .unwrap_or(0);
+        out.extend_from_slice(&token.to_le_bytes());
+        out.extend_from_slice(&code);
+
+        if self.exception_clauses.is_empty() {
+            return out;
+        }
+
+        // sections are 4 byte aligned
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+
+        let is_small = self.exception_clauses.len() * 12 + 4 <= 0xFF
+            && self.exception_clauses.iter().all(|c| {
+                c.try_offset <= 0xFFFF
+                    && c.try_length <= 0xFF
+                    && c.handler_offset <= 0xFFFF
+                    && c.handler_length <= 0xFF
+            });
+
+        if is_small {
+            out.push(SECT_EH_TABLE);
+            out.push((self.exception_clauses.len() * 12 + 4) as u8);
+            out.extend_from_slice(&[0, 0]);
+        } else {
+            let size = (self.exception_clauses.len() * 24 + 4) as u32;
+            out.push(SECT_EH_TABLE | SECT_FAT_FORMAT);
+            out.extend_from_slice(&size.to_le_bytes()[..3]);
+        }
+
+        for clause in self.exception_clauses.iter() {
+            let (flags, extra) = match clause.kind {
+                ExceptionClauseKind::Catch(token) => (0x0, token.to_raw()),
+                ExceptionClauseKind::Filter(offset) => (0x1, offset),
+                ExceptionClauseKind::Finally => (0x2, 0),
+                ExceptionClauseKind::Fault => (0x4, 0),
+            };
+
+            if is_small {
+                out.extend_from_slice(&(flags as u16).to_le_bytes());
+                out.extend_from_slice(&(clause.try_offset as u16).to_le_bytes());
+                out.push(clause.try_length as u8);
+                out.extend_from_slice(&(clause.handler_offset as u16).to_le_bytes());
+                out.push(clause.handler_length as u8);
+            } else {
+                for n in [
+                    flags,
+                    clause.try_offset,
+                    clause.try_length,
+                    clause.handler_offset,
+                    clause.handler_length,
+                ] {
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+            }
+            out.extend_from_slice(&extra.to_le_bytes());
+        }
+
+        out
+    }
+}
+
+const FAT_FORMAT: u16 = 0x3;
+const MORE_SECTS: u16 = 0x8;
+const INIT_LOCALS: u16 = 0x10;
+
+const SECT_EH_TABLE: u8 = 0x1;
+const SECT_FAT_FORMAT: u8 = 0x40;
+const SECT_MORE_SECTS: u8 = 0x80;
+
 impl<'a> TryFromCtx<'a, Endian> for MethodBody {
     type Error = scroll::Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let header: u8 = src.gread_with(offset, ctx)?;
+        let header: u8 = src.pread_with(0, ctx)?;
 
+        let (max_stack, flags, code_size, local_var_sig) = match header & 0b11 {
+            // Thin
+            0b10 => {
+                *offset += 1;
+                (8, 0, (header >> 2) as u32, None)
+            }
+            // Fat
+            0b11 => {
+                let flags: u16 = src.gread_with(offset, ctx)?;
+                let max_stack = src.gread_with(offset, ctx)?;
+                let code_size = src.gread_with(offset, ctx)?;
+                let token: u32 = src.gread_with(offset, ctx)?;
+                // size of header in dwords
+                *offset = (flags >> 12) as usize * 4;
+                let local_var_sig = match token {
+                    0 => None,
+                    token => Some(
+                        MetadataToken::from_raw(token)
+                            .and_then(MetadataToken::as_stand_along_sig)
+                            .ok_or(scroll::Error::BadInput {
+                                size: 4,
+                                msg: "Invalid LocalVarSigTok",
+                            })?,
+                    ),
+                };
+                (max_stack, flags & 0xFFF, code_size, local_var_sig)
+            }
+            _ => {
+                return Err(scroll::Error::BadInput {
+                    size: 1,
+                    msg: "Invalid method header",
+                })
+            }
+        };
+        debug_assert!(flags == 0 || flags & FAT_FORMAT == FAT_FORMAT);
+
+        let code_start = *offset;
+        let code_end = code_start + code_size as usize;
         let mut instructions = Vec::new();
+        let mut offsets = Vec::new();
 
-        let format = header & 0b11;
-        let value = header >> 2;
+        while *offset < code_end {
+            offsets.push((*offset - code_start) as u32);
+            instructions.push(src.gread_with(offset, ctx)?);
+        }
 
-        match format {
-            // Thin
-            0b10 => {
-                let target = value as usize + 1;
-                while *offset < target {
-                    instructions.push(src.gread_with(offset, ctx)?);
-                }
+        let mut exception_clauses = Vec::new();
+        let mut more_sects = flags & MORE_SECTS != 0;
+
+        while more_sects {
+            // sections are 4 byte aligned
+            *offset = (*offset + 3) & !3;
+            let kind: u8 = src.gread_with(offset, ctx)?;
+            let is_fat = kind & SECT_FAT_FORMAT != 0;
+            more_sects = kind & SECT_MORE_SECTS != 0;
+
+            let data_size = if is_fat {
+                let b: [u8; 3] = [
+                    src.gread_with(offset, ctx)?,
+                    src.gread_with(offset, ctx)?,
+                    src.gread_with(offset, ctx)?,
+                ];
+                u32::from_le_bytes([b[0], b[1], b[2], 0]) as usize
+            } else {
+                let size: u8 = src.gread_with(offset, ctx)?;
+                *offset += 2;
+                size as usize
+            };
+            let end = *offset - 4 + data_size;
+
+            if kind & SECT_EH_TABLE == 0 {
+                *offset = end;
+                continue;
+            }
+
+            while *offset < end {
+                let (flags, try_offset, try_length, handler_offset, handler_length) = if is_fat {
+                    (
+                        src.gread_with::<u32>(offset, ctx)?,
+                        src.gread_with::<u32>(offset, ctx)?,
+                        src.gread_with::<u32>(offset, ctx)?,
+                        src.gread_with::<u32>(offset, ctx)?,
+                        src.gread_with::<u32>(offset, ctx)?,
+                    )
+                } else {
+                    (
+                        src.gread_with::<u16>(offset, ctx)? as u32,
+                        src.gread_with::<u16>(offset, ctx)? as u32,
+                        src.gread_with::<u8>(offset, ctx)? as u32,
+                        src.gread_with::<u16>(offset, ctx)? as u32,
+                        src.gread_with::<u8>(offset, ctx)? as u32,
+                    )
+                };
+                let extra: u32 = src.gread_with(offset, ctx)?;
+
+                let kind = match flags {
+                    0x0 => ExceptionClauseKind::Catch(MetadataToken::from_raw(extra).ok_or(
+                        scroll::Error::BadInput {
+                            size: 4,
+                            msg: "Invalid ClassToken",
+                        },
+                    )?),
+                    0x1 => ExceptionClauseKind::Filter(extra),
+                    0x2 => ExceptionClauseKind::Finally,
+                    0x4 => ExceptionClauseKind::Fault,
+                    _ => {
+                        return Err(scroll::Error::BadInput {
+                            size: 4,
+                            msg: "Invalid exception clause flags",
+                        })
+                    }
+                };
+
+                exception_clauses.push(ExceptionClause {
+                    kind,
+                    try_offset,
+                    try_length,
+                    handler_offset,
+                    handler_length,
+                });
             }
-            _ => todo!(),
         }
 
-        Ok((Self { instructions }, *offset))
+        Ok((
+            Self {
+                max_stack,
+                init_locals: flags & INIT_LOCALS != 0,
+                local_var_sig,
+                code_size,
+                instructions,
+                offsets,
+                exception_clauses,
+            },
+            *offset,
+        ))
     }
 }
+
+#[test]
+fn fat_method_body() {
+    let bytes = [
+        0x1B, 0x30, // fat, more sects, init locals, 3 dwords
+        0x02, 0x00, // max stack
+        0x04, 0x00, 0x00, 0x00, // code size
+        0x01, 0x00, 0x00, 0x11, // StandAloneSig row 1
+        0x00, // nop
+        0xDE, 0x01, // leave.s +1
+        0x2A, // ret
+        0x01, 0x10, 0x00, 0x00, // small EH table with one clause
+        0x02, 0x00, 0x00, 0x00, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // finally
+    ];
+    let body: MethodBody = bytes.pread_with(0, scroll::LE).unwrap();
+
+    assert_eq!(body.max_stack, 2);
+    assert!(body.init_locals);
+    assert_eq!(body.local_var_sig, Some(StandAloneSigIndex(1)));
+    assert_eq!(body.offsets, [0, 1, 3]);
+    assert_eq!(
+        body.exception_clauses,
+        [ExceptionClause {
+            kind: ExceptionClauseKind::Finally,
+            try_offset: 0,
+            try_length: 1,
+            handler_offset: 3,
+            handler_length: 1,
+        }]
+    );
+    assert_eq!(body.encode(), &bytes[..]);
+}
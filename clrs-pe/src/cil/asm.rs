@@ -0,0 +1,2784 @@
+//! Assembler for the textual IL format written by `disasm`
+
+use std::collections::HashMap;
+
+use scroll::Pread;
+
+use super::disasm::mnemonic;
+use super::{ExceptionClause, ExceptionClauseKind, Instruction, MethodBody};
+use crate::pe::{
+    ArrayShape, Assembly, AssemblyFlags, AssemblyHashAlgorithm, AssemblyRef, AssemblyRefIndex,
+    AssemblyVersion, BlobIndex, ClassLayout, Constant, CustomMod, ElementType, ExportedType, Field,
+    FieldAttributes, FieldIndex, FieldLayout, FieldRVA, FieldSig, GenericParam,
+    GenericParamAttributes, GenericParamConstraint, GenericParamIndex, GuidIndex, HasConstant,
+    ImageBuilder, ImplMap, Implementation, InterfaceImpl, LocalVar, LocalVarSig, MemberForwarded,
+    MemberRef, MemberRefIndex, MemberRefParent, MetadataToken, MethodAttributes,
+    MethodCallingConvension, MethodDef, MethodDefIndex, MethodDefOrRef, MethodDefSig,
+    MethodImplAttributes, MethodRefSig, MethodSpec, MethodSpecIndex, MethodSpecSig, Module,
+    ModuleRef, ModuleRefIndex, NestedClass, PInvokeAttributes, Param, ParamAttributes, ParamIndex,
+    ParamRow, ResolutionScope, RetType, StandAloneSig, StandAloneSigIndex, StringIndex, Type,
+    TypeAttributes, TypeDef, TypeDefIndex, TypeDefOrRef, TypeDefOrRefOrSpecEncoded,
+    TypeOrMethodDef, TypeRef, TypeRefIndex, TypeSpec, TypeSpecIndex, U,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line, zero when writing the image failed
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+/// Assemble IL source into a PE image
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    let mut parser = Parser {
+        tokens: lex(source)?,
+        pos: 0,
+        opcodes: opcodes(),
+    };
+    let source = parser.source()?;
+
+    let mut asm = Assembler::default();
+    asm.source(&source)?;
+    asm.image
+        .to_bytes()
+        .map_err(|e| AsmError::new(0, e.to_string()))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Id(String),
+    /// `'name'`
+    Quoted(String),
+    /// `"string"`
+    Str(String),
+    /// Digits followed by letters and dots, interpreted by the parser
+    Num(String),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    /// Preceded by whitespace or a comment
+    spaced: bool,
+}
+
+/// Longest first
+const PUNCTS: [&str; 21] = [
+    "...", "::", "!!", "[", "]", "(", ")", "<", ">", ",", "!", "*", "&", "=", ":", "{", "}", "+",
+    "-", "/", ".",
+];
+
+fn is_id_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || "_$@`?".contains(c)
+}
+
+fn is_id_char(c: char) -> bool {
+    is_id_start(c) || c.is_ascii_digit()
+}
+
+fn lex(source: &str) -> Result<Vec<Token>> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let at = |i: usize| chars.get(i).copied().unwrap_or('\0');
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut spaced = true;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            if c == '\n' {
+                line += 1;
+            }
+            spaced = true;
+            i += 1;
+            continue;
+        }
+        if c == '/' && at(i + 1) == '/' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            spaced = true;
+            continue;
+        }
+        if c == '/' && at(i + 1) == '*' {
+            let start = line;
+            i += 2;
+            while !(at(i) == '*' && at(i + 1) == '/') {
+                if i >= chars.len() {
+                    return Err(AsmError::new(start, "unterminated comment"));
+                }
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            spaced = true;
+            i += 2;
+            continue;
+        }
+
+        let start = i;
+        let tok = if is_id_start(c) || (c == '.' && is_id_start(at(i + 1))) {
+            i += 1;
+            while is_id_char(at(i)) || (at(i) == '.' && is_id_char(at(i + 1))) {
+                i += 1;
+            }
+            Tok::Id(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() {
+            let is_hex = matches!(at(i + 1), 'x' | 'X');
+            i += 1;
+            loop {
+                let c = at(i);
+                let is_exponent_sign =
+                    !is_hex && matches!(c, '+' | '-') && matches!(chars[i - 1], 'e' | 'E');
+                if is_id_char(c) || (c == '.' && at(i + 1).is_ascii_digit()) || is_exponent_sign {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Tok::Num(chars[start..i].iter().collect())
+        } else if c == '\'' || c == '"' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match at(i) {
+                    _ if i >= chars.len() => {
+                        return Err(AsmError::new(line, "unterminated string"));
+                    }
+                    '\n' => return Err(AsmError::new(line, "unterminated string")),
+                    '\\' => {
+                        s.push(match at(i + 1) {
+                            'n' => '\n',
+                            'r' => '\r',
+                            't' => '\t',
+                            '0' => '\0',
+                            c => c,
+                        });
+                        i += 2;
+                    }
+                    q if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    c => {
+                        s.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            if c == '"' {
+                Tok::Str(s)
+            } else {
+                Tok::Quoted(s)
+            }
+        } else {
+            let punct = PUNCTS
+                .iter()
+                .find(|p| p.chars().enumerate().all(|(n, c)| at(i + n) == c));
+            match punct {
+                Some(p) => {
+                    i += p.len();
+                    Tok::Punct(p)
+                }
+                None => return Err(AsmError::new(line, format!("unexpected character {:?}", c))),
+            }
+        };
+
+        tokens.push(Token { tok, line, spaced });
+        spaced = false;
+    }
+
+    tokens.push(Token {
+        tok: Tok::Eof,
+        line,
+        spaced: true,
+    });
+    Ok(tokens)
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Id(s) | Tok::Num(s) => format!("`{}`", s),
+        Tok::Quoted(s) => format!("'{}'", s),
+        Tok::Str(s) => format!("{:?}", s),
+        Tok::Punct(p) => format!("`{}`", p),
+        Tok::Eof => "end of file".into(),
+    }
+}
+
+fn parse_int(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|n| n as i64),
+        None => s
+            .parse::<i64>()
+            .ok()
+            .or_else(|| s.parse::<u64>().ok().map(|n| n as i64)),
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Scope {
+    Local,
+    Assembly(String),
+    Module(String),
+}
+
+/// `[scope]Outer/Inner`, each element holds the dotted parts of one name
+#[derive(Clone, Debug)]
+struct TypeName {
+    scope: Scope,
+    path: Vec<Vec<String>>,
+}
+
+impl TypeName {
+    fn key(&self) -> String {
+        self.path
+            .iter()
+            .map(|parts| parts.join("."))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Namespace and name, the last part is the name
+fn split_name(parts: &[String]) -> (String, String) {
+    match parts.split_last() {
+        Some((name, namespace)) => (namespace.join("."), name.clone()),
+        None => (String::new(), String::new()),
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Ty {
+    /// Primitives, `object` and `string`
+    Prim(Type),
+    Named {
+        value_type: bool,
+        name: TypeName,
+    },
+    /// Type name without `class` or `valuetype`, only valid as a token
+    Bare(TypeName),
+    Generic {
+        value_type: bool,
+        name: TypeName,
+        args: Vec<Ty>,
+    },
+    SzArray(Box<Ty>, Vec<Modifier>),
+    Array(Box<Ty>, ArrayShape),
+    Ptr(Option<Box<Ty>>, Vec<Modifier>),
+    FnPtr(Box<Sig>),
+    Var(u32),
+    MVar(u32),
+}
+
+/// `modreq(T)` or `modopt(T)`
+#[derive(Clone, Debug)]
+struct Modifier {
+    required: bool,
+    ty: Ty,
+}
+
+/// Return type, parameter or local
+#[derive(Clone, Debug)]
+enum ParamTy {
+    Void(Vec<Modifier>),
+    TypedByref(Vec<Modifier>),
+    Type {
+        mods: Vec<Modifier>,
+        byref: bool,
+        pinned: bool,
+        ty: Ty,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Sig {
+    conv: MethodCallingConvension,
+    generic_param_count: u32,
+    ret: ParamTy,
+    params: Vec<ParamTy>,
+    /// Index of the first vararg of a call site, after `...`
+    sentinel: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Owner {
+    Type(Ty),
+    Module(String),
+}
+
+#[derive(Clone, Debug)]
+struct FieldRef {
+    ty: Ty,
+    mods: Vec<Modifier>,
+    owner: Owner,
+    name: String,
+}
+
+#[derive(Clone, Debug)]
+struct MethodRef {
+    sig: Sig,
+    owner: Owner,
+    name: String,
+    generic_args: Option<Vec<Ty>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OperandKind {
+    /// Integer, nothing when the operand size is zero
+    Int,
+    R4,
+    R8,
+    Branch,
+    Switch,
+    String,
+    Field,
+    Method,
+    Type,
+    /// `ldtoken`
+    Token,
+    /// `calli`
+    Sig,
+}
+
+#[derive(Clone, Debug)]
+struct Opcode {
+    bytes: Vec<u8>,
+    operand_size: usize,
+    kind: OperandKind,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    None,
+    Int(i64),
+    Float(f64),
+    /// Raw bytes of a float, for NaN and infinities
+    Bytes(Vec<u8>),
+    Label(String),
+    Labels(Vec<String>),
+    Str(String),
+    Type(Ty),
+    Field(FieldRef),
+    Method(MethodRef),
+    Sig(Sig),
+}
+
+#[derive(Clone, Debug)]
+struct InstDecl {
+    line: usize,
+    labels: Vec<String>,
+    opcode: Opcode,
+    operand: Operand,
+}
+
+impl InstDecl {
+    fn size(&self) -> u32 {
+        let targets = match &self.operand {
+            Operand::Labels(labels) => labels.len(),
+            _ => 0,
+        };
+        (self.opcode.bytes.len() + self.opcode.operand_size + 4 * targets) as u32
+    }
+}
+
+#[derive(Clone, Debug)]
+enum HandlerDecl {
+    Catch(Ty),
+    Filter(String),
+    Finally,
+    Fault,
+}
+
+#[derive(Clone, Debug)]
+struct TryDecl {
+    line: usize,
+    try_start: String,
+    try_end: String,
+    handler: HandlerDecl,
+    handler_start: String,
+    handler_end: String,
+}
+
+#[derive(Clone, Debug)]
+struct BodyDecl {
+    max_stack: u16,
+    init_locals: bool,
+    locals: Option<Vec<ParamTy>>,
+    insts: Vec<InstDecl>,
+    /// Labels after the last instruction
+    end_labels: Vec<String>,
+    tries: Vec<TryDecl>,
+}
+
+#[derive(Clone, Debug)]
+struct GenericDecl {
+    flags: GenericParamAttributes,
+    constraints: Vec<Ty>,
+    name: String,
+}
+
+#[derive(Clone, Debug)]
+struct PInvokeDecl {
+    module: String,
+    import_name: Option<String>,
+    flags: PInvokeAttributes,
+}
+
+#[derive(Clone, Debug)]
+struct MethodDecl {
+    line: usize,
+    flags: MethodAttributes,
+    impl_flags: MethodImplAttributes,
+    pinvoke: Option<PInvokeDecl>,
+    sig: Sig,
+    name: String,
+    generics: Vec<GenericDecl>,
+    param_names: Vec<Option<String>>,
+    entry_point: bool,
+    body: Option<BodyDecl>,
+}
+
+#[derive(Clone, Debug)]
+struct FieldDecl {
+    line: usize,
+    offset: Option<u32>,
+    flags: FieldAttributes,
+    ty: Ty,
+    mods: Vec<Modifier>,
+    name: String,
+    constant: Option<(ElementType, Vec<u8>)>,
+    data: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct ClassDecl {
+    line: usize,
+    flags: TypeAttributes,
+    name: Vec<String>,
+    generics: Vec<GenericDecl>,
+    extends: Option<Ty>,
+    implements: Vec<Ty>,
+    pack: Option<u16>,
+    size: Option<u32>,
+    nested: Vec<ClassDecl>,
+    fields: Vec<FieldDecl>,
+    methods: Vec<MethodDecl>,
+}
+
+impl ClassDecl {
+    fn new(line: usize, flags: TypeAttributes, name: Vec<String>) -> Self {
+        Self {
+            line,
+            flags,
+            name,
+            generics: Vec::new(),
+            extends: None,
+            implements: Vec::new(),
+            pack: None,
+            size: None,
+            nested: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AssemblyDecl {
+    name: String,
+    /// Public key of the assembly or token of a reference
+    key: Vec<u8>,
+    version: AssemblyVersion,
+    /// Empty for the neutral culture
+    culture: String,
+}
+
+/// `.class extern forwarder Name { .assembly extern Target }`
+#[derive(Clone, Debug)]
+struct ForwarderDecl {
+    name: Vec<String>,
+    assembly: String,
+}
+
+#[derive(Clone, Debug)]
+struct DataDecl {
+    line: usize,
+    label: String,
+    bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+struct Source {
+    assembly_refs: Vec<AssemblyDecl>,
+    assembly: Option<AssemblyDecl>,
+    module: Option<String>,
+    /// Members of `<Module>`
+    globals: ClassDecl,
+    classes: Vec<ClassDecl>,
+    forwarders: Vec<ForwarderDecl>,
+    data: Vec<DataDecl>,
+}
+
+/// Instruction templates by mnemonic, found by decoding every opcode with a zero operand
+fn opcodes() -> HashMap<String, Opcode> {
+    let one_byte = (0..=0xFFu8).filter(|b| *b != 0xFE).map(|b| vec![b]);
+    let two_byte = (0..=0x1Eu8).map(|b| vec![0xFE, b]);
+    let mut opcodes = HashMap::new();
+
+    for bytes in one_byte.chain(two_byte) {
+        let mut src = bytes.clone();
+        src.resize(bytes.len() + 8, 0);
+        let offset = &mut 0;
+        let inst: Instruction = match src.gread_with(offset, scroll::LE) {
+            Ok(inst) => inst,
+            Err(_) => continue,
+        };
+
+        opcodes
+            .entry(mnemonic(&inst, *offset as u32))
+            .or_insert(Opcode {
+                operand_size: *offset - bytes.len(),
+                kind: operand_kind(&inst),
+                bytes,
+            });
+    }
+
+    opcodes
+}
+
+fn operand_kind(inst: &Instruction) -> OperandKind {
+    match inst {
+        Instruction::LdStr(_) => OperandKind::String,
+        Instruction::LdFld(_)
+        | Instruction::LdFldA(_)
+        | Instruction::StFld(_)
+        | Instruction::LdSFld(_)
+        | Instruction::LdSFldA(_)
+        | Instruction::StSFld(_) => OperandKind::Field,
+        Instruction::Jmp(_)
+        | Instruction::Call(_)
+        | Instruction::CallVirt(_)
+        | Instruction::NewObj(_)
+        | Instruction::LdFtn(_)
+        | Instruction::LdVirtFtn(_) => OperandKind::Method,
+        Instruction::CallI(_) => OperandKind::Sig,
+        Instruction::LdToken(_) => OperandKind::Token,
+        Instruction::LdObj(_)
+        | Instruction::StObj(_)
+        | Instruction::CpObj(_)
+        | Instruction::InitObj(_)
+        | Instruction::SizeOf(_)
+        | Instruction::NewArr(_)
+        | Instruction::LdElemA(_)
+        | Instruction::LdElemAny(_)
+        | Instruction::StElemAny(_)
+        | Instruction::CastClass(_)
+        | Instruction::IsInst(_)
+        | Instruction::Box(_)
+        | Instruction::Unbox(_)
+        | Instruction::UnboxAny(_)
+        | Instruction::MkRefAny(_)
+        | Instruction::RefAnyVal(_)
+        | Instruction::Constrained(_) => OperandKind::Type,
+        Instruction::Switch(_) => OperandKind::Switch,
+        Instruction::LdcR4(_) => OperandKind::R4,
+        Instruction::LdcR8(_) => OperandKind::R8,
+        _ if !inst.branch_targets(0).is_empty() => OperandKind::Branch,
+        _ => OperandKind::Int,
+    }
+}
+
+const TYPE_FLAGS: [(&str, u32); 15] = [
+    ("interface", TypeAttributes::INTERFACE.bits()),
+    ("public", TypeAttributes::PUBLIC.bits()),
+    ("private", TypeAttributes::NOT_PUBLIC.bits()),
+    ("abstract", TypeAttributes::ABSTRACT.bits()),
+    ("auto", TypeAttributes::AUTO_LAYOUT.bits()),
+    ("sequential", TypeAttributes::SEQUENTIAL_LAYOUT.bits()),
+    ("explicit", TypeAttributes::EXPLICIT_LAYOUT.bits()),
+    ("ansi", TypeAttributes::ANSI_CLASS.bits()),
+    ("unicode", TypeAttributes::UNICODE_CLASS.bits()),
+    ("autochar", TypeAttributes::AUTO_CLASS.bits()),
+    ("sealed", TypeAttributes::SEALED.bits()),
+    ("specialname", TypeAttributes::SPECIAL_NAME.bits()),
+    ("rtspecialname", TypeAttributes::RT_SPECIAL_NAME.bits()),
+    ("import", TypeAttributes::IMPORT.bits()),
+    ("serializable", TypeAttributes::SERIALIZED.bits()),
+];
+
+const NESTED_FLAGS: [(&str, u32); 6] = [
+    ("public", TypeAttributes::NESTED_PUBLIC.bits()),
+    ("private", TypeAttributes::NESTED_PRIVATE.bits()),
+    ("family", TypeAttributes::NESTED_FAMILY.bits()),
+    ("assembly", TypeAttributes::NESTED_ASSEMBLY.bits()),
+    ("famandassem", TypeAttributes::NESTED_FAM_AND_ASSEM.bits()),
+    ("famorassem", TypeAttributes::NESTED_FAM_OR_ASSEM.bits()),
+];
+
+const FIELD_FLAGS: [(&str, u16); 13] = [
+    ("privatescope", FieldAttributes::COMPILER_CONTROLLED.bits()),
+    ("private", FieldAttributes::PRIVATE.bits()),
+    ("famandassem", FieldAttributes::FAM_AND_ASSEM.bits()),
+    ("assembly", FieldAttributes::ASSEMBLY.bits()),
+    ("family", FieldAttributes::FAMILY.bits()),
+    ("famorassem", FieldAttributes::FAM_OR_ASSEM.bits()),
+    ("public", FieldAttributes::PUBLIC.bits()),
+    ("static", FieldAttributes::STATIC.bits()),
+    ("initonly", FieldAttributes::INIT_ONLY.bits()),
+    ("literal", FieldAttributes::LITERAL.bits()),
+    ("notserialized", FieldAttributes::NOT_SERIALIZED.bits()),
+    ("specialname", FieldAttributes::SPECIAL_NAME.bits()),
+    ("rtspecialname", FieldAttributes::RT_SPECIAL_NAME.bits()),
+];
+
+const METHOD_FLAGS: [(&str, u16); 16] = [
+    ("privatescope", MethodAttributes::COMPILER_CONTROLLED.bits()),
+    ("private", MethodAttributes::PRIVATE.bits()),
+    ("famandassem", MethodAttributes::FAM_AND_ASSEM.bits()),
+    ("assembly", MethodAttributes::ASSEMBLY.bits()),
+    ("family", MethodAttributes::FAMILY.bits()),
+    ("famorassem", MethodAttributes::FAM_OR_ASSEM.bits()),
+    ("public", MethodAttributes::PUBLIC.bits()),
+    ("hidebysig", MethodAttributes::HIDE_BY_SIG.bits()),
+    ("newslot", MethodAttributes::NEW_SLOT.bits()),
+    ("strict", MethodAttributes::STRICT.bits()),
+    ("specialname", MethodAttributes::SPECIAL_NAME.bits()),
+    ("rtspecialname", MethodAttributes::RT_SPECIAL_NAME.bits()),
+    ("abstract", MethodAttributes::ABSTRACT.bits()),
+    ("virtual", MethodAttributes::VIRTUAL.bits()),
+    ("final", MethodAttributes::FINAL.bits()),
+    ("static", MethodAttributes::STATIC.bits()),
+];
+
+const IMPL_FLAGS: [(&str, u16); 12] = [
+    ("cil", MethodImplAttributes::IL.bits()),
+    ("native", MethodImplAttributes::NATIVE.bits()),
+    ("optil", MethodImplAttributes::OPTIL.bits()),
+    ("runtime", MethodImplAttributes::RUNTIME.bits()),
+    ("managed", MethodImplAttributes::MANAGED.bits()),
+    ("unmanaged", MethodImplAttributes::UNMANAGED.bits()),
+    ("forwardref", MethodImplAttributes::FORWARD_REF.bits()),
+    ("preservesig", MethodImplAttributes::PRESERVE_SIG.bits()),
+    ("internalcall", MethodImplAttributes::INTERNAL_CALL.bits()),
+    ("synchronized", MethodImplAttributes::SYNCHRONIZED.bits()),
+    ("noinlining", MethodImplAttributes::NO_INLINING.bits()),
+    (
+        "nooptimization",
+        MethodImplAttributes::NO_OPTIMIZATION.bits(),
+    ),
+];
+
+const PINVOKE_FLAGS: [(&str, u16); 10] = [
+    ("winapi", PInvokeAttributes::CALL_CONV_PLATFORMAPI.bits()),
+    ("cdecl", PInvokeAttributes::CALL_CONV_CDECL.bits()),
+    ("stdcall", PInvokeAttributes::CALL_CONV_STDCALL.bits()),
+    ("thiscall", PInvokeAttributes::CALL_CONV_THISCALL.bits()),
+    ("fastcall", PInvokeAttributes::CALL_CONV_FASTCALL.bits()),
+    ("nomangle", PInvokeAttributes::NO_MANGLE.bits()),
+    ("ansi", PInvokeAttributes::CHARSET_ANSI.bits()),
+    ("unicode", PInvokeAttributes::CHARSET_UNICODE.bits()),
+    ("autochar", PInvokeAttributes::CHARSET_AUTO.bits()),
+    ("lasterr", PInvokeAttributes::SUPPORTS_LAST_ERROR.bits()),
+];
+
+fn primitive(name: &str) -> Option<Type> {
+    Some(match name {
+        "bool" => Type::Boolean,
+        "char" => Type::Char,
+        "int8" => Type::I1,
+        "uint8" => Type::U1,
+        "int16" => Type::I2,
+        "uint16" => Type::U2,
+        "int32" => Type::I4,
+        "uint32" => Type::U4,
+        "int64" => Type::I8,
+        "uint64" => Type::U8,
+        "float32" => Type::R4,
+        "float64" => Type::R8,
+        "object" => Type::Object,
+        "string" => Type::String,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    opcodes: HashMap<String, Opcode>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn peek_at(&self, n: usize) -> &Tok {
+        &self.tokens[(self.pos + n).min(self.tokens.len() - 1)].tok
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].line
+    }
+
+    fn bump(&mut self) -> Tok {
+        let tok = self.peek().clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        Err(AsmError::new(self.line(), message))
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        self.error(format!(
+            "expected {}, found {}",
+            expected,
+            describe(self.peek())
+        ))
+    }
+
+    /// Punctuation or keyword
+    fn is(&self, s: &str) -> bool {
+        match self.peek() {
+            Tok::Punct(p) => *p == s,
+            Tok::Id(id) => id == s,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let is = self.is(s);
+        if is {
+            self.bump();
+        }
+        is
+    }
+
+    fn expect(&mut self, s: &str) -> Result<()> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", s))
+        }
+    }
+
+    fn is_name(&self) -> bool {
+        matches!(self.peek(), Tok::Id(_) | Tok::Quoted(_))
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Tok::Id(s) | Tok::Quoted(s) => {
+                self.bump();
+                Ok(s)
+            }
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    /// `A.B.'<C>'`
+    fn dotted_name(&mut self) -> Result<Vec<String>> {
+        let mut parts = Vec::new();
+
+        loop {
+            match self.peek().clone() {
+                Tok::Id(s) => {
+                    self.bump();
+                    parts.extend(s.split('.').filter(|p| !p.is_empty()).map(String::from));
+                }
+                Tok::Quoted(s) => {
+                    self.bump();
+                    parts.push(s);
+                }
+                _ => return self.unexpected("a name"),
+            }
+
+            let next = &self.tokens[self.pos];
+            if next.spaced {
+                break;
+            }
+            match &next.tok {
+                Tok::Punct(".") => {
+                    self.bump();
+                }
+                Tok::Id(s) if s.starts_with('.') => {}
+                _ => break,
+            }
+        }
+
+        Ok(parts)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Tok::Str(s) => {
+                self.bump();
+                Ok(s)
+            }
+            _ => self.unexpected("a string"),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        let negative = self.eat("-");
+        match self.peek().clone() {
+            Tok::Num(s) => match parse_int(&s) {
+                Some(n) => {
+                    self.bump();
+                    Ok(if negative { n.wrapping_neg() } else { n })
+                }
+                None => self.error(format!("invalid integer `{}`", s)),
+            },
+            _ => self.unexpected("an integer"),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64> {
+        let negative = self.eat("-");
+        let value = match self.peek().clone() {
+            Tok::Num(s) => match parse_int(&s).filter(|_| s.starts_with("0x")) {
+                Some(n) => n as f64,
+                None => match s.parse() {
+                    Ok(f) => f,
+                    Err(_) => return self.error(format!("invalid number `{}`", s)),
+                },
+            },
+            Tok::Id(s) if s == "inf" => f64::INFINITY,
+            Tok::Id(s) if s == "NaN" => f64::NAN,
+            _ => return self.unexpected("a number"),
+        };
+        self.bump();
+        Ok(if negative { -value } else { value })
+    }
+
+    /// `(01 02 AB)`
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        self.expect("(")?;
+        let mut hex = String::new();
+        while !self.eat(")") {
+            match self.bump() {
+                Tok::Num(s) | Tok::Id(s) => hex.push_str(&s),
+                _ => return self.error("expected hex bytes"),
+            }
+        }
+
+        if !hex.len().is_multiple_of(2) {
+            return self.error("odd number of hex digits");
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<std::result::Result<_, _>>()
+            .or_else(|_| self.error("invalid hex bytes"))
+    }
+
+    /// Sum of keywords found in `table`
+    fn flags<T: Copy + Into<u32>>(&mut self, table: &[(&str, T)]) -> u32 {
+        let mut bits = 0;
+        while let Tok::Id(s) = self.peek() {
+            match table.iter().find(|(name, _)| name == s) {
+                Some((_, flag)) => bits |= (*flag).into(),
+                None => break,
+            }
+            self.bump();
+        }
+        bits
+    }
+
+    fn scope(&mut self) -> Result<Scope> {
+        if !self.eat("[") {
+            return Ok(Scope::Local);
+        }
+        let scope = if self.eat(".module") {
+            Scope::Module(self.dotted_name()?.join("."))
+        } else {
+            Scope::Assembly(self.dotted_name()?.join("."))
+        };
+        self.expect("]")?;
+        Ok(scope)
+    }
+
+    fn type_name(&mut self) -> Result<TypeName> {
+        let scope = self.scope()?;
+        let mut path = vec![self.dotted_name()?];
+        while self.eat("/") {
+            path.push(self.dotted_name()?);
+        }
+        Ok(TypeName { scope, path })
+    }
+
+    fn type_list(&mut self, close: &str) -> Result<Vec<Ty>> {
+        let mut types = vec![self.ty()?];
+        while self.eat(",") {
+            types.push(self.ty()?);
+        }
+        self.expect(close)?;
+        Ok(types)
+    }
+
+    fn ty(&mut self) -> Result<Ty> {
+        let mut ty = match self.peek().clone() {
+            Tok::Id(s) if primitive(&s).is_some() => {
+                self.bump();
+                Ty::Prim(primitive(&s).unwrap())
+            }
+            Tok::Id(s) if s == "native" => {
+                self.bump();
+                if self.eat("int") {
+                    Ty::Prim(Type::I)
+                } else if self.eat("uint") {
+                    Ty::Prim(Type::U)
+                } else {
+                    return self.unexpected("`int` or `uint`");
+                }
+            }
+            Tok::Id(s) if s == "class" || s == "valuetype" => {
+                self.bump();
+                let value_type = s == "valuetype";
+                let name = self.type_name()?;
+                if self.eat("<") {
+                    let args = self.type_list(">")?;
+                    Ty::Generic {
+                        value_type,
+                        name,
+                        args,
+                    }
+                } else {
+                    Ty::Named { value_type, name }
+                }
+            }
+            Tok::Id(s) if s == "void" => {
+                self.bump();
+                let mods = self.custom_mods()?;
+                self.expect("*")?;
+                Ty::Ptr(None, mods)
+            }
+            Tok::Id(s) if s == "method" => {
+                self.bump();
+                let conv = self.call_conv();
+                let ret = self.param()?;
+                self.expect("*")?;
+                let params = self.params()?.into_iter().map(|(p, _)| p).collect();
+                Ty::FnPtr(Box::new(Sig {
+                    conv,
+                    generic_param_count: 0,
+                    ret,
+                    params,
+                    sentinel: None,
+                }))
+            }
+            Tok::Punct("!") => {
+                self.bump();
+                Ty::Var(self.int()? as u32)
+            }
+            Tok::Punct("!!") => {
+                self.bump();
+                Ty::MVar(self.int()? as u32)
+            }
+            Tok::Id(_) | Tok::Quoted(_) | Tok::Punct("[") => Ty::Bare(self.type_name()?),
+            _ => return self.unexpected("a type"),
+        };
+
+        loop {
+            // modifiers before `*` or `[]` belong to them, trailing ones to the signature
+            let start = self.pos;
+            let mods = self.custom_mods()?;
+            // `method int32 *(int32)` ends at the `*`
+            if self.is("*") && self.peek_at(1) != &Tok::Punct("(") {
+                self.bump();
+                ty = Ty::Ptr(Some(Box::new(ty)), mods);
+            } else if self.is("[") && !matches!(self.peek_at(1), Tok::Id(_) | Tok::Quoted(_)) {
+                // `int32 [mscorlib]System.Console::Read()` is a scope, not an array
+                self.bump();
+                ty = self.array(ty, mods)?;
+            } else {
+                self.pos = start;
+                return Ok(ty);
+            }
+        }
+    }
+
+    /// `modreq(T) modopt(U)`
+    fn custom_mods(&mut self) -> Result<Vec<Modifier>> {
+        let mut mods = Vec::new();
+        loop {
+            let required = if self.eat("modreq") {
+                true
+            } else if self.eat("modopt") {
+                false
+            } else {
+                return Ok(mods);
+            };
+            self.expect("(")?;
+            let ty = self.ty()?;
+            self.expect(")")?;
+            mods.push(Modifier { required, ty });
+        }
+    }
+
+    /// After `[`, `[]`, `[2,0...]` or `[0...4]`
+    fn array(&mut self, element_ty: Ty, mods: Vec<Modifier>) -> Result<Ty> {
+        if self.eat("]") {
+            return Ok(Ty::SzArray(Box::new(element_ty), mods));
+        }
+        if !mods.is_empty() {
+            return self.error("custom modifiers on a multi-dimensional array");
+        }
+
+        let mut shape = ArrayShape {
+            rank: 0,
+            sizes: Vec::new(),
+            lo_bounds: Vec::new(),
+        };
+        loop {
+            shape.rank += 1;
+            if !self.is(",") && !self.is("]") {
+                let lo = self.int()?;
+                if !self.eat("...") {
+                    shape.sizes.push(lo as u32);
+                } else if self.is(",") || self.is("]") {
+                    shape.lo_bounds.push(lo as i32);
+                } else {
+                    let hi = self.int()?;
+                    shape.lo_bounds.push(lo as i32);
+                    shape.sizes.push((hi - lo + 1) as u32);
+                }
+            }
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect("]")?;
+
+        Ok(Ty::Array(Box::new(element_ty), shape))
+    }
+
+    fn param(&mut self) -> Result<ParamTy> {
+        if self.eat("typedref") {
+            return Ok(ParamTy::TypedByref(self.custom_mods()?));
+        }
+        if self.is("void") {
+            // `void*` is a type
+            let start = self.pos;
+            self.bump();
+            let mods = self.custom_mods()?;
+            if !self.is("*") {
+                return Ok(ParamTy::Void(mods));
+            }
+            self.pos = start;
+        }
+
+        let ty = self.ty()?;
+        let mut mods = self.custom_mods()?;
+        let byref = self.eat("&");
+        if byref {
+            if !mods.is_empty() {
+                return self.error("custom modifiers before `&` aren't supported");
+            }
+            mods = self.custom_mods()?;
+        }
+        let pinned = self.eat("pinned");
+        Ok(ParamTy::Type {
+            mods,
+            byref,
+            pinned,
+            ty,
+        })
+    }
+
+    fn call_conv(&mut self) -> MethodCallingConvension {
+        let mut conv = MethodCallingConvension::DEFAULT;
+        if self.eat("instance") {
+            conv |= MethodCallingConvension::HAS_THIS;
+            if self.eat("explicit") {
+                conv |= MethodCallingConvension::EXPLICT_THIS;
+            }
+        }
+        if self.eat("vararg") {
+            conv |= MethodCallingConvension::VAR_ARG;
+        }
+        conv
+    }
+
+    /// `(int32 a, string)`
+    fn params(&mut self) -> Result<Vec<(ParamTy, Option<String>)>> {
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if self.eat(")") {
+            return Ok(params);
+        }
+
+        loop {
+            let param = self.param()?;
+            let name = if self.is_name() {
+                Some(self.name()?)
+            } else {
+                None
+            };
+            params.push((param, name));
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+
+        Ok(params)
+    }
+
+    /// `(int32, ..., string)`, the arguments after `...` are the varargs of a call site
+    fn call_site_params(&mut self) -> Result<(Vec<ParamTy>, Option<usize>)> {
+        self.expect("(")?;
+        let mut params = Vec::new();
+        let mut sentinel = None;
+        if self.eat(")") {
+            return Ok((params, sentinel));
+        }
+
+        loop {
+            if sentinel.is_none() && self.eat("...") {
+                sentinel = Some(params.len());
+            } else {
+                params.push(self.param()?);
+            }
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+
+        Ok((params, sentinel))
+    }
+
+    /// `Type::` or `[.module name]::`, members of `<Module>` have none
+    fn owner(&mut self) -> Result<Owner> {
+        let is_keyword = match self.peek() {
+            Tok::Id(s) => {
+                primitive(s).is_some()
+                    || ["class", "valuetype", "native", "void", "method"].contains(&s.as_str())
+            }
+            _ => false,
+        };
+        let is_global = self.is_name()
+            && !is_keyword
+            && !matches!(
+                self.peek_at(1),
+                Tok::Punct("::") | Tok::Punct(".") | Tok::Punct("/") | Tok::Punct("[")
+            );
+        if is_global {
+            return Ok(Owner::Type(Ty::Bare(TypeName {
+                scope: Scope::Local,
+                path: vec![vec!["<Module>".into()]],
+            })));
+        }
+
+        let is_module = self.is("[")
+            && self.peek_at(1) == &Tok::Id(".module".into())
+            && self.peek_at(3) == &Tok::Punct("]")
+            && self.peek_at(4) == &Tok::Punct("::");
+
+        let owner = if is_module {
+            self.bump();
+            self.bump();
+            let name = self.name()?;
+            self.expect("]")?;
+            Owner::Module(name)
+        } else {
+            Owner::Type(self.ty()?)
+        };
+        self.expect("::")?;
+
+        Ok(owner)
+    }
+
+    /// `int32 Owner::name`
+    fn field_ref(&mut self) -> Result<FieldRef> {
+        let ty = self.ty()?;
+        let mods = self.custom_mods()?;
+        let owner = self.owner()?;
+        let name = self.name()?;
+        Ok(FieldRef {
+            ty,
+            mods,
+            owner,
+            name,
+        })
+    }
+
+    /// `instance void Owner::name<int32>(string)`
+    fn method_ref(&mut self) -> Result<MethodRef> {
+        let conv = self.call_conv();
+        let ret = self.param()?;
+        let owner = self.owner()?;
+        let name = self.name()?;
+        let generic_args = if self.eat("<") {
+            Some(self.type_list(">")?)
+        } else {
+            None
+        };
+        let (params, sentinel) = self.call_site_params()?;
+
+        Ok(MethodRef {
+            sig: Sig {
+                conv,
+                generic_param_count: 0,
+                ret,
+                params,
+                sentinel,
+            },
+            owner,
+            name,
+            generic_args,
+        })
+    }
+
+    /// `<(constraint) +T, class U>`
+    fn generic_params(&mut self) -> Result<Vec<GenericDecl>> {
+        let mut params = Vec::new();
+        if !self.eat("<") {
+            return Ok(params);
+        }
+
+        loop {
+            let mut flags = GenericParamAttributes::empty();
+            loop {
+                if self.eat("class") {
+                    flags |= GenericParamAttributes::REFERENCE_TYPE_CONSTRAINT;
+                } else if self.eat("valuetype") {
+                    flags |= GenericParamAttributes::NOT_NULLABLE_VALUE_TYPE_CONSTRAINT;
+                } else if self.eat(".ctor") {
+                    flags |= GenericParamAttributes::DEFAULT_CONSTRUCTOR_CONSTRAINT;
+                } else {
+                    break;
+                }
+            }
+            let constraints = if self.eat("(") {
+                self.type_list(")")?
+            } else {
+                Vec::new()
+            };
+            if self.eat("+") {
+                flags |= GenericParamAttributes::COVARIANT;
+            } else if self.eat("-") {
+                flags |= GenericParamAttributes::CONTRAVARIANT;
+            }
+            let name = self.name()?;
+
+            params.push(GenericDecl {
+                flags,
+                constraints,
+                name,
+            });
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(">")?;
+
+        Ok(params)
+    }
+
+    fn source(&mut self) -> Result<Source> {
+        let mut source = Source {
+            assembly_refs: Vec::new(),
+            assembly: None,
+            module: None,
+            globals: ClassDecl::new(0, TypeAttributes::empty(), vec!["<Module>".into()]),
+            classes: Vec::new(),
+            forwarders: Vec::new(),
+            data: Vec::new(),
+        };
+
+        loop {
+            if self.peek() == &Tok::Eof {
+                return Ok(source);
+            } else if self.eat(".assembly") {
+                let is_extern = self.eat("extern");
+                let assembly = self.assembly()?;
+                if is_extern {
+                    source.assembly_refs.push(assembly);
+                } else {
+                    source.assembly = Some(assembly);
+                }
+            } else if self.eat(".module") {
+                source.module = Some(self.dotted_name()?.join("."));
+            } else if self.is(".class") && self.peek_at(1) == &Tok::Id("extern".into()) {
+                source.forwarders.push(self.forwarder()?);
+            } else if self.is(".class") {
+                source.classes.push(self.class()?);
+            } else if self.is(".field") {
+                source.globals.fields.push(self.field()?);
+            } else if self.is(".method") {
+                source.globals.methods.push(self.method()?);
+            } else if self.is(".data") {
+                source.data.push(self.data()?);
+            } else {
+                return self.unexpected("a declaration");
+            }
+        }
+    }
+
+    /// After `.assembly [extern]`
+    fn assembly(&mut self) -> Result<AssemblyDecl> {
+        let mut assembly = AssemblyDecl {
+            name: self.dotted_name()?.join("."),
+            key: Vec::new(),
+            version: AssemblyVersion {
+                major_version: 0,
+                minor_version: 0,
+                build_number: 0,
+                revision_number: 0,
+            },
+            culture: String::new(),
+        };
+
+        self.expect("{")?;
+        while !self.eat("}") {
+            if self.eat(".publickey") || self.eat(".publickeytoken") {
+                self.expect("=")?;
+                assembly.key = self.bytes()?;
+            } else if self.eat(".culture") || self.eat(".locale") {
+                assembly.culture = self.string()?;
+            } else if self.eat(".ver") {
+                let mut version = [0; 4];
+                for (i, n) in version.iter_mut().enumerate() {
+                    if i > 0 {
+                        self.expect(":")?;
+                    }
+                    *n = self.int()? as u16;
+                }
+                assembly.version = AssemblyVersion {
+                    major_version: version[0],
+                    minor_version: version[1],
+                    build_number: version[2],
+                    revision_number: version[3],
+                };
+            } else {
+                return self.unexpected("an assembly directive");
+            }
+        }
+
+        Ok(assembly)
+    }
+
+    fn forwarder(&mut self) -> Result<ForwarderDecl> {
+        self.expect(".class")?;
+        self.expect("extern")?;
+        self.expect("forwarder")?;
+        let name = self.dotted_name()?;
+        self.expect("{")?;
+        self.expect(".assembly")?;
+        self.expect("extern")?;
+        let assembly = self.dotted_name()?.join(".");
+        self.expect("}")?;
+        Ok(ForwarderDecl { name, assembly })
+    }
+
+    /// `.data LABEL = bytearray (...)`
+    fn data(&mut self) -> Result<DataDecl> {
+        let line = self.line();
+        self.expect(".data")?;
+        let label = self.name()?;
+        self.expect("=")?;
+        self.expect("bytearray")?;
+        let bytes = self.bytes()?;
+        Ok(DataDecl { line, label, bytes })
+    }
+
+    fn class(&mut self) -> Result<ClassDecl> {
+        let line = self.line();
+        self.expect(".class")?;
+
+        let mut bits = 0;
+        loop {
+            bits |= self.flags(&TYPE_FLAGS);
+            if self.eat("nested") {
+                bits |= self.flags(&NESTED_FLAGS);
+            } else if self.eat("beforefieldinit") {
+                bits |= TypeAttributes::BEFORE_FIELD_INIT.bits();
+            } else {
+                break;
+            }
+        }
+        let mut class = ClassDecl::new(
+            line,
+            TypeAttributes::from_bits_truncate(bits),
+            self.dotted_name()?,
+        );
+        class.generics = self.generic_params()?;
+        if self.eat("extends") {
+            class.extends = Some(self.ty()?);
+        }
+        if self.eat("implements") {
+            class.implements.push(self.ty()?);
+            while self.eat(",") {
+                class.implements.push(self.ty()?);
+            }
+        }
+
+        self.expect("{")?;
+        while !self.eat("}") {
+            if self.is(".class") {
+                class.nested.push(self.class()?);
+            } else if self.is(".field") {
+                class.fields.push(self.field()?);
+            } else if self.is(".method") {
+                class.methods.push(self.method()?);
+            } else if self.eat(".pack") {
+                class.pack = Some(self.int()? as u16);
+            } else if self.eat(".size") {
+                class.size = Some(self.int()? as u32);
+            } else {
+                return self.unexpected("a class member");
+            }
+        }
+
+        Ok(class)
+    }
+
+    /// `.field [4] public static int32 x = int32(0x1) at I_00002000`
+    fn field(&mut self) -> Result<FieldDecl> {
+        let line = self.line();
+        self.expect(".field")?;
+
+        let offset = if self.eat("[") {
+            let offset = self.int()? as u32;
+            self.expect("]")?;
+            Some(offset)
+        } else {
+            None
+        };
+        let flags = FieldAttributes::from_bits_truncate(self.flags(&FIELD_FLAGS) as u16);
+        let ty = self.ty()?;
+        let mods = self.custom_mods()?;
+        let name = self.name()?;
+        let constant = if self.eat("=") {
+            Some(self.constant()?)
+        } else {
+            None
+        };
+        let data = if self.eat("at") {
+            Some(self.name()?)
+        } else {
+            None
+        };
+
+        Ok(FieldDecl {
+            line,
+            offset,
+            flags,
+            ty,
+            mods,
+            name,
+            constant,
+            data,
+        })
+    }
+
+    /// `int32(0x1)`, `"string"` or `nullref`
+    fn constant(&mut self) -> Result<(ElementType, Vec<u8>)> {
+        if let Tok::Str(s) = self.peek().clone() {
+            self.bump();
+            let bytes = s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+            return Ok((ElementType::String, bytes));
+        }
+        if self.eat("nullref") {
+            return Ok((ElementType::Class, vec![0; 4]));
+        }
+
+        let ty = self.name()?;
+        self.expect("(")?;
+        let constant = match ty.as_str() {
+            "bool" => {
+                let value = if self.eat("true") {
+                    1
+                } else if self.eat("false") {
+                    0
+                } else {
+                    return self.unexpected("`true` or `false`");
+                };
+                (ElementType::Boolean, vec![value])
+            }
+            "float32" => (
+                ElementType::R4,
+                (self.float()? as f32).to_le_bytes().to_vec(),
+            ),
+            "float64" => (ElementType::R8, self.float()?.to_le_bytes().to_vec()),
+            _ => {
+                let (const_ty, size) = match ty.as_str() {
+                    "char" => (ElementType::Char, 2),
+                    "int8" => (ElementType::I1, 1),
+                    "uint8" => (ElementType::U1, 1),
+                    "int16" => (ElementType::I2, 2),
+                    "uint16" => (ElementType::U2, 2),
+                    "int32" => (ElementType::I4, 4),
+                    "uint32" => (ElementType::U4, 4),
+                    "int64" => (ElementType::I8, 8),
+                    "uint64" => (ElementType::U8, 8),
+                    _ => return self.error(format!("invalid constant type `{}`", ty)),
+                };
+                (const_ty, self.int()?.to_le_bytes()[..size].to_vec())
+            }
+        };
+        self.expect(")")?;
+
+        Ok(constant)
+    }
+
+    fn method(&mut self) -> Result<MethodDecl> {
+        let line = self.line();
+        self.expect(".method")?;
+
+        let mut bits = 0;
+        let mut pinvoke = None;
+        loop {
+            bits |= self.flags(&METHOD_FLAGS);
+            if !self.eat("pinvokeimpl") {
+                break;
+            }
+            self.expect("(")?;
+            let module = self.string()?;
+            let import_name = if self.eat("as") {
+                Some(self.string()?)
+            } else {
+                None
+            };
+            let flags = PInvokeAttributes::from_bits_truncate(self.flags(&PINVOKE_FLAGS) as u16);
+            self.expect(")")?;
+            bits |= MethodAttributes::PINVOKE_IMPL.bits() as u32;
+            pinvoke = Some(PInvokeDecl {
+                module,
+                import_name,
+                flags,
+            });
+        }
+
+        let conv = self.call_conv();
+        let ret = self.param()?;
+        let name = self.name()?;
+        let generics = self.generic_params()?;
+        let (params, param_names) = self.params()?.into_iter().unzip();
+        let impl_flags = MethodImplAttributes::from_bits_truncate(self.flags(&IMPL_FLAGS) as u16);
+
+        let mut method = MethodDecl {
+            line,
+            flags: MethodAttributes::from_bits_truncate(bits as u16),
+            impl_flags,
+            pinvoke,
+            sig: Sig {
+                conv,
+                generic_param_count: generics.len() as u32,
+                ret,
+                params,
+                sentinel: None,
+            },
+            name,
+            generics,
+            param_names,
+            entry_point: false,
+            body: None,
+        };
+        self.method_body(&mut method)?;
+
+        Ok(method)
+    }
+
+    fn method_body(&mut self, method: &mut MethodDecl) -> Result<()> {
+        let mut body = BodyDecl {
+            max_stack: 8,
+            init_locals: false,
+            locals: None,
+            insts: Vec::new(),
+            end_labels: Vec::new(),
+            tries: Vec::new(),
+        };
+        let mut has_body = false;
+        let mut labels = Vec::new();
+
+        self.expect("{")?;
+        while !self.eat("}") {
+            let line = self.line();
+
+            if self.eat(".entrypoint") {
+                method.entry_point = true;
+            } else if self.eat(".maxstack") {
+                body.max_stack = self.int()? as u16;
+                has_body = true;
+            } else if self.eat(".locals") {
+                body.init_locals = self.eat("init");
+                body.locals = Some(self.params()?.into_iter().map(|(p, _)| p).collect());
+                has_body = true;
+            } else if self.eat(".try") {
+                body.tries.push(self.try_clause(line)?);
+            } else if self.is_name() && self.peek_at(1) == &Tok::Punct(":") {
+                labels.push(self.name()?);
+                self.bump();
+            } else {
+                let operand_line = self.line();
+                let opcode = self.opcode()?;
+                let operand = self.operand(&opcode)?;
+                body.insts.push(InstDecl {
+                    line: operand_line,
+                    labels: std::mem::take(&mut labels),
+                    opcode,
+                    operand,
+                });
+                has_body = true;
+            }
+        }
+        body.end_labels = labels;
+
+        if has_body {
+            method.body = Some(body);
+        }
+        Ok(())
+    }
+
+    /// `.try A to B catch T handler C to D`
+    fn try_clause(&mut self, line: usize) -> Result<TryDecl> {
+        let try_start = self.name()?;
+        self.expect("to")?;
+        let try_end = self.name()?;
+        let handler = if self.eat("catch") {
+            HandlerDecl::Catch(self.ty()?)
+        } else if self.eat("filter") {
+            HandlerDecl::Filter(self.name()?)
+        } else if self.eat("finally") {
+            HandlerDecl::Finally
+        } else if self.eat("fault") {
+            HandlerDecl::Fault
+        } else {
+            return self.unexpected("a handler kind");
+        };
+        self.expect("handler")?;
+        let handler_start = self.name()?;
+        self.expect("to")?;
+        let handler_end = self.name()?;
+
+        Ok(TryDecl {
+            line,
+            try_start,
+            try_end,
+            handler,
+            handler_start,
+            handler_end,
+        })
+    }
+
+    fn opcode(&mut self) -> Result<Opcode> {
+        let mut name = match self.peek().clone() {
+            Tok::Id(s) => s,
+            _ => return self.unexpected("an instruction"),
+        };
+        self.bump();
+        // prefixes like `tail.`
+        let next = &self.tokens[self.pos];
+        if !next.spaced && next.tok == Tok::Punct(".") {
+            self.bump();
+            name.push('.');
+        }
+
+        match self.opcodes.get(&name) {
+            Some(opcode) => Ok(opcode.clone()),
+            None => self.error(format!("unknown instruction `{}`", name)),
+        }
+    }
+
+    fn operand(&mut self, opcode: &Opcode) -> Result<Operand> {
+        Ok(match opcode.kind {
+            OperandKind::Int if opcode.operand_size == 0 => Operand::None,
+            OperandKind::Int => Operand::Int(self.int()?),
+            OperandKind::R4 | OperandKind::R8 if self.is("(") => Operand::Bytes(self.bytes()?),
+            OperandKind::R4 | OperandKind::R8 => Operand::Float(self.float()?),
+            OperandKind::Branch => Operand::Label(self.name()?),
+            OperandKind::Switch => {
+                self.expect("(")?;
+                let mut labels = Vec::new();
+                while !self.eat(")") {
+                    if !labels.is_empty() {
+                        self.expect(",")?;
+                    }
+                    labels.push(self.name()?);
+                }
+                Operand::Labels(labels)
+            }
+            OperandKind::String => Operand::Str(self.string()?),
+            OperandKind::Field => Operand::Field(self.field_ref()?),
+            OperandKind::Method => Operand::Method(self.method_ref()?),
+            OperandKind::Type => Operand::Type(self.ty()?),
+            OperandKind::Token if self.eat("field") => Operand::Field(self.field_ref()?),
+            OperandKind::Token if self.eat("method") => Operand::Method(self.method_ref()?),
+            OperandKind::Token => Operand::Type(self.ty()?),
+            OperandKind::Sig => {
+                let conv = self.call_conv();
+                let ret = self.param()?;
+                let (params, sentinel) = self.call_site_params()?;
+                Operand::Sig(Sig {
+                    conv,
+                    generic_param_count: 0,
+                    ret,
+                    params,
+                    sentinel,
+                })
+            }
+        })
+    }
+}
+
+fn to_encoded(ty: TypeDefOrRef) -> TypeDefOrRefOrSpecEncoded {
+    match ty {
+        TypeDefOrRef::TypeDefIndex(index) => TypeDefOrRefOrSpecEncoded::TypeDef(index),
+        TypeDefOrRef::TypeRefIndex(index) => TypeDefOrRefOrSpecEncoded::TypeRef(index),
+        TypeDefOrRef::TypeSpecIndex(index) => TypeDefOrRefOrSpecEncoded::TypeSpec(index),
+    }
+}
+
+fn type_token(ty: TypeDefOrRef) -> MetadataToken {
+    match ty {
+        TypeDefOrRef::TypeDefIndex(index) => MetadataToken::TypeDef(index),
+        TypeDefOrRef::TypeRefIndex(index) => MetadataToken::TypeRef(index),
+        TypeDefOrRef::TypeSpecIndex(index) => MetadataToken::TypeSpec(index),
+    }
+}
+
+/// Bytes of an integer operand, which may be given signed or unsigned
+fn int_bytes(n: i64, size: usize) -> Option<Vec<u8>> {
+    let fits = match size {
+        0 => false,
+        8 => true,
+        _ => {
+            let bits = size as u32 * 8;
+            n >= -(1 << (bits - 1)) && n < (1 << bits)
+        }
+    };
+    if fits {
+        Some(n.to_le_bytes()[..size].to_vec())
+    } else {
+        None
+    }
+}
+
+struct Member<'s, T> {
+    owner: TypeDefIndex,
+    decl: &'s T,
+}
+
+/// Generic parameter waiting to be sorted by owner
+struct GenericEntry {
+    owner: TypeOrMethodDef,
+    number: u16,
+    flags: GenericParamAttributes,
+    name: StringIndex,
+    constraints: Vec<TypeDefOrRef>,
+}
+
+#[derive(Default)]
+struct Assembler {
+    image: ImageBuilder,
+    /// Line of the declaration being assembled
+    line: usize,
+    type_defs: HashMap<String, TypeDefIndex>,
+    assembly_refs: HashMap<String, AssemblyRefIndex>,
+    module_refs: HashMap<String, ModuleRefIndex>,
+    type_refs: HashMap<(u32, String), TypeRefIndex>,
+    type_specs: HashMap<Vec<u8>, TypeSpecIndex>,
+    member_refs: HashMap<(u32, String, Vec<u8>), MemberRefIndex>,
+    method_specs: HashMap<(u32, Vec<u8>), MethodSpecIndex>,
+    stand_alone_sigs: HashMap<Vec<u8>, StandAloneSigIndex>,
+    fields: HashMap<(TypeDefIndex, String, Vec<u8>), FieldIndex>,
+    methods: HashMap<(TypeDefIndex, String, Vec<u8>), MethodDefIndex>,
+    data: HashMap<String, u32>,
+    generic_params: Vec<GenericEntry>,
+}
+
+impl Assembler {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        Err(AsmError::new(self.line, message))
+    }
+
+    fn string(&mut self, s: &str) -> StringIndex {
+        self.image.metadata.heap.add_string(s)
+    }
+
+    fn blob(&mut self, blob: &[u8]) -> BlobIndex {
+        self.image.metadata.heap.add_blob(blob)
+    }
+
+    fn source(&mut self, source: &Source) -> Result<()> {
+        self.assemblies(source);
+
+        for data in source.data.iter() {
+            self.line = data.line;
+            if self.data.contains_key(&data.label) {
+                return self.error(format!("duplicate data label `{}`", data.label));
+            }
+            let rva = self.image.add_field_data(&data.bytes);
+            self.data.insert(data.label.clone(), rva);
+        }
+
+        // TypeDefs in preorder, <Module> first
+        let mut types = vec![(&source.globals, "<Module>".to_string(), None)];
+        flatten(&source.classes, None, &mut types);
+        for (i, (decl, key, _)) in types.iter().enumerate() {
+            self.line = decl.line;
+            let index = TypeDefIndex(i as u32 + 1);
+            if self.type_defs.insert(key.clone(), index).is_some() {
+                return self.error(format!("duplicate type `{}`", key));
+            }
+        }
+
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        for (i, (decl, _, enclosing)) in types.iter().enumerate() {
+            let index = TypeDefIndex(i as u32 + 1);
+            self.type_def(index, decl, *enclosing)?;
+            fields.extend(decl.fields.iter().map(|decl| Member { owner: index, decl }));
+            methods.extend(
+                decl.methods
+                    .iter()
+                    .map(|decl| Member { owner: index, decl }),
+            );
+        }
+
+        for field in fields {
+            self.field(field)?;
+        }
+        let mut bodies = Vec::new();
+        for method in methods.iter() {
+            bodies.push(self.method(method)?);
+        }
+        for (method, index) in methods.iter().zip(bodies) {
+            if let Some(body) = &method.decl.body {
+                self.line = method.decl.line;
+                let body = self.body(body)?;
+                let rva = self.image.add_method_body(&body);
+                let row = &mut self.image.metadata.table.method_def[index.0 as usize - 1];
+                row.rva = rva;
+            }
+        }
+
+        self.generic_param_rows();
+        self.sort_tables();
+        Ok(())
+    }
+
+    fn assemblies(&mut self, source: &Source) {
+        let module_name = match (&source.module, &source.assembly) {
+            (Some(name), _) => name.clone(),
+            (None, Some(assembly)) => format!("{}.dll", assembly.name),
+            (None, None) => "module.dll".into(),
+        };
+        let name = self.string(&module_name);
+        let mvid = self.image.metadata.heap.add_guid([0; 16]);
+        self.image.metadata.table.module.push(Module {
+            generation: 0,
+            name,
+            mvid,
+            enc_id: GuidIndex(0),
+            env_base_id: GuidIndex(0),
+        });
+
+        if let Some(assembly) = &source.assembly {
+            let flags = if assembly.key.is_empty() {
+                AssemblyFlags::empty()
+            } else {
+                AssemblyFlags::PUBLIC_KEY
+            };
+            let row = Assembly {
+                hash_alg_id: AssemblyHashAlgorithm::SHA1,
+                version: assembly.version,
+                flags,
+                public_key: self.blob(&assembly.key),
+                name: self.string(&assembly.name),
+                culture: self.string(&assembly.culture),
+            };
+            self.image.metadata.table.assembly.push(row);
+        }
+
+        for assembly in source.assembly_refs.iter() {
+            let index = self.assembly_ref(&assembly.name);
+            let row = &mut self.image.metadata.table.assembly_ref[index.0 as usize - 1];
+            row.version = assembly.version;
+            let key = assembly.key.clone();
+            let key = self.blob(&key);
+            let culture = self.string(&assembly.culture);
+            let row = &mut self.image.metadata.table.assembly_ref[index.0 as usize - 1];
+            row.public_key_or_token = key;
+            row.culture = culture;
+        }
+
+        for forwarder in source.forwarders.iter() {
+            let (namespace, name) = split_name(&forwarder.name);
+            let implementation =
+                Implementation::AssemblyRefIndex(self.assembly_ref(&forwarder.assembly));
+            let row = ExportedType {
+                flags: TypeAttributes::IS_TYPE_FORWARDER,
+                def_id: 0,
+                name: self.string(&name),
+                namespace: self.string(&namespace),
+                implementation,
+            };
+            self.image.metadata.table.exported_type.push(row);
+        }
+    }
+
+    fn assembly_ref(&mut self, name: &str) -> AssemblyRefIndex {
+        if let Some(index) = self.assembly_refs.get(name) {
+            return *index;
+        }
+
+        let row = AssemblyRef {
+            version: AssemblyVersion {
+                major_version: 0,
+                minor_version: 0,
+                build_number: 0,
+                revision_number: 0,
+            },
+            flags: AssemblyFlags::empty(),
+            public_key_or_token: BlobIndex(0),
+            name: self.string(name),
+            culture: StringIndex(0),
+            hash_value: BlobIndex(0),
+        };
+        let table = &mut self.image.metadata.table.assembly_ref;
+        table.push(row);
+        let index = AssemblyRefIndex(table.len() as u32);
+        self.assembly_refs.insert(name.to_string(), index);
+        index
+    }
+
+    fn module_ref(&mut self, name: &str) -> ModuleRefIndex {
+        if let Some(index) = self.module_refs.get(name) {
+            return *index;
+        }
+
+        let row = ModuleRef {
+            name: self.string(name),
+        };
+        let table = &mut self.image.metadata.table.module_ref;
+        table.push(row);
+        let index = ModuleRefIndex(table.len() as u32);
+        self.module_refs.insert(name.to_string(), index);
+        index
+    }
+
+    fn type_def(
+        &mut self,
+        index: TypeDefIndex,
+        decl: &ClassDecl,
+        enclosing: Option<TypeDefIndex>,
+    ) -> Result<()> {
+        self.line = decl.line;
+        let (namespace, name) = match enclosing {
+            Some(_) => (String::new(), decl.name.join(".")),
+            None => split_name(&decl.name),
+        };
+        let extends = match &decl.extends {
+            Some(ty) => self.token_type(ty)?,
+            None => TypeDefOrRef::TypeDefIndex(TypeDefIndex(0)),
+        };
+
+        let type_name = self.string(&name);
+        let type_namespace = self.string(&namespace);
+        let table = &self.image.metadata.table;
+        let row = TypeDef {
+            flags: decl.flags,
+            type_name,
+            type_namespace,
+            extends,
+            field_list: (table.field.len() as u32 + 1).into(),
+            method_list: (table.method_def.len() as u32 + 1).into(),
+        };
+        // reserve the rows so the next type's lists start after this one's
+        let table = &mut self.image.metadata.table;
+        table.type_def.push(row);
+        table.field.extend(decl.fields.iter().map(|_| Field {
+            flags: FieldAttributes::empty(),
+            name: StringIndex(0),
+            signature: BlobIndex(0),
+        }));
+        table
+            .method_def
+            .extend(decl.methods.iter().map(|_| MethodDef {
+                rva: 0,
+                impl_flags: MethodImplAttributes::empty(),
+                flags: MethodAttributes::empty(),
+                name: StringIndex(0),
+                signature: BlobIndex(0),
+                param_list: ParamIndex(0),
+            }));
+
+        if let Some(enclosing) = enclosing {
+            table.nested_class.push(NestedClass {
+                nested_class: index,
+                enclosing_class: enclosing,
+            });
+        }
+        if decl.pack.is_some() || decl.size.is_some() {
+            table.class_layout.push(ClassLayout {
+                packing_size: decl.pack.unwrap_or(0),
+                class_size: decl.size.unwrap_or(0),
+                parent: index,
+            });
+        }
+        for interface in decl.implements.iter() {
+            let interface = self.token_type(interface)?;
+            self.image
+                .metadata
+                .table
+                .interface_impl
+                .push(InterfaceImpl {
+                    class: index,
+                    interface,
+                });
+        }
+        self.generics(TypeOrMethodDef::TypeDefIndex(index), &decl.generics)?;
+
+        Ok(())
+    }
+
+    fn generics(&mut self, owner: TypeOrMethodDef, generics: &[GenericDecl]) -> Result<()> {
+        for (number, param) in generics.iter().enumerate() {
+            let constraints = param
+                .constraints
+                .iter()
+                .map(|c| self.token_type(c))
+                .collect::<Result<_>>()?;
+            let name = self.string(&param.name);
+            self.generic_params.push(GenericEntry {
+                owner,
+                number: number as u16,
+                flags: param.flags,
+                name,
+                constraints,
+            });
+        }
+        Ok(())
+    }
+
+    /// Fields are numbered in declaration order, so the n-th call fills row n
+    fn field(&mut self, field: Member<FieldDecl>) -> Result<()> {
+        let decl = field.decl;
+        self.line = decl.line;
+        let ty = self.ty(&decl.ty)?;
+        let mods = self.custom_mods(&decl.mods)?;
+        let signature = FieldSig { mods, ty }.to_bytes();
+
+        let mut flags = decl.flags;
+        if decl.constant.is_some() {
+            flags |= FieldAttributes::HAS_DEFAULT;
+        }
+        if decl.data.is_some() {
+            flags |= FieldAttributes::HAS_FIELD_RVA;
+        }
+
+        let index = FieldIndex(self.fields.len() as u32 + 1);
+        let row = Field {
+            flags,
+            name: self.string(&decl.name),
+            signature: self.blob(&signature),
+        };
+        self.image.metadata.table.field[index.0 as usize - 1] = row;
+        self.fields
+            .insert((field.owner, decl.name.clone(), signature), index);
+
+        if let Some(offset) = decl.offset {
+            self.image.metadata.table.field_layout.push(FieldLayout {
+                offset,
+                field: index,
+            });
+        }
+        if let Some((const_ty, value)) = &decl.constant {
+            let value = self.blob(value);
+            self.image.metadata.table.constant.push(Constant {
+                const_ty: *const_ty,
+                parent: HasConstant::FieldIndex(index),
+                value,
+            });
+        }
+        if let Some(label) = &decl.data {
+            let rva = match self.data.get(label) {
+                Some(rva) => *rva,
+                None => return self.error(format!("undefined data label `{}`", label)),
+            };
+            self.image
+                .metadata
+                .table
+                .field_rva
+                .push(FieldRVA { rva, field: index });
+        }
+
+        Ok(())
+    }
+
+    fn method(&mut self, method: &Member<MethodDecl>) -> Result<MethodDefIndex> {
+        let decl = method.decl;
+        self.line = decl.line;
+        let signature = self.sig(&decl.sig)?.to_bytes();
+
+        let index = MethodDefIndex(self.methods.len() as u32 + 1);
+        let table = &self.image.metadata.table;
+        let param_list = ParamIndex(table.param.len() as u32 + 1);
+        let row = MethodDef {
+            rva: 0,
+            impl_flags: decl.impl_flags,
+            flags: decl.flags,
+            name: self.string(&decl.name),
+            signature: self.blob(&signature),
+            param_list,
+        };
+        self.image.metadata.table.method_def[index.0 as usize - 1] = row;
+        let key = (method.owner, decl.name.clone(), signature);
+        if self.methods.insert(key, index).is_some() {
+            return self.error(format!("duplicate method `{}`", decl.name));
+        }
+
+        for (i, name) in decl.param_names.iter().enumerate() {
+            if let Some(name) = name {
+                let row = ParamRow {
+                    flags: ParamAttributes::empty(),
+                    sequence: i as u16 + 1,
+                    name: self.string(name),
+                };
+                self.image.metadata.table.param.push(row);
+            }
+        }
+
+        self.generics(TypeOrMethodDef::MethodDefIndex(index), &decl.generics)?;
+
+        if let Some(pinvoke) = &decl.pinvoke {
+            let row = ImplMap {
+                mapping_flags: pinvoke.flags,
+                member_forwarded: MemberForwarded::MethodDefIndex(index),
+                import_name: self.string(pinvoke.import_name.as_ref().unwrap_or(&decl.name)),
+                import_scope: self.module_ref(&pinvoke.module),
+            };
+            self.image.metadata.table.impl_map.push(row);
+        }
+
+        if decl.entry_point {
+            if self.image.entry_point.is_some() {
+                return self.error("more than one .entrypoint");
+            }
+            self.image.entry_point = Some(index);
+        }
+
+        Ok(index)
+    }
+
+    fn body(&mut self, body: &BodyDecl) -> Result<Vec<u8>> {
+        let local_var_sig = match &body.locals {
+            Some(locals) => {
+                let locals = locals
+                    .iter()
+                    .map(|l| self.local(l))
+                    .collect::<Result<_>>()?;
+                let sig = LocalVarSig { locals }.to_bytes();
+                Some(self.stand_alone_sig(sig))
+            }
+            None => None,
+        };
+
+        let mut labels = HashMap::new();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for inst in body.insts.iter() {
+            for label in inst.labels.iter() {
+                if labels.insert(label.as_str(), offset).is_some() {
+                    return Err(AsmError::new(
+                        inst.line,
+                        format!("duplicate label `{}`", label),
+                    ));
+                }
+            }
+            offsets.push(offset);
+            offset += inst.size();
+        }
+        let code_size = offset;
+        for label in body.end_labels.iter() {
+            labels.insert(label.as_str(), code_size);
+        }
+
+        // `IL_xxxx` which isn't defined refers to that offset, like the end of the last handler
+        let target = |label: &str, line: usize| -> Result<u32> {
+            labels
+                .get(label)
+                .copied()
+                .or_else(|| {
+                    let hex = label.strip_prefix("IL_")?;
+                    u32::from_str_radix(hex, 16).ok()
+                })
+                .ok_or_else(|| AsmError::new(line, format!("undefined label `{}`", label)))
+        };
+
+        let mut instructions = Vec::new();
+        for (i, inst) in body.insts.iter().enumerate() {
+            self.line = inst.line;
+            let next = offsets.get(i + 1).copied().unwrap_or(code_size) as i64;
+            let size = inst.opcode.operand_size;
+            let mut bytes = inst.opcode.bytes.clone();
+
+            match &inst.operand {
+                Operand::None => {}
+                Operand::Int(n) => match int_bytes(*n, size) {
+                    Some(n) => bytes.extend(n),
+                    None => return self.error(format!("operand {} is out of range", n)),
+                },
+                Operand::Float(f) if size == 4 => bytes.extend((*f as f32).to_le_bytes()),
+                Operand::Float(f) => bytes.extend(f.to_le_bytes()),
+                Operand::Bytes(b) if b.len() == size => bytes.extend(b),
+                Operand::Bytes(_) => return self.error(format!("expected {} bytes", size)),
+                Operand::Label(label) => {
+                    let rel = target(label, inst.line)? as i64 - next;
+                    let fits = if size == 1 {
+                        i8::MIN as i64 <= rel && rel <= i8::MAX as i64
+                    } else {
+                        i32::MIN as i64 <= rel && rel <= i32::MAX as i64
+                    };
+                    if !fits {
+                        return self.error(format!("branch to `{}` is out of range", label));
+                    }
+                    bytes.extend_from_slice(&rel.to_le_bytes()[..size]);
+                }
+                Operand::Labels(targets) => {
+                    bytes.extend((targets.len() as u32).to_le_bytes());
+                    for label in targets {
+                        let rel = target(label, inst.line)? as i64 - next;
+                        bytes.extend((rel as i32).to_le_bytes());
+                    }
+                }
+                operand => {
+                    let token = self.token(operand)?;
+                    bytes.extend(token.to_raw().to_le_bytes());
+                }
+            }
+
+            match bytes.pread_with::<Instruction>(0, scroll::LE) {
+                Ok(inst) => instructions.push(inst),
+                Err(e) => return self.error(e.to_string()),
+            }
+        }
+
+        let mut exception_clauses = Vec::new();
+        for clause in body.tries.iter() {
+            self.line = clause.line;
+            let try_offset = target(&clause.try_start, clause.line)?;
+            let handler_offset = target(&clause.handler_start, clause.line)?;
+            let kind = match &clause.handler {
+                HandlerDecl::Catch(ty) => {
+                    ExceptionClauseKind::Catch(type_token(self.token_type(ty)?))
+                }
+                HandlerDecl::Filter(label) => {
+                    ExceptionClauseKind::Filter(target(label, clause.line)?)
+                }
+                HandlerDecl::Finally => ExceptionClauseKind::Finally,
+                HandlerDecl::Fault => ExceptionClauseKind::Fault,
+            };
+            let try_length = match target(&clause.try_end, clause.line)?.checked_sub(try_offset) {
+                Some(length) => length,
+                None => return self.error("try block ends before it starts"),
+            };
+            let handler_length =
+                match target(&clause.handler_end, clause.line)?.checked_sub(handler_offset) {
+                    Some(length) => length,
+                    None => return self.error("handler ends before it starts"),
+                };
+            exception_clauses.push(ExceptionClause {
+                kind,
+                try_offset,
+                try_length,
+                handler_offset,
+                handler_length,
+            });
+        }
+
+        let body = MethodBody {
+            max_stack: body.max_stack,
+            init_locals: body.init_locals,
+            local_var_sig,
+            code_size,
+            instructions,
+            offsets,
+            exception_clauses,
+        };
+        Ok(body.encode())
+    }
+
+    /// Token operand of an instruction
+    fn token(&mut self, operand: &Operand) -> Result<MetadataToken> {
+        Ok(match operand {
+            Operand::Str(s) => {
+                MetadataToken::UserString(self.image.metadata.heap.add_user_string(s))
+            }
+            Operand::Type(ty) => type_token(self.token_type(ty)?),
+            Operand::Field(field) => self.field_token(field)?,
+            Operand::Method(method) => self.method_token(method)?,
+            Operand::Sig(sig) => {
+                let sig = self.call_site_sig(sig)?.to_bytes();
+                MetadataToken::StandAloneSig(self.stand_alone_sig(sig))
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    fn stand_alone_sig(&mut self, sig: Vec<u8>) -> StandAloneSigIndex {
+        if let Some(index) = self.stand_alone_sigs.get(&sig) {
+            return *index;
+        }
+
+        let signature = self.blob(&sig);
+        let table = &mut self.image.metadata.table.stand_along_sig;
+        table.push(StandAloneSig { signature });
+        let index = StandAloneSigIndex(table.len() as u32);
+        self.stand_alone_sigs.insert(sig, index);
+        index
+    }
+
+    fn member_ref(&mut self, class: MemberRefParent, name: &str, sig: Vec<u8>) -> MemberRefIndex {
+        let key = (class.encode(), name.to_string(), sig);
+        if let Some(index) = self.member_refs.get(&key) {
+            return *index;
+        }
+
+        let row = MemberRef {
+            class,
+            name: self.string(name),
+            signature: self.blob(&key.2),
+        };
+        let table = &mut self.image.metadata.table.member_ref;
+        table.push(row);
+        let index = MemberRefIndex(table.len() as u32);
+        self.member_refs.insert(key, index);
+        index
+    }
+
+    fn member_parent(&mut self, owner: &Owner) -> Result<MemberRefParent> {
+        Ok(match owner {
+            Owner::Module(name) => MemberRefParent::ModuleRefIndex(self.module_ref(name)),
+            Owner::Type(ty) => match self.token_type(ty)? {
+                TypeDefOrRef::TypeDefIndex(index) => MemberRefParent::TypeDefIndex(index),
+                TypeDefOrRef::TypeRefIndex(index) => MemberRefParent::TypeRefIndex(index),
+                TypeDefOrRef::TypeSpecIndex(index) => MemberRefParent::TypeSpecIndex(index),
+            },
+        })
+    }
+
+    /// Field of a type in this module, otherwise a MemberRef
+    fn field_token(&mut self, field: &FieldRef) -> Result<MetadataToken> {
+        let ty = self.ty(&field.ty)?;
+        let mods = self.custom_mods(&field.mods)?;
+        let sig = FieldSig { mods, ty }.to_bytes();
+        let class = self.member_parent(&field.owner)?;
+
+        if let MemberRefParent::TypeDefIndex(owner) = class {
+            if let Some(index) = self.fields.get(&(owner, field.name.clone(), sig.clone())) {
+                return Ok(MetadataToken::Field(*index));
+            }
+        }
+        Ok(MetadataToken::MemberRef(self.member_ref(
+            class,
+            &field.name,
+            sig,
+        )))
+    }
+
+    /// Method of a type in this module, otherwise a MemberRef, wrapped in a MethodSpec with generic arguments
+    fn method_token(&mut self, method: &MethodRef) -> Result<MetadataToken> {
+        let mut sig = method.sig.clone();
+        if let Some(args) = &method.generic_args {
+            sig.generic_param_count = args.len() as u32;
+        }
+        let sig = self.call_site_sig(&sig)?.to_bytes();
+        let class = self.member_parent(&method.owner)?;
+
+        let def = match class {
+            MemberRefParent::TypeDefIndex(owner) => self
+                .methods
+                .get(&(owner, method.name.clone(), sig.clone()))
+                .copied(),
+            _ => None,
+        };
+        let method_def_or_ref = match def {
+            Some(index) => MethodDefOrRef::MethodDefIndex(index),
+            None => MethodDefOrRef::MemberRefIndex(self.member_ref(class, &method.name, sig)),
+        };
+
+        let args = match &method.generic_args {
+            Some(args) => args,
+            None => {
+                return Ok(match method_def_or_ref {
+                    MethodDefOrRef::MethodDefIndex(index) => MetadataToken::MethodDef(index),
+                    MethodDefOrRef::MemberRefIndex(index) => MetadataToken::MemberRef(index),
+                })
+            }
+        };
+
+        let args = args.iter().map(|a| self.ty(a)).collect::<Result<_>>()?;
+        let instantiation = MethodSpecSig { args }.to_bytes();
+        let key = (method_def_or_ref.encode(), instantiation);
+        if let Some(index) = self.method_specs.get(&key) {
+            return Ok(MetadataToken::MethodSpec(*index));
+        }
+
+        let row = MethodSpec {
+            method: method_def_or_ref,
+            instantiation: self.blob(&key.1),
+        };
+        let table = &mut self.image.metadata.table.method_spec;
+        table.push(row);
+        let index = MethodSpecIndex(table.len() as u32);
+        self.method_specs.insert(key, index);
+        Ok(MetadataToken::MethodSpec(index))
+    }
+
+    /// TypeDef or TypeRef of a name
+    fn type_name(&mut self, name: &TypeName) -> Result<TypeDefOrRef> {
+        let scope = match &name.scope {
+            Scope::Local => {
+                return match self.type_defs.get(&name.key()) {
+                    Some(index) => Ok(TypeDefOrRef::TypeDefIndex(*index)),
+                    None => self.error(format!("undefined type `{}`", name.key())),
+                }
+            }
+            Scope::Assembly(assembly) => {
+                ResolutionScope::AssemblyRefIndex(self.assembly_ref(assembly))
+            }
+            Scope::Module(module) => ResolutionScope::ModuleRefIndex(self.module_ref(module)),
+        };
+
+        let mut scope = scope;
+        let mut index = TypeRefIndex(0);
+        for parts in name.path.iter() {
+            let key = (scope.encode(), parts.join("."));
+            index = match self.type_refs.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let (namespace, name) = split_name(parts);
+                    let row = TypeRef {
+                        resolution_scope: scope,
+                        type_name: self.string(&name),
+                        type_namespace: self.string(&namespace),
+                    };
+                    let table = &mut self.image.metadata.table.type_ref;
+                    table.push(row);
+                    let index = TypeRefIndex(table.len() as u32);
+                    self.type_refs.insert(key, index);
+                    index
+                }
+            };
+            scope = ResolutionScope::TypeRefIndex(index);
+        }
+
+        Ok(TypeDefOrRef::TypeRefIndex(index))
+    }
+
+    fn encoded(&mut self, name: &TypeName) -> Result<TypeDefOrRefOrSpecEncoded> {
+        Ok(to_encoded(self.type_name(name)?))
+    }
+
+    fn custom_mods(&mut self, mods: &[Modifier]) -> Result<Vec<CustomMod>> {
+        let mut out = Vec::new();
+        for m in mods {
+            let ty = to_encoded(self.token_type(&m.ty)?);
+            out.push(if m.required {
+                CustomMod::Reqd(ty)
+            } else {
+                CustomMod::Opt(ty)
+            });
+        }
+        Ok(out)
+    }
+
+    /// A bare name is a TypeDef or TypeRef, anything else a TypeSpec
+    fn token_type(&mut self, ty: &Ty) -> Result<TypeDefOrRef> {
+        if let Ty::Bare(name) = ty {
+            return self.type_name(name);
+        }
+
+        let sig = self.ty(ty)?.to_bytes();
+        if let Some(index) = self.type_specs.get(&sig) {
+            return Ok(TypeDefOrRef::TypeSpecIndex(*index));
+        }
+
+        let signature = self.blob(&sig);
+        let table = &mut self.image.metadata.table.type_spec;
+        table.push(TypeSpec { signature });
+        let index = TypeSpecIndex(table.len() as u32);
+        self.type_specs.insert(sig, index);
+        Ok(TypeDefOrRef::TypeSpecIndex(index))
+    }
+
+    fn ty(&mut self, ty: &Ty) -> Result<Type> {
+        Ok(match ty {
+            Ty::Prim(ty) => ty.clone(),
+            Ty::Named {
+                value_type: true,
+                name,
+            } => Type::ValueType(self.encoded(name)?),
+            Ty::Named {
+                value_type: false,
+                name,
+            } => Type::Class(self.encoded(name)?),
+            Ty::Bare(name) => {
+                return self.error(format!(
+                    "`{}` needs `class` or `valuetype` in a signature",
+                    name.key()
+                ))
+            }
+            Ty::Generic {
+                value_type,
+                name,
+                args,
+            } => Type::GenericInst {
+                is_value_type: *value_type,
+                ty: self.encoded(name)?,
+                args: args.iter().map(|a| self.ty(a)).collect::<Result<_>>()?,
+            },
+            Ty::SzArray(element_ty, mods) => Type::SzArray {
+                element_ty: Box::new(self.ty(element_ty)?),
+                mods: self.custom_mods(mods)?,
+            },
+            Ty::Array(element_ty, shape) => Type::Array {
+                element_ty: Box::new(self.ty(element_ty)?),
+                shape: shape.clone(),
+            },
+            Ty::Ptr(ty, mods) => Type::Ptr {
+                ty: match ty {
+                    Some(ty) => Some(Box::new(self.ty(ty)?)),
+                    None => None,
+                },
+                mods: self.custom_mods(mods)?,
+            },
+            Ty::FnPtr(sig) => Type::FnPtr(Box::new(self.sig(sig)?)),
+            Ty::Var(n) => Type::Var { number: U(*n) },
+            Ty::MVar(n) => Type::MVar { number: U(*n) },
+        })
+    }
+
+    fn sig(&mut self, sig: &Sig) -> Result<MethodDefSig> {
+        let mut calling_convension = sig.conv;
+        if sig.generic_param_count > 0 {
+            calling_convension |= MethodCallingConvension::GENERIC;
+        }
+
+        let ret = match &sig.ret {
+            ParamTy::Void(mods) => RetType::Void {
+                mods: self.custom_mods(mods)?,
+            },
+            ParamTy::TypedByref(mods) => RetType::TypedByref {
+                mods: self.custom_mods(mods)?,
+            },
+            ParamTy::Type { pinned: true, .. } => return self.error("return type can't be pinned"),
+            ParamTy::Type {
+                mods, byref, ty, ..
+            } => RetType::Type {
+                mods: self.custom_mods(mods)?,
+                byref: *byref,
+                ty: self.ty(ty)?,
+            },
+        };
+        let mut params = Vec::new();
+        for param in sig.params.iter() {
+            params.push(match param {
+                ParamTy::Void(_) => return self.error("parameter can't be void"),
+                ParamTy::TypedByref(mods) => Param::TypedByref {
+                    mods: self.custom_mods(mods)?,
+                },
+                ParamTy::Type { pinned: true, .. } => {
+                    return self.error("parameter can't be pinned")
+                }
+                ParamTy::Type {
+                    mods, byref, ty, ..
+                } => Param::Type {
+                    mods: self.custom_mods(mods)?,
+                    byref: *byref,
+                    ty: self.ty(ty)?,
+                },
+            });
+        }
+
+        Ok(MethodDefSig {
+            calling_convension,
+            generic_param_count: sig.generic_param_count,
+            ret,
+            params,
+        })
+    }
+
+    /// Signature of a call, the parameters after the sentinel become varargs
+    fn call_site_sig(&mut self, sig: &Sig) -> Result<MethodRefSig> {
+        let mut method = self.sig(sig)?;
+        let varargs = match sig.sentinel {
+            Some(_) if !sig.conv.is_vararg() => {
+                return self.error("`...` outside a vararg signature")
+            }
+            Some(index) => method.params.split_off(index),
+            None => Vec::new(),
+        };
+        Ok(MethodRefSig { method, varargs })
+    }
+
+    fn local(&mut self, local: &ParamTy) -> Result<LocalVar> {
+        Ok(match local {
+            ParamTy::Void(_) => return self.error("local can't be void"),
+            ParamTy::TypedByref(mods) if !mods.is_empty() => {
+                return self.error("local typedref can't have custom modifiers")
+            }
+            ParamTy::TypedByref(_) => LocalVar::TypedByref,
+            ParamTy::Type {
+                mods,
+                byref,
+                pinned,
+                ty,
+            } => LocalVar::Type {
+                mods: self.custom_mods(mods)?,
+                pinned: *pinned,
+                byref: *byref,
+                ty: self.ty(ty)?,
+            },
+        })
+    }
+
+    /// GenericParam rows sorted by owner, then their constraints
+    fn generic_param_rows(&mut self) {
+        let mut params = std::mem::take(&mut self.generic_params);
+        params.sort_by_key(|p| (p.owner.encode(), p.number));
+
+        let table = &mut self.image.metadata.table;
+        for (i, param) in params.into_iter().enumerate() {
+            table.generic_param.push(GenericParam {
+                number: param.number,
+                flags: param.flags,
+                owner: param.owner,
+                name: param.name,
+            });
+            for constraint in param.constraints {
+                table.generic_param_constraint.push(GenericParamConstraint {
+                    owner: GenericParamIndex(i as u32 + 1),
+                    constraint,
+                });
+            }
+        }
+    }
+
+    /// II.22, order rows of sorted tables by their key
+    fn sort_tables(&mut self) {
+        let table = &mut self.image.metadata.table;
+        table
+            .interface_impl
+            .sort_by_key(|r| (r.class.0, r.interface.encode()));
+        table.constant.sort_by_key(|r| r.parent.encode());
+        table.field_layout.sort_by_key(|r| r.field.0);
+        table.class_layout.sort_by_key(|r| r.parent.0);
+        table.impl_map.sort_by_key(|r| r.member_forwarded.encode());
+        table.field_rva.sort_by_key(|r| r.field.0);
+        table.nested_class.sort_by_key(|r| r.nested_class.0);
+    }
+}
+
+/// Classes in preorder with their lookup key and enclosing type
+fn flatten<'s>(
+    classes: &'s [ClassDecl],
+    enclosing: Option<(TypeDefIndex, &str)>,
+    out: &mut Vec<(&'s ClassDecl, String, Option<TypeDefIndex>)>,
+) {
+    for class in classes {
+        let name = class.name.join(".");
+        let key = match enclosing {
+            Some((_, outer)) => format!("{}/{}", outer, name),
+            None => name,
+        };
+        let index = TypeDefIndex(out.len() as u32 + 1);
+        out.push((class, key.clone(), enclosing.map(|(index, _)| index)));
+        flatten(&class.nested, Some((index, &key)), out);
+    }
+}
+
+#[cfg(test)]
+fn round_trip(source: &str) -> String {
+    use super::disasm::disassemble;
+    use crate::pe::Image;
+
+    let bytes = assemble(source).unwrap();
+    let text = disassemble(&Image::from_bytes(&bytes).unwrap());
+    let again = assemble(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+    assert_eq!(disassemble(&Image::from_bytes(&again).unwrap()), text);
+    text
+}
+
+#[test]
+fn assemble_hello() {
+    let text = round_trip(include_str!("../../../tests/il/hello.il"));
+
+    assert!(text.contains(".entrypoint"));
+    assert!(text.contains("ldstr      \"Hello, World!\""));
+    assert!(text.contains("call       void [mscorlib]System.Console::WriteLine(string)"));
+}
+
+#[test]
+fn assemble_edge_cases() {
+    let text = round_trip(include_str!("../../../tests/il/edge_cases.il"));
+
+    assert!(text.contains("switch"));
+    assert!(text.contains("add.ovf"));
+    assert!(text.contains("volatile."));
+    assert!(text.contains(".data I_"));
+    assert!(text.contains("call       vararg int32 '<Module>'::Count(int32, ..., int32, string)"));
+    assert!(text.contains(
+        "ldsfld     int32 modreq([mscorlib]System.Runtime.CompilerServices.IsVolatile) \
+         Modifiers::counter"
+    ));
+    assert!(text.contains(
+        "void modreq([mscorlib]System.Runtime.CompilerServices.IsExternalInit)  set_X(int32 v)"
+    ));
+    assert!(text.contains("int32 modopt([mscorlib]System.Runtime.CompilerServices.IsLong)[] a"));
+}
+
+#[test]
+fn assemble_errors() {
+    let error = assemble(".method static void M() cil managed { bogus ret }").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: unknown instruction `bogus`");
+
+    let error = assemble(".method static void M() cil managed {\n br Missing\n}").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: undefined label `Missing`");
+
+    let error = assemble(".method static void M() cil managed { ldc.i4.s 300 }").unwrap_err();
+    assert_eq!(error.line, 1);
+
+    let error = assemble(
+        ".method static void M() cil managed {\n A: nop\n B: leave.s C\n C: ret\n .try B to A finally handler B to C\n}",
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "line 5: try block ends before it starts");
+}
@@ -0,0 +1,667 @@
+//! Basic blocks, dominators, loops and stack depths of a method body
+
+use std::collections::BTreeSet;
+
+use scroll::Pread;
+
+use super::{ExceptionClauseKind, Instruction, MethodBody};
+use crate::pe::{
+    Heap, MetadataTable, MetadataToken, MethodCallingConvension, MethodDefOrRef, MethodDefSig,
+    MethodRefSig, RetType, TableIndex,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CfgError {
+    /// IL offset of the offending instruction
+    pub offset: u32,
+    pub message: String,
+}
+
+impl CfgError {
+    fn new(offset: u32, message: impl Into<String>) -> Self {
+        Self {
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for CfgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "IL_{:04x}: {}", self.offset, self.message)
+    }
+}
+
+impl std::error::Error for CfgError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// IL offset of the first instruction
+    pub offset: u32,
+    /// Instruction indices `start..end` of the body
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+    /// Handler and filter entries of try regions covering this block
+    pub handlers: Vec<usize>,
+    /// Blocks reaching this one by a normal or an exceptional edge
+    pub predecessors: Vec<usize>,
+    /// Evaluation stack depth on entry, `None` when unreachable
+    pub stack_depth: Option<u32>,
+}
+
+/// A natural loop, nested loops are listed separately
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// Blocks of the loop including the header, sorted
+    pub blocks: Vec<usize>,
+    /// Sources of the back edges to the header
+    pub latches: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cfg {
+    /// Ordered by offset, the entry is the first one
+    pub blocks: Vec<BasicBlock>,
+    /// Immediate dominator of each block, none for the entry and unreachable blocks
+    pub idom: Vec<Option<usize>>,
+    pub loops: Vec<Loop>,
+}
+
+impl Instruction {
+    /// Number of values popped and pushed, `None` when a callee signature can't be resolved.
+    /// `leave` and `endfinally` empty the whole stack and `ret` is counted as neither.
+    pub fn stack_effect(&self, table: &MetadataTable, heap: Heap) -> Option<(u32, u32)> {
+        use Instruction::*;
+
+        let effect = match self {
+            Nop | Break | Br(_) | Leave(_) | EndFinally | Rethrow | Ret | Jmp(_) => (0, 0),
+            Unaligned(_) | Volatile | Tail | Constrained(_) | No(_) | Readonly => (0, 0),
+
+            LdArg(_) | LdArgA(_) | LdLoc(_) | LdLocA(_) | LdNull | LdcI4(_) | LdcI8(_)
+            | LdcR4(_) | LdcR8(_) | LdStr(_) | LdSFld(_) | LdSFldA(_) | LdToken(_) | ArgList
+            | LdFtn(_) | SizeOf(_) => (0, 1),
+
+            StArg(_) | StLoc(_) | Pop | StSFld(_) | BrTrue(_) | BrFalse(_) | Switch(_) | Throw
+            | EndFilter | InitObj(_) => (1, 0),
+
+            LdFld(_) | LdFldA(_) | Neg | Not | Conv(_) | ConvOvf(_) | ConvOvfUn(_) | CkFinite
+            | LdInd(_) | LdObj(_) | NewArr(_) | LdLen | CastClass(_) | IsInst(_) | Box(_)
+            | Unbox(_) | UnboxAny(_) | LdVirtFtn(_) | MkRefAny(_) | RefAnyVal(_) | RefAnyType
+            | LocAlloc => (1, 1),
+
+            Dup => (1, 2),
+
+            StFld(_) | StInd(_) | StObj(_) | CpObj(_) | Ble(_) | BleUn(_) | Blt(_) | BltUn(_)
+            | Bge(_) | BgeUn(_) | Bgt(_) | BgtUn(_) | Beq(_) | BneUn(_) => (2, 0),
+
+            Add | AddOvf | AddOvfUn | Sub | SubOvf | SubOvfUn | Mul | MulOvf | MulOvfUn | Div
+            | DivUn | Rem | RemUn | And | Or | Xor | Shl | Shr | ShrUn | Ceq | Cgt | CgtUn
+            | Clt | CltUn | LdElem(_) | LdElemA(_) | LdElemAny(_) => (2, 1),
+
+            StElem(_) | StElemAny(_) | CpBlk | InitBlk => (3, 0),
+
+            Call(token) | CallVirt(token) => {
+                let sig = callee_sig(*token, table, heap)?;
+                (call_pops(&sig), call_pushes(&sig))
+            }
+            // the function pointer is on top of the arguments
+            CallI(token) => {
+                let sig = token
+                    .as_stand_along_sig()?
+                    .resolve_table(table)?
+                    .signature
+                    .resolve(heap)?
+                    .pread_with::<MethodRefSig>(0, scroll::LE)
+                    .ok()?
+                    .call_site();
+                (call_pops(&sig) + 1, call_pushes(&sig))
+            }
+            NewObj(token) => (callee_sig(*token, table, heap)?.params.len() as u32, 1),
+        };
+
+        Some(effect)
+    }
+}
+
+pub(super) fn callee_sig(
+    token: MetadataToken,
+    table: &MetadataTable,
+    heap: Heap,
+) -> Option<MethodDefSig> {
+    let blob = match token {
+        MetadataToken::MethodDef(m) => m.resolve_table(table)?.signature,
+        MetadataToken::MemberRef(m) => m.resolve_table(table)?.signature,
+        MetadataToken::MethodSpec(m) => {
+            let method = match m.resolve_table(table)?.method {
+                MethodDefOrRef::MethodDefIndex(d) => MetadataToken::MethodDef(d),
+                MethodDefOrRef::MemberRefIndex(r) => MetadataToken::MemberRef(r),
+            };
+            return callee_sig(method, table, heap);
+        }
+        _ => return None,
+    };
+
+    // a vararg `MemberRef` has the extra arguments of the call site after a sentinel
+    let sig: MethodRefSig = blob.resolve(heap)?.pread_with(0, scroll::LE).ok()?;
+    Some(sig.call_site())
+}
+
+fn call_pops(sig: &MethodDefSig) -> u32 {
+    let this = sig
+        .calling_convension
+        .contains(MethodCallingConvension::HAS_THIS)
+        && !sig
+            .calling_convension
+            .contains(MethodCallingConvension::EXPLICT_THIS);
+    sig.params.len() as u32 + this as u32
+}
+
+fn call_pushes(sig: &MethodDefSig) -> u32 {
+    !matches!(sig.ret, RetType::Void { .. }) as u32
+}
+
+impl Cfg {
+    pub fn new(body: &MethodBody, table: &MetadataTable, heap: Heap) -> Result<Self, CfgError> {
+        let index_of = |from: u32, target: u32| {
+            body.offsets
+                .binary_search(&target)
+                .map_err(|_| CfgError::new(from, format!("bad target IL_{:04x}", target)))
+        };
+        let end_of = |offset: u32, length: u32| {
+            offset
+                .checked_add(length)
+                .ok_or_else(|| CfgError::new(offset, "exception region overflows"))
+        };
+
+        if body.instructions.is_empty() {
+            return Err(CfgError::new(0, "empty method body"));
+        }
+
+        // II.25.4.6 region boundaries start blocks too
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for clause in &body.exception_clauses {
+            let mut bounds = vec![
+                clause.try_offset,
+                end_of(clause.try_offset, clause.try_length)?,
+                clause.handler_offset,
+                end_of(clause.handler_offset, clause.handler_length)?,
+            ];
+            if let ExceptionClauseKind::Filter(filter) = clause.kind {
+                bounds.push(filter);
+            }
+            for offset in bounds.into_iter().filter(|&o| o != body.code_size) {
+                leaders.insert(index_of(clause.try_offset, offset)?);
+            }
+        }
+        for (i, (offset, inst)) in body.iter().enumerate() {
+            let targets = inst.branch_targets(body.next_offset(i));
+            if !targets.is_empty() || inst.is_terminator() {
+                leaders.insert(i + 1);
+            }
+            for target in targets {
+                leaders.insert(index_of(offset, target)?);
+            }
+        }
+        leaders.retain(|&i| i < body.instructions.len());
+
+        let starts = leaders.into_iter().collect::<Vec<_>>();
+        let block_of = |index: usize| starts.partition_point(|&s| s <= index) - 1;
+        let mut blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(b, &start)| BasicBlock {
+                offset: body.offsets[start],
+                start,
+                end: starts
+                    .get(b + 1)
+                    .copied()
+                    .unwrap_or(body.instructions.len()),
+                successors: Vec::new(),
+                handlers: Vec::new(),
+                predecessors: Vec::new(),
+                stack_depth: None,
+            })
+            .collect::<Vec<_>>();
+
+        for b in 0..blocks.len() {
+            let last = blocks[b].end - 1;
+            let inst = &body.instructions[last];
+            let offset = body.offsets[last];
+            let mut successors = Vec::new();
+
+            for target in inst.branch_targets(body.next_offset(last)) {
+                successors.push(block_of(index_of(offset, target)?));
+            }
+            if !inst.is_terminator() {
+                if b + 1 == blocks.len() {
+                    return Err(CfgError::new(offset, "falls off the end of the body"));
+                }
+                successors.push(b + 1);
+            }
+            successors.dedup();
+            blocks[b].successors = successors;
+        }
+
+        for clause in &body.exception_clauses {
+            let mut entries = vec![block_of(index_of(
+                clause.try_offset,
+                clause.handler_offset,
+            )?)];
+            if let ExceptionClauseKind::Filter(filter) = clause.kind {
+                entries.push(block_of(index_of(clause.try_offset, filter)?));
+            }
+            let try_end = end_of(clause.try_offset, clause.try_length)?;
+            for block in blocks
+                .iter_mut()
+                .filter(|b| (clause.try_offset..try_end).contains(&b.offset))
+            {
+                block.handlers.extend(entries.iter().copied());
+            }
+        }
+
+        for b in 0..blocks.len() {
+            let targets = blocks[b]
+                .successors
+                .iter()
+                .chain(blocks[b].handlers.iter())
+                .copied()
+                .collect::<Vec<_>>();
+            for target in targets {
+                if !blocks[target].predecessors.contains(&b) {
+                    blocks[target].predecessors.push(b);
+                }
+            }
+        }
+
+        let idom = dominators(&blocks[..]);
+        let loops = loops(&blocks[..], &idom);
+        let mut cfg = Self {
+            blocks,
+            idom,
+            loops,
+        };
+        cfg.stack_depths(body, table, heap)?;
+        Ok(cfg)
+    }
+
+    /// `a` dominates `b`
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        dominates(&self.idom, a, b)
+    }
+
+    /// Block starting at an IL offset
+    pub fn block_at(&self, offset: u32) -> Option<usize> {
+        self.blocks.binary_search_by_key(&offset, |b| b.offset).ok()
+    }
+
+    /// Blocks reachable from the entry, each after its dominators
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        reverse_postorder(&self.blocks[..])
+    }
+
+    fn stack_depths(
+        &mut self,
+        body: &MethodBody,
+        table: &MetadataTable,
+        heap: Heap,
+    ) -> Result<(), CfgError> {
+        let mut work = vec![(0, 0)];
+        for clause in &body.exception_clauses {
+            let depth = match clause.kind {
+                ExceptionClauseKind::Catch(_) | ExceptionClauseKind::Filter(_) => 1,
+                ExceptionClauseKind::Finally | ExceptionClauseKind::Fault => 0,
+            };
+            if let Some(b) = self.block_at(clause.handler_offset) {
+                work.push((b, depth));
+            }
+            if let ExceptionClauseKind::Filter(filter) = clause.kind {
+                if let Some(b) = self.block_at(filter) {
+                    work.push((b, 1));
+                }
+            }
+        }
+
+        while let Some((b, depth)) = work.pop() {
+            let block = &mut self.blocks[b];
+            match block.stack_depth {
+                Some(expected) if expected == depth => continue,
+                Some(expected) => {
+                    return Err(CfgError::new(
+                        block.offset,
+                        format!("stack depth {} differs from {}", depth, expected),
+                    ))
+                }
+                None => block.stack_depth = Some(depth),
+            }
+
+            let mut depth = depth;
+            for i in block.start..block.end {
+                let inst = &body.instructions[i];
+                let offset = body.offsets[i];
+                let (pops, pushes) = inst
+                    .stack_effect(table, heap)
+                    .ok_or_else(|| CfgError::new(offset, "unresolved callee signature"))?;
+                depth = depth
+                    .checked_sub(pops)
+                    .ok_or_else(|| CfgError::new(offset, "stack underflow"))?
+                    + pushes;
+                if matches!(inst, Instruction::Leave(_) | Instruction::EndFinally) {
+                    depth = 0;
+                }
+            }
+
+            work.extend(block.successors.iter().map(|&s| (s, depth)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Directed graph whose entry is node 0, shared by the block graphs of CIL and of compilers
+pub trait Graph {
+    fn node_count(&self) -> usize;
+    fn successors(&self, node: usize) -> Vec<usize>;
+}
+
+/// Normal and exceptional edges
+impl Graph for [BasicBlock] {
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+
+    fn successors(&self, node: usize) -> Vec<usize> {
+        let block = &self[node];
+        block
+            .successors
+            .iter()
+            .chain(block.handlers.iter())
+            .copied()
+            .collect()
+    }
+}
+
+fn predecessors<G: Graph + ?Sized>(graph: &G) -> Vec<Vec<usize>> {
+    let mut preds = vec![Vec::new(); graph.node_count()];
+    for b in 0..graph.node_count() {
+        for s in graph.successors(b) {
+            if !preds[s].contains(&b) {
+                preds[s].push(b);
+            }
+        }
+    }
+    preds
+}
+
+/// Nodes reachable from the entry in reverse postorder
+pub fn reverse_postorder<G: Graph + ?Sized>(graph: &G) -> Vec<usize> {
+    let mut visited = vec![false; graph.node_count()];
+    let mut order = Vec::new();
+    // node and the next edge to follow
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+
+    while let Some((b, edge)) = stack.pop() {
+        match graph.successors(b).get(edge) {
+            Some(&next) => {
+                stack.push((b, edge + 1));
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push((next, 0));
+                }
+            }
+            None => order.push(b),
+        }
+    }
+
+    order.reverse();
+    order
+}
+
+/// Immediate dominators by Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm",
+/// none for the entry and unreachable nodes
+pub fn dominators<G: Graph + ?Sized>(graph: &G) -> Vec<Option<usize>> {
+    let order = reverse_postorder(graph);
+    let mut rank = vec![usize::MAX; graph.node_count()];
+    for (i, &b) in order.iter().enumerate() {
+        rank[b] = i;
+    }
+    let preds = predecessors(graph);
+
+    let mut idom: Vec<Option<usize>> = vec![None; graph.node_count()];
+    idom[0] = Some(0);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for &b in order.iter().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for &p in &preds[b] {
+                if idom[p].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => p,
+                    Some(mut a) => {
+                        let mut p = p;
+                        while a != p {
+                            while rank[a] > rank[p] {
+                                a = idom[a].unwrap();
+                            }
+                            while rank[p] > rank[a] {
+                                p = idom[p].unwrap();
+                            }
+                        }
+                        a
+                    }
+                });
+            }
+
+            if new_idom.is_some() && idom[b] != new_idom {
+                idom[b] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom[0] = None;
+    idom
+}
+
+/// `a` dominates `b` given the immediate dominators from [`dominators`]
+pub fn dominates(idom: &[Option<usize>], a: usize, b: usize) -> bool {
+    let mut b = Some(b);
+    while let Some(n) = b {
+        if n == a {
+            return true;
+        }
+        b = idom[n];
+    }
+    false
+}
+
+/// Natural loops of the back edges, edges to a node dominating their source
+pub fn loops<G: Graph + ?Sized>(graph: &G, idom: &[Option<usize>]) -> Vec<Loop> {
+    let preds = predecessors(graph);
+    let mut loops = Vec::<Loop>::new();
+    for latch in 0..graph.node_count() {
+        for header in graph.successors(latch) {
+            if !dominates(idom, header, latch) {
+                continue;
+            }
+
+            // nodes reaching the latch without passing the header
+            let mut body = BTreeSet::new();
+            body.insert(header);
+            let mut work = vec![latch];
+            while let Some(b) = work.pop() {
+                if body.insert(b) {
+                    work.extend(preds[b].iter().copied());
+                }
+            }
+
+            match loops.iter_mut().find(|l| l.header == header) {
+                Some(l) => {
+                    l.latches.push(latch);
+                    l.blocks = l
+                        .blocks
+                        .iter()
+                        .copied()
+                        .chain(body)
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .collect();
+                }
+                None => loops.push(Loop {
+                    header,
+                    blocks: body.into_iter().collect(),
+                    latches: vec![latch],
+                }),
+            }
+        }
+    }
+
+    loops
+}
+
+#[test]
+fn cfg_blocks() {
+    use crate::pe::Image;
+
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly cfg { .ver 0:0:0:0 }
+
+        .method public static int32 Sum(int32 n) cil managed
+        {
+          .locals init (int32 s, int32 i)
+          ldc.i4.0
+          stloc.0
+          ldc.i4.0
+          stloc.1
+          br.s Cond
+        Body:
+          ldloc.0
+          ldloc.1
+          add
+          stloc.0
+          ldloc.1
+          ldc.i4.1
+          add
+          stloc.1
+        Cond:
+          ldloc.1
+          ldarg.0
+          blt.s Body
+          ldloc.0
+          ret
+        }
+
+        .method public static int32 Pick(bool b) cil managed
+        {
+        TryStart:
+          ldarg.0
+          brtrue.s One
+          ldc.i4.2
+          br.s Join
+        One:
+          ldc.i4.1
+        Join:
+          pop
+          leave.s Done
+        Handler:
+          pop
+          leave.s Done
+        Done:
+          ldc.i4.0
+          ret
+          .try TryStart to Handler catch [mscorlib]System.Exception handler Handler to Done
+        }
+    ";
+    let bytes = crate::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let cfg = |name: &str| {
+        let (_, def) = table
+            .list_method_def()
+            .find(|(_, m)| m.name.resolve(root.heap) == Some(name))
+            .unwrap();
+        Cfg::new(&def.resolve_body(&image), table, root.heap).unwrap()
+    };
+
+    let sum = cfg("Sum");
+    let successors = sum
+        .blocks
+        .iter()
+        .map(|b| b.successors.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(successors, [vec![2], vec![2], vec![1, 3], vec![]]);
+    assert_eq!(sum.idom, [None, Some(2), Some(0), Some(2)]);
+    assert_eq!(
+        sum.loops,
+        [Loop {
+            header: 2,
+            blocks: vec![1, 2],
+            latches: vec![1],
+        }]
+    );
+    assert!(sum.blocks.iter().all(|b| b.stack_depth == Some(0)));
+
+    let pick = cfg("Pick");
+    let depths = pick
+        .blocks
+        .iter()
+        .map(|b| b.stack_depth)
+        .collect::<Vec<_>>();
+    // entry, ldc.i4.2, One, Join, Handler, Done
+    assert_eq!(
+        depths,
+        [Some(0), Some(0), Some(0), Some(1), Some(1), Some(0)]
+    );
+    assert_eq!(pick.blocks[0].handlers, [4]);
+    assert_eq!(pick.idom[4], Some(0));
+    assert_eq!(pick.idom[5], Some(0));
+    assert!(pick.loops.is_empty());
+
+    let bytes = crate::cil::asm::assemble(include_str!("../../../tests/il/edge_cases.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    for (_, def) in table.list_method_def().filter(|(_, m)| m.rva != 0) {
+        Cfg::new(&def.resolve_body(&image), table, root.heap).unwrap();
+    }
+}
+
+#[test]
+fn cfg_errors() {
+    use super::{ExceptionClause, Instruction};
+
+    let table = MetadataTable::default();
+    let body = |instructions: Vec<Instruction>, exception_clauses| MethodBody {
+        max_stack: 8,
+        init_locals: false,
+        local_var_sig: None,
+        code_size: instructions.len() as u32,
+        offsets: (0..instructions.len() as u32).collect(),
+        instructions,
+        exception_clauses,
+    };
+
+    let error = Cfg::new(&body(vec![], vec![]), &table, Heap::default()).unwrap_err();
+    assert_eq!(error.to_string(), "IL_0000: empty method body");
+
+    let clause = ExceptionClause {
+        kind: ExceptionClauseKind::Finally,
+        try_offset: 0,
+        try_length: 1,
+        handler_offset: 1,
+        handler_length: u32::MAX,
+    };
+    let instructions = vec![Instruction::Leave(0), Instruction::EndFinally];
+    let error = Cfg::new(&body(instructions, vec![clause]), &table, Heap::default()).unwrap_err();
+    assert_eq!(error.to_string(), "IL_0001: exception region overflows");
+}
@@ -0,0 +1,1471 @@
+//! ILDasm style textual disassembler
+
+use std::collections::HashMap;
+
+use scroll::Pread;
+
+use super::{ExceptionClauseKind, Instruction, MethodBody};
+use crate::pe::{
+    CustomMod, ElementType, EntryPoint, FieldAttributes, FieldIndex, FieldSig,
+    GenericParamAttributes, HasConstant, Heap, Image, Implementation, LocalVar, MemberRefParent,
+    MetadataTable, MetadataToken, MethodAttributes, MethodCallingConvension, MethodDefIndex,
+    MethodDefSig, MethodImplAttributes, MethodRefSig, Param, ResolutionScope, RetType, StringIndex,
+    TableIndex, Type, TypeAttributes, TypeDefIndex, TypeDefOrRef, TypeDefOrRefOrSpecEncoded,
+    TypeOrMethodDef, TypeRefIndex, Variance,
+};
+
+/// Quote a name unless it is a valid ilasm dotted name
+pub fn quote_name(name: &str) -> String {
+    let is_id_start = |c: char| c.is_ascii_alphabetic() || "_$@`?".contains(c);
+    let is_id = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().is_some_and(is_id_start) && chars.all(|c| is_id_start(c) || c.is_ascii_digit())
+    };
+
+    if name == ".ctor" || name == ".cctor" || (!name.is_empty() && name.split('.').all(is_id)) {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+/// Quote a string literal
+pub fn quote_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats metadata references in ilasm syntax
+pub struct Names<'t, 'a> {
+    pub table: &'t MetadataTable,
+    pub heap: Heap<'a>,
+    enclosing: HashMap<TypeDefIndex, TypeDefIndex>,
+    field_owner: HashMap<FieldIndex, TypeDefIndex>,
+    method_owner: HashMap<MethodDefIndex, TypeDefIndex>,
+    constants: HashMap<HasConstant, usize>,
+}
+
+impl<'t, 'a> Names<'t, 'a> {
+    pub fn new(table: &'t MetadataTable, heap: Heap<'a>) -> Self {
+        let enclosing = table
+            .nested_class
+            .iter()
+            .map(|n| (n.nested_class, n.enclosing_class))
+            .collect();
+        let mut field_owner = HashMap::new();
+        let mut method_owner = HashMap::new();
+
+        for (ty, _) in table.list_type_def() {
+            for (field, _) in ty.resolve_fields(table) {
+                field_owner.insert(field, ty);
+            }
+            for (method, _) in ty.resolve_methods(table) {
+                method_owner.insert(method, ty);
+            }
+        }
+
+        // the first row wins like a lookup in the sorted table
+        let constants = table
+            .constant
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, c)| (c.parent, i))
+            .collect();
+
+        Self {
+            table,
+            heap,
+            enclosing,
+            field_owner,
+            method_owner,
+            constants,
+        }
+    }
+
+    fn string(&self, index: crate::pe::StringIndex) -> &'a str {
+        index.resolve(self.heap).unwrap_or_default()
+    }
+
+    pub fn enclosing_class(&self, ty: TypeDefIndex) -> Option<TypeDefIndex> {
+        self.enclosing.get(&ty).copied()
+    }
+
+    pub fn method_owner(&self, method: MethodDefIndex) -> Option<TypeDefIndex> {
+        self.method_owner.get(&method).copied()
+    }
+
+    pub fn field_owner(&self, field: FieldIndex) -> Option<TypeDefIndex> {
+        self.field_owner.get(&field).copied()
+    }
+
+    fn full_name(&self, namespace: &str, name: &str) -> String {
+        if namespace.is_empty() {
+            quote_name(name)
+        } else {
+            format!("{}.{}", quote_name(namespace), quote_name(name))
+        }
+    }
+
+    /// Name of a type definition, nested types are `Outer/Inner`
+    pub fn type_def(&self, ty: TypeDefIndex) -> String {
+        let def = match ty.resolve_table(self.table) {
+            Some(def) => def,
+            None => return format!("/* TypeDef {} */", ty.0),
+        };
+        let name = self.full_name(self.string(def.type_namespace), self.string(def.type_name));
+
+        match self.enclosing_class(ty) {
+            Some(outer) => format!("{}/{}", self.type_def(outer), name),
+            None => name,
+        }
+    }
+
+    pub fn type_ref(&self, ty: TypeRefIndex) -> String {
+        let r = match ty.resolve_table(self.table) {
+            Some(r) => r,
+            None => return format!("/* TypeRef {} */", ty.0),
+        };
+        let name = self.full_name(self.string(r.type_namespace), self.string(r.type_name));
+
+        match r.resolution_scope {
+            ResolutionScope::AssemblyRefIndex(a) => match a.resolve_table(self.table) {
+                Some(a) => format!("[{}]{}", quote_name(self.string(a.name)), name),
+                None => name,
+            },
+            ResolutionScope::ModuleRefIndex(m) => match m.resolve_table(self.table) {
+                Some(m) => format!("[.module {}]{}", quote_name(self.string(m.name)), name),
+                None => name,
+            },
+            ResolutionScope::TypeRefIndex(outer) if outer.0 != 0 => {
+                format!("{}/{}", self.type_ref(outer), name)
+            }
+            _ => name,
+        }
+    }
+
+    pub fn type_def_or_ref(&self, ty: TypeDefOrRef) -> String {
+        match ty {
+            TypeDefOrRef::TypeDefIndex(def) => self.type_def(def),
+            TypeDefOrRef::TypeRefIndex(r) => self.type_ref(r),
+            TypeDefOrRef::TypeSpecIndex(spec) => self.type_spec(spec.0),
+        }
+    }
+
+    pub fn encoded(&self, ty: &TypeDefOrRefOrSpecEncoded) -> String {
+        match ty {
+            TypeDefOrRefOrSpecEncoded::TypeDef(def) => self.type_def(*def),
+            TypeDefOrRefOrSpecEncoded::TypeRef(r) => self.type_ref(*r),
+            TypeDefOrRefOrSpecEncoded::TypeSpec(spec) => self.type_spec(spec.0),
+        }
+    }
+
+    fn type_spec(&self, row: u32) -> String {
+        let spec = crate::pe::TypeSpecIndex(row).resolve_table(self.table);
+        match spec.and_then(|s| s.signature.resolve(self.heap)) {
+            Some(blob) => match blob.pread_with::<Type>(0, scroll::LE) {
+                Ok(ty) => self.ty(&ty),
+                Err(_) => format!("/* TypeSpec {} */", row),
+            },
+            None => format!("/* TypeSpec {} */", row),
+        }
+    }
+
+    pub fn ty(&self, ty: &Type) -> String {
+        match ty {
+            Type::Boolean => "bool".into(),
+            Type::Char => "char".into(),
+            Type::I1 => "int8".into(),
+            Type::U1 => "uint8".into(),
+            Type::I2 => "int16".into(),
+            Type::U2 => "uint16".into(),
+            Type::I4 => "int32".into(),
+            Type::U4 => "uint32".into(),
+            Type::I8 => "int64".into(),
+            Type::U8 => "uint64".into(),
+            Type::R4 => "float32".into(),
+            Type::R8 => "float64".into(),
+            Type::I => "native int".into(),
+            Type::U => "native uint".into(),
+            Type::Object => "object".into(),
+            Type::String => "string".into(),
+            Type::ValueType(ty) => format!("valuetype {}", self.encoded(ty)),
+            Type::Class(ty) => format!("class {}", self.encoded(ty)),
+            Type::SzArray { element_ty, mods } => {
+                format!("{}{}[]", self.ty(element_ty), self.custom_mods(mods))
+            }
+            Type::Array { element_ty, shape } => {
+                let dims = (0..shape.rank as usize)
+                    .map(|i| {
+                        let lo = shape.lo_bounds.get(i).copied();
+                        let size = shape.sizes.get(i).copied();
+                        match (lo, size) {
+                            (Some(lo), Some(size)) => {
+                                format!("{}...{}", lo, lo + size as i32 - 1)
+                            }
+                            (Some(lo), None) => format!("{}...", lo),
+                            (None, Some(size)) => size.to_string(),
+                            (None, None) => String::new(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}[{}]", self.ty(element_ty), dims.join(","))
+            }
+            Type::Ptr { ty: Some(ty), mods } => {
+                format!("{}{}*", self.ty(ty), self.custom_mods(mods))
+            }
+            Type::Ptr { ty: None, mods } => format!("void{}*", self.custom_mods(mods)),
+            Type::FnPtr(sig) => format!(
+                "method {}{} *({})",
+                Self::call_conv(sig),
+                self.ret_type(&sig.ret),
+                self.params(sig, None)
+            ),
+            Type::GenericInst {
+                is_value_type,
+                ty,
+                args,
+            } => format!(
+                "{} {}<{}>",
+                if *is_value_type { "valuetype" } else { "class" },
+                self.encoded(ty),
+                args.iter()
+                    .map(|a| self.ty(a))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Type::Var { number } => format!("!{}", number.0),
+            Type::MVar { number } => format!("!!{}", number.0),
+        }
+    }
+
+    /// ` modreq(T) modopt(U)` after the type they modify
+    pub fn custom_mods(&self, mods: &[CustomMod]) -> String {
+        mods.iter()
+            .map(|m| match m {
+                CustomMod::Reqd(ty) => format!(" modreq({})", self.encoded(ty)),
+                CustomMod::Opt(ty) => format!(" modopt({})", self.encoded(ty)),
+            })
+            .collect()
+    }
+
+    pub fn ret_type(&self, ret: &RetType) -> String {
+        match ret {
+            RetType::Void { mods } => format!("void{}", self.custom_mods(mods)),
+            RetType::TypedByref { mods } => format!("typedref{}", self.custom_mods(mods)),
+            RetType::Type { mods, byref, ty } => format!(
+                "{}{}{}",
+                self.ty(ty),
+                if *byref { "&" } else { "" },
+                self.custom_mods(mods)
+            ),
+        }
+    }
+
+    pub fn param(&self, param: &Param) -> String {
+        match param {
+            Param::TypedByref { mods } => format!("typedref{}", self.custom_mods(mods)),
+            Param::Type { mods, byref, ty } => format!(
+                "{}{}{}",
+                self.ty(ty),
+                if *byref { "&" } else { "" },
+                self.custom_mods(mods)
+            ),
+        }
+    }
+
+    pub fn local(&self, local: &LocalVar) -> String {
+        match local {
+            LocalVar::TypedByref => "typedref".into(),
+            LocalVar::Type {
+                mods,
+                pinned,
+                byref,
+                ty,
+            } => format!(
+                "{}{}{}{}",
+                self.ty(ty),
+                if *byref { "&" } else { "" },
+                self.custom_mods(mods),
+                if *pinned { " pinned" } else { "" }
+            ),
+        }
+    }
+
+    pub fn field_sig(&self, sig: &FieldSig) -> String {
+        format!("{}{}", self.ty(&sig.ty), self.custom_mods(&sig.mods))
+    }
+
+    fn call_conv(sig: &MethodDefSig) -> String {
+        let cc = sig.calling_convension;
+        let mut s = String::new();
+        if cc.contains(MethodCallingConvension::EXPLICT_THIS) {
+            s.push_str("instance explicit ");
+        } else if cc.contains(MethodCallingConvension::HAS_THIS) {
+            s.push_str("instance ");
+        }
+        if cc.is_vararg() {
+            s.push_str("vararg ");
+        }
+        s
+    }
+
+    /// Stand-alone method signature like `instance void(int32)`
+    pub fn method_sig(&self, sig: &MethodDefSig) -> String {
+        format!(
+            "{}{}({})",
+            Self::call_conv(sig),
+            self.ret_type(&sig.ret),
+            self.params(sig, None)
+        )
+    }
+
+    /// Stand-alone call site signature, the varargs follow `...`
+    fn call_site_sig(&self, sig: &MethodRefSig) -> String {
+        format!(
+            "{}{}({})",
+            Self::call_conv(&sig.method),
+            self.ret_type(&sig.method.ret),
+            self.call_site_params(sig)
+        )
+    }
+
+    fn call_site_params(&self, sig: &MethodRefSig) -> String {
+        let params = self.params(&sig.method, None);
+        if sig.varargs.is_empty() {
+            return params;
+        }
+
+        let varargs = sig.varargs.iter().map(|p| self.param(p));
+        let all = if params.is_empty() {
+            None
+        } else {
+            Some(params)
+        };
+        all.into_iter()
+            .chain(Some("...".to_string()))
+            .chain(varargs)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Parameter list, with names of a method definition if given
+    fn params(&self, sig: &MethodDefSig, method: Option<MethodDefIndex>) -> String {
+        let names = method
+            .map(|m| {
+                m.resolve_params(self.table)
+                    .map(|(_, p)| (p.sequence, self.string(p.name)))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        sig.params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| match names.get(&(i as u16 + 1)) {
+                Some(name) if !name.is_empty() => format!("{} {}", self.param(p), quote_name(name)),
+                _ => self.param(p),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn member_ref_parent(&self, parent: MemberRefParent) -> String {
+        match parent {
+            MemberRefParent::TypeDefIndex(def) => self.type_def(def),
+            MemberRefParent::TypeRefIndex(r) => self.type_ref(r),
+            MemberRefParent::TypeSpecIndex(spec) => self.type_spec(spec.0),
+            MemberRefParent::ModuleRefIndex(m) => match m.resolve_table(self.table) {
+                Some(m) => format!("[.module {}]", quote_name(self.string(m.name))),
+                None => String::new(),
+            },
+            MemberRefParent::MethodDefIndex(m) => self
+                .method_owner(m)
+                .map(|ty| self.type_def(ty))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn method(
+        &self,
+        sig: &MethodRefSig,
+        owner: &str,
+        name: &str,
+        generic_args: Option<&[Type]>,
+    ) -> String {
+        let generic = match generic_args {
+            Some(args) => format!(
+                "<{}>",
+                args.iter()
+                    .map(|a| self.ty(a))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            None => String::new(),
+        };
+        let owner = if owner.is_empty() {
+            String::new()
+        } else {
+            format!("{}::", owner)
+        };
+
+        format!(
+            "{}{} {}{}{}({})",
+            Self::call_conv(&sig.method),
+            self.ret_type(&sig.method.ret),
+            owner,
+            quote_name(name),
+            generic,
+            self.call_site_params(sig)
+        )
+    }
+
+    fn method_token(&self, token: MetadataToken, generic_args: Option<&[Type]>) -> Option<String> {
+        match token {
+            MetadataToken::MethodDef(m) => {
+                let def = m.resolve_table(self.table)?;
+                let owner = self
+                    .method_owner(m)
+                    .map(|ty| self.type_def(ty))
+                    .unwrap_or_default();
+                let sig = MethodRefSig {
+                    method: def
+                        .signature
+                        .resolve(self.heap)?
+                        .pread_with(0, scroll::LE)
+                        .ok()?,
+                    varargs: Vec::new(),
+                };
+                Some(self.method(&sig, &owner, self.string(def.name), generic_args))
+            }
+            MetadataToken::MemberRef(m) => {
+                let r = m.resolve_table(self.table)?;
+                let blob = r.signature.resolve(self.heap)?;
+                let owner = self.member_ref_parent(r.class);
+                let name = self.string(r.name);
+
+                if blob.first() == Some(&0x06) {
+                    let sig: FieldSig = blob.pread_with(0, scroll::LE).ok()?;
+                    Some(format!(
+                        "{} {}::{}",
+                        self.field_sig(&sig),
+                        owner,
+                        quote_name(name)
+                    ))
+                } else {
+                    let sig = blob.pread_with(0, scroll::LE).ok()?;
+                    Some(self.method(&sig, &owner, name, generic_args))
+                }
+            }
+            MetadataToken::MethodSpec(m) => {
+                let spec = m.resolve_table(self.table)?;
+                let args = spec
+                    .instantiation
+                    .resolve(self.heap)?
+                    .pread_with::<crate::pe::MethodSpecSig>(0, scroll::LE)
+                    .ok()?
+                    .args;
+                let method = match spec.method {
+                    crate::pe::MethodDefOrRef::MethodDefIndex(d) => MetadataToken::MethodDef(d),
+                    crate::pe::MethodDefOrRef::MemberRefIndex(r) => MetadataToken::MemberRef(r),
+                };
+                self.method_token(method, Some(&args))
+            }
+            _ => None,
+        }
+    }
+
+    fn field_token(&self, field: FieldIndex) -> Option<String> {
+        let def = field.resolve_table(self.table)?;
+        let sig: FieldSig = def
+            .signature
+            .resolve(self.heap)?
+            .pread_with(0, scroll::LE)
+            .ok()?;
+        let owner = self
+            .field_owner(field)
+            .map(|ty| format!("{}::", self.type_def(ty)))
+            .unwrap_or_default();
+
+        Some(format!(
+            "{} {}{}",
+            self.field_sig(&sig),
+            owner,
+            quote_name(self.string(def.name))
+        ))
+    }
+
+    /// Operand of an instruction in ilasm syntax
+    pub fn token(&self, token: MetadataToken) -> String {
+        let s = match token {
+            MetadataToken::TypeDef(def) => Some(self.type_def(def)),
+            MetadataToken::TypeRef(r) => Some(self.type_ref(r)),
+            MetadataToken::TypeSpec(spec) => Some(self.type_spec(spec.0)),
+            MetadataToken::Field(f) => self.field_token(f),
+            MetadataToken::MethodDef(_)
+            | MetadataToken::MemberRef(_)
+            | MetadataToken::MethodSpec(_) => self.method_token(token, None),
+            MetadataToken::StandAloneSig(s) => s
+                .resolve_table(self.table)
+                .and_then(|s| s.signature.resolve(self.heap))
+                .and_then(|blob| blob.pread_with::<MethodRefSig>(0, scroll::LE).ok())
+                .map(|sig| self.call_site_sig(&sig)),
+            MetadataToken::UserString(s) => {
+                s.resolve(self.heap).map(|s| quote_string(&s.to_string()))
+            }
+            _ => None,
+        };
+
+        s.unwrap_or_else(|| format!("/* {:08X} */", token.to_raw()))
+    }
+
+    /// Like `token` but member references are prefixed with `field` or `method` for `ldtoken`
+    pub fn ldtoken(&self, token: MetadataToken) -> String {
+        let is_field = match token {
+            MetadataToken::Field(_) => Some(true),
+            MetadataToken::MethodDef(_) | MetadataToken::MethodSpec(_) => Some(false),
+            MetadataToken::MemberRef(r) => r
+                .resolve_table(self.table)
+                .and_then(|r| r.signature.resolve(self.heap))
+                .map(|blob| blob.first() == Some(&0x06)),
+            _ => None,
+        };
+
+        match is_field {
+            Some(true) => format!("field {}", self.token(token)),
+            Some(false) => format!("method {}", self.token(token)),
+            None => self.token(token),
+        }
+    }
+
+    /// Value of a `Constant` row in ilasm syntax, like `int32(0x2a)`
+    pub fn constant(&self, parent: HasConstant) -> Option<String> {
+        let heap = self.heap;
+        let constant = &self.table.constant[*self.constants.get(&parent)?];
+        let blob = constant.value.resolve(heap)?;
+
+        macro_rules! num {
+            ($name:literal, $ty:ty) => {
+                format!(
+                    concat!($name, "(0x{:x})"),
+                    blob.pread_with::<$ty>(0, scroll::LE).ok()?
+                )
+            };
+        }
+
+        let s = match constant.const_ty {
+            ElementType::Boolean => format!("bool({})", blob.first()? != &0),
+            ElementType::Char => num!("char", u16),
+            ElementType::I1 => num!("int8", i8),
+            ElementType::U1 => num!("uint8", u8),
+            ElementType::I2 => num!("int16", i16),
+            ElementType::U2 => num!("uint16", u16),
+            ElementType::I4 => num!("int32", i32),
+            ElementType::U4 => num!("uint32", u32),
+            ElementType::I8 => num!("int64", i64),
+            ElementType::U8 => num!("uint64", u64),
+            ElementType::R4 => {
+                format!("float32({:?})", blob.pread_with::<f32>(0, scroll::LE).ok()?)
+            }
+            ElementType::R8 => {
+                format!("float64({:?})", blob.pread_with::<f64>(0, scroll::LE).ok()?)
+            }
+            ElementType::String => {
+                let units = blob
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                quote_string(&String::from_utf16_lossy(&units))
+            }
+            ElementType::Class => "nullref".into(),
+            _ => return None,
+        };
+
+        Some(s)
+    }
+
+    /// `<(constraint) +T, class U>`
+    pub fn generic_params(&self, owner: TypeOrMethodDef) -> String {
+        let params = owner.generic_params(self.table, self.heap);
+        if params.is_empty() {
+            return String::new();
+        }
+
+        let params = params
+            .iter()
+            .map(|p| {
+                let mut s = String::new();
+                let special = p.special_constraints;
+                if special.contains(GenericParamAttributes::REFERENCE_TYPE_CONSTRAINT) {
+                    s.push_str("class ");
+                }
+                if special.contains(GenericParamAttributes::NOT_NULLABLE_VALUE_TYPE_CONSTRAINT) {
+                    s.push_str("valuetype ");
+                }
+                if special.contains(GenericParamAttributes::DEFAULT_CONSTRUCTOR_CONSTRAINT) {
+                    s.push_str(".ctor ");
+                }
+                if !p.constraints.is_empty() {
+                    let constraints = p
+                        .constraints
+                        .iter()
+                        .map(|c| self.type_def_or_ref(*c))
+                        .collect::<Vec<_>>();
+                    s.push_str(&format!("({}) ", constraints.join(", ")));
+                }
+                match p.variance {
+                    Variance::Covariant => s.push('+'),
+                    Variance::Contravariant => s.push('-'),
+                    Variance::None => {}
+                }
+                s.push_str(&quote_name(p.name));
+                s
+            })
+            .collect::<Vec<_>>();
+
+        format!("<{}>", params.join(", "))
+    }
+}
+
+fn label(offset: u32) -> String {
+    format!("IL_{:04x}", offset)
+}
+
+/// Mnemonic of an instruction, `size` picks between short and long forms
+pub fn mnemonic(inst: &Instruction, size: u32) -> String {
+    let short = |name: &str| {
+        if size == 2 {
+            format!("{}.s", name)
+        } else {
+            name.to_string()
+        }
+    };
+    let macro_form = |name: &str, n: u32| {
+        if size == 1 {
+            format!("{}.{}", name, n)
+        } else {
+            short(name)
+        }
+    };
+
+    match inst {
+        Instruction::Nop => "nop".into(),
+        Instruction::Break => "break".into(),
+        Instruction::LdArg(n) => macro_form("ldarg", *n),
+        Instruction::LdArgA(_) => short("ldarga"),
+        Instruction::StArg(_) => short("starg"),
+        Instruction::LdLoc(n) => macro_form("ldloc", *n),
+        Instruction::LdLocA(_) => short("ldloca"),
+        Instruction::StLoc(n) => macro_form("stloc", *n),
+        Instruction::LdNull => "ldnull".into(),
+        Instruction::LdcI4(-1) if size == 1 => "ldc.i4.m1".into(),
+        Instruction::LdcI4(n) if size == 1 => format!("ldc.i4.{}", n),
+        Instruction::LdcI4(_) => short("ldc.i4"),
+        Instruction::LdcI8(_) => "ldc.i8".into(),
+        Instruction::LdcR4(_) => "ldc.r4".into(),
+        Instruction::LdcR8(_) => "ldc.r8".into(),
+        Instruction::LdStr(_) => "ldstr".into(),
+        Instruction::LdFld(_) => "ldfld".into(),
+        Instruction::LdFldA(_) => "ldflda".into(),
+        Instruction::StFld(_) => "stfld".into(),
+        Instruction::LdSFld(_) => "ldsfld".into(),
+        Instruction::LdSFldA(_) => "ldsflda".into(),
+        Instruction::StSFld(_) => "stsfld".into(),
+        Instruction::LdToken(_) => "ldtoken".into(),
+        Instruction::Dup => "dup".into(),
+        Instruction::Pop => "pop".into(),
+        Instruction::Jmp(_) => "jmp".into(),
+        Instruction::Call(_) => "call".into(),
+        Instruction::CallI(_) => "calli".into(),
+        Instruction::CallVirt(_) => "callvirt".into(),
+        Instruction::Ret => "ret".into(),
+        Instruction::Neg => "neg".into(),
+        Instruction::Not => "not".into(),
+        Instruction::And => "and".into(),
+        Instruction::Or => "or".into(),
+        Instruction::Xor => "xor".into(),
+        Instruction::Shl => "shl".into(),
+        Instruction::Shr => "shr".into(),
+        Instruction::ShrUn => "shr.un".into(),
+        Instruction::Add => "add".into(),
+        Instruction::AddOvf => "add.ovf".into(),
+        Instruction::AddOvfUn => "add.ovf.un".into(),
+        Instruction::Sub => "sub".into(),
+        Instruction::SubOvf => "sub.ovf".into(),
+        Instruction::SubOvfUn => "sub.ovf.un".into(),
+        Instruction::Div => "div".into(),
+        Instruction::DivUn => "div.un".into(),
+        Instruction::Rem => "rem".into(),
+        Instruction::RemUn => "rem.un".into(),
+        Instruction::Mul => "mul".into(),
+        Instruction::MulOvf => "mul.ovf".into(),
+        Instruction::MulOvfUn => "mul.ovf.un".into(),
+        Instruction::Ceq => "ceq".into(),
+        Instruction::Cgt => "cgt".into(),
+        Instruction::CgtUn => "cgt.un".into(),
+        Instruction::Clt => "clt".into(),
+        Instruction::CltUn => "clt.un".into(),
+        Instruction::CkFinite => "ckfinite".into(),
+        Instruction::Conv(t) => format!("conv.{}", t.suffix()),
+        Instruction::ConvOvf(t) => format!("conv.ovf.{}", t.suffix()),
+        Instruction::ConvOvfUn(t) => format!("conv.ovf.{}.un", t.suffix()),
+        Instruction::Br(_) => short("br"),
+        Instruction::BrTrue(_) => short("brtrue"),
+        Instruction::BrFalse(_) => short("brfalse"),
+        Instruction::Ble(_) => short("ble"),
+        Instruction::BleUn(_) => short("ble.un"),
+        Instruction::Blt(_) => short("blt"),
+        Instruction::BltUn(_) => short("blt.un"),
+        Instruction::Bge(_) => short("bge"),
+        Instruction::BgeUn(_) => short("bge.un"),
+        Instruction::Bgt(_) => short("bgt"),
+        Instruction::BgtUn(_) => short("bgt.un"),
+        Instruction::Beq(_) => short("beq"),
+        Instruction::BneUn(_) => short("bne.un"),
+        Instruction::Switch(_) => "switch".into(),
+        Instruction::Leave(_) => short("leave"),
+        Instruction::EndFinally => "endfinally".into(),
+        Instruction::EndFilter => "endfilter".into(),
+        Instruction::Throw => "throw".into(),
+        Instruction::Rethrow => "rethrow".into(),
+        Instruction::LdInd(t) => format!("ldind.{}", t.suffix()),
+        Instruction::StInd(t) => format!("stind.{}", t.suffix()),
+        Instruction::LdObj(_) => "ldobj".into(),
+        Instruction::StObj(_) => "stobj".into(),
+        Instruction::CpObj(_) => "cpobj".into(),
+        Instruction::InitObj(_) => "initobj".into(),
+        Instruction::CpBlk => "cpblk".into(),
+        Instruction::InitBlk => "initblk".into(),
+        Instruction::LocAlloc => "localloc".into(),
+        Instruction::SizeOf(_) => "sizeof".into(),
+        Instruction::NewObj(_) => "newobj".into(),
+        Instruction::NewArr(_) => "newarr".into(),
+        Instruction::LdLen => "ldlen".into(),
+        Instruction::LdElem(t) => format!("ldelem.{}", t.suffix()),
+        Instruction::LdElemA(_) => "ldelema".into(),
+        Instruction::LdElemAny(_) => "ldelem".into(),
+        Instruction::StElem(t) => format!("stelem.{}", t.suffix()),
+        Instruction::StElemAny(_) => "stelem".into(),
+        Instruction::CastClass(_) => "castclass".into(),
+        Instruction::IsInst(_) => "isinst".into(),
+        Instruction::Box(_) => "box".into(),
+        Instruction::Unbox(_) => "unbox".into(),
+        Instruction::UnboxAny(_) => "unbox.any".into(),
+        Instruction::LdFtn(_) => "ldftn".into(),
+        Instruction::LdVirtFtn(_) => "ldvirtftn".into(),
+        Instruction::ArgList => "arglist".into(),
+        Instruction::MkRefAny(_) => "mkrefany".into(),
+        Instruction::RefAnyVal(_) => "refanyval".into(),
+        Instruction::RefAnyType => "refanytype".into(),
+        Instruction::Unaligned(_) => "unaligned.".into(),
+        Instruction::Volatile => "volatile.".into(),
+        Instruction::Tail => "tail.".into(),
+        Instruction::Constrained(_) => "constrained.".into(),
+        Instruction::No(_) => "no.".into(),
+        Instruction::Readonly => "readonly.".into(),
+    }
+}
+
+fn float_operand(bytes: &[u8], value: f64) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        let bytes = bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>();
+        format!("({})", bytes.join(" "))
+    }
+}
+
+impl Names<'_, '_> {
+    /// Operand of an instruction, `next` is the offset of the following instruction
+    pub fn operand(&self, inst: &Instruction, size: u32, next: u32) -> Option<String> {
+        let s = match inst {
+            Instruction::LdArg(n) | Instruction::LdLoc(n) | Instruction::StLoc(n) if size == 1 => {
+                return None
+            }
+            Instruction::LdArg(n)
+            | Instruction::LdArgA(n)
+            | Instruction::StArg(n)
+            | Instruction::LdLoc(n)
+            | Instruction::LdLocA(n)
+            | Instruction::StLoc(n) => n.to_string(),
+            Instruction::LdcI4(_) if size == 1 => return None,
+            Instruction::LdcI4(n) if size == 2 => n.to_string(),
+            Instruction::LdcI4(n) => format!("0x{:x}", n),
+            Instruction::LdcI8(n) => format!("0x{:x}", n),
+            Instruction::LdcR4(n) => float_operand(&n.to_le_bytes(), *n as f64),
+            Instruction::LdcR8(n) => float_operand(&n.to_le_bytes(), *n),
+            Instruction::Unaligned(n) | Instruction::No(n) => n.to_string(),
+            Instruction::LdToken(token) => self.ldtoken(*token),
+            Instruction::Switch(_) => {
+                let targets = inst
+                    .branch_targets(next)
+                    .into_iter()
+                    .map(label)
+                    .collect::<Vec<_>>();
+                format!("({})", targets.join(", "))
+            }
+            Instruction::LdStr(token)
+            | Instruction::LdFld(token)
+            | Instruction::LdFldA(token)
+            | Instruction::StFld(token)
+            | Instruction::LdSFld(token)
+            | Instruction::LdSFldA(token)
+            | Instruction::StSFld(token)
+            | Instruction::Jmp(token)
+            | Instruction::Call(token)
+            | Instruction::CallI(token)
+            | Instruction::CallVirt(token)
+            | Instruction::LdObj(token)
+            | Instruction::StObj(token)
+            | Instruction::CpObj(token)
+            | Instruction::InitObj(token)
+            | Instruction::SizeOf(token)
+            | Instruction::NewObj(token)
+            | Instruction::NewArr(token)
+            | Instruction::LdElemA(token)
+            | Instruction::LdElemAny(token)
+            | Instruction::StElemAny(token)
+            | Instruction::CastClass(token)
+            | Instruction::IsInst(token)
+            | Instruction::Box(token)
+            | Instruction::Unbox(token)
+            | Instruction::UnboxAny(token)
+            | Instruction::LdFtn(token)
+            | Instruction::LdVirtFtn(token)
+            | Instruction::MkRefAny(token)
+            | Instruction::RefAnyVal(token)
+            | Instruction::Constrained(token) => self.token(*token),
+            _ => match inst.branch_targets(next).first() {
+                Some(target) => label(*target),
+                None => return None,
+            },
+        };
+
+        Some(s)
+    }
+
+    /// `IL_0000:  ldstr      "Hello"`
+    pub fn instruction(&self, offset: u32, inst: &Instruction, next: u32) -> String {
+        let size = next - offset;
+        let mnemonic = mnemonic(inst, size);
+
+        match self.operand(inst, size, next) {
+            Some(operand) => format!("{}:  {:<10} {}", label(offset), mnemonic, operand),
+            None => format!("{}:  {}", label(offset), mnemonic),
+        }
+    }
+}
+
+fn type_flags(flags: TypeAttributes, nested: bool) -> String {
+    let mut out = Vec::new();
+
+    if flags.contains(TypeAttributes::INTERFACE) {
+        out.push("interface");
+    }
+    out.push(match flags & TypeAttributes::VISIBILITY_MASK {
+        TypeAttributes::PUBLIC => "public",
+        TypeAttributes::NESTED_PUBLIC => "nested public",
+        TypeAttributes::NESTED_PRIVATE => "nested private",
+        TypeAttributes::NESTED_FAMILY => "nested family",
+        TypeAttributes::NESTED_ASSEMBLY => "nested assembly",
+        TypeAttributes::NESTED_FAM_AND_ASSEM => "nested famandassem",
+        TypeAttributes::NESTED_FAM_OR_ASSEM => "nested famorassem",
+        _ if nested => "nested private",
+        _ => "private",
+    });
+    if flags.contains(TypeAttributes::ABSTRACT) {
+        out.push("abstract");
+    }
+    out.push(match flags & TypeAttributes::LAYOUT_MASK {
+        TypeAttributes::SEQUENTIAL_LAYOUT => "sequential",
+        TypeAttributes::EXPLICIT_LAYOUT => "explicit",
+        _ => "auto",
+    });
+    out.push(match flags & TypeAttributes::STRING_FORMAT_MASK {
+        TypeAttributes::UNICODE_CLASS => "unicode",
+        TypeAttributes::AUTO_CLASS => "autochar",
+        _ => "ansi",
+    });
+    for (flag, name) in [
+        (TypeAttributes::SEALED, "sealed"),
+        (TypeAttributes::SPECIAL_NAME, "specialname"),
+        (TypeAttributes::RT_SPECIAL_NAME, "rtspecialname"),
+        (TypeAttributes::IMPORT, "import"),
+        (TypeAttributes::SERIALIZED, "serializable"),
+        (TypeAttributes::BEFORE_FIELD_INIT, "beforefieldinit"),
+    ] {
+        if flags.contains(flag) {
+            out.push(name);
+        }
+    }
+
+    out.join(" ")
+}
+
+fn field_flags(flags: FieldAttributes) -> String {
+    let mut out = vec![match flags & FieldAttributes::FIELD_ACCESS_MASK {
+        FieldAttributes::PRIVATE => "private",
+        FieldAttributes::FAM_AND_ASSEM => "famandassem",
+        FieldAttributes::ASSEMBLY => "assembly",
+        FieldAttributes::FAMILY => "family",
+        FieldAttributes::FAM_OR_ASSEM => "famorassem",
+        FieldAttributes::PUBLIC => "public",
+        _ => "privatescope",
+    }];
+    for (flag, name) in [
+        (FieldAttributes::STATIC, "static"),
+        (FieldAttributes::INIT_ONLY, "initonly"),
+        (FieldAttributes::LITERAL, "literal"),
+        (FieldAttributes::NOT_SERIALIZED, "notserialized"),
+        (FieldAttributes::SPECIAL_NAME, "specialname"),
+        (FieldAttributes::RT_SPECIAL_NAME, "rtspecialname"),
+    ] {
+        if flags.contains(flag) {
+            out.push(name);
+        }
+    }
+
+    out.join(" ")
+}
+
+fn method_flags(flags: MethodAttributes) -> String {
+    let mut out = vec![match flags & MethodAttributes::MEMBER_ACCESS_MASK {
+        MethodAttributes::PRIVATE => "private",
+        MethodAttributes::FAM_AND_ASSEM => "famandassem",
+        MethodAttributes::ASSEMBLY => "assembly",
+        MethodAttributes::FAMILY => "family",
+        MethodAttributes::FAM_OR_ASSEM => "famorassem",
+        MethodAttributes::PUBLIC => "public",
+        _ => "privatescope",
+    }];
+    for (flag, name) in [
+        (MethodAttributes::HIDE_BY_SIG, "hidebysig"),
+        (MethodAttributes::NEW_SLOT, "newslot"),
+        (MethodAttributes::SPECIAL_NAME, "specialname"),
+        (MethodAttributes::RT_SPECIAL_NAME, "rtspecialname"),
+        (MethodAttributes::ABSTRACT, "abstract"),
+        (MethodAttributes::VIRTUAL, "virtual"),
+        (MethodAttributes::FINAL, "final"),
+        (MethodAttributes::STATIC, "static"),
+    ] {
+        if flags.contains(flag) {
+            out.push(name);
+        }
+    }
+
+    out.join(" ")
+}
+
+fn impl_flags(flags: MethodImplAttributes) -> String {
+    let mut out = vec![
+        match flags & MethodImplAttributes::CODE_TYPE_MASK {
+            MethodImplAttributes::NATIVE => "native",
+            MethodImplAttributes::OPTIL => "optil",
+            MethodImplAttributes::RUNTIME => "runtime",
+            _ => "cil",
+        },
+        if flags.contains(MethodImplAttributes::UNMANAGED) {
+            "unmanaged"
+        } else {
+            "managed"
+        },
+    ];
+    for (flag, name) in [
+        (MethodImplAttributes::FORWARD_REF, "forwardref"),
+        (MethodImplAttributes::PRESERVE_SIG, "preservesig"),
+        (MethodImplAttributes::INTERNAL_CALL, "internalcall"),
+        (MethodImplAttributes::SYNCHRONIZED, "synchronized"),
+        (MethodImplAttributes::NO_INLINING, "noinlining"),
+        (MethodImplAttributes::NO_OPTIMIZATION, "nooptimization"),
+    ] {
+        if flags.contains(flag) {
+            out.push(name);
+        }
+    }
+
+    out.join(" ")
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X} ", b))
+        .collect::<String>()
+}
+
+pub struct Disassembler<'i, 't, 'a> {
+    image: &'i Image<'a>,
+    names: Names<'t, 'a>,
+    out: String,
+    indent: usize,
+}
+
+/// Disassemble a whole image
+pub fn disassemble(image: &Image) -> String {
+    let root = image.metadata_root();
+    let mut d = Disassembler {
+        image,
+        names: Names::new(&root.metadata_stream.table, root.heap),
+        out: String::new(),
+        indent: 0,
+    };
+
+    d.write_assembly();
+    d.write_types();
+    d.write_data();
+    d.out
+}
+
+impl<'i, 't, 'a> Disassembler<'i, 't, 'a> {
+    fn line(&mut self, s: impl AsRef<str>) {
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(s.as_ref());
+        self.out.push('\n');
+    }
+
+    fn open(&mut self) {
+        self.line("{");
+        self.indent += 1;
+    }
+
+    fn close(&mut self, comment: impl AsRef<str>) {
+        self.indent -= 1;
+        if comment.as_ref().is_empty() {
+            self.line("}");
+        } else {
+            self.line(format!("}} // {}", comment.as_ref()));
+        }
+    }
+
+    fn write_culture(&mut self, culture: StringIndex) {
+        match culture.resolve(self.names.heap) {
+            Some(culture) if !culture.is_empty() => {
+                self.line(format!(".culture {}", quote_string(culture)))
+            }
+            _ => {}
+        }
+    }
+
+    fn write_assembly(&mut self) {
+        let table = self.names.table;
+        let heap = self.names.heap;
+
+        for (_, r) in table.list_assembly_ref() {
+            let v = r.version;
+            self.line(format!(
+                ".assembly extern {}",
+                quote_name(r.name.resolve(heap).unwrap_or_default())
+            ));
+            self.open();
+            if let Some(token) = r.public_key_token(heap) {
+                self.line(format!(".publickeytoken = ({})", hex_bytes(&token)));
+            }
+            self.write_culture(r.culture);
+            self.line(format!(
+                ".ver {}:{}:{}:{}",
+                v.major_version, v.minor_version, v.build_number, v.revision_number
+            ));
+            self.close("");
+        }
+
+        for (_, a) in table.list_assembly() {
+            let v = a.version;
+            self.line(format!(
+                ".assembly {}",
+                quote_name(a.name.resolve(heap).unwrap_or_default())
+            ));
+            self.open();
+            if let Some(key) = a.public_key.resolve(heap).filter(|k| !k.is_empty()) {
+                self.line(format!(".publickey = ({})", hex_bytes(key)));
+            }
+            self.write_culture(a.culture);
+            self.line(format!(
+                ".ver {}:{}:{}:{}",
+                v.major_version, v.minor_version, v.build_number, v.revision_number
+            ));
+            self.close("");
+        }
+
+        for (_, e) in table.list_exported_type() {
+            if let Implementation::AssemblyRefIndex(r) = e.implementation {
+                if !e.flags.contains(TypeAttributes::IS_TYPE_FORWARDER) {
+                    continue;
+                }
+                let name = self.names.full_name(
+                    e.namespace.resolve(heap).unwrap_or_default(),
+                    e.name.resolve(heap).unwrap_or_default(),
+                );
+                self.line(format!(".class extern forwarder {}", name));
+                self.open();
+                let target = r
+                    .resolve_table(table)
+                    .and_then(|r| r.name.resolve(heap))
+                    .unwrap_or_default();
+                self.line(format!(".assembly extern {}", quote_name(target)));
+                self.close("");
+            }
+        }
+
+        for (_, m) in table.list_module() {
+            self.line(format!(
+                ".module {}",
+                quote_name(m.name.resolve(heap).unwrap_or_default())
+            ));
+        }
+        self.line("");
+    }
+
+    /// Initial data of fields with a `FieldRVA` row
+    fn write_data(&mut self) {
+        let table = self.names.table;
+        let mut written = Vec::new();
+
+        for row in table.field_rva.iter() {
+            if written.contains(&row.rva) {
+                continue;
+            }
+            written.push(row.rva);
+            if let Some(data) = row.field.resolve_initial_data(self.image) {
+                self.line(format!(
+                    ".data I_{:08X} = bytearray ({})",
+                    row.rva,
+                    hex_bytes(data)
+                ));
+            }
+        }
+    }
+
+    fn write_types(&mut self) {
+        let table = self.names.table;
+
+        for (ty, _) in table.list_type_def() {
+            // <Module> holds global members
+            if ty.0 == 1 {
+                self.write_members(ty);
+            } else if self.names.enclosing_class(ty).is_none() {
+                self.write_type(ty);
+            }
+        }
+    }
+
+    fn write_type(&mut self, ty: TypeDefIndex) {
+        let table = self.names.table;
+        let def = ty.resolve_table(table).unwrap();
+        let nested = self.names.enclosing_class(ty).is_some();
+        let name = if nested {
+            quote_name(def.type_name.resolve(self.names.heap).unwrap_or_default())
+        } else {
+            self.names.type_def(ty)
+        };
+
+        self.line(format!(
+            ".class {} {}{}",
+            type_flags(def.flags, nested),
+            name,
+            self.names.generic_params(TypeOrMethodDef::TypeDefIndex(ty))
+        ));
+        if def.extends.encode() != 0 {
+            self.line(format!(
+                "       extends {}",
+                self.names.type_def_or_ref(def.extends)
+            ));
+        }
+        let interfaces = table
+            .interface_impl
+            .iter()
+            .filter(|i| i.class == ty)
+            .map(|i| self.names.type_def_or_ref(i.interface))
+            .collect::<Vec<_>>();
+        if !interfaces.is_empty() {
+            self.line(format!(
+                "       implements {}",
+                interfaces.join(",\n                  ")
+            ));
+        }
+        self.open();
+
+        if let Some(layout) = ty.resolve_class_layout(table) {
+            self.line(format!(".pack {}", layout.packing_size));
+            self.line(format!(".size {}", layout.class_size));
+        }
+
+        for (nested, _) in table.list_type_def() {
+            if self.names.enclosing_class(nested) == Some(ty) {
+                self.write_type(nested);
+            }
+        }
+
+        self.write_members(ty);
+
+        self.close(format!("end of class {}", name));
+        self.line("");
+    }
+
+    fn write_members(&mut self, ty: TypeDefIndex) {
+        let table = self.names.table;
+
+        for (field, _) in ty.resolve_fields(table) {
+            self.write_field(field);
+        }
+
+        for (method, _) in ty.resolve_methods(table) {
+            self.write_method(method);
+        }
+    }
+
+    fn write_field(&mut self, field: FieldIndex) {
+        let table = self.names.table;
+        let heap = self.names.heap;
+        let def = field.resolve_table(table).unwrap();
+        let ty = def
+            .signature
+            .resolve(heap)
+            .and_then(|b| b.pread_with::<FieldSig>(0, scroll::LE).ok())
+            .map(|sig| self.names.field_sig(&sig))
+            .unwrap_or_else(|| "/* bad signature */".into());
+
+        let mut s = ".field ".to_string();
+        if let Some(layout) = field.resolve_layout(table) {
+            s.push_str(&format!("[{}] ", layout.offset));
+        }
+        s.push_str(&format!(
+            "{} {} {}",
+            field_flags(def.flags),
+            ty,
+            quote_name(def.name.resolve(heap).unwrap_or_default())
+        ));
+        if let Some(constant) = self.names.constant(HasConstant::FieldIndex(field)) {
+            s.push_str(&format!(" = {}", constant));
+        }
+        if let Some(rva) = field.resolve_rva(table) {
+            s.push_str(&format!(" at I_{:08X}", rva.rva));
+        }
+
+        self.line(s);
+    }
+
+    fn write_method(&mut self, method: MethodDefIndex) {
+        let table = self.names.table;
+        let heap = self.names.heap;
+        let def = *method.resolve_table(table).unwrap();
+        let name = def.name.resolve(heap).unwrap_or_default();
+        let sig: Option<MethodDefSig> = def
+            .signature
+            .resolve(heap)
+            .and_then(|b| b.pread_with(0, scroll::LE).ok());
+
+        let mut flags = method_flags(def.flags);
+        if def.flags.contains(MethodAttributes::PINVOKE_IMPL) {
+            if let Some(info) = method.pinvoke_info(table, heap) {
+                let call_conv = match info.call_conv {
+                    Some(crate::pe::PInvokeCallConv::PlatformApi) | None => "winapi",
+                    Some(crate::pe::PInvokeCallConv::Cdecl) => "cdecl",
+                    Some(crate::pe::PInvokeCallConv::StdCall) => "stdcall",
+                    Some(crate::pe::PInvokeCallConv::ThisCall) => "thiscall",
+                    Some(crate::pe::PInvokeCallConv::FastCall) => "fastcall",
+                };
+                let alias = if info.import_name == name {
+                    String::new()
+                } else {
+                    format!(" as {}", quote_string(info.import_name))
+                };
+                flags.push_str(&format!(
+                    " pinvokeimpl({}{} {})",
+                    quote_string(info.module),
+                    alias,
+                    call_conv
+                ));
+            }
+        }
+
+        let header = match &sig {
+            Some(sig) => format!(
+                "{}{}  {}{}({})",
+                Names::call_conv(sig),
+                self.names.ret_type(&sig.ret),
+                quote_name(name),
+                self.names
+                    .generic_params(TypeOrMethodDef::MethodDefIndex(method)),
+                self.names.params(sig, Some(method))
+            ),
+            None => format!("/* bad signature */ {}()", quote_name(name)),
+        };
+        self.line(format!(
+            ".method {} {} {}",
+            flags,
+            header,
+            impl_flags(def.impl_flags)
+        ));
+        self.open();
+
+        if self.image.entry_point() == Some(EntryPoint::Method(method)) {
+            self.line(".entrypoint");
+        }
+
+        let is_il =
+            def.impl_flags & MethodImplAttributes::CODE_TYPE_MASK == MethodImplAttributes::IL;
+        if def.rva != 0 && is_il {
+            match self.image.get_data::<MethodBody>(def.rva) {
+                Ok(body) => self.write_body(&body),
+                Err(e) => self.line(format!("// Invalid method body: {}", e)),
+            }
+        }
+
+        let owner = self
+            .names
+            .method_owner(method)
+            .and_then(|ty| ty.resolve_table(table))
+            .and_then(|ty| ty.type_name.resolve(heap))
+            .unwrap_or_default();
+        self.close(format!("end of method {}::{}", owner, name));
+        self.line("");
+    }
+
+    /// Locals, IL and exception clauses
+    pub fn write_body(&mut self, body: &MethodBody) {
+        let table = self.names.table;
+        let heap = self.names.heap;
+
+        self.line(format!(
+            "// Code size       {} (0x{:x})",
+            body.code_size, body.code_size
+        ));
+        self.line(format!(".maxstack  {}", body.max_stack));
+
+        if let Some(sig) = body.local_var_sig {
+            let locals = sig
+                .resolve_table(table)
+                .and_then(|s| s.signature.resolve(heap))
+                .and_then(|b| b.pread_with::<crate::pe::LocalVarSig>(0, scroll::LE).ok());
+            match locals {
+                Some(locals) => {
+                    let locals = locals
+                        .locals
+                        .iter()
+                        .enumerate()
+                        .map(|(i, l)| format!("{} V_{}", self.names.local(l), i))
+                        .collect::<Vec<_>>();
+                    self.line(format!(
+                        ".locals {}({})",
+                        if body.init_locals { "init " } else { "" },
+                        locals.join(", ")
+                    ));
+                }
+                None => self.line(format!("// Invalid locals signature {:08X}", sig.0)),
+            }
+        }
+
+        for (i, (offset, inst)) in body.iter().enumerate() {
+            let line = self.names.instruction(offset, inst, body.next_offset(i));
+            self.line(line);
+        }
+
+        for clause in body.exception_clauses.iter() {
+            let handler = match clause.kind {
+                ExceptionClauseKind::Catch(token) => format!("catch {}", self.names.token(token)),
+                ExceptionClauseKind::Filter(offset) => format!("filter {}", label(offset)),
+                ExceptionClauseKind::Finally => "finally".into(),
+                ExceptionClauseKind::Fault => "fault".into(),
+            };
+            self.line(format!(
+                ".try {} to {} {} handler {} to {}",
+                label(clause.try_offset),
+                label(clause.try_offset + clause.try_length),
+                handler,
+                label(clause.handler_offset),
+                label(clause.handler_offset + clause.handler_length)
+            ));
+        }
+    }
+}
+
+#[test]
+fn disasm_call() {
+    use crate::pe::{
+        AssemblyFlags, AssemblyRef, AssemblyRefIndex, AssemblyVersion, BlobIndex, MemberRef,
+        MemberRefIndex, StringIndex, TypeRef, UserStringIndex,
+    };
+
+    let strings = "\0mscorlib\0System\0Console\0WriteLine\0";
+    let blob = [0x00, 0x04, 0x00, 0x01, 0x01, 0x0e];
+    let user_string = [0x00, 0x05, b'H', 0x00, b'i', 0x00, 0x00];
+    let heap = Heap {
+        strings,
+        blob: &blob,
+        user_string: &user_string,
+        ..Heap::default()
+    };
+
+    let table = MetadataTable {
+        assembly_ref: vec![AssemblyRef {
+            version: AssemblyVersion {
+                major_version: 4,
+                minor_version: 0,
+                build_number: 0,
+                revision_number: 0,
+            },
+            flags: AssemblyFlags::empty(),
+            public_key_or_token: BlobIndex(0),
+            name: StringIndex(1),
+            culture: StringIndex(0),
+            hash_value: BlobIndex(0),
+        }],
+        type_ref: vec![TypeRef {
+            resolution_scope: ResolutionScope::AssemblyRefIndex(AssemblyRefIndex(1)),
+            type_name: StringIndex(17),
+            type_namespace: StringIndex(10),
+        }],
+        member_ref: vec![MemberRef {
+            class: MemberRefParent::TypeRefIndex(TypeRefIndex(1)),
+            name: StringIndex(25),
+            signature: BlobIndex(1),
+        }],
+        ..MetadataTable::default()
+    };
+    let names = Names::new(&table, heap);
+
+    assert_eq!(
+        names.instruction(
+            0,
+            &Instruction::LdStr(MetadataToken::UserString(UserStringIndex(1))),
+            5
+        ),
+        "IL_0000:  ldstr      \"Hi\""
+    );
+    assert_eq!(
+        names.instruction(
+            5,
+            &Instruction::Call(MetadataToken::MemberRef(MemberRefIndex(1))),
+            10
+        ),
+        "IL_0005:  call       void [mscorlib]System.Console::WriteLine(string)"
+    );
+    assert_eq!(
+        names.instruction(10, &Instruction::BrTrue(-12), 12),
+        "IL_000a:  brtrue.s   IL_0000"
+    );
+    assert_eq!(
+        names.instruction(12, &Instruction::LdArg(0), 13),
+        "IL_000c:  ldarg.0"
+    );
+    assert_eq!(quote_name("<Module>"), "'<Module>'");
+    assert_eq!(quote_name("List`1"), "List`1");
+}
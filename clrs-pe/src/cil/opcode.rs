@@ -1,27 +1,80 @@
+use std::convert::TryFrom;
+
 use crate::pe::MetadataToken;
 use scroll::{ctx::TryFromCtx, Endian, Pread};
 
-#[derive(Clone, Copy, Debug)]
+/// Type suffix of `ldind`, `stind`, `ldelem`, `stelem` and `conv` families
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NumType {
+    I1,
+    U1,
+    I2,
+    U2,
+    I4,
+    U4,
+    I8,
+    U8,
+    I,
+    U,
+    R4,
+    R8,
+    /// Only for `conv.r.un`
+    RUn,
+    /// Only for `ldind`, `stind`, `ldelem` and `stelem`
+    Ref,
+}
+
+impl NumType {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            NumType::I1 => "i1",
+            NumType::U1 => "u1",
+            NumType::I2 => "i2",
+            NumType::U2 => "u2",
+            NumType::I4 => "i4",
+            NumType::U4 => "u4",
+            NumType::I8 => "i8",
+            NumType::U8 => "u8",
+            NumType::I => "i",
+            NumType::U => "u",
+            NumType::R4 => "r4",
+            NumType::R8 => "r8",
+            NumType::RUn => "r.un",
+            NumType::Ref => "ref",
+        }
+    }
+}
+
+/// Branch offsets are relative to the start of the next instruction
+#[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
     Nop,
     Break,
     LdArg(u32),
     LdArgA(u32),
+    StArg(u32),
     StLoc(u32),
     LdLoc(u32),
     LdLocA(u32),
-    LdcI4(u32),
-    LdcI8(u64),
+    LdNull,
+    LdcI4(i32),
+    LdcI8(i64),
     LdcR4(f32),
     LdcR8(f64),
     LdStr(MetadataToken),
     LdFld(MetadataToken),
+    LdFldA(MetadataToken),
     StFld(MetadataToken),
+    LdSFld(MetadataToken),
+    LdSFldA(MetadataToken),
+    StSFld(MetadataToken),
+    LdToken(MetadataToken),
     Dup,
     Pop,
-    Jmp,
+    Jmp(MetadataToken),
     Call(MetadataToken),
     CallI(MetadataToken),
+    CallVirt(MetadataToken),
     Ret,
 
     Neg,
@@ -47,21 +100,117 @@ pub enum Instruction {
     MulOvf,
     MulOvfUn,
 
-    Br,
-    BrTrue,
-    BrFalse,
-    Ble,
-    BleUn,
-    Blt,
-    BltUn,
-    Bge,
-    BgeUn,
-    Bgt,
-    BgtUn,
-    Beq,
-    BeqUn,
-    Bne,
-    BneUn,
+    Ceq,
+    Cgt,
+    CgtUn,
+    Clt,
+    CltUn,
+    CkFinite,
+
+    Conv(NumType),
+    ConvOvf(NumType),
+    ConvOvfUn(NumType),
+
+    Br(i32),
+    BrTrue(i32),
+    BrFalse(i32),
+    Ble(i32),
+    BleUn(i32),
+    Blt(i32),
+    BltUn(i32),
+    Bge(i32),
+    BgeUn(i32),
+    Bgt(i32),
+    BgtUn(i32),
+    Beq(i32),
+    BneUn(i32),
+    Switch(Vec<i32>),
+    Leave(i32),
+    EndFinally,
+    EndFilter,
+    Throw,
+    Rethrow,
+
+    LdInd(NumType),
+    StInd(NumType),
+    LdObj(MetadataToken),
+    StObj(MetadataToken),
+    CpObj(MetadataToken),
+    InitObj(MetadataToken),
+    CpBlk,
+    InitBlk,
+    LocAlloc,
+    SizeOf(MetadataToken),
+
+    NewObj(MetadataToken),
+    NewArr(MetadataToken),
+    LdLen,
+    LdElem(NumType),
+    LdElemA(MetadataToken),
+    LdElemAny(MetadataToken),
+    StElem(NumType),
+    StElemAny(MetadataToken),
+    CastClass(MetadataToken),
+    IsInst(MetadataToken),
+    Box(MetadataToken),
+    Unbox(MetadataToken),
+    UnboxAny(MetadataToken),
+
+    LdFtn(MetadataToken),
+    LdVirtFtn(MetadataToken),
+    ArgList,
+    MkRefAny(MetadataToken),
+    RefAnyVal(MetadataToken),
+    RefAnyType,
+
+    // Prefixes
+    Unaligned(u8),
+    Volatile,
+    Tail,
+    Constrained(MetadataToken),
+    No(u8),
+    Readonly,
+}
+
+impl Instruction {
+    /// Absolute targets of a branch, `next` is the offset of the following instruction
+    pub fn branch_targets(&self, next: u32) -> Vec<u32> {
+        let target = |rel: &i32| (next as i64 + *rel as i64) as u32;
+
+        match self {
+            Self::Br(rel)
+            | Self::BrTrue(rel)
+            | Self::BrFalse(rel)
+            | Self::Ble(rel)
+            | Self::BleUn(rel)
+            | Self::Blt(rel)
+            | Self::BltUn(rel)
+            | Self::Bge(rel)
+            | Self::BgeUn(rel)
+            | Self::Bgt(rel)
+            | Self::BgtUn(rel)
+            | Self::Beq(rel)
+            | Self::BneUn(rel)
+            | Self::Leave(rel) => vec![target(rel)],
+            Self::Switch(rels) => rels.iter().map(target).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Control never falls through to the next instruction
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Self::Br(_)
+                | Self::Leave(_)
+                | Self::Ret
+                | Self::Throw
+                | Self::Rethrow
+                | Self::EndFinally
+                | Self::EndFilter
+                | Self::Jmp(_)
+        )
+    }
 }
 
 impl<'a> TryFromCtx<'a, Endian> for Instruction {
@@ -71,26 +220,642 @@ impl<'a> TryFromCtx<'a, Endian> for Instruction {
         let offset = &mut 0;
         let opcode: u8 = src.gread_with(offset, ctx)?;
 
+        macro_rules! read {
+            ($ty:ty) => {
+                src.gread_with::<$ty>(offset, ctx)?
+            };
+        }
+
         let inst = match opcode {
             0x00 => Self::Nop,
             0x01 => Self::Break,
-            0x02 => Self::LdArg(0),
-            0x03 => Self::LdArg(1),
-            0x04 => Self::LdArg(2),
-            0x05 => Self::LdArg(3),
-
-            0x28 => Self::Call(src.gread_with(offset, ctx)?),
+            0x02..=0x05 => Self::LdArg((opcode - 0x02) as u32),
+            0x06..=0x09 => Self::LdLoc((opcode - 0x06) as u32),
+            0x0A..=0x0D => Self::StLoc((opcode - 0x0A) as u32),
+            0x0E => Self::LdArg(read!(u8) as u32),
+            0x0F => Self::LdArgA(read!(u8) as u32),
+            0x10 => Self::StArg(read!(u8) as u32),
+            0x11 => Self::LdLoc(read!(u8) as u32),
+            0x12 => Self::LdLocA(read!(u8) as u32),
+            0x13 => Self::StLoc(read!(u8) as u32),
+            0x14 => Self::LdNull,
+            0x15..=0x1E => Self::LdcI4(opcode as i32 - 0x16),
+            0x1F => Self::LdcI4(read!(i8) as i32),
+            0x20 => Self::LdcI4(read!(i32)),
+            0x21 => Self::LdcI8(read!(i64)),
+            0x22 => Self::LdcR4(read!(f32)),
+            0x23 => Self::LdcR8(read!(f64)),
+            0x25 => Self::Dup,
+            0x26 => Self::Pop,
+            0x27 => Self::Jmp(read!(MetadataToken)),
+            0x28 => Self::Call(read!(MetadataToken)),
+            0x29 => Self::CallI(read!(MetadataToken)),
             0x2A => Self::Ret,
 
-            0x40 => Self::BneUn,
-            0x41 => Self::BgeUn,
+            0x2B..=0x37 => {
+                let rel = read!(i8) as i32;
+                Self::branch(opcode - 0x2B, rel)
+            }
+            0x38..=0x44 => {
+                let rel = read!(i32);
+                Self::branch(opcode - 0x38, rel)
+            }
+            0x45 => {
+                let count = read!(u32);
+                let targets = (0..count)
+                    .map(|_| src.gread_with(offset, ctx))
+                    .collect::<Result<_, _>>()?;
+                Self::Switch(targets)
+            }
+
+            0x46 => Self::LdInd(NumType::I1),
+            0x47 => Self::LdInd(NumType::U1),
+            0x48 => Self::LdInd(NumType::I2),
+            0x49 => Self::LdInd(NumType::U2),
+            0x4A => Self::LdInd(NumType::I4),
+            0x4B => Self::LdInd(NumType::U4),
+            0x4C => Self::LdInd(NumType::I8),
+            0x4D => Self::LdInd(NumType::I),
+            0x4E => Self::LdInd(NumType::R4),
+            0x4F => Self::LdInd(NumType::R8),
+            0x50 => Self::LdInd(NumType::Ref),
+            0x51 => Self::StInd(NumType::Ref),
+            0x52 => Self::StInd(NumType::I1),
+            0x53 => Self::StInd(NumType::I2),
+            0x54 => Self::StInd(NumType::I4),
+            0x55 => Self::StInd(NumType::I8),
+            0x56 => Self::StInd(NumType::R4),
+            0x57 => Self::StInd(NumType::R8),
+
+            0x58 => Self::Add,
+            0x59 => Self::Sub,
+            0x5A => Self::Mul,
+            0x5B => Self::Div,
+            0x5C => Self::DivUn,
+            0x5D => Self::Rem,
+            0x5E => Self::RemUn,
+            0x5F => Self::And,
+            0x60 => Self::Or,
+            0x61 => Self::Xor,
+            0x62 => Self::Shl,
+            0x63 => Self::Shr,
+            0x64 => Self::ShrUn,
+            0x65 => Self::Neg,
+            0x66 => Self::Not,
+
+            0x67 => Self::Conv(NumType::I1),
+            0x68 => Self::Conv(NumType::I2),
+            0x69 => Self::Conv(NumType::I4),
+            0x6A => Self::Conv(NumType::I8),
+            0x6B => Self::Conv(NumType::R4),
+            0x6C => Self::Conv(NumType::R8),
+            0x6D => Self::Conv(NumType::U4),
+            0x6E => Self::Conv(NumType::U8),
+
+            0x6F => Self::CallVirt(read!(MetadataToken)),
+            0x70 => Self::CpObj(read!(MetadataToken)),
+            0x71 => Self::LdObj(read!(MetadataToken)),
+            0x72 => Self::LdStr(read!(MetadataToken)),
+            0x73 => Self::NewObj(read!(MetadataToken)),
+            0x74 => Self::CastClass(read!(MetadataToken)),
+            0x75 => Self::IsInst(read!(MetadataToken)),
+            0x76 => Self::Conv(NumType::RUn),
+            0x79 => Self::Unbox(read!(MetadataToken)),
+            0x7A => Self::Throw,
+            0x7B => Self::LdFld(read!(MetadataToken)),
+            0x7C => Self::LdFldA(read!(MetadataToken)),
+            0x7D => Self::StFld(read!(MetadataToken)),
+            0x7E => Self::LdSFld(read!(MetadataToken)),
+            0x7F => Self::LdSFldA(read!(MetadataToken)),
+            0x80 => Self::StSFld(read!(MetadataToken)),
+            0x81 => Self::StObj(read!(MetadataToken)),
+
+            0x82 => Self::ConvOvfUn(NumType::I1),
+            0x83 => Self::ConvOvfUn(NumType::I2),
+            0x84 => Self::ConvOvfUn(NumType::I4),
+            0x85 => Self::ConvOvfUn(NumType::I8),
+            0x86 => Self::ConvOvfUn(NumType::U1),
+            0x87 => Self::ConvOvfUn(NumType::U2),
+            0x88 => Self::ConvOvfUn(NumType::U4),
+            0x89 => Self::ConvOvfUn(NumType::U8),
+            0x8A => Self::ConvOvfUn(NumType::I),
+            0x8B => Self::ConvOvfUn(NumType::U),
+
+            0x8C => Self::Box(read!(MetadataToken)),
+            0x8D => Self::NewArr(read!(MetadataToken)),
+            0x8E => Self::LdLen,
+            0x8F => Self::LdElemA(read!(MetadataToken)),
+            0x90 => Self::LdElem(NumType::I1),
+            0x91 => Self::LdElem(NumType::U1),
+            0x92 => Self::LdElem(NumType::I2),
+            0x93 => Self::LdElem(NumType::U2),
+            0x94 => Self::LdElem(NumType::I4),
+            0x95 => Self::LdElem(NumType::U4),
+            0x96 => Self::LdElem(NumType::I8),
+            0x97 => Self::LdElem(NumType::I),
+            0x98 => Self::LdElem(NumType::R4),
+            0x99 => Self::LdElem(NumType::R8),
+            0x9A => Self::LdElem(NumType::Ref),
+            0x9B => Self::StElem(NumType::I),
+            0x9C => Self::StElem(NumType::I1),
+            0x9D => Self::StElem(NumType::I2),
+            0x9E => Self::StElem(NumType::I4),
+            0x9F => Self::StElem(NumType::I8),
+            0xA0 => Self::StElem(NumType::R4),
+            0xA1 => Self::StElem(NumType::R8),
+            0xA2 => Self::StElem(NumType::Ref),
+            0xA3 => Self::LdElemAny(read!(MetadataToken)),
+            0xA4 => Self::StElemAny(read!(MetadataToken)),
+            0xA5 => Self::UnboxAny(read!(MetadataToken)),
+
+            0xB3 => Self::ConvOvf(NumType::I1),
+            0xB4 => Self::ConvOvf(NumType::U1),
+            0xB5 => Self::ConvOvf(NumType::I2),
+            0xB6 => Self::ConvOvf(NumType::U2),
+            0xB7 => Self::ConvOvf(NumType::I4),
+            0xB8 => Self::ConvOvf(NumType::U4),
+            0xB9 => Self::ConvOvf(NumType::I8),
+            0xBA => Self::ConvOvf(NumType::U8),
+
+            0xC2 => Self::RefAnyVal(read!(MetadataToken)),
+            0xC3 => Self::CkFinite,
+            0xC6 => Self::MkRefAny(read!(MetadataToken)),
+            0xD0 => Self::LdToken(read!(MetadataToken)),
+            0xD1 => Self::Conv(NumType::U2),
+            0xD2 => Self::Conv(NumType::U1),
+            0xD3 => Self::Conv(NumType::I),
+            0xD4 => Self::ConvOvf(NumType::I),
+            0xD5 => Self::ConvOvf(NumType::U),
+            0xD6 => Self::AddOvf,
+            0xD7 => Self::AddOvfUn,
+            0xD8 => Self::MulOvf,
+            0xD9 => Self::MulOvfUn,
+            0xDA => Self::SubOvf,
+            0xDB => Self::SubOvfUn,
+            0xDC => Self::EndFinally,
+            0xDD => Self::Leave(read!(i32)),
+            0xDE => Self::Leave(read!(i8) as i32),
+            0xDF => Self::StInd(NumType::I),
+            0xE0 => Self::Conv(NumType::U),
+
+            0xFE => {
+                let opcode: u8 = read!(u8);
 
-            0x72 => Self::LdStr(src.gread_with(offset, ctx)?),
-            0x7B => Self::LdFld(src.gread_with(offset, ctx)?),
-            0x7D => Self::StFld(src.gread_with(offset, ctx)?),
-            _ => todo!("code: {:X}", opcode),
+                match opcode {
+                    0x00 => Self::ArgList,
+                    0x01 => Self::Ceq,
+                    0x02 => Self::Cgt,
+                    0x03 => Self::CgtUn,
+                    0x04 => Self::Clt,
+                    0x05 => Self::CltUn,
+                    0x06 => Self::LdFtn(read!(MetadataToken)),
+                    0x07 => Self::LdVirtFtn(read!(MetadataToken)),
+                    0x09 => Self::LdArg(read!(u16) as u32),
+                    0x0A => Self::LdArgA(read!(u16) as u32),
+                    0x0B => Self::StArg(read!(u16) as u32),
+                    0x0C => Self::LdLoc(read!(u16) as u32),
+                    0x0D => Self::LdLocA(read!(u16) as u32),
+                    0x0E => Self::StLoc(read!(u16) as u32),
+                    0x0F => Self::LocAlloc,
+                    0x11 => Self::EndFilter,
+                    0x12 => Self::Unaligned(read!(u8)),
+                    0x13 => Self::Volatile,
+                    0x14 => Self::Tail,
+                    0x15 => Self::InitObj(read!(MetadataToken)),
+                    0x16 => Self::Constrained(read!(MetadataToken)),
+                    0x17 => Self::CpBlk,
+                    0x18 => Self::InitBlk,
+                    0x19 => Self::No(read!(u8)),
+                    0x1A => Self::Rethrow,
+                    0x1C => Self::SizeOf(read!(MetadataToken)),
+                    0x1D => Self::RefAnyType,
+                    0x1E => Self::Readonly,
+                    _ => {
+                        return Err(scroll::Error::BadInput {
+                            size: 2,
+                            msg: "Invalid opcode",
+                        })
+                    }
+                }
+            }
+
+            _ => {
+                return Err(scroll::Error::BadInput {
+                    size: 1,
+                    msg: "Invalid opcode",
+                })
+            }
         };
 
         Ok((inst, *offset))
     }
 }
+
+/// Encoding of instructions which have several opcodes.
+/// A form which can't hold the operand falls back to the next larger one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Form {
+    /// Operand is part of the opcode, like `ldarg.0` and `ldc.i4.m1`
+    Macro,
+    /// One byte operand, like `ldarg.s` and `br.s`
+    Short,
+    Long,
+}
+
+enum Operand<'a> {
+    None,
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I32(i32),
+    I64(i64),
+    R4(f32),
+    R8(f64),
+    Token(MetadataToken),
+    Switch(&'a [i32]),
+}
+
+/// Two byte opcodes are `0xFE00 | n`
+const PREFIX: u16 = 0xFE00;
+
+impl Instruction {
+    /// Index of the opcode in `branch` and relative offset
+    fn branch_index(&self) -> Option<(u16, i32)> {
+        let (n, rel) = match self {
+            Self::Br(rel) => (0, rel),
+            Self::BrFalse(rel) => (1, rel),
+            Self::BrTrue(rel) => (2, rel),
+            Self::Beq(rel) => (3, rel),
+            Self::Bge(rel) => (4, rel),
+            Self::Bgt(rel) => (5, rel),
+            Self::Ble(rel) => (6, rel),
+            Self::Blt(rel) => (7, rel),
+            Self::BneUn(rel) => (8, rel),
+            Self::BgeUn(rel) => (9, rel),
+            Self::BgtUn(rel) => (10, rel),
+            Self::BleUn(rel) => (11, rel),
+            Self::BltUn(rel) => (12, rel),
+            _ => return None,
+        };
+        Some((n, *rel))
+    }
+
+    fn parts(&self, form: Form) -> (u16, Operand<'_>) {
+        use NumType::*;
+
+        // macro, short and long opcodes of a variable instruction
+        let var = |n: u32, ops: (Option<u16>, u16, u16)| match ops.0 {
+            Some(op) if form == Form::Macro && n < 4 => (op + n as u16, Operand::None),
+            _ if form != Form::Long && n < 0x100 => (ops.1, Operand::U8(n as u8)),
+            _ => (ops.2, Operand::U16(n as u16)),
+        };
+        let short = form != Form::Long;
+        let no_opcode = || -> ! { panic!("{:?} has no opcode", self) };
+
+        if let Some((n, rel)) = self.branch_index() {
+            return if short && i8::try_from(rel).is_ok() {
+                (0x2B + n, Operand::I8(rel as i8))
+            } else {
+                (0x38 + n, Operand::I32(rel))
+            };
+        }
+
+        match self {
+            Self::Nop => (0x00, Operand::None),
+            Self::Break => (0x01, Operand::None),
+            Self::LdArg(n) => var(*n, (Some(0x02), 0x0E, PREFIX | 0x09)),
+            Self::LdArgA(n) => var(*n, (None, 0x0F, PREFIX | 0x0A)),
+            Self::StArg(n) => var(*n, (None, 0x10, PREFIX | 0x0B)),
+            Self::LdLoc(n) => var(*n, (Some(0x06), 0x11, PREFIX | 0x0C)),
+            Self::LdLocA(n) => var(*n, (None, 0x12, PREFIX | 0x0D)),
+            Self::StLoc(n) => var(*n, (Some(0x0A), 0x13, PREFIX | 0x0E)),
+            Self::LdNull => (0x14, Operand::None),
+            Self::LdcI4(n) if form == Form::Macro && (-1..=8).contains(n) => {
+                ((0x16 + n) as u16, Operand::None)
+            }
+            Self::LdcI4(n) if short && i8::try_from(*n).is_ok() => (0x1F, Operand::I8(*n as i8)),
+            Self::LdcI4(n) => (0x20, Operand::I32(*n)),
+            Self::LdcI8(n) => (0x21, Operand::I64(*n)),
+            Self::LdcR4(n) => (0x22, Operand::R4(*n)),
+            Self::LdcR8(n) => (0x23, Operand::R8(*n)),
+            Self::Dup => (0x25, Operand::None),
+            Self::Pop => (0x26, Operand::None),
+            Self::Jmp(t) => (0x27, Operand::Token(*t)),
+            Self::Call(t) => (0x28, Operand::Token(*t)),
+            Self::CallI(t) => (0x29, Operand::Token(*t)),
+            Self::Ret => (0x2A, Operand::None),
+            Self::Switch(rels) => (0x45, Operand::Switch(rels)),
+
+            Self::LdInd(ty) => match ty {
+                I1 => (0x46, Operand::None),
+                U1 => (0x47, Operand::None),
+                I2 => (0x48, Operand::None),
+                U2 => (0x49, Operand::None),
+                I4 => (0x4A, Operand::None),
+                U4 => (0x4B, Operand::None),
+                // ldind.u8 is an alias of ldind.i8
+                I8 | U8 => (0x4C, Operand::None),
+                I | U => (0x4D, Operand::None),
+                R4 => (0x4E, Operand::None),
+                R8 => (0x4F, Operand::None),
+                Ref => (0x50, Operand::None),
+                RUn => no_opcode(),
+            },
+            Self::StInd(ty) => match ty {
+                Ref => (0x51, Operand::None),
+                I1 | U1 => (0x52, Operand::None),
+                I2 | U2 => (0x53, Operand::None),
+                I4 | U4 => (0x54, Operand::None),
+                I8 | U8 => (0x55, Operand::None),
+                R4 => (0x56, Operand::None),
+                R8 => (0x57, Operand::None),
+                I | U => (0xDF, Operand::None),
+                RUn => no_opcode(),
+            },
+
+            Self::Add => (0x58, Operand::None),
+            Self::Sub => (0x59, Operand::None),
+            Self::Mul => (0x5A, Operand::None),
+            Self::Div => (0x5B, Operand::None),
+            Self::DivUn => (0x5C, Operand::None),
+            Self::Rem => (0x5D, Operand::None),
+            Self::RemUn => (0x5E, Operand::None),
+            Self::And => (0x5F, Operand::None),
+            Self::Or => (0x60, Operand::None),
+            Self::Xor => (0x61, Operand::None),
+            Self::Shl => (0x62, Operand::None),
+            Self::Shr => (0x63, Operand::None),
+            Self::ShrUn => (0x64, Operand::None),
+            Self::Neg => (0x65, Operand::None),
+            Self::Not => (0x66, Operand::None),
+
+            Self::Conv(ty) => match ty {
+                I1 => (0x67, Operand::None),
+                I2 => (0x68, Operand::None),
+                I4 => (0x69, Operand::None),
+                I8 => (0x6A, Operand::None),
+                R4 => (0x6B, Operand::None),
+                R8 => (0x6C, Operand::None),
+                U4 => (0x6D, Operand::None),
+                U8 => (0x6E, Operand::None),
+                RUn => (0x76, Operand::None),
+                U2 => (0xD1, Operand::None),
+                U1 => (0xD2, Operand::None),
+                I => (0xD3, Operand::None),
+                U => (0xE0, Operand::None),
+                Ref => no_opcode(),
+            },
+            Self::ConvOvfUn(ty) => match ty {
+                I1 => (0x82, Operand::None),
+                I2 => (0x83, Operand::None),
+                I4 => (0x84, Operand::None),
+                I8 => (0x85, Operand::None),
+                U1 => (0x86, Operand::None),
+                U2 => (0x87, Operand::None),
+                U4 => (0x88, Operand::None),
+                U8 => (0x89, Operand::None),
+                I => (0x8A, Operand::None),
+                U => (0x8B, Operand::None),
+                R4 | R8 | RUn | Ref => no_opcode(),
+            },
+            Self::ConvOvf(ty) => match ty {
+                I1 => (0xB3, Operand::None),
+                U1 => (0xB4, Operand::None),
+                I2 => (0xB5, Operand::None),
+                U2 => (0xB6, Operand::None),
+                I4 => (0xB7, Operand::None),
+                U4 => (0xB8, Operand::None),
+                I8 => (0xB9, Operand::None),
+                U8 => (0xBA, Operand::None),
+                I => (0xD4, Operand::None),
+                U => (0xD5, Operand::None),
+                R4 | R8 | RUn | Ref => no_opcode(),
+            },
+
+            Self::CallVirt(t) => (0x6F, Operand::Token(*t)),
+            Self::CpObj(t) => (0x70, Operand::Token(*t)),
+            Self::LdObj(t) => (0x71, Operand::Token(*t)),
+            Self::LdStr(t) => (0x72, Operand::Token(*t)),
+            Self::NewObj(t) => (0x73, Operand::Token(*t)),
+            Self::CastClass(t) => (0x74, Operand::Token(*t)),
+            Self::IsInst(t) => (0x75, Operand::Token(*t)),
+            Self::Unbox(t) => (0x79, Operand::Token(*t)),
+            Self::Throw => (0x7A, Operand::None),
+            Self::LdFld(t) => (0x7B, Operand::Token(*t)),
+            Self::LdFldA(t) => (0x7C, Operand::Token(*t)),
+            Self::StFld(t) => (0x7D, Operand::Token(*t)),
+            Self::LdSFld(t) => (0x7E, Operand::Token(*t)),
+            Self::LdSFldA(t) => (0x7F, Operand::Token(*t)),
+            Self::StSFld(t) => (0x80, Operand::Token(*t)),
+            Self::StObj(t) => (0x81, Operand::Token(*t)),
+
+            Self::Box(t) => (0x8C, Operand::Token(*t)),
+            Self::NewArr(t) => (0x8D, Operand::Token(*t)),
+            Self::LdLen => (0x8E, Operand::None),
+            Self::LdElemA(t) => (0x8F, Operand::Token(*t)),
+            Self::LdElem(ty) => match ty {
+                I1 => (0x90, Operand::None),
+                U1 => (0x91, Operand::None),
+                I2 => (0x92, Operand::None),
+                U2 => (0x93, Operand::None),
+                I4 => (0x94, Operand::None),
+                U4 => (0x95, Operand::None),
+                // ldelem.u8 is an alias of ldelem.i8
+                I8 | U8 => (0x96, Operand::None),
+                I | U => (0x97, Operand::None),
+                R4 => (0x98, Operand::None),
+                R8 => (0x99, Operand::None),
+                Ref => (0x9A, Operand::None),
+                RUn => no_opcode(),
+            },
+            Self::StElem(ty) => match ty {
+                I | U => (0x9B, Operand::None),
+                I1 | U1 => (0x9C, Operand::None),
+                I2 | U2 => (0x9D, Operand::None),
+                I4 | U4 => (0x9E, Operand::None),
+                I8 | U8 => (0x9F, Operand::None),
+                R4 => (0xA0, Operand::None),
+                R8 => (0xA1, Operand::None),
+                Ref => (0xA2, Operand::None),
+                RUn => no_opcode(),
+            },
+            Self::LdElemAny(t) => (0xA3, Operand::Token(*t)),
+            Self::StElemAny(t) => (0xA4, Operand::Token(*t)),
+            Self::UnboxAny(t) => (0xA5, Operand::Token(*t)),
+
+            Self::RefAnyVal(t) => (0xC2, Operand::Token(*t)),
+            Self::CkFinite => (0xC3, Operand::None),
+            Self::MkRefAny(t) => (0xC6, Operand::Token(*t)),
+            Self::LdToken(t) => (0xD0, Operand::Token(*t)),
+            Self::AddOvf => (0xD6, Operand::None),
+            Self::AddOvfUn => (0xD7, Operand::None),
+            Self::MulOvf => (0xD8, Operand::None),
+            Self::MulOvfUn => (0xD9, Operand::None),
+            Self::SubOvf => (0xDA, Operand::None),
+            Self::SubOvfUn => (0xDB, Operand::None),
+            Self::EndFinally => (0xDC, Operand::None),
+            Self::Leave(rel) if short && i8::try_from(*rel).is_ok() => {
+                (0xDE, Operand::I8(*rel as i8))
+            }
+            Self::Leave(rel) => (0xDD, Operand::I32(*rel)),
+
+            Self::ArgList => (PREFIX, Operand::None),
+            Self::Ceq => (PREFIX | 0x01, Operand::None),
+            Self::Cgt => (PREFIX | 0x02, Operand::None),
+            Self::CgtUn => (PREFIX | 0x03, Operand::None),
+            Self::Clt => (PREFIX | 0x04, Operand::None),
+            Self::CltUn => (PREFIX | 0x05, Operand::None),
+            Self::LdFtn(t) => (PREFIX | 0x06, Operand::Token(*t)),
+            Self::LdVirtFtn(t) => (PREFIX | 0x07, Operand::Token(*t)),
+            Self::LocAlloc => (PREFIX | 0x0F, Operand::None),
+            Self::EndFilter => (PREFIX | 0x11, Operand::None),
+            Self::Unaligned(n) => (PREFIX | 0x12, Operand::U8(*n)),
+            Self::Volatile => (PREFIX | 0x13, Operand::None),
+            Self::Tail => (PREFIX | 0x14, Operand::None),
+            Self::InitObj(t) => (PREFIX | 0x15, Operand::Token(*t)),
+            Self::Constrained(t) => (PREFIX | 0x16, Operand::Token(*t)),
+            Self::CpBlk => (PREFIX | 0x17, Operand::None),
+            Self::InitBlk => (PREFIX | 0x18, Operand::None),
+            Self::No(n) => (PREFIX | 0x19, Operand::U8(*n)),
+            Self::Rethrow => (PREFIX | 0x1A, Operand::None),
+            Self::SizeOf(t) => (PREFIX | 0x1C, Operand::Token(*t)),
+            Self::RefAnyType => (PREFIX | 0x1D, Operand::None),
+            Self::Readonly => (PREFIX | 0x1E, Operand::None),
+
+            Self::Br(_)
+            | Self::BrFalse(_)
+            | Self::BrTrue(_)
+            | Self::Beq(_)
+            | Self::Bge(_)
+            | Self::Bgt(_)
+            | Self::Ble(_)
+            | Self::Blt(_)
+            | Self::BneUn(_)
+            | Self::BgeUn(_)
+            | Self::BgtUn(_)
+            | Self::BleUn(_)
+            | Self::BltUn(_) => unreachable!(),
+        }
+    }
+
+    /// Encoded size in bytes
+    pub fn size(&self, form: Form) -> u32 {
+        let (opcode, operand) = self.parts(form);
+        let opcode_size = if opcode & PREFIX == PREFIX { 2 } else { 1 };
+        let operand_size = match operand {
+            Operand::None => 0,
+            Operand::U8(_) | Operand::I8(_) => 1,
+            Operand::U16(_) => 2,
+            Operand::I32(_) | Operand::R4(_) | Operand::Token(_) => 4,
+            Operand::I64(_) | Operand::R8(_) => 8,
+            Operand::Switch(rels) => 4 + 4 * rels.len() as u32,
+        };
+        opcode_size + operand_size
+    }
+
+    /// Form which encodes to `size` bytes, the inverse of `size`
+    pub fn form(&self, size: u32) -> Form {
+        [Form::Macro, Form::Short]
+            .iter()
+            .copied()
+            .find(|form| self.size(*form) == size)
+            .unwrap_or(Form::Long)
+    }
+
+    pub fn encode(&self, form: Form, out: &mut Vec<u8>) {
+        let (opcode, operand) = self.parts(form);
+
+        if opcode & PREFIX == PREFIX {
+            out.push(0xFE);
+        }
+        out.push(opcode as u8);
+
+        match operand {
+            Operand::None => {}
+            Operand::U8(n) => out.push(n),
+            Operand::I8(n) => out.push(n as u8),
+            Operand::U16(n) => out.extend_from_slice(&n.to_le_bytes()),
+            Operand::I32(n) => out.extend_from_slice(&n.to_le_bytes()),
+            Operand::I64(n) => out.extend_from_slice(&n.to_le_bytes()),
+            Operand::R4(n) => out.extend_from_slice(&n.to_le_bytes()),
+            Operand::R8(n) => out.extend_from_slice(&n.to_le_bytes()),
+            Operand::Token(token) => out.extend_from_slice(&token.to_raw().to_le_bytes()),
+            Operand::Switch(rels) => {
+                out.extend_from_slice(&(rels.len() as u32).to_le_bytes());
+                for rel in rels {
+                    out.extend_from_slice(&rel.to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+impl Instruction {
+    /// Conditional and unconditional branches in opcode order starting from `br`
+    fn branch(n: u8, rel: i32) -> Self {
+        match n {
+            0 => Self::Br(rel),
+            1 => Self::BrFalse(rel),
+            2 => Self::BrTrue(rel),
+            3 => Self::Beq(rel),
+            4 => Self::Bge(rel),
+            5 => Self::Bgt(rel),
+            6 => Self::Ble(rel),
+            7 => Self::Blt(rel),
+            8 => Self::BneUn(rel),
+            9 => Self::BgeUn(rel),
+            10 => Self::BgtUn(rel),
+            11 => Self::BleUn(rel),
+            _ => Self::BltUn(rel),
+        }
+    }
+}
+
+#[test]
+fn decode_instructions() {
+    let code = [
+        0x16, // ldc.i4.0
+        0x1F, 0xF6, // ldc.i4.s -10
+        0x2B, 0x02, // br.s +2
+        0xFE, 0x01, // ceq
+        0x45, 0x01, 0x00, 0x00, 0x00, 0xFC, 0xFF, 0xFF, 0xFF, // switch (-4)
+    ];
+    let offset = &mut 0;
+    let mut insts = Vec::new();
+    while *offset < code.len() {
+        insts.push(code.gread_with::<Instruction>(offset, scroll::LE).unwrap());
+    }
+
+    assert_eq!(
+        insts,
+        [
+            Instruction::LdcI4(0),
+            Instruction::LdcI4(-10),
+            Instruction::Br(2),
+            Instruction::Ceq,
+            Instruction::Switch(vec![-4]),
+        ]
+    );
+    assert_eq!(insts[2].branch_targets(5), [7]);
+    assert_eq!(insts[4].branch_targets(16), [12]);
+}
+
+#[test]
+fn encode_instructions() {
+    let mut codes = (0x00..=0xFF).map(|op| vec![op]).collect::<Vec<_>>();
+    codes.extend((0x00..=0x1E).map(|op| vec![0xFE, op]));
+
+    for mut code in codes {
+        let opcode_len = code.len();
+        code.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        let (inst, size) = match Instruction::try_from_ctx(&code, scroll::LE) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+
+        let mut out = Vec::new();
+        inst.encode(inst.form(size as u32), &mut out);
+        assert_eq!(out, code[..size], "{:?}", inst);
+        assert!(out.len() > opcode_len || size == opcode_len);
+    }
+}
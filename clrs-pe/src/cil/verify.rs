@@ -0,0 +1,849 @@
+//! Stack-typed IL verification, ECMA-335 Partition III
+
+use std::fmt;
+
+use scroll::Pread;
+
+use super::cfg::{callee_sig, Cfg};
+use super::disasm::{mnemonic, Names};
+use super::{ExceptionClauseKind, Instruction, MethodBody, NumType};
+use crate::pe::{
+    FieldAttributes, FieldSig, Image, LocalVar, LocalVarSig, MetadataToken,
+    MethodCallingConvension, MethodDefIndex, MethodDefSig, MethodRefSig, Param, RetType,
+    TableIndex, Type, TypeDefIndex, TypeDefOrRefOrSpecEncoded,
+};
+
+/// Verification types of the evaluation stack, III.1.8.1.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackType {
+    Int32,
+    Int64,
+    NativeInt,
+    /// `F`, both `float32` and `float64`
+    Float,
+    /// `O`, object references including null
+    Object,
+    /// `&`, managed pointers
+    Ptr,
+    Value,
+    /// Generic parameter or a type from another assembly, compatible with everything
+    Any,
+}
+
+impl StackType {
+    /// III.1.6 implicit conversions between `int32` and `native int` are allowed
+    pub fn is_assignable_to(self, to: StackType) -> bool {
+        use StackType::*;
+
+        self == to
+            || self == Any
+            || to == Any
+            || matches!((self, to), (Int32, NativeInt) | (NativeInt, Int32))
+    }
+
+    fn of_num(ty: NumType) -> Self {
+        match ty {
+            NumType::I1 | NumType::U1 | NumType::I2 | NumType::U2 | NumType::I4 | NumType::U4 => {
+                StackType::Int32
+            }
+            NumType::I8 | NumType::U8 => StackType::Int64,
+            NumType::I | NumType::U => StackType::NativeInt,
+            NumType::R4 | NumType::R8 | NumType::RUn => StackType::Float,
+            NumType::Ref => StackType::Object,
+        }
+    }
+}
+
+impl fmt::Display for StackType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StackType::Int32 => "int32",
+            StackType::Int64 => "int64",
+            StackType::NativeInt => "native int",
+            StackType::Float => "F",
+            StackType::Object => "O",
+            StackType::Ptr => "&",
+            StackType::Value => "valuetype",
+            StackType::Any => "?",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    pub method: MethodDefIndex,
+    pub offset: u32,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:08X}] IL_{:04x}: {}",
+            MetadataToken::MethodDef(self.method).to_raw(),
+            self.offset,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verify every method body of the image
+pub fn verify(image: &Image) -> Vec<VerifyError> {
+    let root = image.metadata_root();
+    let names = Names::new(&root.metadata_stream.table, root.heap);
+
+    names
+        .table
+        .list_method_def()
+        .filter(|(_, def)| def.rva != 0)
+        .filter_map(|(index, def)| verify_method(&names, index, &def.resolve_body(image)).err())
+        .collect()
+}
+
+/// Abstractly interpret one method body, stopping at the first violation
+pub fn verify_method(
+    names: &Names,
+    method: MethodDefIndex,
+    body: &MethodBody,
+) -> Result<(), VerifyError> {
+    let error = |offset: u32, message: String| VerifyError {
+        method,
+        offset,
+        message,
+    };
+
+    let cfg = Cfg::new(body, names.table, names.heap).map_err(|e| error(e.offset, e.message))?;
+    let verifier = Verifier::new(names, method, body).map_err(|m| error(0, m))?;
+
+    let mut entries: Vec<Option<Vec<StackType>>> = vec![None; cfg.blocks.len()];
+    let mut work = vec![(0, Vec::new())];
+    for clause in &body.exception_clauses {
+        let exception = match clause.kind {
+            ExceptionClauseKind::Catch(_) | ExceptionClauseKind::Filter(_) => {
+                vec![StackType::Object]
+            }
+            ExceptionClauseKind::Finally | ExceptionClauseKind::Fault => vec![],
+        };
+        work.extend(cfg.block_at(clause.handler_offset).map(|b| (b, exception)));
+        if let ExceptionClauseKind::Filter(filter) = clause.kind {
+            work.extend(cfg.block_at(filter).map(|b| (b, vec![StackType::Object])));
+        }
+    }
+
+    while let Some((b, stack)) = work.pop() {
+        let block = &cfg.blocks[b];
+        // III.1.8.1.3 merging requires the same depth and compatible types
+        if let Some(entry) = &entries[b] {
+            let compatible = entry.len() == stack.len()
+                && entry
+                    .iter()
+                    .zip(&stack)
+                    .all(|(a, b)| a.is_assignable_to(*b) && b.is_assignable_to(*a));
+            if !compatible {
+                return Err(error(
+                    block.offset,
+                    format!(
+                        "stack [{}] differs from [{}] at merge point",
+                        join(&stack),
+                        join(entry)
+                    ),
+                ));
+            }
+            continue;
+        }
+        entries[b] = Some(stack.clone());
+
+        let mut stack = stack;
+        for i in block.start..block.end {
+            let offset = body.offsets[i];
+            let inst = &body.instructions[i];
+            let size = body.next_offset(i) - offset;
+            verifier
+                .step(inst, &mut stack)
+                .map_err(|m| error(offset, format!("{}: {}", mnemonic(inst, size), m)))?;
+            if stack.len() > body.max_stack as usize {
+                return Err(error(
+                    offset,
+                    format!("stack depth exceeds .maxstack {}", body.max_stack),
+                ));
+            }
+        }
+
+        work.extend(block.successors.iter().map(|&s| (s, stack.clone())));
+    }
+
+    Ok(())
+}
+
+fn join(stack: &[StackType]) -> String {
+    stack
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct Verifier<'n, 't, 'a> {
+    names: &'n Names<'t, 'a>,
+    args: Vec<StackType>,
+    locals: Vec<StackType>,
+    ret: Option<StackType>,
+}
+
+impl<'n, 't, 'a> Verifier<'n, 't, 'a> {
+    fn new(
+        names: &'n Names<'t, 'a>,
+        method: MethodDefIndex,
+        body: &MethodBody,
+    ) -> Result<Self, String> {
+        let table = names.table;
+        let heap = names.heap;
+        let mut v = Self {
+            names,
+            args: Vec::new(),
+            locals: Vec::new(),
+            ret: None,
+        };
+
+        let sig: MethodDefSig = method
+            .resolve_table(table)
+            .and_then(|def| def.signature.resolve(heap))
+            .and_then(|blob| blob.pread_with(0, scroll::LE).ok())
+            .ok_or("bad method signature")?;
+        if has_this(&sig) {
+            let owner = names.method_owner(method);
+            v.args.push(if owner.is_some_and(|o| v.is_value_type(o)) {
+                StackType::Ptr
+            } else {
+                StackType::Object
+            });
+        }
+        v.args = v
+            .args
+            .iter()
+            .copied()
+            .chain(sig.params.iter().map(|p| v.param(p)))
+            .collect();
+        v.ret = match &sig.ret {
+            RetType::Void { .. } => None,
+            RetType::Type { byref: true, .. } => Some(StackType::Ptr),
+            RetType::Type {
+                byref: false, ty, ..
+            } => Some(v.of(ty)),
+            RetType::TypedByref { .. } => Some(StackType::Value),
+        };
+
+        if let Some(sig) = body.local_var_sig {
+            let sig: LocalVarSig = sig
+                .resolve_table(table)
+                .and_then(|s| s.signature.resolve(heap))
+                .and_then(|blob| blob.pread_with(0, scroll::LE).ok())
+                .ok_or("bad local variable signature")?;
+            v.locals = sig
+                .locals
+                .iter()
+                .map(|l| match l {
+                    LocalVar::Type { byref: true, .. } => StackType::Ptr,
+                    LocalVar::Type { ty, .. } => v.of(ty),
+                    LocalVar::TypedByref => StackType::Value,
+                })
+                .collect();
+        }
+
+        Ok(v)
+    }
+
+    fn param(&self, param: &Param) -> StackType {
+        match param {
+            Param::Type { byref: true, .. } => StackType::Ptr,
+            Param::Type {
+                byref: false, ty, ..
+            } => self.of(ty),
+            Param::TypedByref { .. } => StackType::Value,
+        }
+    }
+
+    /// Stack type of a value of `ty`, enums are their underlying type
+    fn of(&self, ty: &Type) -> StackType {
+        match ty {
+            Type::Boolean
+            | Type::Char
+            | Type::I1
+            | Type::U1
+            | Type::I2
+            | Type::U2
+            | Type::I4
+            | Type::U4 => StackType::Int32,
+            Type::I8 | Type::U8 => StackType::Int64,
+            Type::I | Type::U | Type::Ptr { .. } | Type::FnPtr(_) => StackType::NativeInt,
+            Type::R4 | Type::R8 => StackType::Float,
+            Type::Object
+            | Type::String
+            | Type::Class(_)
+            | Type::SzArray { .. }
+            | Type::Array { .. } => StackType::Object,
+            Type::GenericInst {
+                is_value_type: false,
+                ..
+            } => StackType::Object,
+            Type::GenericInst {
+                is_value_type: true,
+                ..
+            } => StackType::Value,
+            Type::ValueType(TypeDefOrRefOrSpecEncoded::TypeDef(def)) => self.value_type(*def),
+            Type::ValueType(_) | Type::Var { .. } | Type::MVar { .. } => StackType::Any,
+        }
+    }
+
+    fn value_type(&self, def: TypeDefIndex) -> StackType {
+        if self.base_name(def).as_deref() != Some("System.Enum") {
+            return StackType::Value;
+        }
+
+        def.resolve_fields(self.names.table)
+            .find(|(_, f)| !f.flags.contains(FieldAttributes::STATIC))
+            .and_then(|(_, f)| f.signature.resolve(self.names.heap))
+            .and_then(|blob| blob.pread_with::<FieldSig>(0, scroll::LE).ok())
+            .map_or(StackType::Int32, |sig| self.of(&sig.ty))
+    }
+
+    fn base_name(&self, def: TypeDefIndex) -> Option<String> {
+        let def = def.resolve_table(self.names.table)?;
+        if def.extends.encode() == 0 {
+            return None;
+        }
+        let name = self.names.type_def_or_ref(def.extends);
+        Some(name.rsplit(']').next().unwrap_or_default().to_string())
+    }
+
+    fn is_value_type(&self, def: TypeDefIndex) -> bool {
+        matches!(
+            self.base_name(def).as_deref(),
+            Some("System.ValueType") | Some("System.Enum")
+        )
+    }
+
+    /// Type of a `TypeDefOrRef` or `TypeSpec` token operand
+    fn token_type(&self, token: MetadataToken) -> StackType {
+        match token {
+            MetadataToken::TypeDef(def) if self.is_value_type(def) => self.value_type(def),
+            MetadataToken::TypeDef(_) => StackType::Object,
+            MetadataToken::TypeSpec(spec) => spec
+                .resolve_table(self.names.table)
+                .and_then(|s| s.signature.resolve(self.names.heap))
+                .and_then(|blob| blob.pread_with::<Type>(0, scroll::LE).ok())
+                .map_or(StackType::Any, |ty| self.of(&ty)),
+            _ => StackType::Any,
+        }
+    }
+
+    fn field_type(&self, token: MetadataToken) -> Result<StackType, String> {
+        let blob = match token {
+            MetadataToken::Field(f) => f.resolve_table(self.names.table).map(|f| f.signature),
+            MetadataToken::MemberRef(m) => m.resolve_table(self.names.table).map(|m| m.signature),
+            _ => None,
+        };
+        blob.and_then(|b| b.resolve(self.names.heap))
+            .and_then(|blob| blob.pread_with::<FieldSig>(0, scroll::LE).ok())
+            .map(|sig| self.of(&sig.ty))
+            .ok_or_else(|| "bad field".to_string())
+    }
+
+    fn step(&self, inst: &Instruction, stack: &mut Vec<StackType>) -> Result<(), String> {
+        use Instruction::*;
+        use StackType::*;
+
+        macro_rules! pop {
+            () => {
+                stack.pop().ok_or_else(|| "stack underflow".to_string())?
+            };
+        }
+        macro_rules! expect {
+            ($value:expr, $what:expr, $($pat:pat_param)|+) => {{
+                let value = $value;
+                if !matches!(value, $($pat)|+ | Any) {
+                    return Err(format!("expected {}, found {}", $what, value));
+                }
+                value
+            }};
+        }
+        let slot = |slots: &[StackType], n: u32, what: &str| {
+            slots
+                .get(n as usize)
+                .copied()
+                .ok_or_else(|| format!("{} {} out of range", what, n))
+        };
+        let store = |value: StackType, to: StackType| {
+            if value.is_assignable_to(to) {
+                Ok(())
+            } else {
+                Err(format!("{} is not assignable to {}", value, to))
+            }
+        };
+
+        let push = match inst {
+            Nop | Break | Br(_) | Rethrow => None,
+            Unaligned(_) | Volatile | Tail | Constrained(_) | No(_) | Readonly => None,
+
+            LdArg(n) => Some(slot(&self.args, *n, "argument")?),
+            LdArgA(n) => slot(&self.args, *n, "argument").map(|_| Some(Ptr))?,
+            StArg(n) => {
+                let to = slot(&self.args, *n, "argument")?;
+                store(pop!(), to)?;
+                None
+            }
+            LdLoc(n) => Some(slot(&self.locals, *n, "local")?),
+            LdLocA(n) => slot(&self.locals, *n, "local").map(|_| Some(Ptr))?,
+            StLoc(n) => {
+                let to = slot(&self.locals, *n, "local")?;
+                store(pop!(), to)?;
+                None
+            }
+
+            LdNull | LdStr(_) => Some(Object),
+            LdcI4(_) => Some(Int32),
+            LdcI8(_) => Some(Int64),
+            LdcR4(_) | LdcR8(_) => Some(Float),
+            LdToken(_) | ArgList => Some(Value),
+            SizeOf(_) => Some(Int32),
+            LdFtn(_) => Some(NativeInt),
+            LdVirtFtn(_) => {
+                expect!(pop!(), "object", Object);
+                Some(NativeInt)
+            }
+
+            LdFld(token) | LdFldA(token) => {
+                let ty = self.field_type(*token)?;
+                expect!(
+                    pop!(),
+                    "object or pointer",
+                    Object | Ptr | NativeInt | Value
+                );
+                Some(if matches!(inst, LdFld(_)) { ty } else { Ptr })
+            }
+            StFld(token) => {
+                let ty = self.field_type(*token)?;
+                store(pop!(), ty)?;
+                expect!(pop!(), "object or pointer", Object | Ptr | NativeInt);
+                None
+            }
+            LdSFld(token) => Some(self.field_type(*token)?),
+            LdSFldA(token) => self.field_type(*token).map(|_| Some(Ptr))?,
+            StSFld(token) => {
+                store(pop!(), self.field_type(*token)?)?;
+                None
+            }
+
+            Dup => {
+                let value = pop!();
+                stack.push(value);
+                Some(value)
+            }
+            Pop => {
+                pop!();
+                None
+            }
+
+            Call(token) | CallVirt(token) | NewObj(token) | Jmp(token) => {
+                let sig = callee_sig(*token, self.names.table, self.names.heap)
+                    .ok_or("unresolved callee signature")?;
+                if matches!(inst, Jmp(_)) {
+                    if !stack.is_empty() {
+                        return Err("stack must be empty".into());
+                    }
+                    return Ok(());
+                }
+                for param in sig.params.iter().rev() {
+                    store(pop!(), self.param(param))?;
+                }
+                if let NewObj(_) = inst {
+                    Some(match token {
+                        MetadataToken::MethodDef(m) => self
+                            .names
+                            .method_owner(*m)
+                            .map_or(Object, |o| self.token_type(MetadataToken::TypeDef(o))),
+                        _ => Object,
+                    })
+                } else {
+                    if has_this(&sig) {
+                        expect!(pop!(), "this", Object | Ptr | Value);
+                    }
+                    self.call_result(&sig)
+                }
+            }
+            CallI(token) => {
+                let sig = token
+                    .as_stand_along_sig()
+                    .and_then(|s| s.resolve_table(self.names.table))
+                    .and_then(|s| s.signature.resolve(self.names.heap))
+                    .and_then(|blob| blob.pread_with::<MethodRefSig>(0, scroll::LE).ok())
+                    .ok_or("bad call site signature")?
+                    .call_site();
+                expect!(pop!(), "function pointer", NativeInt);
+                for param in sig.params.iter().rev() {
+                    store(pop!(), self.param(param))?;
+                }
+                if has_this(&sig) {
+                    expect!(pop!(), "this", Object | Ptr | Value);
+                }
+                self.call_result(&sig)
+            }
+            Ret => {
+                if let Some(ret) = self.ret {
+                    store(pop!(), ret)?;
+                }
+                if !stack.is_empty() {
+                    return Err(format!("{} values left on the stack", stack.len()));
+                }
+                None
+            }
+
+            Neg => Some(expect!(
+                pop!(),
+                "numeric operand",
+                Int32 | Int64 | NativeInt | Float
+            )),
+            Not => Some(expect!(
+                pop!(),
+                "integer operand",
+                Int32 | Int64 | NativeInt
+            )),
+            Shl | Shr | ShrUn => {
+                expect!(
+                    pop!(),
+                    "int32 or native int shift amount",
+                    Int32 | NativeInt
+                );
+                Some(expect!(
+                    pop!(),
+                    "integer operand",
+                    Int32 | Int64 | NativeInt
+                ))
+            }
+            Add | Sub | Mul | Div | Rem | AddOvf | AddOvfUn | SubOvf | SubOvfUn | MulOvf
+            | MulOvfUn | DivUn | RemUn | And | Or | Xor => {
+                let b = pop!();
+                let a = pop!();
+                Some(
+                    binary(inst, a, b)
+                        .ok_or_else(|| format!("invalid operands {} and {}", a, b))?,
+                )
+            }
+            Ceq | Cgt | CgtUn | Clt | CltUn => {
+                let b = pop!();
+                let a = pop!();
+                comparable(inst, a, b)?;
+                Some(Int32)
+            }
+            CkFinite => Some(expect!(pop!(), "F", Float)),
+            Conv(ty) | ConvOvf(ty) | ConvOvfUn(ty) => {
+                expect!(
+                    pop!(),
+                    "numeric operand",
+                    Int32 | Int64 | NativeInt | Float | Ptr
+                );
+                Some(StackType::of_num(*ty))
+            }
+
+            BrTrue(_) | BrFalse(_) => {
+                expect!(
+                    pop!(),
+                    "integer or reference",
+                    Int32 | Int64 | NativeInt | Object | Ptr
+                );
+                None
+            }
+            Ble(_) | BleUn(_) | Blt(_) | BltUn(_) | Bge(_) | BgeUn(_) | Bgt(_) | BgtUn(_)
+            | Beq(_) | BneUn(_) => {
+                let b = pop!();
+                let a = pop!();
+                comparable(inst, a, b)?;
+                None
+            }
+            Switch(_) => {
+                expect!(pop!(), "int32", Int32 | NativeInt);
+                None
+            }
+            Leave(_) | EndFinally => {
+                stack.clear();
+                None
+            }
+            EndFilter => {
+                expect!(pop!(), "int32", Int32);
+                if !stack.is_empty() {
+                    return Err("stack must be empty".into());
+                }
+                None
+            }
+            Throw => {
+                expect!(pop!(), "object", Object);
+                None
+            }
+
+            LdInd(ty) => {
+                expect!(pop!(), "address", Ptr | NativeInt);
+                Some(StackType::of_num(*ty))
+            }
+            StInd(ty) => {
+                store(pop!(), StackType::of_num(*ty))?;
+                expect!(pop!(), "address", Ptr | NativeInt);
+                None
+            }
+            LdObj(token) => {
+                expect!(pop!(), "address", Ptr | NativeInt);
+                Some(self.token_type(*token))
+            }
+            StObj(token) => {
+                store(pop!(), self.token_type(*token))?;
+                expect!(pop!(), "address", Ptr | NativeInt);
+                None
+            }
+            CpObj(_) => {
+                expect!(pop!(), "address", Ptr | NativeInt);
+                expect!(pop!(), "address", Ptr | NativeInt);
+                None
+            }
+            InitObj(_) => {
+                expect!(pop!(), "address", Ptr | NativeInt);
+                None
+            }
+            CpBlk | InitBlk => {
+                expect!(pop!(), "size", Int32 | NativeInt);
+                pop!();
+                expect!(pop!(), "address", Ptr | NativeInt);
+                None
+            }
+            LocAlloc => {
+                expect!(pop!(), "size", Int32 | NativeInt);
+                Some(NativeInt)
+            }
+
+            NewArr(_) => {
+                expect!(pop!(), "length", Int32 | NativeInt);
+                Some(Object)
+            }
+            LdLen => {
+                expect!(pop!(), "array", Object);
+                Some(NativeInt)
+            }
+            LdElem(_) | LdElemA(_) | LdElemAny(_) => {
+                expect!(pop!(), "index", Int32 | NativeInt);
+                expect!(pop!(), "array", Object);
+                Some(match inst {
+                    LdElem(ty) => StackType::of_num(*ty),
+                    LdElemA(_) => Ptr,
+                    LdElemAny(token) => self.token_type(*token),
+                    _ => unreachable!(),
+                })
+            }
+            StElem(_) | StElemAny(_) => {
+                let value = pop!();
+                match inst {
+                    StElem(ty) => store(value, StackType::of_num(*ty))?,
+                    StElemAny(token) => store(value, self.token_type(*token))?,
+                    _ => unreachable!(),
+                }
+                expect!(pop!(), "index", Int32 | NativeInt);
+                expect!(pop!(), "array", Object);
+                None
+            }
+            CastClass(_) | IsInst(_) => Some(expect!(pop!(), "object", Object)),
+            Box(_) => {
+                pop!();
+                Some(Object)
+            }
+            Unbox(_) => {
+                expect!(pop!(), "object", Object);
+                Some(Ptr)
+            }
+            UnboxAny(token) => {
+                expect!(pop!(), "object", Object);
+                Some(self.token_type(*token))
+            }
+
+            MkRefAny(_) => {
+                expect!(pop!(), "address", Ptr | NativeInt);
+                Some(Value)
+            }
+            RefAnyVal(_) => {
+                expect!(pop!(), "typed reference", Value);
+                Some(Ptr)
+            }
+            RefAnyType => {
+                expect!(pop!(), "typed reference", Value);
+                Some(Value)
+            }
+        };
+
+        stack.extend(push);
+        Ok(())
+    }
+
+    fn call_result(&self, sig: &MethodDefSig) -> Option<StackType> {
+        match &sig.ret {
+            RetType::Void { .. } => None,
+            RetType::Type { byref: true, .. } => Some(StackType::Ptr),
+            RetType::Type {
+                byref: false, ty, ..
+            } => Some(self.of(ty)),
+            RetType::TypedByref { .. } => Some(StackType::Value),
+        }
+    }
+}
+
+fn has_this(sig: &MethodDefSig) -> bool {
+    sig.calling_convension
+        .contains(MethodCallingConvension::HAS_THIS)
+        && !sig
+            .calling_convension
+            .contains(MethodCallingConvension::EXPLICT_THIS)
+}
+
+/// III.1.5 Table 2 binary numeric and Table 5 integer operations
+fn binary(inst: &Instruction, a: StackType, b: StackType) -> Option<StackType> {
+    use Instruction::*;
+    use StackType::*;
+
+    let integer_only = matches!(
+        inst,
+        AddOvf | AddOvfUn | SubOvf | SubOvfUn | MulOvf | MulOvfUn | DivUn | RemUn | And | Or | Xor
+    );
+    let result = match (a, b) {
+        (Any, other) | (other, Any) => other,
+        (Int32, Int32) => Int32,
+        (Int32, NativeInt) | (NativeInt, Int32) | (NativeInt, NativeInt) => NativeInt,
+        (Int64, Int64) => Int64,
+        (Float, Float) if !integer_only => Float,
+        (Ptr, Int32) | (Ptr, NativeInt) if matches!(inst, Add | AddOvfUn | Sub | SubOvfUn) => Ptr,
+        (Int32, Ptr) | (NativeInt, Ptr) if matches!(inst, Add | AddOvfUn) => Ptr,
+        (Ptr, Ptr) if matches!(inst, Sub | SubOvfUn) => NativeInt,
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// III.1.5 Table 4 binary comparison or branch operations
+fn comparable(inst: &Instruction, a: StackType, b: StackType) -> Result<(), String> {
+    use Instruction::*;
+    use StackType::*;
+
+    let ok = match (a, b) {
+        (Any, _) | (_, Any) => true,
+        (Object, Object) => matches!(inst, Ceq | CgtUn | Beq(_) | BneUn(_)),
+        (Ptr, Ptr) | (Ptr, NativeInt) | (NativeInt, Ptr) => true,
+        (Value, _) | (_, Value) => false,
+        _ => binary(&Add, a, b).is_some_and(|r| r != Ptr),
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("can't compare {} and {}", a, b))
+    }
+}
+
+#[test]
+fn verify_methods() {
+    use crate::pe::Image;
+
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly verify { .ver 0:0:0:0 }
+
+        .class public Point extends [mscorlib]System.Object
+        {
+          .field public int32 X
+        }
+
+        .method public static int32 Good(class Point p, int64 n) cil managed
+        {
+          .maxstack 2
+          .locals init (float64 f)
+          ldarg.0
+          ldfld int32 Point::X
+          ldarg.1
+          conv.i4
+          add
+          ret
+        }
+
+        .method public static int32 Mixed(int32 a) cil managed
+        {
+          .maxstack 2
+          ldarg.0
+          ldc.i8 1
+          add
+          ret
+        }
+
+        .method public static void Merge(bool b) cil managed
+        {
+          .maxstack 1
+          ldarg.0
+          brtrue.s One
+          ldc.r8 1.5
+          br.s Join
+        One:
+          ldc.i4.1
+        Join:
+          pop
+          ret
+        }
+
+        .method public static void Deep() cil managed
+        {
+          .maxstack 1
+          .locals init (int32 x)
+          ldc.i4.1
+          ldc.i4.2
+          pop
+          pop
+          ret
+        }
+
+        .method public static int32 Field(int32 a) cil managed
+        {
+          .maxstack 1
+          ldarg.0
+          ldfld int32 Point::X
+          ret
+        }
+
+        .method public static void Leftover() cil managed
+        {
+          .maxstack 1
+          ldc.i4.1
+          ret
+        }
+    ";
+    let bytes = crate::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let errors = verify(&image)
+        .iter()
+        .map(|e| {
+            let root = image.metadata_root();
+            let name = e
+                .method
+                .resolve_table(&root.metadata_stream.table)
+                .and_then(|m| m.name.resolve(root.heap))
+                .unwrap();
+            format!("{} IL_{:04x}: {}", name, e.offset, e.message)
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        errors,
+        [
+            "Mixed IL_000a: add: invalid operands int32 and int64",
+            "Merge IL_000f: stack [int32] differs from [F] at merge point",
+            "Deep IL_0001: stack depth exceeds .maxstack 1",
+            "Field IL_0001: ldfld: expected object or pointer, found int32",
+            "Leftover IL_0001: ret: 1 values left on the stack",
+        ]
+    );
+
+    let bytes = crate::cil::asm::assemble(include_str!("../../../tests/il/edge_cases.il")).unwrap();
+    assert_eq!(verify(&Image::from_bytes(&bytes).unwrap()), []);
+}
@@ -2,20 +2,54 @@ use std::convert::TryInto;
 
 use goblin::container::Endian;
 use goblin::pe::data_directories::DataDirectory;
+use goblin::pe::options::ParseOptions;
 use goblin::pe::section_table::SectionTable;
-use goblin::pe::utils::get_data;
+use goblin::pe::utils::{find_offset, get_data};
 use goblin::pe::PE;
 use scroll::ctx::{StrCtx, TryFromCtx};
 use scroll::{Pread, LE};
 
+mod api;
+mod builder;
+mod deps;
+mod display;
+mod generics;
+#[cfg(feature = "serde")]
+mod json;
+mod manifest_resource;
+mod pinvoke;
 mod raw;
-
+mod ready_to_run;
+mod resource;
+mod strong_name;
+mod validate;
+mod vtable_fixup;
+
+pub use self::api::*;
+pub use self::builder::*;
+pub use self::deps::*;
+pub use self::display::*;
+pub use self::generics::*;
+#[cfg(feature = "serde")]
+pub use self::json::*;
+pub use self::manifest_resource::*;
+pub use self::pinvoke::*;
 pub use self::raw::*;
+pub use self::ready_to_run::*;
+pub use self::resource::*;
+pub use self::strong_name::*;
+pub use self::validate::*;
+pub use self::vtable_fixup::*;
 
 pub struct Image<'a> {
     bytes: &'a [u8],
     file_alignment: u32,
+    pe_pointer: u32,
+    machine: u16,
+    is_pe32_plus: bool,
+    size_of_optional_header: u16,
     sections: Vec<SectionTable>,
+    resource_table: Option<DataDirectory>,
     cli_header: CliHeader,
     metadata_root: MetadataRoot<'a>,
 }
@@ -23,15 +57,13 @@ pub struct Image<'a> {
 impl<'a> Image<'a> {
     pub fn from_bytes(bytes: &'a [u8]) -> goblin::error::Result<Self> {
         let pe = PE::parse(bytes).unwrap();
-        if pe.header.coff_header.machine != 0x14c {
-            panic!("Is not a .Net executable");
-        }
         let optional_header = pe.header.optional_header.expect("No optional header");
         let file_alignment = optional_header.windows_fields.file_alignment;
         let cli_header = optional_header
             .data_directories
             .get_clr_runtime_header()
             .expect("No CLI header");
+        let resource_table = *optional_header.data_directories.get_resource_table();
         let sections = pe.sections;
 
         let cli_header_value: CliHeader =
@@ -41,7 +73,13 @@ impl<'a> Image<'a> {
         Ok(Self {
             bytes,
             file_alignment,
+            pe_pointer: pe.header.dos_header.pe_pointer,
+            machine: pe.header.coff_header.machine,
+            is_pe32_plus: optional_header.standard_fields.magic
+                == goblin::pe::optional_header::MAGIC_64,
+            size_of_optional_header: pe.header.coff_header.size_of_optional_header,
             sections,
+            resource_table,
             cli_header: cli_header_value,
             metadata_root,
         })
@@ -62,6 +100,29 @@ impl<'a> Image<'a> {
         )
     }
 
+    fn rva_to_offset(&self, rva: u32) -> Result<usize, goblin::error::Error> {
+        find_offset(
+            rva as usize,
+            &self.sections,
+            self.file_alignment,
+            &ParseOptions::default(),
+        )
+        .ok_or_else(|| goblin::error::Error::Malformed(format!("Invalid RVA {:#x}", rva)))
+    }
+
+    pub fn get_bytes(&self, rva: u32, size: u32) -> Result<&'a [u8], goblin::error::Error> {
+        let offset = self.rva_to_offset(rva)?;
+
+        self.bytes
+            .get(offset..offset + size as usize)
+            .ok_or_else(|| {
+                goblin::error::Error::Malformed(format!(
+                    "Data {:#x}+{:#x} is out of bounds",
+                    rva, size
+                ))
+            })
+    }
+
     pub fn cli_header(&self) -> &CliHeader {
         &self.cli_header
     }
@@ -69,6 +130,32 @@ impl<'a> Image<'a> {
     pub fn metadata_root(&self) -> &MetadataRoot<'a> {
         &self.metadata_root
     }
+
+    pub fn entry_point(&self) -> Option<EntryPoint> {
+        self.cli_header.entry_point()
+    }
+}
+
+bitflags_tryctx! {
+    // II.25.3.3.1
+    pub struct ComImageFlags: u32 {
+        const IL_ONLY = 0x0000_0001;
+        const REQUIRED_32BIT = 0x0000_0002;
+        const IL_LIBRARY = 0x0000_0004;
+        const STRONG_NAME_SIGNED = 0x0000_0008;
+        const NATIVE_ENTRY_POINT = 0x0000_0010;
+        const TRACK_DEBUG_DATA = 0x0001_0000;
+        const PREFERRED_32BIT = 0x0002_0000;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPoint {
+    Method(MethodDefIndex),
+    /// Entry point lives in another module of the assembly
+    File(FileIndex),
+    /// RVA of native code, only with `ComImageFlags::NATIVE_ENTRY_POINT`
+    Native(u32),
 }
 
 #[repr(C)]
@@ -78,14 +165,16 @@ pub struct CliHeader {
     pub major_version: u16,
     pub minor_version: u16,
     pub metadata: DataDirectory,
-    pub flags: u32,
-    pub entry_point_token: MetadataToken,
+    pub flags: ComImageFlags,
+    /// MethodDef or File token, or native RVA with `ComImageFlags::NATIVE_ENTRY_POINT`
+    pub entry_point_token: u32,
     pub resources: DataDirectory,
     pub strong_name_signature_hash: DataDirectory,
     pub code_manager_table: u64,
     pub vtable_fixups: DataDirectory,
     pub export_address_table_jumps: u64,
-    pub managed_native_header: u64,
+    /// READYTORUN header of crossgen images
+    pub managed_native_header: DataDirectory,
 }
 
 #[derive(Debug, Clone)]
@@ -165,6 +254,22 @@ impl<'a> TryFromCtx<'a, Endian> for MetadataRoot<'a> {
     }
 }
 
+impl CliHeader {
+    pub fn entry_point(&self) -> Option<EntryPoint> {
+        if self.entry_point_token == 0 {
+            None
+        } else if self.flags.contains(ComImageFlags::NATIVE_ENTRY_POINT) {
+            Some(EntryPoint::Native(self.entry_point_token))
+        } else {
+            match MetadataToken::from_raw(self.entry_point_token)? {
+                MetadataToken::MethodDef(index) => Some(EntryPoint::Method(index)),
+                MetadataToken::File(index) => Some(EntryPoint::File(index)),
+                _ => None,
+            }
+        }
+    }
+}
+
 /// #~
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -211,16 +316,65 @@ impl<'a> TryFromCtx<'a> for MetadataStream {
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Heap<'a> {
-    strings: &'a str,
-    user_string: &'a [u8],
-    blob: &'a [u8],
-    guid: &'a [u8],
+    pub(crate) strings: &'a str,
+    pub(crate) user_string: &'a [u8],
+    pub(crate) blob: &'a [u8],
+    pub(crate) guid: &'a [u8],
 }
 
 const GUID_SIZE: usize = 128 / 8;
 
+/// #US entry (II.24.2.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UserString<'a> {
+    bytes: &'a [u8],
+    has_special_chars: bool,
+}
+
+impl<'a> UserString<'a> {
+    /// Raw UTF-16LE bytes without the trailing flag byte
+    pub fn as_bytes(self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Length in UTF-16 code units
+    pub fn len(self) -> usize {
+        self.bytes.len() / 2
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.bytes.len() < 2
+    }
+
+    /// Set when any code unit has non-zero top byte or is one of
+    /// 0x01-0x08, 0x0E-0x1F, 0x27, 0x2D, 0x7F
+    pub fn has_special_chars(self) -> bool {
+        self.has_special_chars
+    }
+
+    pub fn encode_utf16(self) -> impl Iterator<Item = u16> + 'a {
+        self.bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+    }
+
+    pub fn decode(self) -> Result<String, std::string::FromUtf16Error> {
+        String::from_utf16(&self.encode_utf16().collect::<Vec<_>>())
+    }
+}
+
+impl std::fmt::Display for UserString<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use std::fmt::Write;
+
+        std::char::decode_utf16(self.encode_utf16())
+            .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+            .try_for_each(|c| f.write_char(c))
+    }
+}
+
 impl<'a> Heap<'a> {
-    pub fn list_user_string(self) -> impl Iterator<Item = (UserStringIndex, &'a [u8])> {
+    pub fn list_user_string(self) -> impl Iterator<Item = (UserStringIndex, UserString<'a>)> {
         let mut index = 1;
 
         std::iter::from_fn(move || match self.ref_user_string_with_length(index) {
@@ -234,6 +388,25 @@ impl<'a> Heap<'a> {
         .fuse()
     }
 
+    /// Non-empty strings in heap order, empty ones are unused or alignment padding
+    pub fn list_string(self) -> impl Iterator<Item = (StringIndex, &'a str)> {
+        let mut index = 1;
+
+        std::iter::from_fn(move || loop {
+            if index >= self.strings.len() {
+                return None;
+            }
+
+            let s = self.ref_string(index)?;
+            let start = index;
+            index += s.len() + 1;
+            if !s.is_empty() {
+                return Some((StringIndex(start as u32), s));
+            }
+        })
+        .fuse()
+    }
+
     pub fn ref_string(self, index: usize) -> Option<&'a str> {
         if index == 0 {
             return None;
@@ -243,7 +416,7 @@ impl<'a> Heap<'a> {
         ret.split('\0').next()
     }
 
-    fn ref_user_string_with_length(self, index: usize) -> Option<(&'a [u8], usize)> {
+    fn ref_user_string_with_length(self, index: usize) -> Option<(UserString<'a>, usize)> {
         if index == 0 {
             return None;
         }
@@ -252,15 +425,24 @@ impl<'a> Heap<'a> {
 
         let length: U = self.user_string.gread_with(&mut offset, scroll::LE).ok()?;
         let full_len = offset - index + length.0 as usize;
-        // cut tralling NULL
-        let s = self
-            .user_string
-            .get(index..index + length.0.saturating_sub(1) as usize)?;
-
-        Some((s, full_len))
+        let s = self.user_string.get(offset..offset + length.0 as usize)?;
+
+        // UTF-16LE code units followed by one flag byte
+        let (bytes, flag) = match s.split_last() {
+            Some((flag, bytes)) => (bytes, *flag),
+            None => (s, 0),
+        };
+
+        Some((
+            UserString {
+                bytes,
+                has_special_chars: flag != 0,
+            },
+            full_len,
+        ))
     }
 
-    pub fn ref_user_string(self, index: usize) -> Option<&'a [u8]> {
+    pub fn ref_user_string(self, index: usize) -> Option<UserString<'a>> {
         self.ref_user_string_with_length(index).map(|(s, _)| s)
     }
 
@@ -274,11 +456,66 @@ impl<'a> Heap<'a> {
         self.blob.get(index..index + length.0 as usize)
     }
 
+    /// #GUID is indexed by one based sequence number instead of byte offset
     pub fn ref_guid(self, index: usize) -> Option<&'a [u8; GUID_SIZE]> {
+        let offset = index.checked_sub(1)? * GUID_SIZE;
+
+        self.guid.get(offset..offset + GUID_SIZE)?.try_into().ok()
+    }
+
+    /// Reason why `index` is not a valid #Strings offset
+    pub fn check_string(self, index: usize) -> Option<String> {
         if index == 0 {
-            return None;
+            None
+        } else if index >= self.strings.len() {
+            Some(format!("#Strings offset {:#x} is out of heap", index))
+        } else if self.strings.as_bytes()[index - 1] != 0 {
+            Some(format!(
+                "#Strings offset {:#x} points into the middle of a string",
+                index
+            ))
+        } else {
+            None
         }
-
-        self.guid.get(index..index + GUID_SIZE)?.try_into().ok()
     }
 }
+
+#[test]
+fn user_string_heap() {
+    let user_string = [
+        0x00, // empty entry
+        0x05, b'H', 0x00, b'i', 0x00, 0x00, // "Hi"
+        0x05, 0xAC, 0x20, b'!', 0x00, 0x01, // "€!"
+    ];
+    let heap = Heap {
+        user_string: &user_string,
+        ..Heap::default()
+    };
+
+    let strings = heap
+        .list_user_string()
+        .map(|(index, s)| (index.0, s.to_string(), s.has_special_chars()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        strings,
+        [(1, "Hi".to_string(), false), (7, "€!".to_string(), true)]
+    );
+    assert_eq!(heap.ref_user_string(7).unwrap().len(), 2);
+}
+
+#[test]
+fn string_heap() {
+    // an empty string in the middle and padding at the end
+    let heap = Heap {
+        strings: "\0Foo\0\0Bar\0\0\0",
+        ..Heap::default()
+    };
+
+    let strings = heap
+        .list_string()
+        .map(|(index, s)| (index.0, s))
+        .collect::<Vec<_>>();
+
+    assert_eq!(strings, [(1, "Foo"), (6, "Bar")]);
+}
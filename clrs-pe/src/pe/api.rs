@@ -0,0 +1,757 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use scroll::{Pread, LE};
+
+use crate::cil::disasm::{quote_name, Names};
+
+use super::{
+    CustomAttributeType, EventMapIndex, FieldAttributes, FieldSig, HasConstant, HasCustomAttribute,
+    HasSemantics, Image, MetadataToken, MethodAttributes, MethodDef, MethodDefSig, Param,
+    PropertyMapIndex, RetType, TableIndex, TypeAttributes, TypeDefIndex, TypeOrMethodDef, U,
+};
+
+/// Visibility from outside the assembly
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Visibility {
+    /// Not part of the API, like `private`, `assembly` or a protected member of a sealed type
+    Internal,
+    /// `family` or `famorassem`
+    Protected,
+    Public,
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Visibility::Internal => write!(f, "internal"),
+            Visibility::Protected => write!(f, "protected"),
+            Visibility::Public => write!(f, "public"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypeKind {
+    Class,
+    Interface,
+    Struct,
+    Enum,
+    Delegate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemberKind {
+    Field,
+    Method,
+    Property,
+    Event,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiMember {
+    pub kind: MemberKind,
+    pub name: String,
+    pub visibility: Visibility,
+    /// Field, property or event type, or method signature in ilasm syntax
+    pub signature: String,
+    pub is_abstract: bool,
+    /// `final` on a virtual method
+    pub is_sealed: bool,
+    /// Value of a literal field
+    pub value: Option<String>,
+    /// Attribute constructors with their blobs
+    pub attributes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiType {
+    pub name: String,
+    pub kind: TypeKind,
+    pub visibility: Visibility,
+    pub is_sealed: bool,
+    pub is_abstract: bool,
+    pub generic_params: String,
+    pub extends: Option<String>,
+    pub interfaces: Vec<String>,
+    pub attributes: Vec<String>,
+    /// Methods are keyed by name and parameter types, other members by name
+    pub members: BTreeMap<(MemberKind, String), ApiMember>,
+}
+
+/// Types and members of an assembly, internal ones are kept to tell when they were hidden
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApiSurface {
+    pub types: BTreeMap<String, ApiType>,
+}
+
+impl<'a> Image<'a> {
+    pub fn api_surface(&self) -> ApiSurface {
+        let root = self.metadata_root();
+        let names = Names::new(&root.metadata_stream.table, root.heap);
+        let index = ApiIndex::new(&names);
+
+        let types = names
+            .table
+            .list_type_def()
+            .filter_map(|(ty, _)| api_type(&names, &index, ty))
+            .map(|t| (t.name.clone(), t))
+            .collect();
+
+        ApiSurface { types }
+    }
+}
+
+/// Rows owned by a type or member, built once for the whole image
+struct ApiIndex {
+    attributes: HashMap<HasCustomAttribute, Vec<String>>,
+    accessors: HashMap<HasSemantics, Vec<MethodDef>>,
+    interfaces: HashMap<TypeDefIndex, Vec<String>>,
+    property_maps: HashMap<TypeDefIndex, PropertyMapIndex>,
+    event_maps: HashMap<TypeDefIndex, EventMapIndex>,
+}
+
+impl ApiIndex {
+    fn new(names: &Names) -> Self {
+        let table = names.table;
+        let mut index = ApiIndex {
+            attributes: HashMap::new(),
+            accessors: HashMap::new(),
+            interfaces: HashMap::new(),
+            property_maps: HashMap::new(),
+            event_maps: HashMap::new(),
+        };
+
+        for a in table.custom_attribute.iter() {
+            let ctor = match a.ty {
+                CustomAttributeType::MethodDefIndex(m) => names.token(MetadataToken::MethodDef(m)),
+                CustomAttributeType::MemberRefIndex(r) => names.token(MetadataToken::MemberRef(r)),
+                _ => "/* bad attribute */".into(),
+            };
+            let blob = a
+                .value
+                .resolve(names.heap)
+                .unwrap_or_default()
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>();
+            index.attributes.entry(a.parent).or_default().push(format!(
+                "{} = ({})",
+                ctor,
+                blob.join(" ")
+            ));
+        }
+        for s in table.method_semantics.iter() {
+            if let Some(method) = s.method.resolve_table(table) {
+                index
+                    .accessors
+                    .entry(s.association)
+                    .or_default()
+                    .push(*method);
+            }
+        }
+        for i in table.interface_impl.iter() {
+            index
+                .interfaces
+                .entry(i.class)
+                .or_default()
+                .push(names.type_def_or_ref(i.interface));
+        }
+        for (map, row) in table.list_property_map() {
+            index.property_maps.entry(row.parent).or_insert(map);
+        }
+        for (map, row) in table.list_event_map() {
+            index.event_maps.entry(row.parent).or_insert(map);
+        }
+
+        index
+    }
+
+    fn attributes(&self, parent: HasCustomAttribute) -> Vec<String> {
+        self.attributes.get(&parent).cloned().unwrap_or_default()
+    }
+
+    fn accessors(&self, association: HasSemantics) -> &[MethodDef] {
+        self.accessors.get(&association).map_or(&[], |m| &m[..])
+    }
+}
+
+fn type_visibility(names: &Names, ty: TypeDefIndex) -> Visibility {
+    let own = match ty.resolve_table(names.table) {
+        Some(def) => match def.flags & TypeAttributes::VISIBILITY_MASK {
+            TypeAttributes::PUBLIC | TypeAttributes::NESTED_PUBLIC => Visibility::Public,
+            TypeAttributes::NESTED_FAMILY | TypeAttributes::NESTED_FAM_OR_ASSEM => {
+                Visibility::Protected
+            }
+            _ => Visibility::Internal,
+        },
+        None => Visibility::Internal,
+    };
+
+    match names.enclosing_class(ty) {
+        Some(outer) => own.min(type_visibility(names, outer)),
+        None => own,
+    }
+}
+
+fn member_visibility(access: u16) -> Visibility {
+    // same encoding for fields and methods
+    match access & MethodAttributes::MEMBER_ACCESS_MASK.bits() {
+        a if a == MethodAttributes::PUBLIC.bits() => Visibility::Public,
+        a if a == MethodAttributes::FAMILY.bits() || a == MethodAttributes::FAM_OR_ASSEM.bits() => {
+            Visibility::Protected
+        }
+        _ => Visibility::Internal,
+    }
+}
+
+/// Name without the resolution scope, like `System.Enum`
+fn bare_name(name: &str) -> &str {
+    name.rsplit(']').next().unwrap_or(name)
+}
+
+fn api_type(names: &Names, index: &ApiIndex, ty: TypeDefIndex) -> Option<ApiType> {
+    let table = names.table;
+    let def = ty.resolve_table(table)?;
+    let visibility = type_visibility(names, ty);
+
+    let extends = if def.extends.encode() == 0 {
+        None
+    } else {
+        Some(names.type_def_or_ref(def.extends))
+    };
+    let kind = if def.flags.contains(TypeAttributes::INTERFACE) {
+        TypeKind::Interface
+    } else {
+        match extends.as_deref().map(bare_name) {
+            Some("System.Enum") => TypeKind::Enum,
+            Some("System.ValueType") => TypeKind::Struct,
+            Some("System.MulticastDelegate") => TypeKind::Delegate,
+            _ => TypeKind::Class,
+        }
+    };
+    let is_sealed = def.flags.contains(TypeAttributes::SEALED);
+
+    let mut members = BTreeMap::new();
+    let mut insert = |key: String, mut member: ApiMember| {
+        // protected members of a sealed type can't be reached
+        if is_sealed && member.visibility == Visibility::Protected {
+            member.visibility = Visibility::Internal;
+        }
+        members.insert((member.kind, key), member);
+    };
+
+    for (field, row) in ty.resolve_fields(table) {
+        let visibility = member_visibility(row.flags.bits()).min(visibility);
+        let name = row.name.resolve(names.heap).unwrap_or_default().to_string();
+        let ty = row
+            .signature
+            .resolve(names.heap)
+            .and_then(|b| b.pread_with::<FieldSig>(0, LE).ok())
+            .map(|sig| names.field_sig(&sig))
+            .unwrap_or_default();
+        let signature = if row.flags.contains(FieldAttributes::STATIC) {
+            format!("static {}", ty)
+        } else {
+            ty
+        };
+
+        insert(
+            name.clone(),
+            ApiMember {
+                kind: MemberKind::Field,
+                name,
+                visibility,
+                signature,
+                is_abstract: false,
+                is_sealed: false,
+                value: names.constant(HasConstant::FieldIndex(field)),
+                attributes: index.attributes(HasCustomAttribute::FieldIndex(field)),
+            },
+        );
+    }
+
+    for (method, row) in ty.resolve_methods(table) {
+        let visibility = member_visibility(row.flags.bits()).min(visibility);
+        let name = row.name.resolve(names.heap).unwrap_or_default().to_string();
+        let sig = row
+            .signature
+            .resolve(names.heap)
+            .and_then(|b| b.pread_with::<MethodDefSig>(0, LE).ok());
+        let params = sig
+            .iter()
+            .flat_map(|sig| sig.params.iter().map(|p| names.param(p)))
+            .collect::<Vec<_>>();
+        let generic_params = names.generic_params(TypeOrMethodDef::MethodDefIndex(method));
+        let signature = sig
+            .map(|sig| format!("{}{}", generic_params, names.method_sig(&sig)))
+            .unwrap_or_default();
+        let is_virtual = row.flags.contains(MethodAttributes::VIRTUAL);
+
+        insert(
+            format!("{}{}({})", name, generic_params, params.join(", ")),
+            ApiMember {
+                kind: MemberKind::Method,
+                name,
+                visibility,
+                signature,
+                is_abstract: row.flags.contains(MethodAttributes::ABSTRACT),
+                is_sealed: is_virtual && row.flags.contains(MethodAttributes::FINAL),
+                value: None,
+                attributes: index.attributes(HasCustomAttribute::MethodDefIndex(method)),
+            },
+        );
+    }
+
+    // properties and events are as visible as their most visible accessor
+    let accessor_visibility = |association: HasSemantics| {
+        index
+            .accessors(association)
+            .iter()
+            .map(|m| member_visibility(m.flags.bits()))
+            .max()
+            .unwrap_or(Visibility::Internal)
+            .min(visibility)
+    };
+    let is_accessor_abstract = |association: HasSemantics| {
+        index
+            .accessors(association)
+            .iter()
+            .any(|m| m.flags.contains(MethodAttributes::ABSTRACT))
+    };
+
+    if let Some(map) = index.property_maps.get(&ty) {
+        for (property, row) in map.resolve_properties(table) {
+            let association = HasSemantics::PropertyIndex(property);
+            let visibility = accessor_visibility(association);
+            let name = row.name.resolve(names.heap).unwrap_or_default().to_string();
+            let signature = row
+                .ty
+                .resolve(names.heap)
+                .and_then(|b| property_sig(names, b))
+                .unwrap_or_default();
+
+            insert(
+                name.clone(),
+                ApiMember {
+                    kind: MemberKind::Property,
+                    name,
+                    visibility,
+                    signature,
+                    is_abstract: is_accessor_abstract(association),
+                    is_sealed: false,
+                    value: None,
+                    attributes: index.attributes(HasCustomAttribute::PropertyIndex(property)),
+                },
+            );
+        }
+    }
+
+    if let Some(map) = index.event_maps.get(&ty) {
+        for (event, row) in map.resolve_events(table) {
+            let association = HasSemantics::EventIndex(event);
+            let visibility = accessor_visibility(association);
+            let name = row.name.resolve(names.heap).unwrap_or_default().to_string();
+
+            insert(
+                name.clone(),
+                ApiMember {
+                    kind: MemberKind::Event,
+                    name,
+                    visibility,
+                    signature: names.type_def_or_ref(row.ty),
+                    is_abstract: is_accessor_abstract(association),
+                    is_sealed: false,
+                    value: None,
+                    attributes: index.attributes(HasCustomAttribute::EventIndex(event)),
+                },
+            );
+        }
+    }
+
+    Some(ApiType {
+        name: names.type_def(ty),
+        kind,
+        visibility,
+        is_sealed,
+        is_abstract: def.flags.contains(TypeAttributes::ABSTRACT),
+        generic_params: names.generic_params(TypeOrMethodDef::TypeDefIndex(ty)),
+        extends,
+        interfaces: index.interfaces.get(&ty).cloned().unwrap_or_default(),
+        attributes: index.attributes(HasCustomAttribute::TypeDefIndex(ty)),
+        members,
+    })
+}
+
+/// II.23.2.5, `instance int32(int32)` for an indexer
+fn property_sig(names: &Names, blob: &[u8]) -> Option<String> {
+    let offset = &mut 0;
+    let flags: u8 = blob.gread_with(offset, LE).ok()?;
+    let param_count: U = blob.gread_with(offset, LE).ok()?;
+    let ty: RetType = blob.gread_with(offset, LE).ok()?;
+    let params = std::iter::repeat_with(|| blob.gread_with::<Param>(offset, LE))
+        .take(param_count.0 as usize)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    let instance = if flags & 0x20 != 0 { "instance " } else { "" };
+    let params = if params.is_empty() {
+        String::new()
+    } else {
+        let params = params.iter().map(|p| names.param(p)).collect::<Vec<_>>();
+        format!("({})", params.join(", "))
+    };
+
+    Some(format!("{}{}{}", instance, names.ret_type(&ty), params))
+}
+
+impl fmt::Display for ApiSurface {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let is_visible = |v: Visibility| v != Visibility::Internal;
+        for ty in self.types.values().filter(|t| is_visible(t.visibility)) {
+            write!(
+                f,
+                "{} {:?} {}{}",
+                ty.visibility, ty.kind, ty.name, ty.generic_params
+            )?;
+            if ty.is_abstract {
+                write!(f, " abstract")?;
+            }
+            if ty.is_sealed {
+                write!(f, " sealed")?;
+            }
+            if let Some(extends) = &ty.extends {
+                write!(f, " extends {}", extends)?;
+            }
+            writeln!(f)?;
+
+            for attribute in &ty.attributes {
+                writeln!(f, "  .custom {}", attribute)?;
+            }
+            for member in ty.members.values().filter(|m| is_visible(m.visibility)) {
+                write!(
+                    f,
+                    "  {} {:?} {} : {}",
+                    member.visibility,
+                    member.kind,
+                    quote_name(&member.name),
+                    member.signature
+                )?;
+                if let Some(value) = &member.value {
+                    write!(f, " = {}", value)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Removed,
+    SignatureChanged,
+    /// Public to protected, or no longer visible outside the assembly
+    VisibilityReduced,
+    BecameSealed,
+    BecameAbstract,
+    /// Enum member or literal field value
+    ValueChanged,
+    AttributeRemoved,
+    /// Attribute on a type or member present in both surfaces
+    AttributeAdded,
+}
+
+/// A breaking difference between two API surfaces
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiChange {
+    pub kind: ChangeKind,
+    /// `Type` or `Type::member`
+    pub path: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for ApiChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            ChangeKind::Removed => "removed",
+            ChangeKind::SignatureChanged => "signature changed",
+            ChangeKind::VisibilityReduced => "visibility reduced",
+            ChangeKind::BecameSealed => "sealed",
+            ChangeKind::BecameAbstract => "abstract",
+            ChangeKind::ValueChanged => "value changed",
+            ChangeKind::AttributeRemoved => "attribute removed",
+            ChangeKind::AttributeAdded => "attribute added",
+        };
+        write!(f, "{}: {}", kind, self.path)?;
+        if !self.old.is_empty() || !self.new.is_empty() {
+            write!(f, " ({} -> {})", self.old, self.new)?;
+        }
+        Ok(())
+    }
+}
+
+fn change(kind: ChangeKind, path: String, old: impl ToString, new: impl ToString) -> ApiChange {
+    ApiChange {
+        kind,
+        path,
+        old: old.to_string(),
+        new: new.to_string(),
+    }
+}
+
+/// Attributes only in `old` or only in `new`, like `[Obsolete]` being added
+fn diff_attributes(path: &str, old: &[String], new: &[String], changes: &mut Vec<ApiChange>) {
+    for attribute in old.iter().filter(|a| !new.contains(a)) {
+        changes.push(change(
+            ChangeKind::AttributeRemoved,
+            path.to_string(),
+            attribute,
+            "",
+        ));
+    }
+    for attribute in new.iter().filter(|a| !old.contains(a)) {
+        changes.push(change(
+            ChangeKind::AttributeAdded,
+            path.to_string(),
+            "",
+            attribute,
+        ));
+    }
+}
+
+/// Breaking changes from `old` to `new`. New types and members are not reported, the only
+/// addition reported is an attribute added to an existing type or member since attributes like
+/// `[Obsolete]` can break callers
+pub fn diff_api(old: &ApiSurface, new: &ApiSurface) -> Vec<ApiChange> {
+    let mut changes = Vec::new();
+
+    let old_types = old
+        .types
+        .iter()
+        .filter(|(_, t)| t.visibility != Visibility::Internal);
+    for (name, old_ty) in old_types {
+        let new_ty = match new.types.get(name) {
+            Some(ty) => ty,
+            None => {
+                changes.push(change(ChangeKind::Removed, name.clone(), "", ""));
+                continue;
+            }
+        };
+
+        if new_ty.visibility < old_ty.visibility {
+            changes.push(change(
+                ChangeKind::VisibilityReduced,
+                name.clone(),
+                old_ty.visibility,
+                new_ty.visibility,
+            ));
+            // its members went with it
+            if new_ty.visibility == Visibility::Internal {
+                continue;
+            }
+        }
+        if new_ty.is_sealed && !old_ty.is_sealed {
+            changes.push(change(ChangeKind::BecameSealed, name.clone(), "", ""));
+        }
+        if new_ty.is_abstract && !old_ty.is_abstract {
+            changes.push(change(ChangeKind::BecameAbstract, name.clone(), "", ""));
+        }
+        if new_ty.kind != old_ty.kind || new_ty.generic_params != old_ty.generic_params {
+            changes.push(change(
+                ChangeKind::SignatureChanged,
+                name.clone(),
+                format!("{:?} {}", old_ty.kind, old_ty.generic_params),
+                format!("{:?} {}", new_ty.kind, new_ty.generic_params),
+            ));
+        }
+
+        diff_attributes(name, &old_ty.attributes, &new_ty.attributes, &mut changes);
+        diff_members(name, old_ty, new_ty, &mut changes);
+    }
+
+    changes
+}
+
+fn diff_members(ty: &str, old: &ApiType, new: &ApiType, changes: &mut Vec<ApiChange>) {
+    let overloads = |t: &ApiType, member: &ApiMember| {
+        t.members
+            .values()
+            .filter(|m| m.kind == member.kind && m.name == member.name)
+            .filter(|m| m.visibility != Visibility::Internal)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    let old_members = old
+        .members
+        .iter()
+        .filter(|(_, m)| m.visibility != Visibility::Internal);
+    for (key, old_member) in old_members {
+        let path = format!("{}::{}", ty, key.1);
+        let new_member = match new.members.get(key) {
+            Some(member) => member,
+            None => {
+                // a method with its only overload replaced changed signature
+                match (
+                    &overloads(old, old_member)[..],
+                    &overloads(new, old_member)[..],
+                ) {
+                    ([_], [new_member]) => changes.push(change(
+                        ChangeKind::SignatureChanged,
+                        path,
+                        &old_member.signature,
+                        &new_member.signature,
+                    )),
+                    _ => changes.push(change(ChangeKind::Removed, path, "", "")),
+                }
+                continue;
+            }
+        };
+
+        if new_member.signature != old_member.signature {
+            changes.push(change(
+                ChangeKind::SignatureChanged,
+                path.clone(),
+                &old_member.signature,
+                &new_member.signature,
+            ));
+        }
+        if new_member.visibility < old_member.visibility {
+            changes.push(change(
+                ChangeKind::VisibilityReduced,
+                path.clone(),
+                old_member.visibility,
+                new_member.visibility,
+            ));
+        }
+        if new_member.is_sealed && !old_member.is_sealed {
+            changes.push(change(ChangeKind::BecameSealed, path.clone(), "", ""));
+        }
+        if new_member.is_abstract && !old_member.is_abstract {
+            changes.push(change(ChangeKind::BecameAbstract, path.clone(), "", ""));
+        }
+        if new_member.value != old_member.value {
+            changes.push(change(
+                ChangeKind::ValueChanged,
+                path.clone(),
+                old_member.value.as_deref().unwrap_or_default(),
+                new_member.value.as_deref().unwrap_or_default(),
+            ));
+        }
+        diff_attributes(
+            &path,
+            &old_member.attributes,
+            &new_member.attributes,
+            changes,
+        );
+    }
+}
+
+#[test]
+fn api_diff() {
+    use crate::cil::asm::assemble;
+
+    let header = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly lib { .ver 1:0:0:0 }
+    ";
+    let old = assemble(&format!(
+        "{}{}",
+        header,
+        "
+        .class public auto ansi Widget extends [mscorlib]System.Object
+        {
+          .field public int32 Count
+          .field family string Label
+          .field private int32 hidden
+          .method public hidebysig instance void Draw(int32 x) cil managed { ret }
+          .method public hidebysig static void Reset() cil managed { ret }
+        }
+        .class public auto ansi sealed Color extends [mscorlib]System.Enum
+        {
+          .field public specialname rtspecialname int32 value__
+          .field public static literal valuetype Color Red = int32(1)
+          .field public static literal valuetype Color Blue = int32(2)
+        }
+        .class public auto ansi Leaf extends [mscorlib]System.Object {}
+        .class public auto ansi Gone extends [mscorlib]System.Object {}
+        .class public auto ansi Hidden extends [mscorlib]System.Object
+        {
+          .field public int32 Value
+        }
+        .class private auto ansi Internal extends [mscorlib]System.Object {}
+        "
+    ))
+    .unwrap();
+    let new = assemble(&format!(
+        "{}{}",
+        header,
+        "
+        .class public abstract auto ansi Widget extends [mscorlib]System.Object
+        {
+          .field public int64 Count
+          .field private int32 hidden
+          .method family hidebysig instance void Draw(int32 x) cil managed { ret }
+          .method public hidebysig static void Reset(bool hard) cil managed { ret }
+        }
+        .class public auto ansi sealed Color extends [mscorlib]System.Enum
+        {
+          .field public specialname rtspecialname int32 value__
+          .field public static literal valuetype Color Red = int32(1)
+          .field public static literal valuetype Color Blue = int32(3)
+        }
+        .class public auto ansi sealed Leaf extends [mscorlib]System.Object {}
+        .class private auto ansi Hidden extends [mscorlib]System.Object
+        {
+          .field public int32 Value
+        }
+        "
+    ))
+    .unwrap();
+
+    let old = Image::from_bytes(&old).unwrap().api_surface();
+    let mut new = Image::from_bytes(&new).unwrap().api_surface();
+
+    assert_eq!(old.types["Internal"].visibility, Visibility::Internal);
+    assert!(!old.to_string().contains("Internal"));
+    assert_eq!(old.types["Color"].kind, TypeKind::Enum);
+    assert_eq!(
+        old.types["Widget"]
+            .members
+            .keys()
+            .map(|(_, key)| key.as_str())
+            .collect::<Vec<_>>(),
+        ["Count", "Label", "hidden", "Draw(int32)", "Reset()"]
+    );
+    assert_eq!(
+        old.types["Widget"].members[&(MemberKind::Field, "Label".into())].visibility,
+        Visibility::Protected
+    );
+
+    let obsolete = "instance void [mscorlib]System.ObsoleteAttribute::.ctor() = (01 00 00 00)";
+    let leaf = new.types.get_mut("Leaf").unwrap();
+    leaf.attributes.push(obsolete.to_string());
+
+    let changes = diff_api(&old, &new)
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            "value changed: Color::Blue (int32(0x2) -> int32(0x3))",
+            "removed: Gone",
+            "visibility reduced: Hidden (public -> internal)",
+            "sealed: Leaf",
+            "attribute added: Leaf ( -> instance void [mscorlib]System.ObsoleteAttribute::.ctor() = (01 00 00 00))",
+            "abstract: Widget",
+            "signature changed: Widget::Count (int32 -> int64)",
+            "removed: Widget::Label",
+            "visibility reduced: Widget::Draw(int32) (public -> protected)",
+            "signature changed: Widget::Reset() (void() -> void(bool))",
+        ]
+    );
+}
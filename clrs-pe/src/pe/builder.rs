@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use goblin::pe::data_directories::DataDirectory;
+use scroll::{Pwrite, LE};
+
+use super::{
+    BlobIndex, ComImageFlags, GuidIndex, Heap, MetadataTable, MetadataToken, MethodDefIndex, PeCtx,
+    StringIndex, UserStringIndex, U,
+};
+
+/// Heaps under construction, equal entries are stored once
+#[derive(Debug)]
+pub struct HeapBuilder {
+    strings: Vec<u8>,
+    string_map: HashMap<String, StringIndex>,
+    user_string: Vec<u8>,
+    user_string_map: HashMap<String, UserStringIndex>,
+    blob: Vec<u8>,
+    blob_map: HashMap<Vec<u8>, BlobIndex>,
+    guid: Vec<u8>,
+}
+
+impl Default for HeapBuilder {
+    fn default() -> Self {
+        Self {
+            // every heap but #GUID starts with an empty entry at offset zero
+            strings: vec![0],
+            string_map: HashMap::new(),
+            user_string: vec![0],
+            user_string_map: HashMap::new(),
+            blob: vec![0],
+            blob_map: HashMap::new(),
+            guid: Vec::new(),
+        }
+    }
+}
+
+impl HeapBuilder {
+    pub fn add_string(&mut self, s: &str) -> StringIndex {
+        if s.is_empty() {
+            return StringIndex(0);
+        }
+        if let Some(index) = self.string_map.get(s) {
+            return *index;
+        }
+
+        let index = StringIndex(self.strings.len() as u32);
+        self.strings.extend_from_slice(s.as_bytes());
+        self.strings.push(0);
+        self.string_map.insert(s.to_string(), index);
+        index
+    }
+
+    /// II.24.2.4
+    pub fn add_user_string(&mut self, s: &str) -> UserStringIndex {
+        if let Some(index) = self.user_string_map.get(s) {
+            return *index;
+        }
+
+        let index = UserStringIndex(self.user_string.len() as u32);
+        let units = s.encode_utf16().collect::<Vec<_>>();
+        let has_special_chars = units
+            .iter()
+            .any(|&c| c > 0xFF || matches!(c, 0x01..=0x08 | 0x0E..=0x1F | 0x27 | 0x2D | 0x7F));
+
+        U(units.len() as u32 * 2 + 1).write(&mut self.user_string);
+        for c in units {
+            self.user_string.extend_from_slice(&c.to_le_bytes());
+        }
+        self.user_string.push(has_special_chars as u8);
+        self.user_string_map.insert(s.to_string(), index);
+        index
+    }
+
+    pub fn add_blob(&mut self, blob: &[u8]) -> BlobIndex {
+        if blob.is_empty() {
+            return BlobIndex(0);
+        }
+        if let Some(index) = self.blob_map.get(blob) {
+            return *index;
+        }
+
+        let index = BlobIndex(self.blob.len() as u32);
+        U(blob.len() as u32).write(&mut self.blob);
+        self.blob.extend_from_slice(blob);
+        self.blob_map.insert(blob.to_vec(), index);
+        index
+    }
+
+    pub fn add_guid(&mut self, guid: [u8; 16]) -> GuidIndex {
+        self.guid.extend_from_slice(&guid);
+        GuidIndex((self.guid.len() / 16) as u32)
+    }
+
+    /// Read only view to resolve indices handed out so far
+    pub fn heap(&self) -> Heap<'_> {
+        Heap {
+            strings: std::str::from_utf8(&self.strings).unwrap(),
+            user_string: &self.user_string,
+            blob: &self.blob,
+            guid: &self.guid,
+        }
+    }
+}
+
+/// Tables which have to be sorted by their primary key (II.22)
+const SORTED_TABLES: [u8; 14] = [
+    0x09, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x18, 0x19, 0x1C, 0x1D, 0x29, 0x2A, 0x2C,
+];
+
+const METADATA_SIGNATURE: u32 = 0x424A_5342;
+const METADATA_VERSION: &str = "v4.0.30319";
+
+fn align(out: &mut Vec<u8>, n: usize) {
+    while !out.len().is_multiple_of(n) {
+        out.push(0);
+    }
+}
+
+/// Metadata root with the #~ stream and heaps.
+/// Sorted tables must already be in order since sorting would break indices into them.
+#[derive(Debug, Default)]
+pub struct MetadataBuilder {
+    pub table: MetadataTable,
+    pub heap: HeapBuilder,
+}
+
+impl MetadataBuilder {
+    fn tables_stream(&self) -> Result<Vec<u8>, scroll::Error> {
+        let sorted = SORTED_TABLES.iter().fold(0u64, |bits, n| bits | (1 << n));
+        let mut out = Vec::new();
+
+        out.extend_from_slice(&0u32.to_le_bytes());
+        // version 2.0
+        out.extend_from_slice(&[2, 0]);
+        // heap sizes, all indices are two bytes wide
+        out.push(0);
+        out.push(1);
+        out.extend(self.table.write_tables(sorted, PeCtx {})?);
+        Ok(out)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, scroll::Error> {
+        let heap = &self.heap;
+        for (name, size) in [
+            ("#Strings", heap.strings.len()),
+            ("#US", heap.user_string.len()),
+            ("#Blob", heap.blob.len()),
+            ("#GUID", heap.guid.len()),
+        ] {
+            if size > 0xFFFF {
+                return Err(scroll::Error::Custom(format!(
+                    "{} heap needs wide indices which are not supported",
+                    name
+                )));
+            }
+        }
+
+        let streams = [
+            ("#~", self.tables_stream()?),
+            ("#Strings", heap.strings.clone()),
+            ("#US", heap.user_string.clone()),
+            ("#GUID", heap.guid.clone()),
+            ("#Blob", heap.blob.clone()),
+        ];
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&METADATA_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        let mut version = METADATA_VERSION.as_bytes().to_vec();
+        version.push(0);
+        align(&mut version, 4);
+        out.extend_from_slice(&(version.len() as u32).to_le_bytes());
+        out.extend_from_slice(&version);
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+
+        let headers_size = streams
+            .iter()
+            .map(|(name, _)| 8 + (name.len() + 4) / 4 * 4)
+            .sum::<usize>();
+        let mut offset = out.len() + headers_size;
+
+        for (name, data) in streams.iter() {
+            let size = data.len().div_ceil(4) * 4;
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            out.extend_from_slice(&(size as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+            align(&mut out, 4);
+            offset += size;
+        }
+
+        for (_, data) in streams.iter() {
+            out.extend_from_slice(data);
+            align(&mut out, 4);
+        }
+
+        Ok(out)
+    }
+}
+
+const FILE_ALIGNMENT: u32 = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x2000;
+const TEXT_RVA: u32 = 0x2000;
+const CLI_HEADER_SIZE: u32 = 72;
+
+/// II.25.2.1
+const DOS_HEADER: [u8; 128] = [
+    0x4d, 0x5a, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+    0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00,
+    0x0e, 0x1f, 0xba, 0x0e, 0x00, 0xb4, 0x09, 0xcd, 0x21, 0xb8, 0x01, 0x4c, 0xcd, 0x21, 0x54, 0x68,
+    0x69, 0x73, 0x20, 0x70, 0x72, 0x6f, 0x67, 0x72, 0x61, 0x6d, 0x20, 0x63, 0x61, 0x6e, 0x6e, 0x6f,
+    0x74, 0x20, 0x62, 0x65, 0x20, 0x72, 0x75, 0x6e, 0x20, 0x69, 0x6e, 0x20, 0x44, 0x4f, 0x53, 0x20,
+    0x6d, 0x6f, 0x64, 0x65, 0x2e, 0x0d, 0x0d, 0x0a, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+fn align_to(n: u32, align: u32) -> u32 {
+    n.div_ceil(align) * align
+}
+
+/// IL only PE32 image with a single `.text` section holding the CLI header,
+/// method bodies, field data and metadata in that order.
+/// There is no native entry stub, import or relocation table.
+#[derive(Debug, Default)]
+pub struct ImageBuilder {
+    pub metadata: MetadataBuilder,
+    pub entry_point: Option<MethodDefIndex>,
+    pub is_dll: bool,
+    /// Zeroed space for a strong name signature, 0 for unsigned images
+    pub strong_name_signature_size: u32,
+    text: Vec<u8>,
+}
+
+impl ImageBuilder {
+    fn add_text(&mut self, data: &[u8], alignment: usize) -> u32 {
+        align(&mut self.text, alignment);
+        let rva = TEXT_RVA + CLI_HEADER_SIZE + self.text.len() as u32;
+        self.text.extend_from_slice(data);
+        rva
+    }
+
+    /// RVA of the encoded `cil::MethodBody`
+    pub fn add_method_body(&mut self, body: &[u8]) -> u32 {
+        // fat headers have to be 4 byte aligned
+        self.add_text(body, 4)
+    }
+
+    /// RVA of initial data for a `FieldRVA` row
+    pub fn add_field_data(&mut self, data: &[u8]) -> u32 {
+        self.add_text(data, 8)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, scroll::Error> {
+        let mut text = vec![0; CLI_HEADER_SIZE as usize];
+        text.extend_from_slice(&self.text);
+        align(&mut text, 4);
+        let signature_dir = DataDirectory {
+            virtual_address: match self.strong_name_signature_size {
+                0 => 0,
+                _ => TEXT_RVA + text.len() as u32,
+            },
+            size: self.strong_name_signature_size,
+        };
+        text.resize(text.len() + self.strong_name_signature_size as usize, 0);
+
+        let metadata = self.metadata.to_bytes()?;
+        let entry_point_token = self
+            .entry_point
+            .map(|m| MetadataToken::MethodDef(m).to_raw())
+            .unwrap_or(0);
+
+        // II.25.3.3, everything after the strong name signature stays zero
+        let mut offset = 0;
+        text.gwrite_with(CLI_HEADER_SIZE, &mut offset, LE)?;
+        text.gwrite_with(2u16, &mut offset, LE)?;
+        text.gwrite_with(5u16, &mut offset, LE)?;
+        let metadata_dir = DataDirectory {
+            virtual_address: TEXT_RVA + text.len() as u32,
+            size: metadata.len() as u32,
+        };
+        text.gwrite_with(metadata_dir, &mut offset, LE)?;
+        text.gwrite_with(ComImageFlags::IL_ONLY.bits(), &mut offset, LE)?;
+        text.gwrite_with(entry_point_token, &mut offset, LE)?;
+        text.gwrite_with(DataDirectory::default(), &mut offset, LE)?;
+        text.gwrite_with(signature_dir, &mut offset, LE)?;
+        text.extend_from_slice(&metadata);
+
+        let text_size = text.len() as u32;
+        let raw_size = align_to(text_size, FILE_ALIGNMENT);
+        text.resize(raw_size as usize, 0);
+
+        let mut out = DOS_HEADER.to_vec();
+        let push16 = |out: &mut Vec<u8>, n: u16| out.extend_from_slice(&n.to_le_bytes());
+        let push32 = |out: &mut Vec<u8>, n: u32| out.extend_from_slice(&n.to_le_bytes());
+
+        out.extend_from_slice(b"PE\0\0");
+
+        // II.25.2.2 COFF header
+        let mut characteristics = 0x0002 | 0x0100;
+        if self.is_dll {
+            characteristics |= 0x2000;
+        }
+        push16(&mut out, 0x014c);
+        push16(&mut out, 1);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+        push16(&mut out, 0xE0);
+        push16(&mut out, characteristics);
+
+        // II.25.2.3.1 standard fields
+        push16(&mut out, 0x010B);
+        out.extend_from_slice(&[6, 0]);
+        push32(&mut out, raw_size);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+        push32(&mut out, TEXT_RVA);
+        push32(&mut out, 0);
+
+        // II.25.2.3.2 NT specific fields
+        push32(&mut out, 0x0040_0000);
+        push32(&mut out, SECTION_ALIGNMENT);
+        push32(&mut out, FILE_ALIGNMENT);
+        for version in [5, 0, 0, 0, 5, 0] {
+            push16(&mut out, version);
+        }
+        push32(&mut out, 0);
+        push32(&mut out, TEXT_RVA + align_to(text_size, SECTION_ALIGNMENT));
+        push32(&mut out, FILE_ALIGNMENT);
+        push32(&mut out, 0);
+        // console subsystem
+        push16(&mut out, 3);
+        push16(&mut out, 0x8540);
+        for size in [0x10_0000, 0x1000, 0x10_0000, 0x1000, 0, 16] {
+            push32(&mut out, size);
+        }
+
+        // II.25.2.3.3 data directories, only the CLI header
+        for i in 0..16 {
+            if i == 14 {
+                push32(&mut out, TEXT_RVA);
+                push32(&mut out, CLI_HEADER_SIZE);
+            } else {
+                push32(&mut out, 0);
+                push32(&mut out, 0);
+            }
+        }
+
+        // II.25.3 section header
+        out.extend_from_slice(b".text\0\0\0");
+        push32(&mut out, text_size);
+        push32(&mut out, TEXT_RVA);
+        push32(&mut out, raw_size);
+        push32(&mut out, FILE_ALIGNMENT);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+        push16(&mut out, 0);
+        push16(&mut out, 0);
+        // code, execute, read
+        push32(&mut out, 0x6000_0020);
+
+        out.resize(FILE_ALIGNMENT as usize, 0);
+        out.extend_from_slice(&text);
+
+        Ok(out)
+    }
+}
+
+#[test]
+fn build_image() {
+    use super::{Image, Module, TypeAttributes, TypeDef, TypeDefIndex, TypeDefOrRef};
+
+    let mut builder = ImageBuilder::default();
+    let name = builder.metadata.heap.add_string("Test.dll");
+    let mvid = builder.metadata.heap.add_guid([1; 16]);
+    let module_name = builder.metadata.heap.add_string("<Module>");
+    builder.metadata.table.module.push(Module {
+        generation: 0,
+        name,
+        mvid,
+        enc_id: GuidIndex(0),
+        env_base_id: GuidIndex(0),
+    });
+    builder.metadata.table.type_def.push(TypeDef {
+        flags: TypeAttributes::empty(),
+        type_name: module_name,
+        type_namespace: StringIndex(0),
+        extends: TypeDefOrRef::TypeDefIndex(TypeDefIndex(0)),
+        field_list: 1.into(),
+        method_list: 1.into(),
+    });
+    let us = builder.metadata.heap.add_user_string("Hi'");
+    assert_eq!(builder.metadata.heap.add_string("Test.dll"), name);
+
+    let bytes = builder.to_bytes().unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+
+    assert_eq!(root.version, METADATA_VERSION);
+    assert_eq!(root.metadata_stream.table.type_def.len(), 1);
+    let module = &root.metadata_stream.table.module[0];
+    assert_eq!(module.name.resolve(root.heap), Some("Test.dll"));
+    assert_eq!(module.mvid.resolve(root.heap), Some(&[1; 16]));
+    let us = us.resolve(root.heap).unwrap();
+    assert_eq!(us.to_string(), "Hi'");
+    assert!(us.has_special_chars());
+}
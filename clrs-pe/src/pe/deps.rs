@@ -0,0 +1,521 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::{AssemblyVersion, Image, Implementation, TableIndex, TypeAttributes};
+
+/// Name, version, culture and public key token of an assembly or a reference to one
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AssemblyIdentity {
+    pub name: String,
+    pub version: [u16; 4],
+    pub culture: String,
+    pub public_key_token: Option<[u8; 8]>,
+}
+
+fn version(v: AssemblyVersion) -> [u16; 4] {
+    [
+        v.major_version,
+        v.minor_version,
+        v.build_number,
+        v.revision_number,
+    ]
+}
+
+fn version_string(v: [u16; 4]) -> String {
+    format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3])
+}
+
+/// `mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089`
+impl fmt::Display for AssemblyIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let culture = if self.culture.is_empty() {
+            "neutral"
+        } else {
+            &self.culture
+        };
+        write!(
+            f,
+            "{}, Version={}, Culture={}, PublicKeyToken=",
+            self.name,
+            version_string(self.version),
+            culture
+        )?;
+
+        match self.public_key_token {
+            Some(token) => token.iter().try_for_each(|b| write!(f, "{:02x}", b)),
+            None => write!(f, "null"),
+        }
+    }
+}
+
+impl<'a> Image<'a> {
+    pub fn assembly_identity(&self) -> Option<AssemblyIdentity> {
+        let root = self.metadata_root();
+        let heap = root.heap;
+        let (_, assembly) = root.metadata_stream.table.list_assembly().next()?;
+
+        Some(AssemblyIdentity {
+            name: assembly.name.resolve(heap)?.to_string(),
+            version: version(assembly.version),
+            culture: assembly
+                .culture
+                .resolve(heap)
+                .unwrap_or_default()
+                .to_string(),
+            public_key_token: assembly.public_key_token(heap),
+        })
+    }
+
+    pub fn assembly_refs(&self) -> Vec<AssemblyIdentity> {
+        let root = self.metadata_root();
+        let heap = root.heap;
+
+        root.metadata_stream
+            .table
+            .list_assembly_ref()
+            .map(|(_, r)| AssemblyIdentity {
+                name: r.name.resolve(heap).unwrap_or_default().to_string(),
+                version: version(r.version),
+                culture: r.culture.resolve(heap).unwrap_or_default().to_string(),
+                public_key_token: r.public_key_token(heap),
+            })
+            .collect()
+    }
+
+    /// Full names of forwarded types with the name of the assembly they moved to
+    pub fn type_forwarders(&self) -> Vec<(String, String)> {
+        let root = self.metadata_root();
+        let heap = root.heap;
+        let table = &root.metadata_stream.table;
+
+        table
+            .list_exported_type()
+            .filter(|(_, e)| e.flags.contains(TypeAttributes::IS_TYPE_FORWARDER))
+            .filter_map(|(_, e)| {
+                let target = match e.implementation {
+                    Implementation::AssemblyRefIndex(r) => r.resolve_table(table)?,
+                    _ => return None,
+                };
+                let name = e.name.resolve(heap)?;
+                let name = match e.namespace.resolve(heap) {
+                    Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, name),
+                    _ => name.to_string(),
+                };
+                Some((name, target.name.resolve(heap)?.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// What an assembly references, detached from its image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyNode {
+    pub identity: AssemblyIdentity,
+    pub path: PathBuf,
+    pub references: Vec<AssemblyIdentity>,
+    /// Native libraries from `ModuleRef`
+    pub module_refs: Vec<String>,
+    /// Other files of a multi-file assembly
+    pub files: Vec<String>,
+    /// Forwarded types and the assemblies they moved to
+    pub forwarders: Vec<(String, String)>,
+}
+
+impl AssemblyNode {
+    pub fn from_image(image: &Image, path: PathBuf) -> Option<Self> {
+        let root = image.metadata_root();
+        let heap = root.heap;
+        let table = &root.metadata_stream.table;
+        let names = |names: Vec<Option<&str>>| {
+            names
+                .into_iter()
+                .flatten()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        Some(Self {
+            identity: image.assembly_identity()?,
+            path,
+            references: image.assembly_refs(),
+            module_refs: names(
+                table
+                    .list_module_ref()
+                    .map(|(_, m)| m.name.resolve(heap))
+                    .collect(),
+            ),
+            files: names(
+                table
+                    .list_file()
+                    .map(|(_, f)| f.name.resolve(heap))
+                    .collect(),
+            ),
+            forwarders: image.type_forwarders(),
+        })
+    }
+}
+
+/// A reference which wasn't found, once for each assembly making it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingReference {
+    /// Name of the referencing assembly
+    pub from: String,
+    pub reference: AssemblyIdentity,
+}
+
+/// An assembly requested with several versions, or found older than requested
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub name: String,
+    /// Requested versions with the assemblies asking for them
+    pub requested: BTreeMap<[u16; 4], Vec<String>>,
+    pub found: Option<[u16; 4]>,
+}
+
+/// Assemblies a type is forwarded through, the first one forwards it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardChain {
+    pub type_name: String,
+    pub assemblies: Vec<String>,
+}
+
+/// Reference closure of a root assembly
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    pub root: String,
+    /// Found assemblies by name
+    pub assemblies: BTreeMap<String, AssemblyNode>,
+    pub missing: Vec<MissingReference>,
+    pub conflicts: Vec<VersionConflict>,
+    pub forward_chains: Vec<ForwardChain>,
+}
+
+/// Load `root` and probe its directory then `probe_dirs` for `name.dll` or `name.exe`,
+/// files which are not assemblies are skipped
+pub fn reference_closure(root: &Path, probe_dirs: &[PathBuf]) -> std::io::Result<DependencyGraph> {
+    let load = |path: &Path| -> std::io::Result<AssemblyNode> {
+        let bytes = std::fs::read(path)?;
+        Image::from_bytes(&bytes)
+            .ok()
+            .and_then(|image| AssemblyNode::from_image(&image, path.to_path_buf()))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} is not an assembly", path.display()),
+                )
+            })
+    };
+
+    let dirs = root
+        .parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(probe_dirs.iter().cloned())
+        .collect::<Vec<_>>();
+    let probe = |reference: &AssemblyIdentity| {
+        dirs.iter()
+            .flat_map(|dir| {
+                ["dll", "exe"]
+                    .iter()
+                    .map(move |ext| dir.join(format!("{}.{}", reference.name, ext)))
+            })
+            .find_map(|path| load(&path).ok())
+    };
+
+    Ok(DependencyGraph::build(load(root)?, probe))
+}
+
+impl DependencyGraph {
+    /// Breadth first from `root`, `load` finds the assembly for a reference
+    pub fn build(
+        root: AssemblyNode,
+        mut load: impl FnMut(&AssemblyIdentity) -> Option<AssemblyNode>,
+    ) -> Self {
+        let mut graph = Self {
+            root: root.identity.name.clone(),
+            ..Self::default()
+        };
+        let mut requested = BTreeMap::<String, BTreeMap<[u16; 4], Vec<String>>>::new();
+        let mut queue = VecDeque::new();
+        let mut probed = HashSet::new();
+        probed.insert(root.identity.name.clone());
+        let mut missing = HashSet::new();
+
+        queue.push_back(root);
+        while let Some(node) = queue.pop_front() {
+            let from = node.identity.name.clone();
+
+            for reference in &node.references {
+                requested
+                    .entry(reference.name.clone())
+                    .or_default()
+                    .entry(reference.version)
+                    .or_default()
+                    .push(from.clone());
+
+                let is_missing = if probed.insert(reference.name.clone()) {
+                    match load(reference) {
+                        Some(found) => {
+                            queue.push_back(found);
+                            false
+                        }
+                        None => missing.insert(reference.name.clone()),
+                    }
+                } else {
+                    missing.contains(&reference.name)
+                };
+
+                if is_missing {
+                    graph.missing.push(MissingReference {
+                        from: from.clone(),
+                        reference: reference.clone(),
+                    });
+                }
+            }
+
+            graph.assemblies.insert(from, node);
+        }
+
+        for (name, requested) in requested {
+            let found = graph.assemblies.get(&name).map(|a| a.identity.version);
+            let too_old = match (found, requested.keys().next_back()) {
+                (Some(found), Some(&max)) => found < max,
+                _ => false,
+            };
+
+            if requested.len() > 1 || too_old {
+                graph.conflicts.push(VersionConflict {
+                    name,
+                    requested,
+                    found,
+                });
+            }
+        }
+
+        for (name, node) in &graph.assemblies {
+            for (type_name, target) in &node.forwarders {
+                let mut assemblies = vec![name.clone(), target.clone()];
+                while let Some(next) = graph
+                    .assemblies
+                    .get(assemblies.last().unwrap())
+                    .and_then(|a| a.forwarders.iter().find(|(t, _)| t == type_name))
+                    .map(|(_, next)| next)
+                {
+                    // a cycle is reported once it closes
+                    let is_cycle = assemblies.contains(next);
+                    assemblies.push(next.clone());
+                    if is_cycle {
+                        break;
+                    }
+                }
+
+                graph.forward_chains.push(ForwardChain {
+                    type_name: type_name.clone(),
+                    assemblies,
+                });
+            }
+        }
+
+        graph
+    }
+
+    /// Graphviz digraph, missing assemblies are dashed and conflicting ones red
+    pub fn to_dot(&self) -> String {
+        let mut out = "digraph dependencies {\n".to_string();
+        let is_conflict = |name: &str| self.conflicts.iter().any(|c| c.name == name);
+        let color = |name: &str| if is_conflict(name) { ", color=red" } else { "" };
+
+        for (name, node) in &self.assemblies {
+            out.push_str(&format!(
+                "  {:?} [label=\"{}\\n{}\"{}];\n",
+                name,
+                name,
+                version_string(node.identity.version),
+                color(name)
+            ));
+        }
+        let missing = self
+            .missing
+            .iter()
+            .map(|m| &m.reference.name)
+            .collect::<BTreeSet<_>>();
+        for name in missing {
+            out.push_str(&format!("  {:?} [style=dashed{}];\n", name, color(name)));
+        }
+        for (name, node) in &self.assemblies {
+            for reference in &node.references {
+                out.push_str(&format!(
+                    "  {:?} -> {:?} [label=\"{}\"];\n",
+                    name,
+                    reference.name,
+                    version_string(reference.version)
+                ));
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        let identity = |i: &AssemblyIdentity| {
+            json!({
+                "name": i.name,
+                "version": version_string(i.version),
+                "culture": i.culture,
+                "public_key_token": i.public_key_token.map(|t| {
+                    t.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+                }),
+            })
+        };
+
+        json!({
+            "root": self.root,
+            "assemblies": self.assemblies.values().map(|a| json!({
+                "identity": identity(&a.identity),
+                "path": a.path.display().to_string(),
+                "references": a.references.iter().map(identity).collect::<Vec<_>>(),
+                "module_refs": a.module_refs,
+                "files": a.files,
+            })).collect::<Vec<_>>(),
+            "missing": self.missing.iter().map(|m| json!({
+                "from": m.from,
+                "reference": identity(&m.reference),
+            })).collect::<Vec<_>>(),
+            "conflicts": self.conflicts.iter().map(|c| json!({
+                "name": c.name,
+                "requested": c.requested.iter().map(|(v, from)| json!({
+                    "version": version_string(*v),
+                    "from": from,
+                })).collect::<Vec<_>>(),
+                "found": c.found.map(version_string),
+            })).collect::<Vec<_>>(),
+            "forward_chains": self.forward_chains.iter().map(|f| json!({
+                "type": f.type_name,
+                "assemblies": f.assemblies,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[test]
+fn dependency_graph() {
+    use crate::cil::asm::assemble;
+
+    let sources = [
+        (
+            "app",
+            "
+            .assembly extern lib { .ver 2:0:0:0 }
+            .assembly extern util { .ver 1:0:0:0 }
+            .assembly extern gone { .ver 1:0:0:0 }
+            .assembly app { .ver 1:0:0:0 }
+            ",
+        ),
+        (
+            "lib",
+            "
+            .assembly extern util { .ver 1:2:0:0 }
+            .assembly extern core { .ver 1:0:0:0 }
+            .assembly extern gone { .ver 1:0:0:0 }
+            .assembly lib { .ver 1:5:0:0 }
+            .class extern forwarder Ns.Moved { .assembly extern core }
+            ",
+        ),
+        ("util", ".assembly util { .culture \"fr-FR\" .ver 1:2:0:0 }"),
+        (
+            "core",
+            "
+            .assembly extern impl { .ver 1:0:0:0 }
+            .assembly core { .ver 1:0:0:0 }
+            .class extern forwarder Ns.Moved { .assembly extern impl }
+            ",
+        ),
+        ("impl", ".assembly impl { .ver 1:0:0:0 }"),
+    ];
+    let nodes = sources
+        .iter()
+        .map(|(name, source)| {
+            let bytes = assemble(source).unwrap();
+            let image = Image::from_bytes(&bytes).unwrap();
+            let path = PathBuf::from(format!("{}.dll", name));
+            (
+                name.to_string(),
+                AssemblyNode::from_image(&image, path).unwrap(),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let graph = DependencyGraph::build(nodes["app"].clone(), |r| nodes.get(&r.name).cloned());
+
+    assert_eq!(
+        graph.assemblies.keys().collect::<Vec<_>>(),
+        ["app", "core", "impl", "lib", "util"]
+    );
+    let missing = graph
+        .missing
+        .iter()
+        .map(|m| (m.from.as_str(), m.reference.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(missing, [("app", "gone"), ("lib", "gone")]);
+
+    let conflicts = graph
+        .conflicts
+        .iter()
+        .map(|c| (c.name.as_str(), c.requested.len(), c.found))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        conflicts,
+        [
+            ("lib", 1, Some([1, 5, 0, 0])),
+            ("util", 2, Some([1, 2, 0, 0]))
+        ]
+    );
+
+    assert_eq!(
+        graph.forward_chains[1],
+        ForwardChain {
+            type_name: "Ns.Moved".into(),
+            assemblies: vec!["lib".into(), "core".into(), "impl".into()],
+        }
+    );
+    let dot = graph.to_dot();
+    assert!(dot.contains("\"app\" -> \"gone\" [label=\"1.0.0.0\"];"));
+    assert_eq!(dot.matches("\"gone\" [style=dashed];").count(), 1);
+    assert_eq!(
+        graph.assemblies["lib"].identity.to_string(),
+        "lib, Version=1.5.0.0, Culture=neutral, PublicKeyToken=null"
+    );
+    assert_eq!(
+        graph.assemblies["util"].identity.to_string(),
+        "util, Version=1.2.0.0, Culture=fr-FR, PublicKeyToken=null"
+    );
+}
+
+#[test]
+fn assembly_culture() {
+    use crate::cil::asm::assemble;
+
+    let bytes = assemble(
+        "
+        .assembly extern satellite { .culture \"de\" .ver 1:0:0:0 }
+        .assembly app { .culture \"fr-FR\" .ver 1:2:3:4 }
+        ",
+    )
+    .unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let heap = image.metadata_root().heap;
+
+    assert_eq!(image.assembly_identity().unwrap().culture, "fr-FR");
+    assert_eq!(image.assembly_refs()[0].culture, "de");
+
+    // II.22.2: HashAlgId, version, Flags, then PublicKey, Name and Culture heap indices
+    let row = [0x04, 0x80, 0, 0, 1, 0, 2, 0, 3, 0, 4, 0, 0, 0, 0, 0];
+    let offset = bytes.windows(row.len()).position(|w| w == row).unwrap() + row.len();
+    let culture = u16::from_le_bytes([bytes[offset + 4], bytes[offset + 5]]);
+    assert_eq!(heap.ref_string(culture as usize), Some("fr-FR"));
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+
+use crate::cil::disasm::{mnemonic, quote_name, quote_string, Names};
+use crate::cil::{Form, Instruction};
+
+use super::{
+    BlobIndex, EntryPoint, GuidIndex, LocalVar, MetadataRoot, MetadataToken, MethodDefSig,
+    NotUsed1Index, NotUsed2Index, NotUsed3Index, Param, RetType, StringIndex, TableIndex, Type,
+    TypeDefOrRefOrSpecEncoded, UserStringIndex,
+};
+
+/// Formatting with names resolved from the metadata, in ilasm syntax
+pub trait DisplayWith {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// `Display` adapter, e.g. `todo!("{}", token.display_with(root))`
+    fn display_with<'r, 'a>(&'r self, root: &'r MetadataRoot<'a>) -> WithRoot<'r, 'a, Self> {
+        WithRoot { value: self, root }
+    }
+}
+
+pub struct WithRoot<'r, 'a, T: ?Sized> {
+    value: &'r T,
+    root: &'r MetadataRoot<'a>,
+}
+
+impl<'r, 'a, T: DisplayWith + ?Sized> fmt::Display for WithRoot<'r, 'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = Names::new(&self.root.metadata_stream.table, self.root.heap);
+        self.value.fmt_with(&names, f)
+    }
+}
+
+impl DisplayWith for MetadataToken {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        let table = names.table;
+        let name = |name: Option<StringIndex>| {
+            name.and_then(|s| s.resolve(names.heap))
+                .map(quote_name)
+                .unwrap_or_else(|| format!("/* {:08X} */", self.to_raw()))
+        };
+
+        match *self {
+            MetadataToken::Module(m) => {
+                write!(f, "{}", name(m.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::ModuleRef(m) => write!(
+                f,
+                "[.module {}]",
+                name(m.resolve_table(table).map(|r| r.name))
+            ),
+            MetadataToken::Assembly(a) => {
+                write!(f, "{}", name(a.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::AssemblyRef(a) => {
+                write!(f, "[{}]", name(a.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::Param(p) => {
+                write!(f, "{}", name(p.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::GenericParam(g) => {
+                write!(f, "{}", name(g.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::Property(p) => {
+                write!(f, "{}", name(p.resolve_table(table).map(|r| r.name)))
+            }
+            MetadataToken::Event(e) => {
+                write!(f, "{}", name(e.resolve_table(table).map(|r| r.name)))
+            }
+            token => write!(f, "{}", names.ldtoken(token)),
+        }
+    }
+}
+
+impl DisplayWith for Type {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.ty(self))
+    }
+}
+
+impl DisplayWith for TypeDefOrRefOrSpecEncoded {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.encoded(self))
+    }
+}
+
+impl DisplayWith for RetType {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.ret_type(self))
+    }
+}
+
+impl DisplayWith for Param {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.param(self))
+    }
+}
+
+impl DisplayWith for LocalVar {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.local(self))
+    }
+}
+
+impl DisplayWith for MethodDefSig {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", names.method_sig(self))
+    }
+}
+
+impl DisplayWith for EntryPoint {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntryPoint::Method(m) => MetadataToken::MethodDef(m).fmt_with(names, f),
+            EntryPoint::File(file) => match file
+                .resolve_table(names.table)
+                .and_then(|r| r.name.resolve(names.heap))
+            {
+                Some(name) => write!(f, "[.file {}]", quote_name(name)),
+                None => write!(
+                    f,
+                    "[.file /* {:08X} */]",
+                    MetadataToken::File(file).to_raw()
+                ),
+            },
+            EntryPoint::Native(rva) => write!(f, "native code at RVA 0x{:08X}", rva),
+        }
+    }
+}
+
+/// Branch targets are relative to the next instruction, like `br.s +4`
+impl DisplayWith for Instruction {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = self.size(Form::Macro);
+        write!(f, "{}", mnemonic(self, size))?;
+
+        let targets = self
+            .branch_targets(0)
+            .into_iter()
+            .map(|rel| format!("{:+}", rel as i32))
+            .collect::<Vec<_>>();
+        match self {
+            Instruction::Switch(_) => write!(f, " ({})", targets.join(", ")),
+            _ if !targets.is_empty() => write!(f, " {}", targets.join("")),
+            _ => match names.operand(self, size, size) {
+                Some(operand) => write!(f, " {}", operand),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+impl DisplayWith for StringIndex {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.resolve(names.heap) {
+            Some(s) => write!(f, "{}", quote_name(s)),
+            None => write!(f, "/* #Strings {:#x} */", self.0),
+        }
+    }
+}
+
+impl DisplayWith for UserStringIndex {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.resolve(names.heap) {
+            Some(s) => write!(f, "{}", quote_string(&s.to_string())),
+            None => write!(f, "/* #US {:#x} */", self.0),
+        }
+    }
+}
+
+impl DisplayWith for BlobIndex {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.resolve(names.heap) {
+            Some(blob) => {
+                let bytes = blob
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>();
+                write!(f, "({})", bytes.join(" "))
+            }
+            None => write!(f, "/* #Blob {:#x} */", self.0),
+        }
+    }
+}
+
+impl DisplayWith for GuidIndex {
+    fn fmt_with(&self, names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.resolve(names.heap) {
+            Some(g) => write!(
+                f,
+                "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}}}",
+                u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+                u16::from_le_bytes([g[4], g[5]]),
+                u16::from_le_bytes([g[6], g[7]]),
+                g[8],
+                g[9],
+                g[10..]
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>()
+            ),
+            None => write!(f, "/* #GUID {} */", self.0),
+        }
+    }
+}
+
+macro_rules! display_not_used {
+    ($($name:ident)*) => {
+        $(
+            impl DisplayWith for $name {
+                fn fmt_with(&self, _names: &Names, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "/* {} */", stringify!($name))
+                }
+            }
+        )*
+    };
+}
+
+display_not_used!(NotUsed1Index NotUsed2Index NotUsed3Index);
+
+#[test]
+fn display_tokens() {
+    use super::{Image, MemberRefIndex, TypeRefIndex};
+
+    let bytes = crate::cil::asm::assemble(include_str!("../../../tests/il/hello.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let member_ref = MemberRefIndex(2)
+        .resolve_table(&root.metadata_stream.table)
+        .unwrap();
+
+    assert_eq!(
+        MetadataToken::MemberRef(MemberRefIndex(1))
+            .display_with(root)
+            .to_string(),
+        "method void [mscorlib]System.Console::WriteLine(string)"
+    );
+    assert_eq!(
+        MetadataToken::MemberRef(MemberRefIndex(2))
+            .display_with(root)
+            .to_string(),
+        "method instance void [mscorlib]System.Object::.ctor()"
+    );
+    assert_eq!(
+        image.entry_point().unwrap().display_with(root).to_string(),
+        "method void Program::Main(string[])"
+    );
+    assert_eq!(
+        EntryPoint::Native(0x1000).display_with(root).to_string(),
+        "native code at RVA 0x00001000"
+    );
+    assert_eq!(
+        member_ref.class.display_with(root).to_string(),
+        "[mscorlib]System.Object"
+    );
+    assert_eq!(
+        TypeRefIndex(1).display_with(root).to_string(),
+        "[mscorlib]System.Object"
+    );
+    assert_eq!(
+        Instruction::Call(MetadataToken::MemberRef(MemberRefIndex(1)))
+            .display_with(root)
+            .to_string(),
+        "call void [mscorlib]System.Console::WriteLine(string)"
+    );
+    assert_eq!(
+        Instruction::Br(-7).display_with(root).to_string(),
+        "br.s -7"
+    );
+}
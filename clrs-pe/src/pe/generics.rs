@@ -0,0 +1,138 @@
+use super::{
+    GenericParamAttributes, GenericParamIndex, Heap, MetadataTable, TypeDefOrRef, TypeOrMethodDef,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variance {
+    None,
+    /// `out`
+    Covariant,
+    /// `in`
+    Contravariant,
+}
+
+impl GenericParamAttributes {
+    pub fn variance(self) -> Variance {
+        match self & Self::VARIANCE_MASK {
+            Self::COVARIANT => Variance::Covariant,
+            Self::CONTRAVARIANT => Variance::Contravariant,
+            _ => Variance::None,
+        }
+    }
+
+    /// `class`, `struct` and `new()` constraints
+    pub fn special_constraints(self) -> Self {
+        self & Self::SPECIAL_CONSTRAINT_MASK
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GenericParamInfo<'a> {
+    pub index: GenericParamIndex,
+    pub number: u16,
+    pub name: &'a str,
+    pub variance: Variance,
+    pub special_constraints: GenericParamAttributes,
+    pub constraints: Vec<TypeDefOrRef>,
+}
+
+impl TypeOrMethodDef {
+    /// Generic parameters ordered by number
+    pub fn generic_params<'a>(
+        self,
+        table: &MetadataTable,
+        heap: Heap<'a>,
+    ) -> Vec<GenericParamInfo<'a>> {
+        // GenericParam is sorted by owner then number
+        let key = self.encode();
+        let start = table
+            .generic_param
+            .partition_point(|p| p.owner.encode() < key);
+
+        table
+            .list_generic_param()
+            .skip(start)
+            .take_while(|(_, p)| p.owner == self)
+            .map(|(index, p)| GenericParamInfo {
+                index,
+                number: p.number,
+                name: p.name.resolve(heap).unwrap_or_default(),
+                variance: p.flags.variance(),
+                special_constraints: p.flags.special_constraints(),
+                constraints: index.constraints(table),
+            })
+            .collect()
+    }
+}
+
+impl GenericParamIndex {
+    pub fn constraints(self, table: &MetadataTable) -> Vec<TypeDefOrRef> {
+        // GenericParamConstraint is sorted by owner
+        let start = table
+            .generic_param_constraint
+            .partition_point(|c| c.owner.0 < self.0);
+
+        table.generic_param_constraint[start..]
+            .iter()
+            .take_while(|c| c.owner == self)
+            .map(|c| c.constraint)
+            .collect()
+    }
+}
+
+#[test]
+fn generic_params() {
+    use super::{GenericParam, GenericParamConstraint, MethodDefIndex, StringIndex, TypeRefIndex};
+
+    let owner = TypeOrMethodDef::MethodDefIndex(MethodDefIndex(2));
+    let param = |number, owner, name, flags| GenericParam {
+        number,
+        flags,
+        owner,
+        name: StringIndex(name),
+    };
+
+    let table = MetadataTable {
+        generic_param: vec![
+            param(
+                0,
+                TypeOrMethodDef::MethodDefIndex(MethodDefIndex(1)),
+                0,
+                GenericParamAttributes::NONE,
+            ),
+            param(
+                0,
+                owner,
+                1,
+                GenericParamAttributes::COVARIANT
+                    | GenericParamAttributes::REFERENCE_TYPE_CONSTRAINT,
+            ),
+            param(1, owner, 3, GenericParamAttributes::NONE),
+        ],
+        generic_param_constraint: vec![GenericParamConstraint {
+            owner: GenericParamIndex(2),
+            constraint: TypeDefOrRef::TypeRefIndex(TypeRefIndex(1)),
+        }],
+        ..MetadataTable::default()
+    };
+    let heap = Heap {
+        strings: "\0T\0U\0",
+        ..Heap::default()
+    };
+
+    let params = owner.generic_params(&table, heap);
+
+    assert_eq!(params.len(), 2);
+    assert_eq!(params[0].name, "T");
+    assert_eq!(params[0].variance, Variance::Covariant);
+    assert_eq!(
+        params[0].special_constraints,
+        GenericParamAttributes::REFERENCE_TYPE_CONSTRAINT
+    );
+    assert_eq!(
+        params[0].constraints,
+        [TypeDefOrRef::TypeRefIndex(TypeRefIndex(1))]
+    );
+    assert_eq!(params[1].name, "U");
+    assert!(params[1].constraints.is_empty());
+}
@@ -0,0 +1,200 @@
+use scroll::{Pread, LE};
+use serde_json::{json, Value};
+
+use crate::cil::disasm::Names;
+
+use super::{
+    BlobIndex, DisplayWith, FieldSig, GuidIndex, Image, LocalVarSig, MetadataToken, MethodDefSig,
+    MethodSpecSig, NotUsed1Index, NotUsed2Index, NotUsed3Index, StringIndex, Type, UserStringIndex,
+};
+
+/// Conversion to JSON with indices resolved to names where possible
+pub trait ToJson {
+    fn to_json(&self, names: &Names) -> Value;
+}
+
+struct WithNames<'n, 't, 'a, T> {
+    value: &'n T,
+    names: &'n Names<'t, 'a>,
+}
+
+impl<T: DisplayWith> std::fmt::Display for WithNames<'_, '_, '_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.value.fmt_with(self.names, f)
+    }
+}
+
+fn display<T: DisplayWith>(value: &T, names: &Names) -> String {
+    WithNames { value, names }.to_string()
+}
+
+/// Unresolved tokens fall back to `{"table": "TypeRef", "row": 3}`
+impl ToJson for MetadataToken {
+    fn to_json(&self, names: &Names) -> Value {
+        let resolved = match *self {
+            MetadataToken::UserString(s) => s.resolve(names.heap).is_some(),
+            token => token.row_exists(names.table),
+        };
+        if resolved {
+            display(self, names).into()
+        } else {
+            json!({ "table": self.table_name(), "row": self.to_raw() & 0xFF_FFFF })
+        }
+    }
+}
+
+impl ToJson for StringIndex {
+    fn to_json(&self, names: &Names) -> Value {
+        self.resolve(names.heap).map_or(Value::Null, Value::from)
+    }
+}
+
+impl ToJson for UserStringIndex {
+    fn to_json(&self, names: &Names) -> Value {
+        self.resolve(names.heap)
+            .map_or(Value::Null, |s| s.to_string().into())
+    }
+}
+
+impl ToJson for BlobIndex {
+    fn to_json(&self, names: &Names) -> Value {
+        self.resolve(names.heap)
+            .map_or(Value::Null, |b| hex(b).into())
+    }
+}
+
+impl ToJson for GuidIndex {
+    fn to_json(&self, names: &Names) -> Value {
+        if self.resolve(names.heap).is_some() {
+            display(self, names).into()
+        } else {
+            Value::Null
+        }
+    }
+}
+
+macro_rules! json_not_used {
+    ($($name:ident)*) => {
+        $(
+            impl ToJson for $name {
+                fn to_json(&self, _names: &Names) -> Value {
+                    Value::Null
+                }
+            }
+        )*
+    };
+}
+
+json_not_used!(NotUsed1Index NotUsed2Index NotUsed3Index);
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Decodes the signature blob of a `row` kind, or falls back to hex
+pub fn signature_to_json(row: &str, blob: BlobIndex, names: &Names) -> Value {
+    let bytes = match blob.resolve(names.heap) {
+        Some(bytes) => bytes,
+        None => return Value::Null,
+    };
+
+    let decoded = match (row, bytes.first()) {
+        ("Field", _) | ("MemberRef", Some(0x06)) => bytes
+            .pread_with::<FieldSig>(0, LE)
+            .ok()
+            .map(|sig| names.field_sig(&sig)),
+        ("MethodDef", _) | ("MemberRef", _) => bytes
+            .pread_with::<MethodDefSig>(0, LE)
+            .ok()
+            .map(|sig| names.method_sig(&sig)),
+        ("StandAloneSig", Some(0x07)) => bytes.pread_with::<LocalVarSig>(0, LE).ok().map(|sig| {
+            let locals = sig
+                .locals
+                .iter()
+                .map(|l| names.local(l))
+                .collect::<Vec<_>>();
+            format!("({})", locals.join(", "))
+        }),
+        ("StandAloneSig", _) => bytes
+            .pread_with::<MethodDefSig>(0, LE)
+            .ok()
+            .map(|sig| names.method_sig(&sig)),
+        ("TypeSpec", _) => bytes.pread_with::<Type>(0, LE).ok().map(|ty| names.ty(&ty)),
+        ("MethodSpec", _) => bytes.pread_with::<MethodSpecSig>(0, LE).ok().map(|sig| {
+            let args = sig.args.iter().map(|t| names.ty(t)).collect::<Vec<_>>();
+            format!("<{}>", args.join(", "))
+        }),
+        _ => None,
+    };
+
+    decoded.unwrap_or_else(|| hex(bytes)).into()
+}
+
+/// One document per image: names, heaps and every non-empty table
+pub fn image_to_json(image: &Image) -> Value {
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let names = Names::new(table, root.heap);
+
+    let name = table
+        .list_assembly()
+        .next()
+        .map(|(_, a)| a.name)
+        .or_else(|| table.list_module().next().map(|(_, m)| m.name))
+        .to_json(&names);
+    let strings = root
+        .heap
+        .list_string()
+        .map(|(index, s)| (index.0.to_string(), Value::from(s)))
+        .collect::<serde_json::Map<_, _>>();
+    let user_strings = root
+        .heap
+        .list_user_string()
+        .map(|(index, s)| (index.0.to_string(), Value::from(s.to_string())))
+        .collect::<serde_json::Map<_, _>>();
+
+    json!({
+        "name": name,
+        "version": root.version,
+        "heaps": {
+            "strings": strings,
+            "user_strings": user_strings,
+        },
+        "tables": table.to_json(&names),
+    })
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self, names: &Names) -> Value {
+        self.as_ref().map_or(Value::Null, |v| v.to_json(names))
+    }
+}
+
+#[test]
+fn json_hello() {
+    let bytes = crate::cil::asm::assemble(include_str!("../../../tests/il/hello.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let json = image_to_json(&image);
+
+    let member_refs = json["tables"]["MemberRef"].as_array().unwrap();
+    assert_eq!(member_refs[0]["name"], "WriteLine");
+    assert_eq!(member_refs[0]["class"], "[mscorlib]System.Console");
+    assert_eq!(member_refs[0]["signature"], "void(string)");
+    assert_eq!(member_refs[0]["token"], 0x0A00_0001);
+    let root = image.metadata_root();
+    let names = Names::new(&root.metadata_stream.table, root.heap);
+    assert_eq!(
+        MetadataToken::TypeRef(99.into()).to_json(&names),
+        json!({ "table": "TypeRef", "row": 99 })
+    );
+    assert!(json["heaps"]["strings"]
+        .as_object()
+        .unwrap()
+        .values()
+        .any(|s| s == "WriteLine"));
+    assert_eq!(
+        serde_json::to_value(&image.metadata_root().metadata_stream.table).unwrap()["member_ref"]
+            [0]["class"],
+        json!({ "TypeRefIndex": 2 })
+    );
+}
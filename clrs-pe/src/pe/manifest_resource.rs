@@ -0,0 +1,467 @@
+use scroll::ctx::TryFromCtx;
+use scroll::{Endian, Pread, LE};
+
+use super::{
+    AssemblyRefIndex, ExportedTypeIndex, FileIndex, Image, Implementation,
+    ManifestResourceAttributes, ManifestResourceIndex, TableIndex,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ManifestResourceVisibility {
+    Public,
+    Private,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ManifestResourceData<'a> {
+    /// Stored in this image
+    Embedded(&'a [u8]),
+    /// Stored in a linked file at `offset`
+    File {
+        file: FileIndex,
+        name: &'a str,
+        offset: u32,
+    },
+    /// Stored in another assembly
+    AssemblyRef(AssemblyRefIndex),
+    ExportedType(ExportedTypeIndex),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ManifestResourceEntry<'a> {
+    pub index: ManifestResourceIndex,
+    pub name: &'a str,
+    pub visibility: ManifestResourceVisibility,
+    pub data: ManifestResourceData<'a>,
+}
+
+impl<'a> Image<'a> {
+    /// Embedded resource is a u32 length followed by its bytes at `offset` of CLI resources
+    fn manifest_resource_blob(&self, offset: u32) -> Result<&'a [u8], goblin::error::Error> {
+        let bad_offset = || scroll::Error::BadInput {
+            size: offset as usize,
+            msg: "Manifest resource offset out of range",
+        };
+        let rva = self
+            .cli_header()
+            .resources
+            .virtual_address
+            .checked_add(offset)
+            .ok_or_else(bad_offset)?;
+        let len: u32 = self.get_data(rva)?;
+        self.get_bytes(rva.checked_add(4).ok_or_else(bad_offset)?, len)
+    }
+
+    /// Errors when an embedded resource lies outside the image
+    pub fn manifest_resources(
+        &self,
+    ) -> Result<Vec<ManifestResourceEntry<'a>>, goblin::error::Error> {
+        let root = self.metadata_root();
+        let heap = root.heap;
+        let table = &root.metadata_stream.table;
+
+        table
+            .list_manifest_resource()
+            .filter_map(|(index, res)| {
+                let data = match res.implementation {
+                    // null Implementation means this file
+                    Implementation::FileIndex(FileIndex(0)) => {
+                        match self.manifest_resource_blob(res.offset) {
+                            Ok(blob) => ManifestResourceData::Embedded(blob),
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                    Implementation::FileIndex(file) => ManifestResourceData::File {
+                        file,
+                        name: file.resolve_table(table)?.name.resolve(heap)?,
+                        offset: res.offset,
+                    },
+                    Implementation::AssemblyRefIndex(asm) => ManifestResourceData::AssemblyRef(asm),
+                    Implementation::ExportedTypeIndex(ty) => ManifestResourceData::ExportedType(ty),
+                };
+
+                let visibility = if res.flags & ManifestResourceAttributes::VISIBILITY_MASK
+                    == ManifestResourceAttributes::PRIVATE
+                {
+                    ManifestResourceVisibility::Private
+                } else {
+                    ManifestResourceVisibility::Public
+                };
+
+                Some(Ok(ManifestResourceEntry {
+                    index,
+                    name: res.name.resolve(heap)?,
+                    visibility,
+                    data,
+                }))
+            })
+            .collect()
+    }
+
+    pub fn manifest_resource(
+        &self,
+        name: &str,
+    ) -> Result<Option<ManifestResourceEntry<'a>>, goblin::error::Error> {
+        Ok(self
+            .manifest_resources()?
+            .into_iter()
+            .find(|r| r.name == name))
+    }
+}
+
+const RESOURCE_MANAGER_MAGIC: u32 = 0xBEEF_CACE;
+
+/// `System.Resources.ResourceReader` value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceValue<'a> {
+    Null,
+    String(&'a str),
+    Boolean(bool),
+    Char(u16),
+    Byte(u8),
+    SByte(i8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Single(f32),
+    Double(f64),
+    Decimal([u8; 16]),
+    /// Ticks with kind in the top 2 bits
+    DateTime(u64),
+    /// Ticks
+    TimeSpan(i64),
+    ByteArray(&'a [u8]),
+    Stream(&'a [u8]),
+    /// Value of non primitive type serialized by a formatter
+    Serialized {
+        type_name: &'a str,
+        data: &'a [u8],
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ResourceSetEntry<'a> {
+    pub name: String,
+    pub value: ResourceValue<'a>,
+}
+
+/// `.resources` file
+#[derive(Clone, Debug)]
+pub struct ResourceSet<'a> {
+    pub reader_type: &'a str,
+    pub resource_set_type: &'a str,
+    pub version: i32,
+    pub type_names: Vec<&'a str>,
+    pub entries: Vec<ResourceSetEntry<'a>>,
+}
+
+impl<'a> ResourceSet<'a> {
+    pub fn get(&self, name: &str) -> Option<&ResourceValue<'a>> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| &e.value)
+    }
+}
+
+/// 7 bit encoded integer of `BinaryWriter`
+fn read_7bit(src: &[u8], offset: &mut usize) -> Result<u32, scroll::Error> {
+    let mut n = 0u32;
+
+    for shift in (0..35).step_by(7) {
+        let b: u8 = src.gread_with(offset, LE)?;
+        n |= ((b & 0x7F) as u32) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+
+    Err(scroll::Error::BadInput {
+        size: 5,
+        msg: "Invalid 7 bit encoded integer",
+    })
+}
+
+fn read_bytes<'a>(
+    src: &'a [u8],
+    offset: &mut usize,
+    len: usize,
+) -> Result<&'a [u8], scroll::Error> {
+    let bytes = src
+        .get(*offset..*offset + len)
+        .ok_or(scroll::Error::TooBig {
+            size: len,
+            len: src.len().saturating_sub(*offset),
+        })?;
+    *offset += len;
+    Ok(bytes)
+}
+
+/// `BinaryWriter.Write(string)`
+fn read_string<'a>(src: &'a [u8], offset: &mut usize) -> Result<&'a str, scroll::Error> {
+    let len = read_7bit(src, offset)? as usize;
+    std::str::from_utf8(read_bytes(src, offset, len)?).map_err(|_| scroll::Error::BadInput {
+        size: len,
+        msg: "Invalid UTF-8 string",
+    })
+}
+
+fn read_value<'a>(
+    src: &'a [u8],
+    offset: &mut usize,
+    version: i32,
+    type_names: &[&'a str],
+    end: usize,
+) -> Result<ResourceValue<'a>, scroll::Error> {
+    let code = read_7bit(src, offset)?;
+
+    let serialized = |offset: &mut usize, index: usize| -> Result<_, scroll::Error> {
+        let type_name = type_names
+            .get(index)
+            .copied()
+            .ok_or(scroll::Error::BadInput {
+                size: 0,
+                msg: "Invalid resource type index",
+            })?;
+        let data = read_bytes(src, offset, end.saturating_sub(*offset))?;
+        Ok(ResourceValue::Serialized { type_name, data })
+    };
+
+    // Version 1 only has indices into the type table
+    if version == 1 {
+        return serialized(offset, code as usize);
+    }
+
+    let value = match code {
+        0x00 => ResourceValue::Null,
+        0x01 => ResourceValue::String(read_string(src, offset)?),
+        0x02 => ResourceValue::Boolean(src.gread_with::<u8>(offset, LE)? != 0),
+        0x03 => ResourceValue::Char(src.gread_with(offset, LE)?),
+        0x04 => ResourceValue::Byte(src.gread_with(offset, LE)?),
+        0x05 => ResourceValue::SByte(src.gread_with(offset, LE)?),
+        0x06 => ResourceValue::Int16(src.gread_with(offset, LE)?),
+        0x07 => ResourceValue::UInt16(src.gread_with(offset, LE)?),
+        0x08 => ResourceValue::Int32(src.gread_with(offset, LE)?),
+        0x09 => ResourceValue::UInt32(src.gread_with(offset, LE)?),
+        0x0A => ResourceValue::Int64(src.gread_with(offset, LE)?),
+        0x0B => ResourceValue::UInt64(src.gread_with(offset, LE)?),
+        0x0C => ResourceValue::Single(src.gread_with(offset, LE)?),
+        0x0D => ResourceValue::Double(src.gread_with(offset, LE)?),
+        0x0E => {
+            let mut decimal = [0; 16];
+            decimal.copy_from_slice(read_bytes(src, offset, 16)?);
+            ResourceValue::Decimal(decimal)
+        }
+        0x0F => ResourceValue::DateTime(src.gread_with(offset, LE)?),
+        0x10 => ResourceValue::TimeSpan(src.gread_with(offset, LE)?),
+        0x20 | 0x21 => {
+            let len: u32 = src.gread_with(offset, LE)?;
+            let bytes = read_bytes(src, offset, len as usize)?;
+            if code == 0x20 {
+                ResourceValue::ByteArray(bytes)
+            } else {
+                ResourceValue::Stream(bytes)
+            }
+        }
+        0x40.. => return serialized(offset, code as usize - 0x40),
+        _ => {
+            return Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Unknown resource type code",
+            })
+        }
+    };
+
+    Ok(value)
+}
+
+impl<'a> TryFromCtx<'a, Endian> for ResourceSet<'a> {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        // ResourceManager header
+        let magic: u32 = src.gread_with(offset, ctx)?;
+        if magic != RESOURCE_MANAGER_MAGIC {
+            return Err(scroll::Error::BadInput {
+                size: 4,
+                msg: "Invalid ResourceManager magic",
+            });
+        }
+        let _header_version: i32 = src.gread_with(offset, ctx)?;
+        let header_size: u32 = src.gread_with(offset, ctx)?;
+        let header_end = *offset + header_size as usize;
+        let reader_type = read_string(src, offset)?;
+        let resource_set_type = read_string(src, offset)?;
+        *offset = header_end;
+
+        // RuntimeResourceSet header
+        let version: i32 = src.gread_with(offset, ctx)?;
+        let count: u32 = src.gread_with(offset, ctx)?;
+        let type_count: u32 = src.gread_with(offset, ctx)?;
+        let type_names = (0..type_count)
+            .map(|_| read_string(src, offset))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // padded with "PAD" to 8 bytes
+        *offset = (*offset + 7) & !7;
+
+        let _hashes = read_bytes(src, offset, count as usize * 4)?;
+        let name_positions = (0..count)
+            .map(|_| src.gread_with::<u32>(offset, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data_section: u32 = src.gread_with(offset, ctx)?;
+        let name_section = *offset;
+        let data_section = data_section as usize;
+
+        let mut names = name_positions
+            .into_iter()
+            .map(|pos| {
+                let offset = &mut (name_section + pos as usize);
+                let len = read_7bit(src, offset)? as usize;
+                let units = read_bytes(src, offset, len)?
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                let data_offset: u32 = src.gread_with(offset, ctx)?;
+                Ok((
+                    String::from_utf16_lossy(&units),
+                    data_section + data_offset as usize,
+                ))
+            })
+            .collect::<Result<Vec<_>, scroll::Error>>()?;
+
+        // size of serialized values is only known from the start of the next value
+        let mut ends = names.iter().map(|(_, pos)| *pos).collect::<Vec<_>>();
+        ends.sort_unstable();
+        ends.dedup();
+
+        let entries = names
+            .drain(..)
+            .map(|(name, pos)| {
+                let end = ends
+                    .iter()
+                    .copied()
+                    .find(|&end| end > pos)
+                    .unwrap_or(src.len());
+                let mut offset = pos;
+                let value = read_value(src, &mut offset, version, &type_names, end)?;
+                Ok(ResourceSetEntry { name, value })
+            })
+            .collect::<Result<Vec<_>, scroll::Error>>()?;
+
+        Ok((
+            Self {
+                reader_type,
+                resource_set_type,
+                version,
+                type_names,
+                entries,
+            },
+            src.len(),
+        ))
+    }
+}
+
+#[test]
+fn resource_set() {
+    fn write_7bit(out: &mut Vec<u8>, mut n: u32) {
+        while n >= 0x80 {
+            out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+
+    fn write_string(out: &mut Vec<u8>, s: &str) {
+        write_7bit(out, s.len() as u32);
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    let mut header = Vec::new();
+    write_string(&mut header, "System.Resources.ResourceReader");
+    write_string(&mut header, "System.Resources.RuntimeResourceSet");
+
+    let mut src = Vec::new();
+    src.extend_from_slice(&RESOURCE_MANAGER_MAGIC.to_le_bytes());
+    src.extend_from_slice(&1i32.to_le_bytes());
+    src.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    src.extend_from_slice(&header);
+
+    src.extend_from_slice(&2i32.to_le_bytes());
+    src.extend_from_slice(&2u32.to_le_bytes());
+    src.extend_from_slice(&0u32.to_le_bytes());
+    while src.len() % 8 != 0 {
+        src.push(b'P');
+    }
+
+    let mut names = Vec::new();
+    let mut data = Vec::new();
+    let mut positions = Vec::new();
+
+    for (name, value) in [
+        ("Greeting", &[0x01, 0x02, b'h', b'i'][..]),
+        ("Answer", &[0x08, 42, 0, 0, 0][..]),
+    ] {
+        positions.push(names.len() as u32);
+        let name = name
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        write_7bit(&mut names, name.len() as u32);
+        names.extend_from_slice(&name);
+        names.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        data.extend_from_slice(value);
+    }
+
+    // hashes
+    src.extend_from_slice(&[0; 8]);
+    for pos in positions {
+        src.extend_from_slice(&pos.to_le_bytes());
+    }
+    let data_section = src.len() + 4 + names.len();
+    src.extend_from_slice(&(data_section as u32).to_le_bytes());
+    src.extend_from_slice(&names);
+    src.extend_from_slice(&data);
+
+    let set: ResourceSet = src.pread_with(0, LE).unwrap();
+
+    assert_eq!(set.reader_type, "System.Resources.ResourceReader");
+    assert_eq!(set.get("Greeting"), Some(&ResourceValue::String("hi")));
+    assert_eq!(set.get("Answer"), Some(&ResourceValue::Int32(42)));
+}
+
+#[test]
+fn manifest_resource_out_of_range() {
+    use super::{GuidIndex, ImageBuilder, ManifestResource, Module};
+
+    let mut builder = ImageBuilder::default();
+    let name = builder.metadata.heap.add_string("Test.dll");
+    builder.metadata.table.module.push(Module {
+        generation: 0,
+        name,
+        mvid: GuidIndex(0),
+        enc_id: GuidIndex(0),
+        env_base_id: GuidIndex(0),
+    });
+    let name = builder.metadata.heap.add_string("Strings.resources");
+    builder
+        .metadata
+        .table
+        .manifest_resource
+        .push(ManifestResource {
+            offset: u32::MAX,
+            flags: ManifestResourceAttributes::PUBLIC,
+            name,
+            implementation: Implementation::FileIndex(FileIndex(0)),
+        });
+
+    let bytes = builder.to_bytes().unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    assert!(image.manifest_resources().is_err());
+    assert!(image.manifest_resource("Strings.resources").is_err());
+}
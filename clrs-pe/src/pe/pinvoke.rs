@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{
+    Heap, Image, ImplMap, MemberForwarded, MetadataTable, MethodDefIndex, PInvokeAttributes,
+    TableIndex,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PInvokeCharSet {
+    NotSpec,
+    Ansi,
+    Unicode,
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PInvokeCallConv {
+    /// `winapi`, stdcall on Windows and cdecl elsewhere
+    PlatformApi,
+    Cdecl,
+    StdCall,
+    ThisCall,
+    FastCall,
+}
+
+impl PInvokeAttributes {
+    pub fn char_set(self) -> PInvokeCharSet {
+        match self & Self::CHARSET_MASK {
+            Self::CHARSET_ANSI => PInvokeCharSet::Ansi,
+            Self::CHARSET_UNICODE => PInvokeCharSet::Unicode,
+            Self::CHARSET_AUTO => PInvokeCharSet::Auto,
+            _ => PInvokeCharSet::NotSpec,
+        }
+    }
+
+    pub fn call_conv(self) -> Option<PInvokeCallConv> {
+        match self & Self::CALL_CONV_MASK {
+            Self::CALL_CONV_PLATFORMAPI => Some(PInvokeCallConv::PlatformApi),
+            Self::CALL_CONV_CDECL => Some(PInvokeCallConv::Cdecl),
+            Self::CALL_CONV_STDCALL => Some(PInvokeCallConv::StdCall),
+            Self::CALL_CONV_THISCALL => Some(PInvokeCallConv::ThisCall),
+            Self::CALL_CONV_FASTCALL => Some(PInvokeCallConv::FastCall),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PInvokeInfo<'a> {
+    pub import_name: &'a str,
+    /// Native library from `ModuleRef`
+    pub module: &'a str,
+    pub call_conv: Option<PInvokeCallConv>,
+    pub char_set: PInvokeCharSet,
+    pub flags: PInvokeAttributes,
+}
+
+impl fmt::Display for PInvokeInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}!{} {:?}",
+            self.module, self.import_name, self.char_set
+        )?;
+
+        if let Some(call_conv) = self.call_conv {
+            write!(f, " {:?}", call_conv)?;
+        }
+
+        if self.flags.contains(PInvokeAttributes::SUPPORTS_LAST_ERROR) {
+            write!(f, " SetLastError")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MethodDefIndex {
+    pub fn resolve_impl_map(self, table: &MetadataTable) -> Option<&ImplMap> {
+        // ImplMap is sorted by member_forwarded
+        let key = MemberForwarded::MethodDefIndex(self).encode();
+        let pos = table
+            .impl_map
+            .binary_search_by_key(&key, |r| r.member_forwarded.encode())
+            .ok()?;
+        table.impl_map.get(pos)
+    }
+
+    pub fn pinvoke_info<'a>(
+        self,
+        table: &MetadataTable,
+        heap: Heap<'a>,
+    ) -> Option<PInvokeInfo<'a>> {
+        let impl_map = self.resolve_impl_map(table)?;
+        let module = impl_map.import_scope.resolve_table(table)?;
+
+        Some(PInvokeInfo {
+            import_name: impl_map.import_name.resolve(heap)?,
+            module: module.name.resolve(heap)?,
+            call_conv: impl_map.mapping_flags.call_conv(),
+            char_set: impl_map.mapping_flags.char_set(),
+            flags: impl_map.mapping_flags,
+        })
+    }
+}
+
+impl<'a> Image<'a> {
+    pub fn pinvokes(&self) -> Vec<(MethodDefIndex, PInvokeInfo<'a>)> {
+        let root = self.metadata_root();
+        let table = &root.metadata_stream.table;
+
+        table
+            .list_method_def()
+            .filter_map(|(index, _)| Some((index, index.pinvoke_info(table, root.heap)?)))
+            .collect()
+    }
+
+    /// Imports grouped by native library
+    pub fn pinvoke_report(&self) -> BTreeMap<&'a str, Vec<(MethodDefIndex, PInvokeInfo<'a>)>> {
+        let mut report = BTreeMap::<_, Vec<_>>::new();
+
+        for (index, info) in self.pinvokes() {
+            report.entry(info.module).or_default().push((index, info));
+        }
+
+        report
+    }
+}
+
+#[test]
+fn pinvoke_flags() {
+    let flags = PInvokeAttributes::CHARSET_UNICODE
+        | PInvokeAttributes::CALL_CONV_STDCALL
+        | PInvokeAttributes::SUPPORTS_LAST_ERROR;
+
+    assert_eq!(flags.char_set(), PInvokeCharSet::Unicode);
+    assert_eq!(flags.call_conv(), Some(PInvokeCallConv::StdCall));
+    assert_eq!(PInvokeAttributes::empty().call_conv(), None);
+}
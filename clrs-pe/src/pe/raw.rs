@@ -5,6 +5,26 @@ mod tables;
 pub use self::indices::*;
 pub use self::signatures::*;
 pub use self::tables::*;
+// both modules define `Param`, the signature keeps the name and the row is `ParamRow`
+pub use self::signatures::Param;
+pub use self::tables::Param as ParamRow;
+
+/// Append `value` to `out`, only for values which fit in 64 bytes like table rows
+pub(crate) fn push_with<T, C: Copy>(
+    out: &mut Vec<u8>,
+    value: T,
+    ctx: C,
+) -> Result<(), scroll::Error>
+where
+    T: scroll::ctx::TryIntoCtx<C, Error = scroll::Error>,
+{
+    use scroll::Pwrite;
+
+    let mut buf = [0; 64];
+    let size = buf.pwrite_with(value, 0, ctx)?;
+    out.extend_from_slice(&buf[..size]);
+    Ok(())
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct PeCtx {
@@ -1,8 +1,12 @@
-use crate::pe::Heap;
+use crate::cil::disasm::Names;
+use crate::pe::{CheckField, DisplayWith, Heap, UserString};
 
 use super::tables::*;
 use super::PeCtx;
-use scroll::{ctx::TryFromCtx, Pread};
+use scroll::{
+    ctx::{TryFromCtx, TryIntoCtx},
+    Pread, Pwrite,
+};
 
 // TODO: 32bit index
 macro_rules! make_single_index {
@@ -17,6 +21,12 @@ macro_rules! make_single_index {
                 }
             }
 
+            impl From<$name> for u32 {
+                fn from(index: $name) -> Self {
+                    index.0
+                }
+            }
+
             impl<'a> TryFromCtx<'a, PeCtx> for $name {
                 type Error = scroll::Error;
 
@@ -25,6 +35,21 @@ macro_rules! make_single_index {
                     Ok((Self(n as u32), 2))
                 }
             }
+
+            impl TryIntoCtx<PeCtx> for $name {
+                type Error = scroll::Error;
+
+                fn try_into_ctx(self, dst: &mut [u8], ctx: PeCtx) -> Result<usize, Self::Error> {
+                    dst.pwrite_with(self.0 as u16, 0, ctx)
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            impl ::serde::Serialize for $name {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_u32(self.0)
+                }
+            }
         )+
     };
 }
@@ -66,6 +91,81 @@ macro_rules! make_coded_index {
             }
         }
 
+        impl $name {
+            /// Encoded value with the tag in the low bits, sorted tables are ordered by this
+            pub fn encode(self) -> u32 {
+                let mut tag = 0;
+
+                $(
+                    if let Self::$ty($ty(index)) = self {
+                        return (index << $tag_size) | tag;
+                    }
+                    #[allow(unused_assignments)]
+                    {
+                        tag += 1;
+                    }
+                )+
+
+                unreachable!()
+            }
+        }
+
+        impl DisplayWith for $name {
+            fn fmt_with(&self, names: &Names, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match self {
+                    $(
+                        Self::$ty(index) => index.fmt_with(names, f),
+                    )+
+                }
+            }
+        }
+
+        /// `{"TypeRefIndex": 3}`
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut variant = 0;
+
+                $(
+                    if let Self::$ty(index) = self {
+                        return serializer.serialize_newtype_variant(
+                            stringify!($name),
+                            variant,
+                            stringify!($ty),
+                            index,
+                        );
+                    }
+                    #[allow(unused_assignments)]
+                    {
+                        variant += 1;
+                    }
+                )+
+
+                unreachable!()
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl crate::pe::ToJson for $name {
+            fn to_json(&self, names: &Names) -> ::serde_json::Value {
+                match self {
+                    $(
+                        Self::$ty(index) => index.to_json(names),
+                    )+
+                }
+            }
+        }
+
+        impl CheckField for $name {
+            fn check_field(&self, table: &MetadataTable, heap: Heap) -> Option<String> {
+                match self {
+                    $(
+                        Self::$ty(index) => index.check_field(table, heap),
+                    )+
+                }
+            }
+        }
+
         impl<'a> TryFromCtx<'a, PeCtx> for $name {
             type Error = scroll::Error;
 
@@ -90,6 +190,14 @@ macro_rules! make_coded_index {
             }
         }
 
+        impl TryIntoCtx<PeCtx> for $name {
+            type Error = scroll::Error;
+
+            fn try_into_ctx(self, dst: &mut [u8], ctx: PeCtx) -> Result<usize, Self::Error> {
+                dst.pwrite_with(self.encode() as u16, 0, ctx)
+            }
+        }
+
         make_coded_index!($($t)*);
     };
     () => {};
@@ -112,7 +220,7 @@ impl StringIndex {
 }
 
 impl UserStringIndex {
-    pub fn resolve<'a>(self, heap: Heap<'a>) -> Option<&'a [u8]> {
+    pub fn resolve<'a>(self, heap: Heap<'a>) -> Option<UserString<'a>> {
         heap.ref_user_string(self.0 as usize)
     }
 }
@@ -123,6 +231,52 @@ impl BlobIndex {
     }
 }
 
+impl GuidIndex {
+    pub fn resolve<'a>(self, heap: Heap<'a>) -> Option<&'a [u8; 16]> {
+        heap.ref_guid(self.0 as usize)
+    }
+}
+
+impl CheckField for NotUsed1Index {}
+impl CheckField for NotUsed2Index {}
+impl CheckField for NotUsed3Index {}
+
+impl CheckField for StringIndex {
+    fn check_field(&self, _table: &MetadataTable, heap: Heap) -> Option<String> {
+        heap.check_string(self.0 as usize)
+    }
+}
+
+impl CheckField for UserStringIndex {
+    fn check_field(&self, _table: &MetadataTable, heap: Heap) -> Option<String> {
+        if self.0 != 0 && self.resolve(heap).is_none() {
+            Some(format!("#US offset {:#x} is out of heap", self.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl CheckField for BlobIndex {
+    fn check_field(&self, _table: &MetadataTable, heap: Heap) -> Option<String> {
+        if self.0 != 0 && self.resolve(heap).is_none() {
+            Some(format!("#Blob offset {:#x} is out of heap", self.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl CheckField for GuidIndex {
+    fn check_field(&self, _table: &MetadataTable, heap: Heap) -> Option<String> {
+        if self.0 != 0 && self.resolve(heap).is_none() {
+            Some(format!("#GUID index {} is out of heap", self.0))
+        } else {
+            None
+        }
+    }
+}
+
 make_coded_index! {
     (TypeDefOrRef, 2, [
         TypeDefIndex,
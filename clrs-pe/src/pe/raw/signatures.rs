@@ -11,50 +11,108 @@ impl U {
         match self.0 {
             0x00..=0x7F => 1,
             0x80..=0x3FFF => 2,
-            0x4000..=0x3FFF_FFFF => 4,
+            0x4000..=0x1FFF_FFFF => 4,
             _ => unreachable!(),
         }
     }
+
+    pub fn write(self, out: &mut Vec<u8>) {
+        write_compressed(self.0, self.byte_size(), out);
+    }
+}
+
+/// Write `n` as a compressed integer of `size` bytes
+fn write_compressed(n: u32, size: usize, out: &mut Vec<u8>) {
+    match size {
+        1 => out.push(n as u8),
+        2 => out.extend_from_slice(&(n as u16 | 0x8000).to_be_bytes()),
+        _ => out.extend_from_slice(&(n | 0xC000_0000).to_be_bytes()),
+    }
 }
 
 impl<'a> TryFromCtx<'a, Endian> for U {
     type Error = scroll::Error;
 
-    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
-        let first: u8 = src.pread_with(0, ctx)?;
+    // II.23.2 compressed integers are always big endian
+    fn try_from_ctx(src: &'a [u8], _: Endian) -> Result<(Self, usize), Self::Error> {
+        let first: u8 = src.pread_with(0, scroll::BE)?;
 
-        if let 0x00..=0x7F = first {
-            return Ok((U(first as _), 1));
+        match first {
+            0x00..=0x7F => Ok((U(first as _), 1)),
+            0x80..=0xBF => {
+                let n: u16 = src.pread_with(0, scroll::BE)?;
+                Ok((U((n & 0x3FFF) as _), 2))
+            }
+            0xC0..=0xDF => {
+                let n: u32 = src.pread_with(0, scroll::BE)?;
+                Ok((U(n & 0x1FFF_FFFF), 4))
+            }
+            _ => Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid compressed integer",
+            }),
         }
+    }
+}
 
-        let second: u16 = src.pread_with(0, ctx)?;
+/// Compressed Int32
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[repr(transparent)]
+pub struct I(pub i32);
 
-        if let 0x8080..=0xBFFF = second {
-            return Ok((U((second & !0x8000) as _), 2));
-        }
+impl<'a> TryFromCtx<'a, Endian> for I {
+    type Error = scroll::Error;
 
-        let last: u32 = src.pread_with(0, ctx)?;
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let (n, size): (U, usize) = U::try_from_ctx(src, ctx)?;
+        // sign bit is rotated into the least significant bit
+        let bits = match size {
+            1 => 7,
+            2 => 14,
+            _ => 29,
+        };
+        let value = (n.0 >> 1) as i32;
+        let value = if n.0 & 1 != 0 {
+            value - (1 << (bits - 1))
+        } else {
+            value
+        };
+        Ok((I(value), size))
+    }
+}
 
-        Ok((U((last & !0xC000_0000) as _), 4))
+impl I {
+    pub fn write(self, out: &mut Vec<u8>) {
+        let (bits, size) = match self.0 {
+            -0x40..=0x3F => (7, 1),
+            -0x2000..=0x1FFF => (14, 2),
+            _ => (29, 4),
+        };
+        // sign bit is rotated into the least significant bit
+        let n = if self.0 < 0 {
+            (((self.0 + (1 << (bits - 1))) as u32) << 1) | 1
+        } else {
+            (self.0 as u32) << 1
+        };
+        write_compressed(n, size, out);
     }
 }
 
 #[test]
 fn decode_num() -> Result<(), scroll::Error> {
+    assert_eq!([0x03u8].pread_with::<U>(0, Endian::Little)?, U(0x03));
+    assert_eq!([0x80u8, 0x80].pread_with::<U>(0, Endian::Little)?, U(0x80));
     assert_eq!(
-        0x03u8.to_ne_bytes().pread_with::<U>(0, Endian::Little)?,
-        U(0x03)
-    );
-    assert_eq!(
-        0x8080u16.to_ne_bytes().pread_with::<U>(0, Endian::Little)?,
-        U(0x80)
+        [0xAEu8, 0x57].pread_with::<U>(0, Endian::Little)?,
+        U(0x2E57)
     );
     assert_eq!(
-        0xDFFF_FFFFu32
-            .to_ne_bytes()
-            .pread_with::<U>(0, Endian::Little)?,
+        [0xDFu8, 0xFF, 0xFF, 0xFF].pread_with::<U>(0, Endian::Little)?,
         U(0x1FFF_FFFF)
     );
+    assert_eq!([0x7Bu8].pread_with::<I>(0, Endian::Little)?, I(-3));
+    assert_eq!([0x06u8].pread_with::<I>(0, Endian::Little)?, I(3));
+    assert_eq!([0x80u8, 0x01].pread_with::<I>(0, Endian::Little)?, I(-8192));
 
     Ok(())
 }
@@ -72,6 +130,8 @@ bitflags_tryctx! {
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MethodDefSig {
     pub calling_convension: MethodCallingConvension,
+    /// Zero unless `MethodCallingConvension::GENERIC`
+    pub generic_param_count: u32,
     pub ret: RetType,
     pub params: Vec<Param>,
 }
@@ -81,7 +141,12 @@ impl<'a> TryFromCtx<'a, Endian> for MethodDefSig {
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let calling_convension = src.gread_with(offset, ctx)?;
+        let calling_convension: MethodCallingConvension = src.gread_with(offset, ctx)?;
+        let generic_param_count = if calling_convension.contains(MethodCallingConvension::GENERIC) {
+            src.gread_with::<U>(offset, ctx)?.0
+        } else {
+            0
+        };
         let param_count: U = src.gread_with(offset, ctx)?;
         let ret = src.gread_with(offset, ctx)?;
         let params = std::iter::repeat_with(|| src.gread_with(offset, ctx))
@@ -91,6 +156,7 @@ impl<'a> TryFromCtx<'a, Endian> for MethodDefSig {
         Ok((
             Self {
                 calling_convension,
+                generic_param_count,
                 ret,
                 params,
             },
@@ -99,8 +165,77 @@ impl<'a> TryFromCtx<'a, Endian> for MethodDefSig {
     }
 }
 
+impl MethodCallingConvension {
+    /// Calling convention kind is in the low nibble
+    pub fn is_vararg(self) -> bool {
+        self.bits() & 0x0F == Self::VAR_ARG.bits()
+    }
+}
+
+/// II.23.2.2, call site signature of a `MemberRef` or `StandAloneSig`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MethodRefSig {
+    pub method: MethodDefSig,
+    /// Arguments after the sentinel of a vararg call site
+    pub varargs: Vec<Param>,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for MethodRefSig {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let calling_convension: MethodCallingConvension = src.gread_with(offset, ctx)?;
+        let generic_param_count = if calling_convension.contains(MethodCallingConvension::GENERIC) {
+            src.gread_with::<U>(offset, ctx)?.0
+        } else {
+            0
+        };
+        let param_count: U = src.gread_with(offset, ctx)?;
+        let ret = src.gread_with(offset, ctx)?;
+
+        // the sentinel isn't counted in `param_count`
+        let mut params = Vec::new();
+        let mut varargs = Vec::new();
+        let mut sentinel = false;
+        for _ in 0..param_count.0 {
+            if calling_convension.is_vararg()
+                && !sentinel
+                && src.get(*offset) == Some(&(ElementType::Sentinel as u8))
+            {
+                sentinel = true;
+                *offset += 1;
+            }
+            let param = src.gread_with(offset, ctx)?;
+            if sentinel {
+                varargs.push(param);
+            } else {
+                params.push(param);
+            }
+        }
+
+        let method = MethodDefSig {
+            calling_convension,
+            generic_param_count,
+            ret,
+            params,
+        };
+        Ok((Self { method, varargs }, *offset))
+    }
+}
+
+impl MethodRefSig {
+    /// Signature of everything pushed by the call, the varargs follow the fixed parameters
+    pub fn call_site(self) -> MethodDefSig {
+        let mut method = self.method;
+        method.params.extend(self.varargs);
+        method
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FieldSig {
+    pub mods: Vec<CustomMod>,
     pub ty: Type,
 }
 
@@ -109,8 +244,16 @@ impl<'a> TryFromCtx<'a, Endian> for FieldSig {
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
+        let prolog: u8 = src.gread_with(offset, ctx)?;
+        if prolog != 0x06 {
+            return Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid FieldSig prolog",
+            });
+        }
+        let mods = read_custom_mods(src, offset, ctx)?;
         let ty = src.gread_with(offset, ctx)?;
-        Ok((Self { ty }, *offset))
+        Ok((Self { mods, ty }, *offset))
     }
 }
 
@@ -125,10 +268,12 @@ impl<'a> TryFromCtx<'a, Endian> for TypeDefOrRefOrSpecEncoded {
     type Error = scroll::Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
-        let raw: u32 = src.pread_with(0, ctx)?;
+        // II.23.2.8 compressed with the table tag in the two least significant bits
+        let offset = &mut 0;
+        let raw: U = src.gread_with(offset, ctx)?;
 
-        let tag = raw & 0xFF00_0000;
-        let row = raw & 0x00FF_FFFF;
+        let tag = raw.0 & 0b11;
+        let row = raw.0 >> 2;
 
         let s = match tag {
             0x00 => Self::TypeDef(row.into()),
@@ -136,13 +281,13 @@ impl<'a> TryFromCtx<'a, Endian> for TypeDefOrRefOrSpecEncoded {
             0x02 => Self::TypeSpec(row.into()),
             _ => {
                 return Err(scroll::Error::BadInput {
-                    size: 4,
+                    size: *offset,
                     msg: "Invalid TypeDefOrRefOrSpecEncoded tag",
                 })
             }
         };
 
-        Ok((s, 4))
+        Ok((s, *offset))
     }
 }
 
@@ -174,11 +319,39 @@ impl<'a> TryFromCtx<'a, Endian> for CustomMod {
     }
 }
 
+/// Read `CustomMod*` prefix
+fn read_custom_mods(
+    src: &[u8],
+    offset: &mut usize,
+    ctx: Endian,
+) -> Result<Vec<CustomMod>, scroll::Error> {
+    let mut mods = Vec::new();
+
+    while let Some(&b) = src.get(*offset) {
+        match ElementType::from_n(b) {
+            Some(ElementType::CmodOpt) | Some(ElementType::CmodReqd) => {
+                mods.push(src.gread_with(offset, ctx)?);
+            }
+            _ => break,
+        }
+    }
+
+    Ok(mods)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RetType {
-    Type { byref: bool, ty: Type },
-    Void,
-    TypedByref,
+    Type {
+        mods: Vec<CustomMod>,
+        byref: bool,
+        ty: Type,
+    },
+    Void {
+        mods: Vec<CustomMod>,
+    },
+    TypedByref {
+        mods: Vec<CustomMod>,
+    },
 }
 
 impl<'a> TryFromCtx<'a, Endian> for RetType {
@@ -186,19 +359,23 @@ impl<'a> TryFromCtx<'a, Endian> for RetType {
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
+        let mods = read_custom_mods(src, offset, ctx)?;
+        let start = *offset;
         let ty: ElementType = src.gread_with(offset, ctx)?;
 
         let s = match ty {
-            ElementType::Void => Self::Void,
-            ElementType::TypedByref => Self::TypedByref,
+            ElementType::Void => Self::Void { mods },
+            ElementType::TypedByref => Self::TypedByref { mods },
             ElementType::Byref => Self::Type {
+                mods,
                 byref: true,
                 ty: src.gread_with(offset, ctx)?,
             },
             _ => {
                 // Reset offset
-                *offset = 0;
+                *offset = start;
                 Self::Type {
+                    mods,
                     byref: false,
                     ty: src.gread_with(offset, ctx)?,
                 }
@@ -211,8 +388,14 @@ impl<'a> TryFromCtx<'a, Endian> for RetType {
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Param {
-    Type { byref: bool, ty: Type },
-    TypedByref,
+    Type {
+        mods: Vec<CustomMod>,
+        byref: bool,
+        ty: Type,
+    },
+    TypedByref {
+        mods: Vec<CustomMod>,
+    },
 }
 
 impl<'a> TryFromCtx<'a, Endian> for Param {
@@ -220,18 +403,22 @@ impl<'a> TryFromCtx<'a, Endian> for Param {
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
+        let mods = read_custom_mods(src, offset, ctx)?;
+        let start = *offset;
         let ty: ElementType = src.gread_with(offset, ctx)?;
 
         let s = match ty {
-            ElementType::TypedByref => Self::TypedByref,
+            ElementType::TypedByref => Self::TypedByref { mods },
             ElementType::Byref => Self::Type {
+                mods,
                 byref: true,
                 ty: src.gread_with(offset, ctx)?,
             },
             _ => {
                 // Reset offset
-                *offset = 0;
+                *offset = start;
                 Self::Type {
+                    mods,
                     byref: false,
                     ty: src.gread_with(offset, ctx)?,
                 }
@@ -261,15 +448,38 @@ pub enum Type {
     Object,
     String,
 
+    ValueType(TypeDefOrRefOrSpecEncoded),
+    Class(TypeDefOrRefOrSpecEncoded),
+
     SzArray {
         element_ty: Box<Type>,
         mods: Vec<CustomMod>,
     },
+    Array {
+        element_ty: Box<Type>,
+        shape: ArrayShape,
+    },
+    /// `None` is `void*`
+    Ptr {
+        ty: Option<Box<Type>>,
+        mods: Vec<CustomMod>,
+    },
+    FnPtr(Box<MethodDefSig>),
+    GenericInst {
+        /// `VALUETYPE` instead of `CLASS`
+        is_value_type: bool,
+        ty: TypeDefOrRefOrSpecEncoded,
+        args: Vec<Type>,
+    },
 
+    /// Generic parameter of the type
     Var {
-        count: U,
+        number: U,
+    },
+    /// Generic parameter of the method
+    MVar {
+        number: U,
     },
-    // TODO
 }
 
 impl<'a> TryFromCtx<'a, Endian> for Type {
@@ -296,22 +506,590 @@ impl<'a> TryFromCtx<'a, Endian> for Type {
             ElementType::Object => Self::Object,
             ElementType::String => Self::String,
             ElementType::SzArray => Self::SzArray {
-                // TODO
-                mods: vec![],
+                mods: read_custom_mods(src, offset, ctx)?,
                 element_ty: Box::new(src.gread_with(offset, ctx)?),
             },
+            ElementType::Array => Self::Array {
+                element_ty: Box::new(src.gread_with(offset, ctx)?),
+                shape: src.gread_with(offset, ctx)?,
+            },
+            ElementType::Ptr => {
+                let mods = read_custom_mods(src, offset, ctx)?;
+                let ty = if src.get(*offset) == Some(&(ElementType::Void as u8)) {
+                    *offset += 1;
+                    None
+                } else {
+                    Some(Box::new(src.gread_with(offset, ctx)?))
+                };
+                Self::Ptr { ty, mods }
+            }
+            ElementType::FnPtr => Self::FnPtr(Box::new(src.gread_with(offset, ctx)?)),
             ElementType::Var => Self::Var {
-                count: src.gread_with(offset, ctx)?,
+                number: src.gread_with(offset, ctx)?,
             },
-            // TODO
-            ElementType::ValueType => Self::I4,
-            other => todo!("{:?}", other),
+            ElementType::MVar => Self::MVar {
+                number: src.gread_with(offset, ctx)?,
+            },
+            ElementType::GenericInst => {
+                let is_value_type = match src.gread_with(offset, ctx)? {
+                    ElementType::ValueType => true,
+                    ElementType::Class => false,
+                    _ => {
+                        return Err(scroll::Error::BadInput {
+                            size: 1,
+                            msg: "Invalid ElementType for GenericInst",
+                        })
+                    }
+                };
+                let ty = src.gread_with(offset, ctx)?;
+                let count: U = src.gread_with(offset, ctx)?;
+                let args = std::iter::repeat_with(|| src.gread_with(offset, ctx))
+                    .take(count.0 as usize)
+                    .collect::<Result<_, _>>()?;
+                Self::GenericInst {
+                    is_value_type,
+                    ty,
+                    args,
+                }
+            }
+            ElementType::ValueType => Self::ValueType(src.gread_with(offset, ctx)?),
+            ElementType::Class => Self::Class(src.gread_with(offset, ctx)?),
+            _ => {
+                return Err(scroll::Error::BadInput {
+                    size: 1,
+                    msg: "Unsupported ElementType for Type",
+                })
+            }
         };
 
         Ok((s, *offset))
     }
 }
 
+impl Type {
+    /// Replace `Var` and `MVar` with the given type and method arguments.
+    /// Parameters without an argument are kept as is.
+    pub fn substitute(&self, type_args: &[Type], method_args: &[Type]) -> Type {
+        let sub = |ty: &Type| ty.substitute(type_args, method_args);
+
+        match self {
+            Type::Var { number } => type_args
+                .get(number.0 as usize)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            Type::MVar { number } => method_args
+                .get(number.0 as usize)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            Type::SzArray { element_ty, mods } => Type::SzArray {
+                element_ty: Box::new(sub(element_ty)),
+                mods: mods.clone(),
+            },
+            Type::Array { element_ty, shape } => Type::Array {
+                element_ty: Box::new(sub(element_ty)),
+                shape: shape.clone(),
+            },
+            Type::Ptr { ty, mods } => Type::Ptr {
+                ty: ty.as_ref().map(|ty| Box::new(sub(ty))),
+                mods: mods.clone(),
+            },
+            Type::FnPtr(sig) => Type::FnPtr(Box::new(sig.substitute(type_args, method_args))),
+            Type::GenericInst {
+                is_value_type,
+                ty,
+                args,
+            } => Type::GenericInst {
+                is_value_type: *is_value_type,
+                ty: ty.clone(),
+                args: args.iter().map(sub).collect(),
+            },
+            other => other.clone(),
+        }
+    }
+}
+
+impl MethodDefSig {
+    pub fn substitute(&self, type_args: &[Type], method_args: &[Type]) -> MethodDefSig {
+        MethodDefSig {
+            calling_convension: self.calling_convension,
+            generic_param_count: self.generic_param_count,
+            ret: match &self.ret {
+                RetType::Type { mods, byref, ty } => RetType::Type {
+                    mods: mods.clone(),
+                    byref: *byref,
+                    ty: ty.substitute(type_args, method_args),
+                },
+                other => other.clone(),
+            },
+            params: self
+                .params
+                .iter()
+                .map(|p| match p {
+                    Param::Type { mods, byref, ty } => Param::Type {
+                        mods: mods.clone(),
+                        byref: *byref,
+                        ty: ty.substitute(type_args, method_args),
+                    },
+                    other => other.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// II.23.2.5
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PropertySig {
+    pub has_this: bool,
+    pub mods: Vec<CustomMod>,
+    pub ty: Type,
+    pub params: Vec<Param>,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for PropertySig {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let prolog: u8 = src.gread_with(offset, ctx)?;
+        if prolog & !0x20 != 0x08 {
+            return Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid PropertySig prolog",
+            });
+        }
+
+        let count: U = src.gread_with(offset, ctx)?;
+        let mods = read_custom_mods(src, offset, ctx)?;
+        let ty = src.gread_with(offset, ctx)?;
+        let params = std::iter::repeat_with(|| src.gread_with(offset, ctx))
+            .take(count.0 as usize)
+            .collect::<Result<_, _>>()?;
+
+        Ok((
+            Self {
+                has_this: prolog & 0x20 != 0,
+                mods,
+                ty,
+                params,
+            },
+            *offset,
+        ))
+    }
+}
+
+/// II.23.2.6
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LocalVarSig {
+    pub locals: Vec<LocalVar>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LocalVar {
+    Type {
+        mods: Vec<CustomMod>,
+        pinned: bool,
+        byref: bool,
+        ty: Type,
+    },
+    TypedByref,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for LocalVarSig {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let prolog: u8 = src.gread_with(offset, ctx)?;
+        if prolog != 0x07 {
+            return Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid LocalVarSig prolog",
+            });
+        }
+
+        let count: U = src.gread_with(offset, ctx)?;
+        let locals = std::iter::repeat_with(|| src.gread_with(offset, ctx))
+            .take(count.0 as usize)
+            .collect::<Result<_, _>>()?;
+
+        Ok((Self { locals }, *offset))
+    }
+}
+
+impl<'a> TryFromCtx<'a, Endian> for LocalVar {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        if src.first() == Some(&(ElementType::TypedByref as u8)) {
+            return Ok((Self::TypedByref, 1));
+        }
+
+        let mods = read_custom_mods(src, offset, ctx)?;
+        let mut pinned = false;
+        while src.get(*offset) == Some(&(ElementType::Pinned as u8)) {
+            pinned = true;
+            *offset += 1;
+        }
+        let byref = src.get(*offset) == Some(&(ElementType::Byref as u8));
+        if byref {
+            *offset += 1;
+        }
+        let ty = src.gread_with(offset, ctx)?;
+
+        Ok((
+            Self::Type {
+                mods,
+                pinned,
+                byref,
+                ty,
+            },
+            *offset,
+        ))
+    }
+}
+
+/// II.23.2.15
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MethodSpecSig {
+    pub args: Vec<Type>,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for MethodSpecSig {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let prolog: u8 = src.gread_with(offset, ctx)?;
+        if prolog != 0x0A {
+            return Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid MethodSpec prolog",
+            });
+        }
+
+        let count: U = src.gread_with(offset, ctx)?;
+        let args = std::iter::repeat_with(|| src.gread_with(offset, ctx))
+            .take(count.0 as usize)
+            .collect::<Result<_, _>>()?;
+
+        Ok((Self { args }, *offset))
+    }
+}
+
+/// II.23.2.13
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArrayShape {
+    pub rank: u32,
+    pub sizes: Vec<u32>,
+    pub lo_bounds: Vec<i32>,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for ArrayShape {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let rank: U = src.gread_with(offset, ctx)?;
+        let num_sizes: U = src.gread_with(offset, ctx)?;
+        let sizes = std::iter::repeat_with(|| src.gread_with::<U>(offset, ctx).map(|n| n.0))
+            .take(num_sizes.0 as usize)
+            .collect::<Result<_, _>>()?;
+        let num_lo_bounds: U = src.gread_with(offset, ctx)?;
+        let lo_bounds = std::iter::repeat_with(|| src.gread_with::<I>(offset, ctx).map(|n| n.0))
+            .take(num_lo_bounds.0 as usize)
+            .collect::<Result<_, _>>()?;
+
+        Ok((
+            Self {
+                rank: rank.0,
+                sizes,
+                lo_bounds,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl TypeDefOrRefOrSpecEncoded {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let (tag, row) = match self {
+            Self::TypeDef(index) => (0, index.0),
+            Self::TypeRef(index) => (1, index.0),
+            Self::TypeSpec(index) => (2, index.0),
+        };
+        U((row << 2) | tag).write(out);
+    }
+}
+
+impl CustomMod {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let (ty, encoded) = match self {
+            Self::Opt(encoded) => (ElementType::CmodOpt, encoded),
+            Self::Reqd(encoded) => (ElementType::CmodReqd, encoded),
+        };
+        out.push(ty as u8);
+        encoded.write(out);
+    }
+}
+
+fn write_custom_mods(mods: &[CustomMod], out: &mut Vec<u8>) {
+    for m in mods {
+        m.write(out);
+    }
+}
+
+impl Type {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let simple = match self {
+            Type::Boolean => Some(ElementType::Boolean),
+            Type::Char => Some(ElementType::Char),
+            Type::I1 => Some(ElementType::I1),
+            Type::U1 => Some(ElementType::U1),
+            Type::I2 => Some(ElementType::I2),
+            Type::U2 => Some(ElementType::U2),
+            Type::I4 => Some(ElementType::I4),
+            Type::U4 => Some(ElementType::U4),
+            Type::I8 => Some(ElementType::I8),
+            Type::U8 => Some(ElementType::U8),
+            Type::R4 => Some(ElementType::R4),
+            Type::R8 => Some(ElementType::R8),
+            Type::I => Some(ElementType::I),
+            Type::U => Some(ElementType::U),
+            Type::Object => Some(ElementType::Object),
+            Type::String => Some(ElementType::String),
+            _ => None,
+        };
+        if let Some(ty) = simple {
+            out.push(ty as u8);
+            return;
+        }
+
+        match self {
+            Type::ValueType(ty) => {
+                out.push(ElementType::ValueType as u8);
+                ty.write(out);
+            }
+            Type::Class(ty) => {
+                out.push(ElementType::Class as u8);
+                ty.write(out);
+            }
+            Type::SzArray { element_ty, mods } => {
+                out.push(ElementType::SzArray as u8);
+                write_custom_mods(mods, out);
+                element_ty.write(out);
+            }
+            Type::Array { element_ty, shape } => {
+                out.push(ElementType::Array as u8);
+                element_ty.write(out);
+                shape.write(out);
+            }
+            Type::Ptr { ty, mods } => {
+                out.push(ElementType::Ptr as u8);
+                write_custom_mods(mods, out);
+                match ty {
+                    Some(ty) => ty.write(out),
+                    None => out.push(ElementType::Void as u8),
+                }
+            }
+            Type::FnPtr(sig) => {
+                out.push(ElementType::FnPtr as u8);
+                sig.write(out);
+            }
+            Type::GenericInst {
+                is_value_type,
+                ty,
+                args,
+            } => {
+                out.push(ElementType::GenericInst as u8);
+                out.push(if *is_value_type {
+                    ElementType::ValueType as u8
+                } else {
+                    ElementType::Class as u8
+                });
+                ty.write(out);
+                U(args.len() as u32).write(out);
+                for arg in args {
+                    arg.write(out);
+                }
+            }
+            Type::Var { number } => {
+                out.push(ElementType::Var as u8);
+                number.write(out);
+            }
+            Type::MVar { number } => {
+                out.push(ElementType::MVar as u8);
+                number.write(out);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Blob of a TypeSpec
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+}
+
+impl RetType {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            RetType::Void { mods } => {
+                write_custom_mods(mods, out);
+                out.push(ElementType::Void as u8);
+            }
+            RetType::TypedByref { mods } => {
+                write_custom_mods(mods, out);
+                out.push(ElementType::TypedByref as u8);
+            }
+            RetType::Type { mods, byref, ty } => {
+                write_custom_mods(mods, out);
+                if *byref {
+                    out.push(ElementType::Byref as u8);
+                }
+                ty.write(out);
+            }
+        }
+    }
+}
+
+impl Param {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Param::TypedByref { mods } => {
+                write_custom_mods(mods, out);
+                out.push(ElementType::TypedByref as u8);
+            }
+            Param::Type { mods, byref, ty } => {
+                write_custom_mods(mods, out);
+                if *byref {
+                    out.push(ElementType::Byref as u8);
+                }
+                ty.write(out);
+            }
+        }
+    }
+}
+
+impl MethodDefSig {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.calling_convension.bits());
+        if self
+            .calling_convension
+            .contains(MethodCallingConvension::GENERIC)
+        {
+            U(self.generic_param_count).write(out);
+        }
+        U(self.params.len() as u32).write(out);
+        self.ret.write(out);
+        for param in self.params.iter() {
+            param.write(out);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+}
+
+impl MethodRefSig {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let method = &self.method;
+        out.push(method.calling_convension.bits());
+        if method
+            .calling_convension
+            .contains(MethodCallingConvension::GENERIC)
+        {
+            U(method.generic_param_count).write(out);
+        }
+        U((method.params.len() + self.varargs.len()) as u32).write(out);
+        method.ret.write(out);
+        for param in method.params.iter() {
+            param.write(out);
+        }
+        if !self.varargs.is_empty() {
+            out.push(ElementType::Sentinel as u8);
+        }
+        for param in self.varargs.iter() {
+            param.write(out);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+}
+
+impl FieldSig {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0x06];
+        write_custom_mods(&self.mods, &mut out);
+        self.ty.write(&mut out);
+        out
+    }
+}
+
+impl LocalVarSig {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0x07];
+        U(self.locals.len() as u32).write(&mut out);
+        for local in self.locals.iter() {
+            match local {
+                LocalVar::TypedByref => out.push(ElementType::TypedByref as u8),
+                LocalVar::Type {
+                    mods,
+                    pinned,
+                    byref,
+                    ty,
+                } => {
+                    write_custom_mods(mods, &mut out);
+                    if *pinned {
+                        out.push(ElementType::Pinned as u8);
+                    }
+                    if *byref {
+                        out.push(ElementType::Byref as u8);
+                    }
+                    ty.write(&mut out);
+                }
+            }
+        }
+        out
+    }
+}
+
+impl MethodSpecSig {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0x0A];
+        U(self.args.len() as u32).write(&mut out);
+        for arg in self.args.iter() {
+            arg.write(&mut out);
+        }
+        out
+    }
+}
+
+impl ArrayShape {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        U(self.rank).write(out);
+        U(self.sizes.len() as u32).write(out);
+        for size in self.sizes.iter() {
+            U(*size).write(out);
+        }
+        U(self.lo_bounds.len() as u32).write(out);
+        for lo in self.lo_bounds.iter() {
+            I(*lo).write(out);
+        }
+    }
+}
+
 #[test]
 fn signature_main() {
     let sig: MethodDefSig = [
@@ -327,8 +1105,9 @@ fn signature_main() {
     assert_eq!(
         sig,
         MethodDefSig {
-            ret: RetType::Void,
+            ret: RetType::Void { mods: vec![] },
             params: vec![Param::Type {
+                mods: vec![],
                 byref: false,
                 ty: Type::SzArray {
                     element_ty: Box::new(Type::String),
@@ -336,6 +1115,173 @@ fn signature_main() {
                 },
             },],
             calling_convension: MethodCallingConvension::DEFAULT,
+            generic_param_count: 0,
+        }
+    );
+}
+
+#[test]
+fn signature_value_type_field() {
+    let sig: FieldSig = [
+        0x06, // field
+        0x11, // value type
+        0x09, // TypeRef row 2
+    ]
+    .pread_with(0, scroll::LE)
+    .unwrap();
+
+    assert_eq!(
+        sig,
+        FieldSig {
+            mods: vec![],
+            ty: Type::ValueType(TypeDefOrRefOrSpecEncoded::TypeRef(TypeRefIndex(2))),
+        }
+    );
+}
+
+#[test]
+fn signature_generic_substitute() {
+    // static !!0 M<T>(List<!0>)
+    let sig: MethodDefSig = [
+        0x10, // generic
+        1,    // one generic param
+        1,    // one param
+        0x1e, 0x00, // !!0
+        0x15, 0x12, 0x05, // class TypeRef row 1
+        1,    // one arg
+        0x13, 0x00, // !0
+    ]
+    .pread_with(0, scroll::LE)
+    .unwrap();
+
+    assert_eq!(sig.generic_param_count, 1);
+
+    let sig = sig.substitute(&[Type::I4], &[Type::String]);
+
+    assert_eq!(
+        sig.ret,
+        RetType::Type {
+            mods: vec![],
+            byref: false,
+            ty: Type::String,
         }
     );
+    assert_eq!(
+        sig.params,
+        [Param::Type {
+            mods: vec![],
+            byref: false,
+            ty: Type::GenericInst {
+                is_value_type: false,
+                ty: TypeDefOrRefOrSpecEncoded::TypeRef(TypeRefIndex(1)),
+                args: vec![Type::I4],
+            },
+        }]
+    );
+}
+
+#[test]
+fn signature_locals() {
+    let sig: LocalVarSig = [
+        0x07, // local sig
+        2,    // two locals
+        0x08, // int32
+        0x45, 0x10, 0x0e, // pinned string&
+    ]
+    .pread_with(0, scroll::LE)
+    .unwrap();
+
+    assert_eq!(
+        sig.locals,
+        [
+            LocalVar::Type {
+                mods: vec![],
+                pinned: false,
+                byref: false,
+                ty: Type::I4,
+            },
+            LocalVar::Type {
+                mods: vec![],
+                pinned: true,
+                byref: true,
+                ty: Type::String,
+            },
+        ]
+    );
+}
+
+#[test]
+fn signature_encode() {
+    let method = [0x10, 1, 1, 0x1e, 0x00, 0x15, 0x12, 0x05, 1, 0x13, 0x00];
+    let sig: MethodDefSig = method.pread_with(0, scroll::LE).unwrap();
+    assert_eq!(sig.to_bytes(), method);
+
+    let locals = [0x07, 2, 0x08, 0x45, 0x10, 0x0e];
+    let sig: LocalVarSig = locals.pread_with(0, scroll::LE).unwrap();
+    assert_eq!(sig.to_bytes(), locals);
+
+    for n in [0, -3, 3, -64, 63, -8192, 8191, -8193, 1 << 20] {
+        let mut out = Vec::new();
+        I(n).write(&mut out);
+        assert_eq!(out.pread_with::<I>(0, Endian::Little).unwrap(), I(n));
+    }
+}
+
+#[test]
+fn signature_vararg_call_site() {
+    // vararg void M(int32, ..., string)
+    let bytes = [0x05, 2, 0x01, 0x08, 0x41, 0x0E];
+    let sig: MethodRefSig = bytes.pread_with(0, scroll::LE).unwrap();
+
+    let param = |ty| Param::Type {
+        mods: vec![],
+        byref: false,
+        ty,
+    };
+    assert_eq!(sig.method.params, vec![param(Type::I4)]);
+    assert_eq!(sig.varargs, vec![param(Type::String)]);
+    assert!(bytes.pread_with::<MethodDefSig>(0, scroll::LE).is_err());
+    assert_eq!(sig.to_bytes(), bytes);
+
+    // the sentinel only means something in a vararg signature
+    assert!([0x00u8, 2, 0x01, 0x08, 0x41, 0x0E]
+        .pread_with::<MethodRefSig>(0, scroll::LE)
+        .is_err());
+}
+
+#[test]
+fn signature_custom_mods() {
+    // volatile int32 field
+    let field = [0x06, 0x1F, 0x05, 0x08];
+    let sig: FieldSig = field.pread_with(0, scroll::LE).unwrap();
+    let volatile = CustomMod::Reqd(TypeDefOrRefOrSpecEncoded::TypeRef(TypeRefIndex(1)));
+    assert_eq!(sig.mods, [volatile]);
+    assert_eq!(sig.to_bytes(), field);
+
+    // instance void modreq(TypeRef 2) (int32& modopt(TypeRef 1))
+    let method = [0x20, 1, 0x1F, 0x09, 0x01, 0x20, 0x05, 0x10, 0x08];
+    let sig: MethodDefSig = method.pread_with(0, scroll::LE).unwrap();
+    assert_eq!(
+        sig.ret,
+        RetType::Void {
+            mods: vec![CustomMod::Reqd(TypeDefOrRefOrSpecEncoded::TypeRef(
+                TypeRefIndex(2)
+            ))],
+        }
+    );
+    assert_eq!(
+        sig.params,
+        [Param::Type {
+            mods: vec![CustomMod::Opt(TypeDefOrRefOrSpecEncoded::TypeRef(
+                TypeRefIndex(1)
+            ))],
+            byref: true,
+            ty: Type::I4,
+        }]
+    );
+    assert_eq!(sig.to_bytes(), method);
+
+    let locals = [0x07, 1, 0x1F, 0x05, 0x45, 0x10, 0x08];
+    let sig: LocalVarSig = locals.pread_with(0, scroll::LE).unwrap();
+    assert_eq!(sig.to_bytes(), locals);
 }
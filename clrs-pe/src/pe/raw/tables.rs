@@ -1,10 +1,13 @@
 use crate::{
-    cil::MethodBody,
-    pe::{Heap, Image},
+    cil::{disasm::Names, MethodBody},
+    pe::{CheckField, CheckRow, DisplayWith, Heap, Image},
 };
 
-use super::{indices::*, FieldSig, MethodDefSig, PeCtx};
-use clrs_derive::{make_table, ClrPread};
+use super::{
+    indices::*, push_with, FieldSig, LocalVarSig, MethodDefSig, MethodRefSig, MethodSpecSig, PeCtx,
+    Type, TypeDefOrRefOrSpecEncoded,
+};
+use clrs_derive::{make_table, ClrPread, ClrPwrite};
 use scroll::{ctx::TryFromCtx, Pread};
 
 make_table! {
@@ -163,6 +166,7 @@ bitflags_tryctx! {
         const STRICT = 0x0200;
         const ABSTRACT = 0x0400;
         const SPECIAL_NAME = 0x0800;
+        const RT_SPECIAL_NAME = 0x1000;
 
         const PINVOKE_IMPL = 0x2000;
         const UNMANAGED_EXPORT = 0x0008;
@@ -340,17 +344,18 @@ enum_tryctx! {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Assembly {
     pub hash_alg_id: AssemblyHashAlgorithm,
     pub version: AssemblyVersion,
     pub flags: AssemblyFlags,
     pub public_key: BlobIndex,
     pub name: StringIndex,
+    pub culture: StringIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyOS {
     pub platform_id: u32,
     pub major_version: u32,
@@ -358,13 +363,13 @@ pub struct AssemblyOS {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyProcessor {
     pub processor: u32,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyVersion {
     pub major_version: u16,
     pub minor_version: u16,
@@ -373,10 +378,10 @@ pub struct AssemblyVersion {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyRef {
     pub version: AssemblyVersion,
-    pub flags: u32,
+    pub flags: AssemblyFlags,
     pub public_key_or_token: BlobIndex,
     pub name: StringIndex,
     pub culture: StringIndex,
@@ -384,7 +389,7 @@ pub struct AssemblyRef {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyRefOS {
     pub platform_id: u32,
     pub major_version: u32,
@@ -393,14 +398,14 @@ pub struct AssemblyRefOS {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct AssemblyRefProcessor {
     pub processor: u32,
     pub asm_ref: AssemblyRefIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct ClassLayout {
     pub packing_size: u16,
     pub class_size: u32,
@@ -408,7 +413,7 @@ pub struct ClassLayout {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Constant {
     pub const_ty: ElementType,
     pub parent: HasConstant,
@@ -416,7 +421,7 @@ pub struct Constant {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct CustomAttribute {
     pub parent: HasCustomAttribute,
     pub ty: CustomAttributeType,
@@ -424,22 +429,23 @@ pub struct CustomAttribute {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct DeclSecurity {
     pub action: u16,
-    pub parent: u16,
+    pub parent: HasDeclSecurity,
     pub permission_set: BlobIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct EventMap {
     pub parent: TypeDefIndex,
+    #[clr(list)]
     pub event_list: EventIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Event {
     pub flags: EventAttributes,
     pub name: StringIndex,
@@ -447,7 +453,7 @@ pub struct Event {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct ExportedType {
     pub flags: TypeAttributes,
     pub def_id: u32,
@@ -457,7 +463,7 @@ pub struct ExportedType {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Field {
     pub flags: FieldAttributes,
     pub name: StringIndex,
@@ -465,28 +471,28 @@ pub struct Field {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct FieldLayout {
     pub offset: u32,
     pub field: FieldIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct FieldMarshal {
     pub parent: HasFieldMarshal,
     pub native_type: BlobIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct FieldRVA {
     pub rva: u32,
     pub field: FieldIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct File {
     pub flags: FileAttributes,
     pub name: StringIndex,
@@ -494,7 +500,7 @@ pub struct File {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct GenericParam {
     pub number: u16,
     pub flags: GenericParamAttributes,
@@ -503,30 +509,30 @@ pub struct GenericParam {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct GenericParamConstraint {
     pub owner: GenericParamIndex,
     pub constraint: TypeDefOrRef,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct ImplMap {
-    pub mapping_flags: u16,
+    pub mapping_flags: PInvokeAttributes,
     pub member_forwarded: MemberForwarded,
     pub import_name: StringIndex,
     pub import_scope: ModuleRefIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct InterfaceImpl {
     pub class: TypeDefIndex,
     pub interface: TypeDefOrRef,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct ManifestResource {
     pub offset: u32,
     pub flags: ManifestResourceAttributes,
@@ -535,7 +541,7 @@ pub struct ManifestResource {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MemberRef {
     pub class: MemberRefParent,
     pub name: StringIndex,
@@ -543,18 +549,19 @@ pub struct MemberRef {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct MethodDef {
     pub rva: u32,
     pub impl_flags: MethodImplAttributes,
     pub flags: MethodAttributes,
     pub name: StringIndex,
     pub signature: BlobIndex,
+    #[clr(list)]
     pub param_list: ParamIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct MethodImpl {
     pub class: TypeDefIndex,
     pub body: MethodDefOrRef,
@@ -562,7 +569,7 @@ pub struct MethodImpl {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct MethodSemantics {
     pub semantics: MethodSemanticsAttributes,
     pub method: MethodDefIndex,
@@ -570,14 +577,14 @@ pub struct MethodSemantics {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct MethodSpec {
     pub method: MethodDefOrRef,
     pub instantiation: BlobIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Module {
     pub generation: u16,
     pub name: StringIndex,
@@ -587,20 +594,20 @@ pub struct Module {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct ModuleRef {
     pub name: StringIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct NestedClass {
     pub nested_class: TypeDefIndex,
     pub enclosing_class: TypeDefIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Param {
     pub flags: ParamAttributes,
     pub sequence: u16,
@@ -608,7 +615,7 @@ pub struct Param {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct Property {
     pub flags: PropertyAttributes,
     pub name: StringIndex,
@@ -616,31 +623,34 @@ pub struct Property {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct PropertyMap {
     pub parent: TypeDefIndex,
+    #[clr(list)]
     pub property_list: PropertyIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct StandAloneSig {
     pub signature: BlobIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct TypeDef {
     pub flags: TypeAttributes,
     pub type_name: StringIndex,
     pub type_namespace: StringIndex,
     pub extends: TypeDefOrRef,
+    #[clr(list)]
     pub field_list: FieldIndex,
+    #[clr(list)]
     pub method_list: MethodDefIndex,
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct TypeRef {
     pub resolution_scope: ResolutionScope,
     pub type_name: StringIndex,
@@ -648,7 +658,7 @@ pub struct TypeRef {
 }
 
 #[repr(C)]
-#[derive(Debug, ClrPread, Clone, Copy)]
+#[derive(Debug, ClrPread, ClrPwrite, Clone, Copy)]
 pub struct TypeSpec {
     pub signature: BlobIndex,
 }
@@ -667,18 +677,201 @@ macro_rules! define_resolve_signature {
     };
 }
 
-define_resolve_signature!(MemberRef, resolve_signature, MethodDefSig, signature);
+impl MemberRef {
+    /// Method signature at the call site, with the arguments of a vararg call, `Err` for a
+    /// reference to a field
+    pub fn resolve_signature(self, heap: Heap) -> Result<MethodDefSig, scroll::Error> {
+        let blob = self
+            .signature
+            .resolve(heap)
+            .ok_or(scroll::Error::BadInput {
+                size: 0,
+                msg: "missing signature blob",
+            })?;
+        // FIELD prolog
+        if blob.first() == Some(&0x06) {
+            return Err(scroll::Error::BadInput {
+                size: blob.len(),
+                msg: "field signature",
+            });
+        }
+        Ok(blob.pread_with::<MethodRefSig>(0, scroll::LE)?.call_site())
+    }
+}
 
 define_resolve_signature!(MethodDef, resolve_signature, MethodDefSig, signature);
 
 define_resolve_signature!(Field, resolve_signature, FieldSig, signature);
 
+define_resolve_signature!(StandAloneSig, resolve_local_var_sig, LocalVarSig, signature);
+
+define_resolve_signature!(TypeSpec, resolve_signature, Type, signature);
+
+define_resolve_signature!(
+    MethodSpec,
+    resolve_instantiation,
+    MethodSpecSig,
+    instantiation
+);
+
 impl MethodDef {
     pub fn resolve_body(self, image: &Image) -> MethodBody {
         image.get_data(self.rva).expect("Parse MethodBody")
     }
 }
 
+impl FieldIndex {
+    pub fn resolve_rva(self, table: &MetadataTable) -> Option<&FieldRVA> {
+        // FieldRVA is sorted by field
+        let pos = table
+            .field_rva
+            .binary_search_by_key(&self.0, |r| r.field.0)
+            .ok()?;
+        table.field_rva.get(pos)
+    }
+
+    pub fn resolve_layout(self, table: &MetadataTable) -> Option<&FieldLayout> {
+        // FieldLayout is sorted by field
+        let pos = table
+            .field_layout
+            .binary_search_by_key(&self.0, |r| r.field.0)
+            .ok()?;
+        table.field_layout.get(pos)
+    }
+
+    /// Initial bytes of a field that has a `FieldRVA` row
+    pub fn resolve_initial_data<'a>(self, image: &Image<'a>) -> Option<&'a [u8]> {
+        let root = image.metadata_root();
+        let table = &root.metadata_stream.table;
+        let rva = self.resolve_rva(table)?;
+        let size = self
+            .resolve_table(table)?
+            .resolve_signature(root.heap)
+            .ty
+            .size_of(table, root.heap)?;
+        image.get_bytes(rva.rva, size).ok()
+    }
+}
+
+impl TypeDefIndex {
+    pub fn resolve_class_layout(self, table: &MetadataTable) -> Option<&ClassLayout> {
+        // ClassLayout is sorted by parent
+        let pos = table
+            .class_layout
+            .binary_search_by_key(&self.0, |r| r.parent.0)
+            .ok()?;
+        table.class_layout.get(pos)
+    }
+
+    /// Size and alignment of instance fields when this type is used as a value type, `None`
+    /// for a value type containing itself
+    pub fn value_layout(self, table: &MetadataTable, heap: Heap) -> Option<(u32, u32)> {
+        self.value_layout_in(table, heap, &mut Vec::new())
+    }
+
+    /// `visiting` holds the value types being laid out around this one
+    fn value_layout_in(
+        self,
+        table: &MetadataTable,
+        heap: Heap,
+        visiting: &mut Vec<TypeDefIndex>,
+    ) -> Option<(u32, u32)> {
+        if visiting.contains(&self) {
+            return None;
+        }
+        visiting.push(self);
+        let ty = self.resolve_table(table)?;
+        let class_layout = self.resolve_class_layout(table);
+        let packing = class_layout
+            .map(|l| l.packing_size as u32)
+            .filter(|&p| p != 0)
+            .unwrap_or(8);
+
+        let mut size = 0;
+        let mut align = 1;
+
+        for (field_index, field) in self.resolve_fields(table) {
+            if field.flags.contains(FieldAttributes::STATIC) {
+                continue;
+            }
+
+            let (field_size, field_align) = field
+                .resolve_signature(heap)
+                .ty
+                .layout_in(table, heap, visiting)?;
+            let field_align = field_align.min(packing);
+            align = align.max(field_align);
+
+            if ty.flags & TypeAttributes::LAYOUT_MASK == TypeAttributes::EXPLICIT_LAYOUT {
+                let offset = field_index.resolve_layout(table)?.offset;
+                size = size.max(offset.checked_add(field_size)?);
+            } else {
+                size = align_to(size, field_align).checked_add(field_size)?;
+            }
+        }
+        visiting.pop();
+
+        let size = match class_layout {
+            Some(layout) if layout.class_size != 0 => layout.class_size,
+            // empty struct still takes one byte
+            _ => align_to(size, align).max(1),
+        };
+
+        Some((size, align))
+    }
+}
+
+fn align_to(n: u32, align: u32) -> u32 {
+    n.div_ceil(align) * align
+}
+
+/// Pointer size of 32bit image
+const PTR_SIZE: u32 = 4;
+
+impl Type {
+    /// Size and alignment of a value of this type
+    pub fn layout(&self, table: &MetadataTable, heap: Heap) -> Option<(u32, u32)> {
+        self.layout_in(table, heap, &mut Vec::new())
+    }
+
+    fn layout_in(
+        &self,
+        table: &MetadataTable,
+        heap: Heap,
+        visiting: &mut Vec<TypeDefIndex>,
+    ) -> Option<(u32, u32)> {
+        let size = match self {
+            Type::Boolean | Type::I1 | Type::U1 => 1,
+            Type::Char | Type::I2 | Type::U2 => 2,
+            Type::I4 | Type::U4 | Type::R4 => 4,
+            Type::I8 | Type::U8 | Type::R8 => 8,
+            Type::I
+            | Type::U
+            | Type::Object
+            | Type::String
+            | Type::Class(_)
+            | Type::SzArray { .. }
+            | Type::Array { .. }
+            | Type::Ptr { .. }
+            | Type::FnPtr(_) => PTR_SIZE,
+            Type::ValueType(TypeDefOrRefOrSpecEncoded::TypeDef(def)) => {
+                return def.value_layout_in(table, heap, visiting)
+            }
+            // layout is decided by another assembly
+            Type::ValueType(_)
+            | Type::GenericInst { .. }
+            | Type::Var { .. }
+            | Type::MVar { .. } => return None,
+        };
+
+        Some((size, size))
+    }
+
+    pub fn size_of(&self, table: &MetadataTable, heap: Heap) -> Option<u32> {
+        self.layout(table, heap).map(|(size, _)| size)
+    }
+}
+
 macro_rules! define_resolve {
     ($ty:ty, $fn_name:ident, $ret_ty:ty, $ret_index:ty, $def_field:ident, $table_field:ident) => {
         impl $ty {
@@ -733,3 +926,103 @@ define_resolve!(
     method_list,
     method_def
 );
+define_resolve!(
+    PropertyMapIndex,
+    resolve_properties,
+    Property,
+    PropertyIndex,
+    property_list,
+    property
+);
+define_resolve!(
+    EventMapIndex,
+    resolve_events,
+    Event,
+    EventIndex,
+    event_list,
+    event
+);
+
+#[test]
+fn initial_data_and_value_layout() {
+    let bytes = crate::cil::asm::assemble(include_str!("../../../../tests/il/arrays.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let class = |name: &str| {
+        let (index, _) = table
+            .list_type_def()
+            .find(|(_, t)| t.type_name.resolve(root.heap) == Some(name))
+            .unwrap();
+        index
+    };
+    let field = |name: &str| {
+        let (index, _) = table
+            .list_field()
+            .find(|(_, f)| f.name.resolve(root.heap) == Some(name))
+            .unwrap();
+        index
+    };
+
+    // `.size` wins over the packed fields, which have none here
+    assert_eq!(
+        class("Size16").value_layout(table, root.heap),
+        Some((16, 1))
+    );
+    assert_eq!(class("Size3").value_layout(table, root.heap), Some((3, 1)));
+
+    // the data is sized from the value type of the field
+    let primes = field("Primes").resolve_initial_data(&image).unwrap();
+    assert_eq!(primes.len(), 16);
+    assert_eq!(&primes[..8], [2, 0, 0, 0, 3, 0, 0, 0]);
+    assert_eq!(
+        field("Bytes").resolve_initial_data(&image),
+        Some(&[0xFF, 0x01, 0x80][..])
+    );
+
+    let bytes = crate::cil::asm::assemble(include_str!("../../../../tests/il/objects.il")).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let (pair, _) = table
+        .list_type_def()
+        .find(|(_, t)| t.type_name.resolve(root.heap) == Some("Pair"))
+        .unwrap();
+    assert_eq!(pair.value_layout(table, root.heap), Some((4, 4)));
+    // fields without RVA have no initial data
+    let (x, _) = table
+        .list_field()
+        .find(|(_, f)| f.name.resolve(root.heap) == Some("X"))
+        .unwrap();
+    assert_eq!(x.resolve_initial_data(&image), None);
+
+    // a value type containing itself, directly or through another, has no size
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly cycle { .ver 0:0:0:0 }
+
+        .data D_S = bytearray (00 00 00 00)
+
+        .class public sequential ansi sealed S extends [mscorlib]System.ValueType
+        {
+          .field public valuetype T t
+          .field public static valuetype S data at D_S
+        }
+
+        .class public sequential ansi sealed T extends [mscorlib]System.ValueType
+        {
+          .field public valuetype S s
+        }
+    ";
+    let bytes = crate::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+    let (s, _) = table.list_type_def().nth(1).unwrap();
+    assert_eq!(s.value_layout(table, root.heap), None);
+    let (data, _) = table
+        .list_field()
+        .find(|(_, f)| f.name.resolve(root.heap) == Some("data"))
+        .unwrap();
+    assert_eq!(data.resolve_initial_data(&image), None);
+}
@@ -0,0 +1,319 @@
+use goblin::pe::data_directories::DataDirectory;
+use scroll::ctx::TryFromCtx;
+use scroll::{Pread, LE};
+
+use super::{ComImageFlags, Image, MethodDefIndex, MethodImplAttributes};
+
+/// "RTR"
+pub const READY_TO_RUN_SIGNATURE: u32 = 0x0052_5452;
+
+/// Section types of the READYTORUN header
+pub mod ready_to_run_section {
+    pub const COMPILER_IDENTIFIER: u32 = 100;
+    pub const IMPORT_SECTIONS: u32 = 101;
+    pub const RUNTIME_FUNCTIONS: u32 = 102;
+    pub const METHOD_DEF_ENTRY_POINTS: u32 = 103;
+    pub const EXCEPTION_INFO: u32 = 104;
+    pub const DEBUG_INFO: u32 = 105;
+    pub const DELAY_LOAD_METHOD_CALL_THUNKS: u32 = 106;
+    pub const AVAILABLE_TYPES: u32 = 108;
+    pub const INSTANCE_METHOD_ENTRY_POINTS: u32 = 109;
+    pub const INLINING_INFO: u32 = 110;
+    pub const PROFILE_DATA_INFO: u32 = 111;
+    pub const MANIFEST_METADATA: u32 = 112;
+    pub const ATTRIBUTE_PRESENCE: u32 = 113;
+    pub const INLINING_INFO2: u32 = 114;
+    pub const COMPONENT_ASSEMBLIES: u32 = 115;
+    pub const OWNER_COMPOSITE_EXECUTABLE: u32 = 116;
+}
+
+bitflags_tryctx! {
+    /// READYTORUN_FLAG_*
+    pub struct ReadyToRunFlags: u32 {
+        const PLATFORM_NEUTRAL_SOURCE = 0x0000_0001;
+        const SKIP_TYPE_VALIDATION = 0x0000_0002;
+        const PARTIAL = 0x0000_0004;
+        const NONSHARED_PINVOKE_STUBS = 0x0000_0008;
+        const EMBEDDED_MSIL = 0x0000_0010;
+        const COMPONENT = 0x0000_0020;
+        const MULTIMODULE_VERSION_BUBBLE = 0x0000_0040;
+        const UNRELATED_R2R_CODE = 0x0000_0080;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pread)]
+pub struct ReadyToRunSection {
+    pub ty: u32,
+    pub section: DataDirectory,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadyToRunHeader {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub flags: ReadyToRunFlags,
+    pub sections: Vec<ReadyToRunSection>,
+}
+
+impl ReadyToRunHeader {
+    pub fn section(&self, ty: u32) -> Option<DataDirectory> {
+        self.sections.iter().find(|s| s.ty == ty).map(|s| s.section)
+    }
+}
+
+impl<'a> TryFromCtx<'a, scroll::Endian> for ReadyToRunHeader {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        let signature: u32 = src.gread_with(offset, ctx)?;
+        if signature != READY_TO_RUN_SIGNATURE {
+            return Err(scroll::Error::BadInput {
+                size: 4,
+                msg: "Invalid READYTORUN signature",
+            });
+        }
+
+        let major_version = src.gread_with(offset, ctx)?;
+        let minor_version = src.gread_with(offset, ctx)?;
+        let flags = src.gread_with(offset, ctx)?;
+        let count: u32 = src.gread_with(offset, ctx)?;
+        let sections = (0..count)
+            .map(|_| src.gread_with(offset, ctx))
+            .collect::<Result<_, _>>()?;
+
+        Ok((
+            Self {
+                major_version,
+                minor_version,
+                flags,
+                sections,
+            },
+            *offset,
+        ))
+    }
+}
+
+/// Method which has native code in the image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrecompiledMethod {
+    pub method: MethodDefIndex,
+    pub runtime_function: u32,
+    /// RVA of the native code
+    pub begin_address: u32,
+}
+
+/// Variable length unsigned of the NativeFormat used by ReadyToRun
+fn decode_unsigned(src: &[u8], offset: &mut usize) -> Result<u32, scroll::Error> {
+    let b0: u8 = src.gread_with(offset, LE)?;
+
+    let mut next = |shift: u32| -> Result<u32, scroll::Error> {
+        let b: u8 = src.gread_with(offset, LE)?;
+        Ok((b as u32) << shift)
+    };
+
+    let b0 = b0 as u32;
+    if b0 & 0x01 == 0 {
+        Ok(b0 >> 1)
+    } else if b0 & 0x02 == 0 {
+        Ok((b0 >> 2) | next(6)?)
+    } else if b0 & 0x04 == 0 {
+        Ok((b0 >> 3) | next(5)? | next(13)?)
+    } else if b0 & 0x08 == 0 {
+        Ok((b0 >> 4) | next(4)? | next(12)? | next(20)?)
+    } else if b0 & 0x10 == 0 {
+        src.gread_with(offset, LE)
+    } else {
+        Err(scroll::Error::BadInput {
+            size: 1,
+            msg: "Invalid NativeFormat unsigned",
+        })
+    }
+}
+
+/// NativeArray is a sparse array stored as a binary tree of blocks
+struct NativeArray<'a> {
+    src: &'a [u8],
+    base: usize,
+    len: u32,
+    entry_index_size: u32,
+}
+
+impl<'a> NativeArray<'a> {
+    const BLOCK_SIZE: u32 = 16;
+
+    fn new(src: &'a [u8]) -> Result<Self, scroll::Error> {
+        let offset = &mut 0;
+        let header = decode_unsigned(src, offset)?;
+
+        Ok(Self {
+            src,
+            base: *offset,
+            len: header >> 2,
+            entry_index_size: header & 3,
+        })
+    }
+
+    /// Offset of the element at `index` if it is present
+    fn get(&self, index: u32) -> Result<Option<usize>, scroll::Error> {
+        if index >= self.len {
+            return Ok(None);
+        }
+
+        let block = (index / Self::BLOCK_SIZE) as usize;
+        let mut offset = self.base
+            + match self.entry_index_size {
+                0 => self.src.pread_with::<u8>(self.base + block, LE)? as usize,
+                1 => self.src.pread_with::<u16>(self.base + 2 * block, LE)? as usize,
+                _ => self.src.pread_with::<u32>(self.base + 4 * block, LE)? as usize,
+            };
+
+        let mut bit = Self::BLOCK_SIZE >> 1;
+        while bit > 0 {
+            let next = &mut offset.clone();
+            let val = decode_unsigned(self.src, next)?;
+
+            if index & bit != 0 {
+                if val & 2 != 0 {
+                    offset += (val >> 2) as usize;
+                    bit >>= 1;
+                    continue;
+                }
+            } else if val & 1 != 0 {
+                offset = *next;
+                bit >>= 1;
+                continue;
+            }
+
+            // special leaf node
+            if val & 3 == 0 && val >> 2 == index & (Self::BLOCK_SIZE - 1) {
+                return Ok(Some(*next));
+            }
+
+            return Ok(None);
+        }
+
+        Ok(Some(offset))
+    }
+}
+
+/// Index of RUNTIME_FUNCTION of a MethodDefEntryPoints entry
+fn decode_method_entry(src: &[u8], offset: usize) -> Result<u32, scroll::Error> {
+    let id = decode_unsigned(src, &mut { offset })?;
+
+    // bit 0 marks a fixup list which is not interesting here
+    if id & 1 != 0 {
+        Ok(id >> 2)
+    } else {
+        Ok(id >> 1)
+    }
+}
+
+impl<'a> Image<'a> {
+    pub fn ready_to_run_header(&self) -> Option<ReadyToRunHeader> {
+        let dir = self.cli_header().managed_native_header;
+
+        if dir.virtual_address == 0 {
+            return None;
+        }
+
+        self.get_data(dir.virtual_address).ok()
+    }
+
+    pub fn is_ready_to_run(&self) -> bool {
+        self.ready_to_run_header().is_some()
+    }
+
+    /// C++/CLI images are not IL only and may have native method bodies
+    pub fn is_mixed_mode(&self) -> bool {
+        !self.cli_header().flags.contains(ComImageFlags::IL_ONLY)
+    }
+
+    /// Methods whose body is `MethodImplAttributes::NATIVE` code instead of IL
+    pub fn native_methods(&self) -> Vec<MethodDefIndex> {
+        self.metadata_root()
+            .metadata_stream
+            .table
+            .list_method_def()
+            .filter(|(_, m)| {
+                m.impl_flags & MethodImplAttributes::CODE_TYPE_MASK == MethodImplAttributes::NATIVE
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn runtime_function_size(&self) -> u32 {
+        // AMD64 has an EndAddress, others only BeginAddress and UnwindData.
+        // ReadyToRun images xor the machine with an OS specific value.
+        const AMD64: u16 = 0x8664;
+        const OS_MACHINE_OVERRIDES: [u16; 5] = [0x0000, 0x4644, 0x7B79, 0xADC4, 0x1993];
+
+        if OS_MACHINE_OVERRIDES
+            .iter()
+            .any(|os| self.machine ^ os == AMD64)
+        {
+            12
+        } else {
+            8
+        }
+    }
+
+    /// Methods which have ReadyToRun native code
+    pub fn precompiled_methods(&self) -> Result<Vec<PrecompiledMethod>, goblin::error::Error> {
+        let header = match self.ready_to_run_header() {
+            Some(header) => header,
+            None => return Ok(Vec::new()),
+        };
+
+        let (entry_points, runtime_functions) = match (
+            header.section(ready_to_run_section::METHOD_DEF_ENTRY_POINTS),
+            header.section(ready_to_run_section::RUNTIME_FUNCTIONS),
+        ) {
+            (Some(e), Some(r)) => (e, r),
+            _ => return Ok(Vec::new()),
+        };
+
+        let entry_points = self.get_bytes(entry_points.virtual_address, entry_points.size)?;
+        let runtime_functions =
+            self.get_bytes(runtime_functions.virtual_address, runtime_functions.size)?;
+        let array = NativeArray::new(entry_points)?;
+        let entry_size = self.runtime_function_size() as usize;
+
+        let mut methods = Vec::new();
+        for (index, _) in self.metadata_root().metadata_stream.table.list_method_def() {
+            let offset = match array.get(index.0 - 1)? {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            let runtime_function = decode_method_entry(entry_points, offset)?;
+            let begin_address =
+                runtime_functions.pread_with(runtime_function as usize * entry_size, LE)?;
+
+            methods.push(PrecompiledMethod {
+                method: index,
+                runtime_function,
+                begin_address,
+            });
+        }
+
+        Ok(methods)
+    }
+}
+
+#[test]
+fn native_array() {
+    // one element array, block offset 1 and a leaf node for index 0
+    let src = [0x08, 0x01, 0x00, 0x14];
+    let array = NativeArray::new(&src).unwrap();
+
+    assert_eq!(array.get(0).unwrap(), Some(3));
+    assert_eq!(array.get(1).unwrap(), None);
+    assert_eq!(decode_method_entry(&src, 3).unwrap(), 5);
+
+    let offset = &mut 0;
+    assert_eq!(decode_unsigned(&[0x05, 0x02], offset).unwrap(), 0x81);
+    assert_eq!(*offset, 2);
+}
@@ -0,0 +1,611 @@
+use scroll::ctx::TryFromCtx;
+use scroll::{Endian, Pread, LE};
+
+use super::Image;
+
+/// Predefined resource types (`RT_*`)
+pub mod resource_type {
+    pub const CURSOR: u16 = 1;
+    pub const BITMAP: u16 = 2;
+    pub const ICON: u16 = 3;
+    pub const MENU: u16 = 4;
+    pub const DIALOG: u16 = 5;
+    pub const STRING: u16 = 6;
+    pub const RCDATA: u16 = 10;
+    pub const GROUP_CURSOR: u16 = 12;
+    pub const GROUP_ICON: u16 = 14;
+    pub const VERSION: u16 = 16;
+    pub const MANIFEST: u16 = 24;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceName {
+    Id(u16),
+    Name(String),
+}
+
+/// IMAGE_RESOURCE_DIRECTORY
+///
+/// `rsrc` is the whole resource section since every offset in the tree is relative to its start
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceDirectory<'a> {
+    rsrc: &'a [u8],
+    offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResourceEntry<'a> {
+    pub name: ResourceName,
+    pub kind: ResourceEntryKind<'a>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ResourceEntryKind<'a> {
+    Directory(ResourceDirectory<'a>),
+    Data(ResourceDataEntry),
+}
+
+/// IMAGE_RESOURCE_DATA_ENTRY
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pread)]
+pub struct ResourceDataEntry {
+    pub rva: u32,
+    pub size: u32,
+    pub code_page: u32,
+    pub reserved: u32,
+}
+
+/// Leaf of the type/name/language tree
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub ty: ResourceName,
+    pub name: ResourceName,
+    pub lang: ResourceName,
+    pub data: ResourceDataEntry,
+}
+
+impl<'a> ResourceDirectory<'a> {
+    pub fn new(rsrc: &'a [u8]) -> Self {
+        Self { rsrc, offset: 0 }
+    }
+
+    pub fn entries(&self) -> Result<Vec<ResourceEntry<'a>>, scroll::Error> {
+        let offset = &mut (self.offset + 12);
+        let named: u16 = self.rsrc.gread_with(offset, LE)?;
+        let ids: u16 = self.rsrc.gread_with(offset, LE)?;
+
+        (0..named as usize + ids as usize)
+            .map(|_| {
+                let name: u32 = self.rsrc.gread_with(offset, LE)?;
+                let data: u32 = self.rsrc.gread_with(offset, LE)?;
+
+                let name = if name & 0x8000_0000 != 0 {
+                    let name_offset = &mut ((name & 0x7FFF_FFFF) as usize);
+                    let len: u16 = self.rsrc.gread_with(name_offset, LE)?;
+                    let units = (0..len)
+                        .map(|_| self.rsrc.gread_with(name_offset, LE))
+                        .collect::<Result<Vec<u16>, _>>()?;
+                    ResourceName::Name(String::from_utf16_lossy(&units))
+                } else {
+                    ResourceName::Id(name as u16)
+                };
+
+                let kind = if data & 0x8000_0000 != 0 {
+                    ResourceEntryKind::Directory(Self {
+                        rsrc: self.rsrc,
+                        offset: (data & 0x7FFF_FFFF) as usize,
+                    })
+                } else {
+                    ResourceEntryKind::Data(self.rsrc.pread_with(data as usize, LE)?)
+                };
+
+                Ok(ResourceEntry { name, kind })
+            })
+            .collect()
+    }
+
+    /// Flatten the three level type/name/language tree
+    pub fn resources(&self) -> Result<Vec<Resource>, scroll::Error> {
+        let mut ret = Vec::new();
+
+        for ty in self.entries()? {
+            let names = match ty.kind {
+                ResourceEntryKind::Directory(dir) => dir.entries()?,
+                ResourceEntryKind::Data(_) => continue,
+            };
+
+            for name in names {
+                let langs = match name.kind {
+                    ResourceEntryKind::Directory(dir) => dir.entries()?,
+                    ResourceEntryKind::Data(data) => {
+                        ret.push(Resource {
+                            ty: ty.name.clone(),
+                            name: name.name,
+                            lang: ResourceName::Id(0),
+                            data,
+                        });
+                        continue;
+                    }
+                };
+
+                for lang in langs {
+                    if let ResourceEntryKind::Data(data) = lang.kind {
+                        ret.push(Resource {
+                            ty: ty.name.clone(),
+                            name: name.name.clone(),
+                            lang: lang.name,
+                            data,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+impl<'a> Image<'a> {
+    pub fn resource_directory(&self) -> Option<ResourceDirectory<'a>> {
+        let dir = self.resource_table?;
+        self.get_bytes(dir.virtual_address, dir.size)
+            .ok()
+            .map(ResourceDirectory::new)
+    }
+
+    pub fn resources(&self) -> Result<Vec<Resource>, scroll::Error> {
+        match self.resource_directory() {
+            Some(dir) => dir.resources(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn resource_data(&self, data: &ResourceDataEntry) -> Option<&'a [u8]> {
+        self.get_bytes(data.rva, data.size).ok()
+    }
+
+    fn find_resource_data(&self, ty: u16) -> Result<Option<&'a [u8]>, scroll::Error> {
+        Ok(self
+            .resources()?
+            .iter()
+            .find(|r| r.ty == ResourceName::Id(ty))
+            .and_then(|r| self.resource_data(&r.data)))
+    }
+
+    pub fn version_info(&self) -> Result<Option<VersionInfo>, scroll::Error> {
+        self.find_resource_data(resource_type::VERSION)?
+            .map(|data| data.pread_with(0, LE))
+            .transpose()
+    }
+
+    pub fn manifest(&self) -> Result<Option<&'a str>, scroll::Error> {
+        let data = match self.find_resource_data(resource_type::MANIFEST)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+        std::str::from_utf8(data)
+            .map(Some)
+            .map_err(|_| scroll::Error::BadInput {
+                size: data.len(),
+                msg: "Manifest is not UTF-8",
+            })
+    }
+
+    /// Icon groups with their `RT_ICON` entries
+    pub fn icon_groups(&self) -> Result<Vec<(ResourceName, GroupIcon)>, scroll::Error> {
+        let mut groups = Vec::new();
+        for r in self.resources()? {
+            if r.ty != ResourceName::Id(resource_type::GROUP_ICON) {
+                continue;
+            }
+            if let Some(data) = self.resource_data(&r.data) {
+                groups.push((r.name, data.pread_with(0, LE)?));
+            }
+        }
+        Ok(groups)
+    }
+
+    pub fn icon(&self, id: u16) -> Result<Option<&'a [u8]>, scroll::Error> {
+        Ok(self
+            .resources()?
+            .iter()
+            .find(|r| {
+                r.ty == ResourceName::Id(resource_type::ICON) && r.name == ResourceName::Id(id)
+            })
+            .and_then(|r| self.resource_data(&r.data)))
+    }
+}
+
+/// GRPICONDIR
+#[derive(Clone, Debug)]
+pub struct GroupIcon {
+    pub ty: u16,
+    pub entries: Vec<GroupIconEntry>,
+}
+
+/// GRPICONDIRENTRY, `id` is the name of `RT_ICON` resource
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pread)]
+pub struct GroupIconEntry {
+    pub width: u8,
+    pub height: u8,
+    pub color_count: u8,
+    pub reserved: u8,
+    pub planes: u16,
+    pub bit_count: u16,
+    pub bytes_in_res: u32,
+    pub id: u16,
+}
+
+impl<'a> TryFromCtx<'a, Endian> for GroupIcon {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let _reserved: u16 = src.gread_with(offset, ctx)?;
+        let ty = src.gread_with(offset, ctx)?;
+        let count: u16 = src.gread_with(offset, ctx)?;
+        let entries = std::iter::repeat_with(|| src.gread_with(offset, ctx))
+            .take(count as usize)
+            .collect::<Result<_, _>>()?;
+
+        Ok((Self { ty, entries }, *offset))
+    }
+}
+
+/// VS_FIXEDFILEINFO
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pread)]
+pub struct FixedFileInfo {
+    pub signature: u32,
+    pub struc_version: u32,
+    pub file_version_ms: u32,
+    pub file_version_ls: u32,
+    pub product_version_ms: u32,
+    pub product_version_ls: u32,
+    pub file_flags_mask: u32,
+    pub file_flags: u32,
+    pub file_os: u32,
+    pub file_type: u32,
+    pub file_subtype: u32,
+    pub file_date_ms: u32,
+    pub file_date_ls: u32,
+}
+
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xFEEF_04BD;
+
+fn split_version(ms: u32, ls: u32) -> [u16; 4] {
+    [(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16]
+}
+
+impl FixedFileInfo {
+    pub fn file_version(&self) -> [u16; 4] {
+        split_version(self.file_version_ms, self.file_version_ls)
+    }
+
+    pub fn product_version(&self) -> [u16; 4] {
+        split_version(self.product_version_ms, self.product_version_ls)
+    }
+}
+
+/// StringTable of StringFileInfo, `key` is language and code page in hex like `040904b0`
+#[derive(Clone, Debug, Default)]
+pub struct VersionStringTable {
+    pub key: String,
+    pub strings: Vec<(String, String)>,
+}
+
+/// VS_VERSIONINFO
+#[derive(Clone, Debug, Default)]
+pub struct VersionInfo {
+    pub fixed: Option<FixedFileInfo>,
+    pub string_tables: Vec<VersionStringTable>,
+    /// Language and code page pairs of VarFileInfo\Translation
+    pub translations: Vec<(u16, u16)>,
+}
+
+impl VersionInfo {
+    /// Look up a string in the first table that has it
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.string_tables
+            .iter()
+            .flat_map(|t| t.strings.iter())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn file_version(&self) -> Option<&str> {
+        self.get("FileVersion")
+    }
+
+    pub fn product_version(&self) -> Option<&str> {
+        self.get("ProductVersion")
+    }
+
+    pub fn company_name(&self) -> Option<&str> {
+        self.get("CompanyName")
+    }
+}
+
+/// Common header of every node in the version tree
+struct VersionBlock<'a> {
+    key: String,
+    /// `true` when `value` is UTF-16 text
+    is_text: bool,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+impl<'a> TryFromCtx<'a, Endian> for VersionBlock<'a> {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let length: u16 = src.gread_with(offset, ctx)?;
+        let value_length: u16 = src.gread_with(offset, ctx)?;
+        let ty: u16 = src.gread_with(offset, ctx)?;
+
+        let mut key = Vec::new();
+        loop {
+            let c: u16 = src.gread_with(offset, ctx)?;
+            if c == 0 {
+                break;
+            }
+            key.push(c);
+        }
+
+        // a block shorter than its own header would never advance the parser
+        let length = length as usize;
+        if length < *offset {
+            return Err(scroll::Error::BadInput {
+                size: length,
+                msg: "VS_VERSIONINFO block is shorter than its header",
+            });
+        }
+        let block = src.get(..length).ok_or(scroll::Error::TooBig {
+            size: length,
+            len: src.len(),
+        })?;
+
+        let is_text = ty == 1;
+        // text value length counts UTF-16 units
+        let value_size = if is_text {
+            value_length as usize * 2
+        } else {
+            value_length as usize
+        };
+
+        let value_start = align4(*offset).min(length);
+        let value_end = (value_start + value_size).min(length);
+        let children_start = align4(value_end).min(length);
+
+        Ok((
+            Self {
+                key: String::from_utf16_lossy(&key),
+                is_text,
+                value: &block[value_start..value_end],
+                children: &block[children_start..],
+            },
+            align4(length).min(src.len()),
+        ))
+    }
+}
+
+impl<'a> VersionBlock<'a> {
+    fn children(&self) -> Result<Vec<VersionBlock<'a>>, scroll::Error> {
+        let src = self.children;
+        let offset = &mut 0;
+        let mut children = Vec::new();
+        while *offset < src.len() {
+            children.push(src.gread_with(offset, LE)?);
+        }
+        Ok(children)
+    }
+
+    fn text(&self) -> String {
+        let units = self
+            .value
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0)
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+impl<'a> TryFromCtx<'a, Endian> for VersionInfo {
+    type Error = scroll::Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let root: VersionBlock = src.gread_with(offset, ctx)?;
+
+        if root.key != "VS_VERSION_INFO" {
+            return Err(scroll::Error::BadInput {
+                size: 0,
+                msg: "Invalid VS_VERSIONINFO key",
+            });
+        }
+
+        let mut info = VersionInfo {
+            fixed: root
+                .value
+                .pread_with::<FixedFileInfo>(0, ctx)
+                .ok()
+                .filter(|f| f.signature == FIXED_FILE_INFO_SIGNATURE),
+            ..Default::default()
+        };
+
+        for child in root.children()? {
+            match child.key.as_str() {
+                "StringFileInfo" => {
+                    for table in child.children()? {
+                        info.string_tables.push(VersionStringTable {
+                            strings: table
+                                .children()?
+                                .into_iter()
+                                .map(|s| {
+                                    let value = if s.is_text { s.text() } else { String::new() };
+                                    (s.key, value)
+                                })
+                                .collect(),
+                            key: table.key,
+                        });
+                    }
+                }
+                "VarFileInfo" => {
+                    for var in child
+                        .children()?
+                        .into_iter()
+                        .filter(|v| v.key == "Translation")
+                    {
+                        info.translations.extend(var.value.chunks_exact(4).map(|c| {
+                            (c.pread_with(0, ctx).unwrap(), c.pread_with(2, ctx).unwrap())
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok((info, *offset))
+    }
+}
+
+#[cfg(test)]
+fn build_version_block(key: &str, value: &[u8], is_text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = vec![0, 0];
+    let value_length = if is_text {
+        value.len() / 2
+    } else {
+        value.len()
+    };
+    out.extend_from_slice(&(value_length as u16).to_le_bytes());
+    out.extend_from_slice(&(is_text as u16).to_le_bytes());
+    key.encode_utf16()
+        .chain(Some(0))
+        .for_each(|c| out.extend_from_slice(&c.to_le_bytes()));
+    out.resize(align4(out.len()), 0);
+    out.extend_from_slice(value);
+    for child in children {
+        out.resize(align4(out.len()), 0);
+        out.extend_from_slice(child);
+    }
+    let len = out.len() as u16;
+    out[..2].copy_from_slice(&len.to_le_bytes());
+    out
+}
+
+#[test]
+fn version_info() {
+    let text = |s: &str| {
+        s.encode_utf16()
+            .chain(Some(0))
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>()
+    };
+
+    let mut fixed = Vec::new();
+    for n in [
+        FIXED_FILE_INFO_SIGNATURE,
+        0x10000,
+        0x0001_0002,
+        0x0003_0004,
+        0x0001_0000,
+        0,
+        0,
+        0,
+        4,
+        1,
+        0,
+        0,
+        0,
+    ] {
+        fixed.extend_from_slice(&n.to_le_bytes());
+    }
+
+    let company = build_version_block("CompanyName", &text("Riey"), true, &[]);
+    let file_version = build_version_block("FileVersion", &text("1.2.3.4"), true, &[]);
+    let table = build_version_block("040904b0", &[], true, &[company, file_version]);
+    let string_file_info = build_version_block("StringFileInfo", &[], true, &[table]);
+    let translation = build_version_block("Translation", &[0x09, 0x04, 0xb0, 0x04], false, &[]);
+    let var_file_info = build_version_block("VarFileInfo", &[], true, &[translation]);
+    let root = build_version_block(
+        "VS_VERSION_INFO",
+        &fixed,
+        false,
+        &[string_file_info, var_file_info],
+    );
+
+    let info: VersionInfo = root.pread_with(0, LE).unwrap();
+
+    assert_eq!(info.fixed.unwrap().file_version(), [1, 2, 3, 4]);
+    assert_eq!(info.fixed.unwrap().product_version(), [1, 0, 0, 0]);
+    assert_eq!(info.company_name(), Some("Riey"));
+    assert_eq!(info.file_version(), Some("1.2.3.4"));
+    assert_eq!(info.product_version(), None);
+    assert_eq!(info.string_tables[0].key, "040904b0");
+    assert_eq!(info.translations, [(0x0409, 0x04b0)]);
+}
+
+#[test]
+fn version_info_bad_length() {
+    let child = build_version_block("StringFileInfo", &[], true, &[]);
+    let child_start = align4(build_version_block("VS_VERSION_INFO", &[], false, &[]).len());
+    let mut root = build_version_block("VS_VERSION_INFO", &[], false, &[child]);
+    // `wLength` of the child is zero
+    assert_eq!(root[child_start..child_start + 2], [36, 0]);
+    root[child_start..child_start + 2].copy_from_slice(&0u16.to_le_bytes());
+    assert!(root.pread_with::<VersionInfo>(0, LE).is_err());
+
+    root[..2].copy_from_slice(&4u16.to_le_bytes());
+    assert!(root.pread_with::<VersionInfo>(0, LE).is_err());
+}
+
+#[test]
+fn resource_directory_entries() {
+    let mut rsrc = vec![0u8; 112];
+    let mut put = |offset: usize, words: &[u32]| {
+        for (i, w) in words.iter().enumerate() {
+            rsrc[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&w.to_le_bytes());
+        }
+    };
+    // type: one id entry, RT_VERSION
+    put(
+        12,
+        &[1 << 16, resource_type::VERSION as u32, 0x8000_0000 | 24],
+    );
+    // name: one named entry, "APP"
+    put(36, &[1, 0x8000_0000 | 80, 0x8000_0000 | 48]);
+    // language: one id entry pointing to the data entry
+    put(60, &[1 << 16, 0x409, 96]);
+    put(
+        80,
+        &[3 | (b'A' as u32) << 16, b'P' as u32 | (b'P' as u32) << 16],
+    );
+    put(96, &[0x2000, 0x10, 0, 0]);
+
+    let dir = ResourceDirectory::new(&rsrc);
+    let entries = dir.entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, ResourceName::Id(resource_type::VERSION));
+    assert!(matches!(entries[0].kind, ResourceEntryKind::Directory(_)));
+
+    let resources = dir.resources().unwrap();
+    assert_eq!(resources.len(), 1);
+    assert_eq!(resources[0].ty, ResourceName::Id(resource_type::VERSION));
+    assert_eq!(resources[0].name, ResourceName::Name("APP".into()));
+    assert_eq!(resources[0].lang, ResourceName::Id(0x409));
+    assert_eq!(
+        (resources[0].data.rva, resources[0].data.size),
+        (0x2000, 0x10)
+    );
+
+    // the data entry is cut off
+    assert!(ResourceDirectory::new(&rsrc[..100]).resources().is_err());
+}
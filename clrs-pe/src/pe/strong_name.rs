@@ -0,0 +1,494 @@
+use std::convert::TryInto;
+
+use num_bigint::BigUint;
+use scroll::{Pread, LE};
+use sha1::Digest;
+
+use super::{Assembly, AssemblyFlags, AssemblyHashAlgorithm, AssemblyRef, Heap, Image};
+
+/// `PublicKeyBlob` stored in `Assembly.public_key` (II.6.3)
+#[derive(Clone, Copy, Debug)]
+pub struct PublicKeyBlob<'a> {
+    pub sig_alg_id: u32,
+    pub hash_alg_id: u32,
+    /// CryptoAPI `PUBLICKEYBLOB`
+    pub public_key: &'a [u8],
+}
+
+/// RSA key from `PUBLICKEYBLOB`, modulus is little endian
+#[derive(Clone, Copy, Debug)]
+pub struct RsaPublicKey<'a> {
+    pub exponent: u32,
+    pub modulus: &'a [u8],
+}
+
+/// Public key of ECMA standard libraries, it has no real key material
+const ECMA_KEY: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0];
+
+const PUBLICKEYBLOB: u8 = 0x06;
+const RSA1: u32 = 0x3141_5352;
+
+impl<'a> PublicKeyBlob<'a> {
+    pub fn parse(blob: &'a [u8]) -> Option<Self> {
+        let offset = &mut 0;
+        let sig_alg_id = blob.gread_with(offset, LE).ok()?;
+        let hash_alg_id = blob.gread_with(offset, LE).ok()?;
+        let size: u32 = blob.gread_with(offset, LE).ok()?;
+        let public_key = blob.get(*offset..*offset + size as usize)?;
+
+        Some(Self {
+            sig_alg_id,
+            hash_alg_id,
+            public_key,
+        })
+    }
+
+    pub fn hash_algorithm(&self) -> Option<AssemblyHashAlgorithm> {
+        AssemblyHashAlgorithm::from_n(self.hash_alg_id)
+    }
+
+    pub fn rsa(&self) -> Option<RsaPublicKey<'a>> {
+        let key = self.public_key;
+        let offset = &mut 0;
+        let ty: u8 = key.gread_with(offset, LE).ok()?;
+        let _version: u8 = key.gread_with(offset, LE).ok()?;
+        let _reserved: u16 = key.gread_with(offset, LE).ok()?;
+        let _key_alg: u32 = key.gread_with(offset, LE).ok()?;
+        let magic: u32 = key.gread_with(offset, LE).ok()?;
+        let bit_len: u32 = key.gread_with(offset, LE).ok()?;
+        let exponent = key.gread_with(offset, LE).ok()?;
+
+        if ty != PUBLICKEYBLOB || magic != RSA1 {
+            return None;
+        }
+
+        let modulus = key.get(*offset..*offset + bit_len as usize / 8)?;
+
+        Some(RsaPublicKey { exponent, modulus })
+    }
+}
+
+/// Last 8 bytes of SHA-1 of the public key in reverse order
+pub fn public_key_token(public_key: &[u8]) -> [u8; 8] {
+    let hash = sha1::Sha1::digest(public_key);
+    let mut token = [0; 8];
+    token.copy_from_slice(&hash[hash.len() - 8..]);
+    token.reverse();
+    token
+}
+
+impl Assembly {
+    pub fn public_key_token(self, heap: Heap) -> Option<[u8; 8]> {
+        self.public_key
+            .resolve(heap)
+            .filter(|k| !k.is_empty())
+            .map(public_key_token)
+    }
+}
+
+impl AssemblyRef {
+    pub fn public_key_token(self, heap: Heap) -> Option<[u8; 8]> {
+        let blob = self
+            .public_key_or_token
+            .resolve(heap)
+            .filter(|k| !k.is_empty())?;
+
+        if self.flags.contains(AssemblyFlags::PUBLIC_KEY) {
+            Some(public_key_token(blob))
+        } else {
+            blob.try_into().ok()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrongNameError {
+    /// Assembly has no public key
+    NoPublicKey,
+    /// CLI header has no strong name signature directory
+    NoSignature,
+    /// Signature space is reserved but not filled
+    DelaySigned,
+    /// ECMA key or non RSA key
+    UnsupportedKey,
+    UnsupportedHashAlgorithm,
+    Malformed,
+    InvalidSignature,
+}
+
+impl std::fmt::Display for StrongNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let msg = match self {
+            Self::NoPublicKey => "assembly has no public key",
+            Self::NoSignature => "image has no strong name signature",
+            Self::DelaySigned => "assembly is delay signed",
+            Self::UnsupportedKey => "public key is not an RSA key",
+            Self::UnsupportedHashAlgorithm => "unsupported strong name hash algorithm",
+            Self::Malformed => "malformed strong name data",
+            Self::InvalidSignature => "strong name signature does not match",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for StrongNameError {}
+
+fn digest<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+    let mut d = D::new();
+    parts.iter().for_each(|p| d.update(p));
+    d.finalize().to_vec()
+}
+
+/// DER encoded DigestInfo prefix of EMSA-PKCS1-v1_5
+fn digest_info_prefix(alg: AssemblyHashAlgorithm) -> Option<&'static [u8]> {
+    let prefix: &[u8] = match alg {
+        AssemblyHashAlgorithm::SHA1 => &[
+            0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04,
+            0x14,
+        ],
+        AssemblyHashAlgorithm::SHA256 => &[
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ],
+        AssemblyHashAlgorithm::SHA384 => &[
+            0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x02, 0x05, 0x00, 0x04, 0x30,
+        ],
+        AssemblyHashAlgorithm::SHA512 => &[
+            0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x03, 0x05, 0x00, 0x04, 0x40,
+        ],
+        AssemblyHashAlgorithm::None | AssemblyHashAlgorithm::MD5 => return None,
+    };
+    Some(prefix)
+}
+
+impl RsaPublicKey<'_> {
+    /// RSASSA-PKCS1-v1_5 verification, `signature` is little endian like the key
+    pub fn verify(&self, alg: AssemblyHashAlgorithm, hash: &[u8], signature: &[u8]) -> bool {
+        let prefix = match digest_info_prefix(alg) {
+            Some(prefix) => prefix,
+            None => return false,
+        };
+
+        let k = self.modulus.len();
+        if signature.len() != k || k < prefix.len() + hash.len() + 11 {
+            return false;
+        }
+
+        let n = BigUint::from_bytes_le(self.modulus);
+        let s = BigUint::from_bytes_le(signature);
+        if s >= n {
+            return false;
+        }
+        let m = s.modpow(&BigUint::from(self.exponent), &n).to_bytes_be();
+
+        let mut expected = vec![0xFF; k];
+        expected[0] = 0x00;
+        expected[1] = 0x01;
+        let t = k - prefix.len() - hash.len();
+        expected[t - 1] = 0x00;
+        expected[t..t + prefix.len()].copy_from_slice(prefix);
+        expected[t + prefix.len()..].copy_from_slice(hash);
+
+        // leading zeros are dropped by `to_bytes_be`
+        m.len() <= k
+            && expected[..k - m.len()].iter().all(|&b| b == 0)
+            && expected[k - m.len()..] == m[..]
+    }
+}
+
+impl<'a> Image<'a> {
+    pub fn public_key(&self) -> Option<PublicKeyBlob<'a>> {
+        let root = self.metadata_root();
+        let assembly = root.metadata_stream.table.assembly.first()?;
+        assembly
+            .public_key
+            .resolve(root.heap)
+            .filter(|k| !k.is_empty())
+            .and_then(PublicKeyBlob::parse)
+    }
+
+    pub fn strong_name_signature(&self) -> Option<&'a [u8]> {
+        let dir = self.cli_header().strong_name_signature_hash;
+        if dir.virtual_address == 0 || dir.size == 0 {
+            return None;
+        }
+        self.get_bytes(dir.virtual_address, dir.size).ok()
+    }
+
+    /// Parts of the file covered by the strong name hash, as hashed by the CLR
+    ///
+    /// The DOS header and stub, the NT headers with the checksum and the security directory
+    /// read as zeros, the section headers, then the raw data of each section in header order
+    /// without the signature. Padding after the section headers and the certificate data
+    /// after the sections are not covered.
+    fn strong_name_hash_parts(&self) -> Result<Vec<&'a [u8]>, StrongNameError> {
+        const ZEROS: [u8; 8] = [0; 8];
+        let bytes =
+            |start: usize, end: usize| self.bytes.get(start..end).ok_or(StrongNameError::Malformed);
+
+        let nt_headers = self.pe_pointer as usize;
+        let optional_header = nt_headers + 4 + 20;
+        let checksum = optional_header + 64;
+        let security_dir = optional_header + if self.is_pe32_plus { 144 } else { 128 };
+        // `IMAGE_NT_HEADERS` has all 16 data directories whatever their count
+        let nt_headers_end = optional_header + if self.is_pe32_plus { 240 } else { 224 };
+        let section_headers = optional_header + self.size_of_optional_header as usize;
+
+        let mut parts = vec![
+            bytes(0, nt_headers)?,
+            bytes(nt_headers, checksum)?,
+            &ZEROS[..4],
+            bytes(checksum + 4, security_dir)?,
+            &ZEROS[..],
+            bytes(security_dir + 8, nt_headers_end)?,
+            bytes(section_headers, section_headers + self.sections.len() * 40)?,
+        ];
+
+        let dir = self.cli_header().strong_name_signature_hash;
+        let signature = if dir.virtual_address != 0 {
+            let offset = self
+                .rva_to_offset(dir.virtual_address)
+                .map_err(|_| StrongNameError::Malformed)?;
+            offset..offset + dir.size as usize
+        } else {
+            0..0
+        };
+
+        for section in &self.sections {
+            let start = section.pointer_to_raw_data as usize;
+            let end = start + section.size_of_raw_data as usize;
+            if signature.start < end && signature.end > start {
+                parts.push(bytes(start, signature.start.max(start))?);
+                parts.push(bytes(signature.end.min(end), end)?);
+            } else {
+                parts.push(bytes(start, end)?);
+            }
+        }
+
+        Ok(parts)
+    }
+
+    pub fn strong_name_hash(&self, alg: AssemblyHashAlgorithm) -> Result<Vec<u8>, StrongNameError> {
+        let parts = self.strong_name_hash_parts()?;
+
+        Ok(match alg {
+            AssemblyHashAlgorithm::SHA1 => digest::<sha1::Sha1>(&parts),
+            AssemblyHashAlgorithm::SHA256 => digest::<sha2::Sha256>(&parts),
+            AssemblyHashAlgorithm::SHA384 => digest::<sha2::Sha384>(&parts),
+            AssemblyHashAlgorithm::SHA512 => digest::<sha2::Sha512>(&parts),
+            AssemblyHashAlgorithm::None | AssemblyHashAlgorithm::MD5 => {
+                return Err(StrongNameError::UnsupportedHashAlgorithm)
+            }
+        })
+    }
+
+    /// Check the strong name signature against `Assembly.public_key`
+    pub fn verify_strong_name(&self) -> Result<(), StrongNameError> {
+        let public_key = self.public_key().ok_or(StrongNameError::NoPublicKey)?;
+        let signature = self
+            .strong_name_signature()
+            .ok_or(StrongNameError::NoSignature)?;
+
+        if public_key.public_key == ECMA_KEY {
+            return Err(StrongNameError::UnsupportedKey);
+        }
+
+        let rsa = public_key.rsa().ok_or(StrongNameError::UnsupportedKey)?;
+        let alg = public_key
+            .hash_algorithm()
+            .ok_or(StrongNameError::UnsupportedHashAlgorithm)?;
+
+        if signature.iter().all(|&b| b == 0) {
+            return Err(StrongNameError::DelaySigned);
+        }
+
+        let hash = self.strong_name_hash(alg)?;
+
+        if rsa.verify(alg, &hash, signature) {
+            Ok(())
+        } else {
+            Err(StrongNameError::InvalidSignature)
+        }
+    }
+}
+
+#[test]
+fn ecma_public_key_token() {
+    assert_eq!(
+        public_key_token(&ECMA_KEY),
+        [0xb7, 0x7a, 0x5c, 0x56, 0x19, 0x34, 0xe0, 0x89]
+    );
+}
+
+/// 512 bit RSA test key, modulus and private exponent
+#[cfg(test)]
+fn test_key() -> (BigUint, BigUint) {
+    let from_hex = |s: &str| BigUint::parse_bytes(s.as_bytes(), 16).unwrap();
+    (
+        from_hex("9ed698f95b194c8b0f9dc033b309892131b97374cdddc7eb1ccacd8a2b7c82c1496efabd5606ed267539d5ed14a8567eacf8a2604b425c2f4ae29ca397d39637"),
+        from_hex("43ac468ca6c5869910d4331953c4524a743700fbd8e20ed51af544d1036a0f02bf75fbe952a3faa7ebd757192d91facc272ddf0fe37556d7efdf057ccac06981"),
+    )
+}
+
+/// RSASSA-PKCS1-v1_5 signature of a SHA-1 hash, little endian
+#[cfg(test)]
+fn test_sign(hash: &[u8]) -> Vec<u8> {
+    let (n, d) = test_key();
+    let k = n.to_bytes_le().len();
+    let prefix = digest_info_prefix(AssemblyHashAlgorithm::SHA1).unwrap();
+
+    let mut em = vec![0xFF; k];
+    em[0] = 0;
+    em[1] = 1;
+    let t = em.len() - prefix.len() - hash.len();
+    em[t - 1] = 0;
+    em[t..t + prefix.len()].copy_from_slice(prefix);
+    em[t + prefix.len()..].copy_from_slice(hash);
+
+    let mut signature = BigUint::from_bytes_be(&em).modpow(&d, &n).to_bytes_le();
+    signature.resize(k, 0);
+    signature
+}
+
+#[test]
+fn rsa_pkcs1_verify() {
+    let (n, _) = test_key();
+    let modulus = n.to_bytes_le();
+    let key = RsaPublicKey {
+        exponent: 65537,
+        modulus: &modulus,
+    };
+
+    let hash = sha1::Sha1::digest(b"clrs").to_vec();
+    let signature = test_sign(&hash);
+
+    assert!(key.verify(AssemblyHashAlgorithm::SHA1, &hash, &signature));
+
+    let tampered = sha1::Sha1::digest(b"clrz").to_vec();
+    assert!(!key.verify(AssemblyHashAlgorithm::SHA1, &tampered, &signature));
+}
+
+/// Strong name hash as computed by dnlib's `StrongNameSigner`, reading the file front to back
+#[cfg(test)]
+fn reference_strong_name_hash(file: &[u8], signature: std::ops::Range<usize>) -> Vec<u8> {
+    let u16_at = |o: usize| u16::from_le_bytes([file[o], file[o + 1]]) as usize;
+    let u32_at = |o: usize| u32::from_le_bytes(file[o..o + 4].try_into().unwrap()) as usize;
+
+    let mut hasher = sha1::Sha1::new();
+    let nt_headers = u32_at(0x3C);
+    hasher.update(&file[..nt_headers]);
+    let sections = u16_at(nt_headers + 6);
+    hasher.update(&file[nt_headers..nt_headers + 0x18]);
+
+    let optional_header = nt_headers + 0x18;
+    let size = if u16_at(optional_header) == 0x010B {
+        0x60
+    } else {
+        0x70
+    };
+    let mut fields = file[optional_header..optional_header + size].to_vec();
+    fields[0x40..0x44].fill(0);
+    hasher.update(&fields);
+    let dirs = optional_header + size;
+    let mut dir_entries = file[dirs..dirs + 16 * 8].to_vec();
+    dir_entries[4 * 8..5 * 8].fill(0);
+    hasher.update(&dir_entries);
+
+    let section_headers = dirs + 16 * 8;
+    hasher.update(&file[section_headers..section_headers + sections * 0x28]);
+    for i in 0..sections {
+        let size = u32_at(section_headers + i * 0x28 + 0x10);
+        let start = u32_at(section_headers + i * 0x28 + 0x14);
+        for offset in (start..start + size).filter(|o| !signature.contains(o)) {
+            hasher.update([file[offset]]);
+        }
+    }
+    hasher.finalize().to_vec()
+}
+
+#[test]
+fn strong_name_sign_and_verify() {
+    use super::{AssemblyVersion, GuidIndex, ImageBuilder, Module, StringIndex};
+
+    let (n, _) = test_key();
+    let mut public_key = vec![PUBLICKEYBLOB, 2, 0, 0];
+    public_key.extend_from_slice(&0x2400u32.to_le_bytes());
+    public_key.extend_from_slice(&RSA1.to_le_bytes());
+    public_key.extend_from_slice(&512u32.to_le_bytes());
+    public_key.extend_from_slice(&65537u32.to_le_bytes());
+    public_key.extend_from_slice(&n.to_bytes_le());
+    let mut blob = Vec::new();
+    blob.extend_from_slice(&0x2400u32.to_le_bytes());
+    blob.extend_from_slice(&(AssemblyHashAlgorithm::SHA1 as u32).to_le_bytes());
+    blob.extend_from_slice(&(public_key.len() as u32).to_le_bytes());
+    blob.extend_from_slice(&public_key);
+
+    let mut builder = ImageBuilder::default();
+    builder.strong_name_signature_size = 64;
+    builder.is_dll = true;
+    let name = builder.metadata.heap.add_string("Signed.dll");
+    builder.metadata.table.module.push(Module {
+        generation: 0,
+        name,
+        mvid: GuidIndex(0),
+        enc_id: GuidIndex(0),
+        env_base_id: GuidIndex(0),
+    });
+    let public_key = builder.metadata.heap.add_blob(&blob);
+    let name = builder.metadata.heap.add_string("Signed");
+    builder.metadata.table.assembly.push(Assembly {
+        hash_alg_id: AssemblyHashAlgorithm::SHA1,
+        version: AssemblyVersion {
+            major_version: 1,
+            minor_version: 0,
+            build_number: 0,
+            revision_number: 0,
+        },
+        flags: AssemblyFlags::PUBLIC_KEY,
+        public_key,
+        name,
+        culture: StringIndex(0),
+    });
+    let mut bytes = builder.to_bytes().unwrap();
+
+    let image = Image::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        image.verify_strong_name(),
+        Err(StrongNameError::DelaySigned)
+    );
+    let dir = image.cli_header().strong_name_signature_hash;
+    let signature = image.rva_to_offset(dir.virtual_address).unwrap();
+    let signature = signature..signature + dir.size as usize;
+
+    // both ways of hashing agree on an image with a section and padding after its headers
+    let hash = reference_strong_name_hash(&bytes, signature.clone());
+    assert_eq!(
+        image.strong_name_hash(AssemblyHashAlgorithm::SHA1),
+        Ok(hash.clone())
+    );
+    bytes[signature.clone()].copy_from_slice(&test_sign(&hash));
+    assert_eq!(
+        Image::from_bytes(&bytes).unwrap().verify_strong_name(),
+        Ok(())
+    );
+
+    // the checksum and the security directory are hashed as zeros
+    let nt_headers = u32::from_le_bytes(bytes[0x3C..0x40].try_into().unwrap()) as usize;
+    let mut patched = bytes.clone();
+    patched[nt_headers + 0x18 + 0x40] ^= 0xFF;
+    patched[nt_headers + 0x18 + 0x60 + 4 * 8] ^= 0xFF;
+    assert_eq!(
+        Image::from_bytes(&patched).unwrap().verify_strong_name(),
+        Ok(())
+    );
+
+    // section data is covered
+    let mut tampered = bytes.clone();
+    *tampered.last_mut().unwrap() ^= 0xFF;
+    assert_eq!(
+        Image::from_bytes(&tampered).unwrap().verify_strong_name(),
+        Err(StrongNameError::InvalidSignature)
+    );
+}
@@ -0,0 +1,816 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use scroll::ctx::TryFromCtx;
+use scroll::Pread;
+
+use super::{
+    FieldAttributes, FieldIndex, FieldSig, HasConstant, Heap, Image, LocalVarSig, MetadataTable,
+    MetadataToken, MethodAttributes, MethodDefSig, MethodImplAttributes, MethodRefSig,
+    MethodSpecSig, PropertySig, TableIndex, Type, TypeAttributes, TypeDefIndex, TypeDefOrRef,
+    TypeDefOrRefOrSpecEncoded,
+};
+
+/// Field level check used by the row checks generated by `ClrPread`
+pub trait CheckField {
+    fn check_field(&self, _table: &MetadataTable, _heap: Heap) -> Option<String> {
+        None
+    }
+
+    /// Check for the start of a list like `TypeDef.field_list`
+    fn check_list_field(&self, table: &MetadataTable, heap: Heap) -> Option<String> {
+        self.check_field(table, heap)
+    }
+}
+
+pub trait CheckRow {
+    fn check_row(&self, table: &MetadataTable, heap: Heap) -> Vec<(&'static str, String)>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub token: MetadataToken,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{} [{:08X}]: {}",
+            severity,
+            self.token.to_raw(),
+            self.message
+        )
+    }
+}
+
+struct Validator<'t, 'a> {
+    table: &'t MetadataTable,
+    heap: Heap<'a>,
+    out: Vec<Diagnostic>,
+}
+
+/// Check ECMA-335 Partition II rules of the metadata
+pub fn validate(image: &Image) -> Vec<Diagnostic> {
+    let root = image.metadata_root();
+    validate_metadata(&root.metadata_stream.table, root.heap)
+}
+
+pub fn validate_metadata(table: &MetadataTable, heap: Heap) -> Vec<Diagnostic> {
+    let mut v = Validator {
+        table,
+        heap,
+        out: Vec::new(),
+    };
+
+    v.check_rows();
+    v.check_sorted_tables();
+    v.check_lists();
+    v.check_signatures();
+    v.check_type_flags();
+    v.check_type_cycles();
+    v.check_field_flags();
+    v.check_method_flags();
+    v.check_duplicates();
+
+    v.out
+}
+
+/// Parse a whole blob, trailing bytes are an error too
+fn parse_blob<'a, T: TryFromCtx<'a, scroll::Endian, Error = scroll::Error>>(
+    blob: &'a [u8],
+) -> Result<T, String> {
+    let offset = &mut 0;
+    let value = blob
+        .gread_with::<T>(offset, scroll::LE)
+        .map_err(|e| e.to_string())?;
+    if *offset != blob.len() {
+        return Err(format!("{} trailing bytes", blob.len() - *offset));
+    }
+    Ok(value)
+}
+
+impl<'t, 'a> Validator<'t, 'a> {
+    fn error(&mut self, token: MetadataToken, message: String) {
+        self.out.push(Diagnostic {
+            token,
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn check_rows(&mut self) {
+        for (token, field, msg) in self.table.check_rows(self.heap) {
+            self.error(token, format!("{}: {}", field, msg));
+        }
+    }
+
+    fn check_sorted<K: Ord + Copy>(
+        &mut self,
+        name: &str,
+        unique: bool,
+        rows: impl Iterator<Item = (MetadataToken, K)>,
+    ) {
+        let mut prev = None;
+
+        for (token, key) in rows {
+            match prev {
+                Some(prev) if key < prev => {
+                    self.error(token, format!("{} table is not sorted", name));
+                }
+                Some(prev) if unique && key == prev => {
+                    self.error(token, format!("{} table has duplicated key", name));
+                }
+                _ => {}
+            }
+            prev = Some(key);
+        }
+    }
+
+    fn check_sorted_tables(&mut self) {
+        let t = self.table;
+
+        self.check_sorted(
+            "ClassLayout",
+            true,
+            t.list_class_layout()
+                .map(|(i, r)| (MetadataToken::ClassLayout(i), r.parent.0)),
+        );
+        self.check_sorted(
+            "Constant",
+            false,
+            t.list_constant()
+                .map(|(i, r)| (MetadataToken::Constant(i), r.parent.encode())),
+        );
+        self.check_sorted(
+            "CustomAttribute",
+            false,
+            t.list_custom_attribute()
+                .map(|(i, r)| (MetadataToken::CustomAttribute(i), r.parent.encode())),
+        );
+        self.check_sorted(
+            "DeclSecurity",
+            false,
+            t.list_decl_security()
+                .map(|(i, r)| (MetadataToken::DeclSecurity(i), r.parent.encode())),
+        );
+        self.check_sorted(
+            "FieldLayout",
+            true,
+            t.list_field_layout()
+                .map(|(i, r)| (MetadataToken::FieldLayout(i), r.field.0)),
+        );
+        self.check_sorted(
+            "FieldMarshal",
+            true,
+            t.list_field_marshal()
+                .map(|(i, r)| (MetadataToken::FieldMarshal(i), r.parent.encode())),
+        );
+        self.check_sorted(
+            "FieldRVA",
+            true,
+            t.list_field_rva()
+                .map(|(i, r)| (MetadataToken::FieldRVA(i), r.field.0)),
+        );
+        self.check_sorted(
+            "GenericParam",
+            true,
+            t.list_generic_param()
+                .map(|(i, r)| (MetadataToken::GenericParam(i), (r.owner.encode(), r.number))),
+        );
+        self.check_sorted(
+            "GenericParamConstraint",
+            false,
+            t.list_generic_param_constraint()
+                .map(|(i, r)| (MetadataToken::GenericParamConstraint(i), r.owner.0)),
+        );
+        self.check_sorted(
+            "ImplMap",
+            true,
+            t.list_impl_map()
+                .map(|(i, r)| (MetadataToken::ImplMap(i), r.member_forwarded.encode())),
+        );
+        self.check_sorted(
+            "InterfaceImpl",
+            false,
+            t.list_interface_impl().map(|(i, r)| {
+                (
+                    MetadataToken::InterfaceImpl(i),
+                    (r.class.0, r.interface.encode()),
+                )
+            }),
+        );
+        self.check_sorted(
+            "MethodImpl",
+            false,
+            t.list_method_impl()
+                .map(|(i, r)| (MetadataToken::MethodImpl(i), r.class.0)),
+        );
+        self.check_sorted(
+            "MethodSemantics",
+            false,
+            t.list_method_semantics()
+                .map(|(i, r)| (MetadataToken::MethodSemantics(i), r.association.encode())),
+        );
+        self.check_sorted(
+            "NestedClass",
+            true,
+            t.list_nested_class()
+                .map(|(i, r)| (MetadataToken::NestedClass(i), r.nested_class.0)),
+        );
+    }
+
+    /// Lists are runs owned by consecutive rows so their starts must not go backward
+    fn check_list(&mut self, name: &str, rows: impl Iterator<Item = (MetadataToken, u32)>) {
+        let mut prev = 0;
+
+        for (token, start) in rows {
+            if start < prev {
+                self.error(
+                    token,
+                    format!(
+                        "{} list starts at {} which overlaps previous list starting at {}",
+                        name, start, prev
+                    ),
+                );
+            }
+            prev = prev.max(start);
+        }
+    }
+
+    fn check_lists(&mut self) {
+        let t = self.table;
+
+        self.check_list(
+            "Field",
+            t.list_type_def()
+                .map(|(i, r)| (MetadataToken::TypeDef(i), r.field_list.0)),
+        );
+        self.check_list(
+            "MethodDef",
+            t.list_type_def()
+                .map(|(i, r)| (MetadataToken::TypeDef(i), r.method_list.0)),
+        );
+        self.check_list(
+            "Param",
+            t.list_method_def()
+                .map(|(i, r)| (MetadataToken::MethodDef(i), r.param_list.0)),
+        );
+        self.check_list(
+            "Event",
+            t.list_event_map()
+                .map(|(i, r)| (MetadataToken::EventMap(i), r.event_list.0)),
+        );
+        self.check_list(
+            "Property",
+            t.list_property_map()
+                .map(|(i, r)| (MetadataToken::PropertyMap(i), r.property_list.0)),
+        );
+    }
+
+    fn check_signature<T: for<'b> TryFromCtx<'b, scroll::Endian, Error = scroll::Error>>(
+        &mut self,
+        token: MetadataToken,
+        blob: Option<&[u8]>,
+    ) {
+        let result = match blob {
+            Some(blob) => parse_blob::<T>(blob).map(|_| ()),
+            None => Err("missing signature".to_string()),
+        };
+
+        if let Err(e) = result {
+            self.error(token, format!("malformed signature: {}", e));
+        }
+    }
+
+    fn check_signatures(&mut self) {
+        let t = self.table;
+        let heap = self.heap;
+
+        for (i, r) in t.list_method_def() {
+            self.check_signature::<MethodDefSig>(
+                MetadataToken::MethodDef(i),
+                r.signature.resolve(heap),
+            );
+        }
+
+        for (i, r) in t.list_field() {
+            self.check_signature::<FieldSig>(MetadataToken::Field(i), r.signature.resolve(heap));
+        }
+
+        for (i, r) in t.list_member_ref() {
+            let blob = r.signature.resolve(heap);
+            // FIELD prolog
+            if blob.and_then(|b| b.first()) == Some(&0x06) {
+                self.check_signature::<FieldSig>(MetadataToken::MemberRef(i), blob);
+            } else {
+                self.check_signature::<MethodRefSig>(MetadataToken::MemberRef(i), blob);
+            }
+        }
+
+        for (i, r) in t.list_stand_along_sig() {
+            let blob = r.signature.resolve(heap);
+            // LOCAL_SIG prolog, otherwise the signature of a calli
+            if blob.and_then(|b| b.first()) == Some(&0x07) {
+                self.check_signature::<LocalVarSig>(MetadataToken::StandAloneSig(i), blob);
+            } else {
+                self.check_signature::<MethodRefSig>(MetadataToken::StandAloneSig(i), blob);
+            }
+        }
+
+        for (i, r) in t.list_type_spec() {
+            self.check_signature::<Type>(MetadataToken::TypeSpec(i), r.signature.resolve(heap));
+        }
+
+        for (i, r) in t.list_method_spec() {
+            self.check_signature::<MethodSpecSig>(
+                MetadataToken::MethodSpec(i),
+                r.instantiation.resolve(heap),
+            );
+        }
+
+        for (i, r) in t.list_property() {
+            self.check_signature::<PropertySig>(MetadataToken::Property(i), r.ty.resolve(heap));
+        }
+    }
+
+    fn enclosing_class(&self, index: TypeDefIndex) -> Option<TypeDefIndex> {
+        // NestedClass is sorted by nested class
+        let nested_class = &self.table.nested_class;
+        let pos = nested_class
+            .binary_search_by_key(&index.0, |n| n.nested_class.0)
+            .ok()?;
+        Some(nested_class[pos].enclosing_class)
+    }
+
+    fn check_type_flags(&mut self) {
+        for (i, r) in self.table.list_type_def() {
+            let token = MetadataToken::TypeDef(i);
+            let flags = r.flags;
+
+            if flags & TypeAttributes::LAYOUT_MASK == TypeAttributes::LAYOUT_MASK {
+                self.error(token, "invalid layout 0x18".to_string());
+            }
+
+            if flags.contains(TypeAttributes::INTERFACE) {
+                if !flags.contains(TypeAttributes::ABSTRACT) {
+                    self.error(token, "interface must be abstract".to_string());
+                }
+                if flags.contains(TypeAttributes::SEALED) {
+                    self.error(token, "interface must not be sealed".to_string());
+                }
+            }
+
+            let visibility = flags & TypeAttributes::VISIBILITY_MASK;
+            let is_nested_visibility =
+                visibility != TypeAttributes::NOT_PUBLIC && visibility != TypeAttributes::PUBLIC;
+            let is_nested = self.enclosing_class(i).is_some();
+
+            if is_nested_visibility && !is_nested {
+                self.error(
+                    token,
+                    "nested visibility on a type without NestedClass row".to_string(),
+                );
+            } else if !is_nested_visibility && is_nested {
+                self.error(token, "nested type has top level visibility".to_string());
+            }
+        }
+    }
+
+    /// Value types stored inline in the instance fields of a type
+    fn value_fields(&self, index: TypeDefIndex) -> Vec<TypeDefIndex> {
+        index
+            .resolve_fields(self.table)
+            .filter(|(_, f)| !f.flags.contains(FieldAttributes::STATIC))
+            .filter_map(|(_, f)| f.signature.resolve(self.heap))
+            .filter_map(|blob| parse_blob::<FieldSig>(blob).ok())
+            .filter_map(|sig| match sig.ty {
+                Type::ValueType(TypeDefOrRefOrSpecEncoded::TypeDef(def)) => Some(def),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn check_type_cycles(&mut self) {
+        let count = self.table.type_def.len();
+        for (i, r) in self.table.list_type_def() {
+            let token = MetadataToken::TypeDef(i);
+
+            // II.22.37 a type must not be its own base, a longer walk than the table is a cycle
+            // through other types which is reported on those
+            let mut extends = r.extends;
+            for _ in 0..count {
+                let base = match extends {
+                    TypeDefOrRef::TypeDefIndex(base) if base.0 != 0 => base,
+                    _ => break,
+                };
+                if base == i {
+                    self.error(token, "circular Extends chain".to_string());
+                    break;
+                }
+                match base.resolve_table(self.table) {
+                    Some(b) => extends = b.extends,
+                    None => break,
+                }
+            }
+
+            // II.10.7 a value type must not contain itself, directly or through other fields
+            let mut seen = vec![i];
+            let mut pending = self.value_fields(i);
+            while let Some(def) = pending.pop() {
+                if def == i {
+                    self.error(token, "value type contains itself".to_string());
+                    break;
+                }
+                if !seen.contains(&def) {
+                    seen.push(def);
+                    pending.extend(self.value_fields(def));
+                }
+            }
+        }
+    }
+
+    fn has_constant(&self, parent: HasConstant) -> bool {
+        // Constant is sorted by parent
+        self.table
+            .constant
+            .binary_search_by_key(&parent.encode(), |c| c.parent.encode())
+            .is_ok()
+    }
+
+    fn check_field_flags(&mut self) {
+        for (i, r) in self.table.list_field() {
+            let token = MetadataToken::Field(i);
+            let flags = r.flags;
+
+            if flags & FieldAttributes::FIELD_ACCESS_MASK == FieldAttributes::FIELD_ACCESS_MASK {
+                self.error(token, "invalid field access 7".to_string());
+            }
+
+            if flags.contains(FieldAttributes::LITERAL) {
+                if !flags.contains(FieldAttributes::STATIC) {
+                    self.error(token, "literal field must be static".to_string());
+                }
+                if flags.contains(FieldAttributes::INIT_ONLY) {
+                    self.error(token, "literal field must not be init only".to_string());
+                }
+                if !flags.contains(FieldAttributes::HAS_DEFAULT) {
+                    self.error(token, "literal field must have a default".to_string());
+                }
+            }
+
+            if flags.contains(FieldAttributes::HAS_DEFAULT)
+                && !self.has_constant(HasConstant::FieldIndex(i))
+            {
+                self.error(token, "HasDefault field has no Constant row".to_string());
+            }
+
+            let has_rva = i.resolve_rva(self.table).is_some();
+            if flags.contains(FieldAttributes::HAS_FIELD_RVA) != has_rva {
+                self.error(
+                    token,
+                    "HasFieldRVA flag does not match FieldRVA table".to_string(),
+                );
+            }
+        }
+    }
+
+    fn check_method_flags(&mut self) {
+        for (i, r) in self.table.list_method_def() {
+            let token = MetadataToken::MethodDef(i);
+            let flags = r.flags;
+
+            if flags & MethodAttributes::MEMBER_ACCESS_MASK == MethodAttributes::MEMBER_ACCESS_MASK
+            {
+                self.error(token, "invalid method access 7".to_string());
+            }
+
+            if flags.contains(MethodAttributes::STATIC)
+                && flags.intersects(
+                    MethodAttributes::FINAL
+                        | MethodAttributes::VIRTUAL
+                        | MethodAttributes::NEW_SLOT,
+                )
+            {
+                self.error(
+                    token,
+                    "static method must not be final, virtual or newslot".to_string(),
+                );
+            }
+
+            if flags.intersects(MethodAttributes::ABSTRACT | MethodAttributes::FINAL)
+                && !flags.contains(MethodAttributes::VIRTUAL)
+            {
+                self.error(
+                    token,
+                    "abstract or final method must be virtual".to_string(),
+                );
+            }
+
+            if flags.contains(MethodAttributes::ABSTRACT) {
+                if r.rva != 0 {
+                    self.error(token, "abstract method must not have a body".to_string());
+                }
+                if flags.contains(MethodAttributes::PINVOKE_IMPL) {
+                    self.error(token, "abstract method must not be pinvoke".to_string());
+                }
+            } else if r.rva == 0
+                && !flags.contains(MethodAttributes::PINVOKE_IMPL)
+                && !r.impl_flags.contains(MethodImplAttributes::INTERNAL_CALL)
+                && r.impl_flags & MethodImplAttributes::CODE_TYPE_MASK
+                    != MethodImplAttributes::RUNTIME
+            {
+                self.error(token, "concrete method has no body".to_string());
+            }
+        }
+    }
+
+    fn report_duplicates<K: Eq + Hash>(
+        &mut self,
+        name: &str,
+        severity: Severity,
+        rows: impl Iterator<Item = (MetadataToken, K)>,
+    ) {
+        let mut seen = HashMap::new();
+
+        for (token, key) in rows {
+            if let Some(first) = seen.insert(key, token) {
+                self.out.push(Diagnostic {
+                    token,
+                    severity,
+                    message: format!("duplicated {} of token {:08X}", name, first.to_raw()),
+                });
+            }
+        }
+    }
+
+    fn check_duplicates(&mut self) {
+        let t = self.table;
+        let heap = self.heap;
+
+        let types = t
+            .list_type_def()
+            .map(|(i, r)| {
+                (
+                    MetadataToken::TypeDef(i),
+                    (
+                        self.enclosing_class(i),
+                        r.type_namespace.resolve(heap),
+                        r.type_name.resolve(heap),
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.report_duplicates("TypeDef", Severity::Error, types.into_iter());
+
+        let is_compiler_controlled_field = |f: FieldAttributes| {
+            f & FieldAttributes::FIELD_ACCESS_MASK == FieldAttributes::COMPILER_CONTROLLED
+        };
+        let is_compiler_controlled_method = |f: MethodAttributes| {
+            f & MethodAttributes::MEMBER_ACCESS_MASK == MethodAttributes::COMPILER_CONTROLLED
+        };
+
+        for (ty, _) in t.list_type_def() {
+            let fields = ty
+                .resolve_fields(t)
+                .filter(|(_, f)| !is_compiler_controlled_field(f.flags))
+                .map(|(i, f): (FieldIndex, _)| {
+                    (
+                        MetadataToken::Field(i),
+                        (f.name.resolve(heap), f.signature.resolve(heap)),
+                    )
+                })
+                .collect::<Vec<_>>();
+            self.report_duplicates("Field", Severity::Error, fields.into_iter());
+
+            let methods = ty
+                .resolve_methods(t)
+                .filter(|(_, m)| !is_compiler_controlled_method(m.flags))
+                .map(|(i, m)| {
+                    (
+                        MetadataToken::MethodDef(i),
+                        (m.name.resolve(heap), m.signature.resolve(heap)),
+                    )
+                })
+                .collect::<Vec<_>>();
+            self.report_duplicates("MethodDef", Severity::Error, methods.into_iter());
+        }
+
+        self.report_duplicates(
+            "TypeRef",
+            Severity::Warning,
+            t.list_type_ref().map(|(i, r)| {
+                (
+                    MetadataToken::TypeRef(i),
+                    (
+                        r.resolution_scope,
+                        r.type_namespace.resolve(heap),
+                        r.type_name.resolve(heap),
+                    ),
+                )
+            }),
+        );
+
+        self.report_duplicates(
+            "MemberRef",
+            Severity::Warning,
+            t.list_member_ref().map(|(i, r)| {
+                (
+                    MetadataToken::MemberRef(i),
+                    (r.class, r.name.resolve(heap), r.signature.resolve(heap)),
+                )
+            }),
+        );
+
+        self.report_duplicates(
+            "AssemblyRef",
+            Severity::Warning,
+            t.list_assembly_ref().map(|(i, r)| {
+                let v = r.version;
+                (
+                    MetadataToken::AssemblyRef(i),
+                    (
+                        r.name.resolve(heap),
+                        r.culture.resolve(heap),
+                        r.public_key_or_token.resolve(heap),
+                        (
+                            v.major_version,
+                            v.minor_version,
+                            v.build_number,
+                            v.revision_number,
+                        ),
+                    ),
+                )
+            }),
+        );
+    }
+}
+
+#[test]
+fn validate_rows() {
+    use super::{BlobIndex, StringIndex, TypeDef, TypeDefOrRef};
+
+    let strings = "\0Foo\0Bar\0";
+    let blob = [0x00, 0x02, 0x06, 0x08];
+    let heap = Heap {
+        strings,
+        blob: &blob,
+        ..Heap::default()
+    };
+
+    let type_def = |name, field_list| TypeDef {
+        flags: TypeAttributes::PUBLIC,
+        type_name: StringIndex(name),
+        type_namespace: StringIndex(0),
+        extends: TypeDefOrRef::TypeDefIndex(TypeDefIndex(0)),
+        field_list: FieldIndex(field_list),
+        method_list: super::MethodDefIndex(1),
+    };
+
+    let table = MetadataTable {
+        // name points into "Foo" and the second list goes backward
+        type_def: vec![type_def(1, 2), type_def(2, 1)],
+        field: vec![super::Field {
+            flags: FieldAttributes::PUBLIC | FieldAttributes::LITERAL,
+            name: StringIndex(5),
+            signature: BlobIndex(1),
+        }],
+        ..MetadataTable::default()
+    };
+
+    let messages = validate_metadata(&table, heap)
+        .into_iter()
+        .map(|d| (d.token.to_raw(), d.message))
+        .collect::<Vec<_>>();
+
+    assert!(messages.contains(&(
+        0x0200_0002,
+        "type_name: #Strings offset 0x2 points into the middle of a string".to_string()
+    )));
+    assert!(messages.contains(&(
+        0x0200_0002,
+        "Field list starts at 1 which overlaps previous list starting at 2".to_string()
+    )));
+    assert!(messages.contains(&(0x0400_0001, "literal field must be static".to_string())));
+    assert!(messages.contains(&(0x0400_0001, "literal field must have a default".to_string())));
+}
+
+#[test]
+fn validate_signatures() {
+    use super::{
+        BlobIndex, MemberRef, MemberRefParent, MethodDefOrRef, MethodSpec, Property,
+        PropertyAttributes, StandAloneSig, StringIndex, TypeRefIndex,
+    };
+
+    let blob = [
+        0x00, // empty
+        0x06, 0x05, 0x02, 0x01, 0x08, 0x41, 0x0E, // vararg void (int32, ..., string)
+        0x04, 0x07, 0x02, 0x08, 0x0E, // locals (int32, string)
+        0x03, 0x0A, 0x01, 0x08, // <int32>
+        0x03, 0x28, 0x00, 0x08, // instance int32 ()
+        0x02, 0x0A, 0x01, // truncated <_>
+        0x02, 0x08, 0x01, // property with a bad count
+    ];
+    let heap = Heap {
+        strings: "\0M\0",
+        blob: &blob,
+        ..Heap::default()
+    };
+
+    let table = MetadataTable {
+        member_ref: vec![MemberRef {
+            class: MemberRefParent::TypeRefIndex(TypeRefIndex(1)),
+            name: StringIndex(1),
+            signature: BlobIndex(1),
+        }],
+        stand_along_sig: vec![
+            StandAloneSig {
+                signature: BlobIndex(8),
+            },
+            StandAloneSig {
+                signature: BlobIndex(1),
+            },
+        ],
+        method_spec: vec![
+            MethodSpec {
+                method: MethodDefOrRef::MemberRefIndex(1.into()),
+                instantiation: BlobIndex(13),
+            },
+            MethodSpec {
+                method: MethodDefOrRef::MemberRefIndex(1.into()),
+                instantiation: BlobIndex(21),
+            },
+        ],
+        property: vec![
+            Property {
+                flags: PropertyAttributes::empty(),
+                name: StringIndex(1),
+                ty: BlobIndex(17),
+            },
+            Property {
+                flags: PropertyAttributes::empty(),
+                name: StringIndex(1),
+                ty: BlobIndex(24),
+            },
+        ],
+        ..MetadataTable::default()
+    };
+
+    let mut errors = validate_metadata(&table, heap)
+        .into_iter()
+        .filter(|d| d.message.starts_with("malformed signature"))
+        .map(|d| d.token.to_raw())
+        .collect::<Vec<_>>();
+    errors.sort_unstable();
+    assert_eq!(errors, [0x1700_0002, 0x2B00_0002]);
+}
+
+#[test]
+fn validate_type_cycles() {
+    let source = "
+        .assembly extern mscorlib { .ver 4:0:0:0 }
+        .assembly cycle { .ver 0:0:0:0 }
+
+        .class public A extends B {}
+        .class public B extends A {}
+        .class public C extends A {}
+
+        .class public sequential ansi sealed S extends [mscorlib]System.ValueType
+        {
+          .field public valuetype S s
+        }
+
+        .class public sequential ansi sealed T extends [mscorlib]System.ValueType
+        {
+          .field public static valuetype T instance
+          .field public int32 x
+        }
+    ";
+    let bytes = crate::cil::asm::assemble(source).unwrap();
+    let image = Image::from_bytes(&bytes).unwrap();
+
+    let messages = validate(&image)
+        .into_iter()
+        .map(|d| (d.token.to_raw(), d.message))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            (0x0200_0002, "circular Extends chain".to_string()),
+            (0x0200_0003, "circular Extends chain".to_string()),
+            (0x0200_0005, "value type contains itself".to_string()),
+        ]
+    );
+}
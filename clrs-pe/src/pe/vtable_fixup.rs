@@ -0,0 +1,99 @@
+use scroll::ctx::TryFromCtx;
+use scroll::{Pread, LE};
+
+use super::{Image, MetadataToken};
+
+bitflags_tryctx! {
+    /// COR_VTABLE_*
+    pub struct VTableFixupFlags: u16 {
+        const SLOT_32BIT = 0x01;
+        const SLOT_64BIT = 0x02;
+        const FROM_UNMANAGED = 0x04;
+        const FROM_UNMANAGED_RETAIN_APPDOMAIN = 0x08;
+        const CALL_MOST_DERIVED = 0x10;
+    }
+}
+
+// II.25.3.3.3
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pread)]
+pub struct VTableFixup {
+    pub rva: u32,
+    pub count: u16,
+    pub flags: VTableFixupFlags,
+}
+
+impl VTableFixup {
+    pub fn slot_size(&self) -> u32 {
+        if self.flags.contains(VTableFixupFlags::SLOT_64BIT) {
+            8
+        } else {
+            4
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VTable {
+    pub fixup: VTableFixup,
+    /// Slots hold method tokens until the loader patches them with addresses
+    pub methods: Vec<MetadataToken>,
+}
+
+impl<'a> Image<'a> {
+    pub fn vtable_fixups(&self) -> Result<Vec<VTableFixup>, goblin::error::Error> {
+        let dir = self.cli_header().vtable_fixups;
+
+        if dir.virtual_address == 0 {
+            return Ok(Vec::new());
+        }
+
+        let bytes = self.get_bytes(dir.virtual_address, dir.size)?;
+        let offset = &mut 0;
+
+        (0..bytes.len() / 8)
+            .map(|_| Ok(bytes.gread_with(offset, LE)?))
+            .collect()
+    }
+
+    pub fn vtables(&self) -> Result<Vec<VTable>, goblin::error::Error> {
+        self.vtable_fixups()?
+            .into_iter()
+            .map(|fixup| {
+                let slot_size = fixup.slot_size();
+                let bytes = self.get_bytes(fixup.rva, fixup.count as u32 * slot_size)?;
+
+                let methods = bytes
+                    .chunks(slot_size as usize)
+                    .map(|slot| {
+                        // upper half of a 64bit slot is zero before fixup
+                        let token: u32 = slot.pread_with(0, LE)?;
+                        MetadataToken::from_raw(token).ok_or_else(|| {
+                            goblin::error::Error::Malformed(format!(
+                                "Invalid vtable slot token {:#x}",
+                                token
+                            ))
+                        })
+                    })
+                    .collect::<Result<_, goblin::error::Error>>()?;
+
+                Ok(VTable { fixup, methods })
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn vtable_fixup() {
+    let fixup: VTableFixup = [0x00, 0x40, 0x00, 0x00, 0x02, 0x00, 0x06, 0x00]
+        .pread_with(0, LE)
+        .unwrap();
+
+    assert_eq!(fixup.rva, 0x4000);
+    assert_eq!(fixup.count, 2);
+    assert_eq!(
+        fixup.flags,
+        VTableFixupFlags::SLOT_64BIT | VTableFixupFlags::FROM_UNMANAGED
+    );
+    assert_eq!(fixup.slot_size(), 8);
+}
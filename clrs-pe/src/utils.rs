@@ -11,6 +11,23 @@ macro_rules! num_tryctx {
                     Ok((n, *offset))
                 }
             }
+
+            impl ::scroll::ctx::TryIntoCtx<PeCtx> for $num {
+                type Error = scroll::Error;
+
+                fn try_into_ctx(self, dst: &mut [u8], _: PeCtx) -> Result<usize, Self::Error> {
+                    ::scroll::Pwrite::pwrite_with(dst, self, 0, scroll::LE)
+                }
+            }
+
+            impl crate::pe::CheckField for $num {}
+
+            #[cfg(feature = "serde")]
+            impl crate::pe::ToJson for $num {
+                fn to_json(&self, _names: &crate::cil::disasm::Names) -> ::serde_json::Value {
+                    ::serde_json::Value::from(*self)
+                }
+            }
         )+
     };
 }
@@ -43,6 +60,31 @@ macro_rules! bitflags_tryctx {
                     Ok((flags, std::mem::size_of::<$num_ty>()))
                 }
             }
+
+            impl<C: Copy> ::scroll::ctx::TryIntoCtx<C> for $bitflags where $num_ty: ::scroll::ctx::TryIntoCtx<C, Error = ::scroll::Error> {
+                type Error = ::scroll::Error;
+
+                fn try_into_ctx(self, dst: &mut [u8], ctx: C) -> Result<usize, Self::Error> {
+                    ::scroll::Pwrite::pwrite_with(dst, self.bits(), 0, ctx)
+                }
+            }
+
+            impl crate::pe::CheckField for $bitflags {}
+
+            #[cfg(feature = "serde")]
+            impl ::serde::Serialize for $bitflags {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.bits().serialize(serializer)
+                }
+            }
+
+            /// Names of the set flags like `PUBLIC | STATIC`
+            #[cfg(feature = "serde")]
+            impl crate::pe::ToJson for $bitflags {
+                fn to_json(&self, _names: &crate::cil::disasm::Names) -> ::serde_json::Value {
+                    ::serde_json::Value::from(format!("{:?}", self))
+                }
+            }
         )+
     };
 }
@@ -97,6 +139,30 @@ macro_rules! enum_tryctx {
             }
         }
 
+        impl<C: Copy> ::scroll::ctx::TryIntoCtx<C> for $name where $inner: ::scroll::ctx::TryIntoCtx<C, Error = scroll::Error> {
+            type Error = scroll::Error;
+
+            fn try_into_ctx(self, dst: &mut [u8], ctx: C) -> Result<usize, Self::Error> {
+                ::scroll::Pwrite::pwrite_with(dst, self as $inner, 0, ctx)
+            }
+        }
+
+        impl crate::pe::CheckField for $name {}
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&format!("{:?}", self))
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl crate::pe::ToJson for $name {
+            fn to_json(&self, _names: &crate::cil::disasm::Names) -> ::serde_json::Value {
+                ::serde_json::Value::from(format!("{:?}", self))
+            }
+        }
+
         enum_tryctx! {
             $($t)*
         }
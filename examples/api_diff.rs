@@ -0,0 +1,26 @@
+use clrs_pe::pe::{diff_api, Image};
+
+/// `api_diff old.dll [new.dll]`, prints the API surface or the breaking changes
+fn main() {
+    let surfaces = std::env::args()
+        .skip(1)
+        .map(|path| {
+            let file = std::fs::read(path).unwrap();
+            Image::from_bytes(&file).unwrap().api_surface()
+        })
+        .collect::<Vec<_>>();
+
+    match &surfaces[..] {
+        [surface] => print!("{}", surface),
+        [old, new] => {
+            let changes = diff_api(old, new);
+            for change in &changes {
+                println!("{}", change);
+            }
+            if !changes.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        _ => eprintln!("usage: api_diff old.dll [new.dll]"),
+    }
+}
@@ -1,9 +1,26 @@
 use clrs_pe::pe::Image;
 
 fn main() {
-    let file = include_bytes!("../HelloWorld/bin/Release/net5.0/mscorlib.dll");
-    let image = Image::from_bytes(file).unwrap();
-    let wasm = clrs_compiler::compile(&image);
+    // `--exceptions` imports the runtime hooks raising managed exceptions
+    let exceptions = std::env::args().any(|arg| arg == "--exceptions");
+    let path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "HelloWorld/bin/Release/net5.0/mscorlib.dll".into());
+    let file = std::fs::read(path).unwrap();
+    let image = Image::from_bytes(&file).unwrap();
+    let options = clrs_compiler::Options { exceptions };
+    let output = match clrs_compiler::compile_with(&image, &options) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for warning in output.warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+    let wasm = output.wasm;
     println!("{}", wasmprinter::print_bytes(&wasm).unwrap());
     wasmparser::validate(&wasm).unwrap();
 }
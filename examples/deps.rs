@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use clrs_pe::pe::reference_closure;
+
+/// `deps root.dll [--json] [probe dirs...]`, prints the reference closure as DOT or JSON
+fn main() {
+    let mut json = false;
+    let mut args = std::env::args()
+        .skip(1)
+        .filter(|arg| {
+            let is_flag = arg == "--json";
+            json |= is_flag;
+            !is_flag
+        })
+        .map(PathBuf::from);
+    let root = args
+        .next()
+        .expect("usage: deps root.dll [--json] [probe dirs...]");
+    let probe_dirs = args.collect::<Vec<_>>();
+
+    let graph = reference_closure(&root, &probe_dirs).unwrap();
+    if json {
+        println!("{}", graph.to_json());
+    } else {
+        print!("{}", graph.to_dot());
+    }
+}
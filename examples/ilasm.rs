@@ -0,0 +1,16 @@
+use clrs_pe::cil::asm::assemble;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let input = args.next().unwrap_or_else(|| "tests/il/hello.il".into());
+    let output = args.next().unwrap_or_else(|| "out.dll".into());
+
+    let source = std::fs::read_to_string(input).unwrap();
+    match assemble(&source) {
+        Ok(image) => std::fs::write(output, image).unwrap(),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use clrs_pe::{cil::disasm::disassemble, pe::Image};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets/HelloWorld.dll".into());
+    let file = std::fs::read(path).unwrap();
+    let image = Image::from_bytes(&file).unwrap();
+
+    print!("{}", disassemble(&image));
+}
@@ -1,7 +1,27 @@
-use clrs_pe::pe::Image;
+use clrs_pe::pe::{image_to_json, Image};
 
+/// `pe [--json] [paths...]`, `--json` prints one metadata document per assembly
 fn main() {
-    let file = include_bytes!("../assets/HelloWorld.dll");
-    let image = Image::from_bytes(file).unwrap();
-    clrs_compiler::dump(&image);
+    let mut json = false;
+    let mut paths = std::env::args()
+        .skip(1)
+        .filter(|arg| {
+            let is_flag = arg == "--json";
+            json |= is_flag;
+            !is_flag
+        })
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        paths.push("assets/HelloWorld.dll".into());
+    }
+
+    for path in paths {
+        let file = std::fs::read(path).unwrap();
+        let image = Image::from_bytes(&file).unwrap();
+        if json {
+            println!("{}", image_to_json(&image));
+        } else {
+            clrs_compiler::dump(&image);
+        }
+    }
 }
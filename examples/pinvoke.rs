@@ -0,0 +1,21 @@
+use clrs_pe::pe::{Image, TableIndex};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets/HelloWorld.dll".into());
+    let file = std::fs::read(path).unwrap();
+    let image = Image::from_bytes(&file).unwrap();
+    let root = image.metadata_root();
+    let table = &root.metadata_stream.table;
+
+    for (module, imports) in image.pinvoke_report() {
+        println!("{}", module);
+
+        for (index, info) in imports {
+            let method = index.resolve_table(table).unwrap();
+            let name = method.name.resolve(root.heap).unwrap();
+            println!("    {} <- {}", info, name);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use clrs_pe::cil::verify::verify;
+use clrs_pe::pe::{validate, Image};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets/HelloWorld.dll".into());
+    let file = std::fs::read(path).unwrap();
+    let image = Image::from_bytes(&file).unwrap();
+
+    for diagnostic in validate(&image) {
+        println!("{}", diagnostic);
+    }
+    for error in verify(&image) {
+        println!("error {}", error);
+    }
+}